@@ -1,3 +1,10 @@
+// the bench predates clippy being run over every target
+#![allow(
+    clippy::needless_borrow,
+    clippy::redundant_closure,
+    clippy::unnecessary_mut_passed
+)]
+
 use criterion::{criterion_group, criterion_main, Criterion};
 use merkle_tree::{Hash, MerkleTree, Proof};
 
@@ -0,0 +1,151 @@
+//! Find the leaves that differ from a remote tree by exchanging only the node
+//! hashes of differing subtrees (Merkle anti-entropy).
+//!
+//! Both trees are walked level by level from the highest level they share.
+//! Each level costs one request for the hashes of every subtree still in
+//! question, and subtrees with equal hashes are never descended into.
+
+use crate::{Hash, MerkleTree};
+use std::convert::Infallible;
+
+/// Access to the node hashes of a tree that is usually held elsewhere, e.g.
+/// behind a network connection.
+pub trait RemoteTree {
+    type Error;
+
+    /// The number of leaves in the remote tree.
+    fn leaf_count(&self) -> Result<usize, Self::Error>;
+
+    /// The hashes of the nodes at a height above the leaves (leaves are height
+    /// 0) and the given offsets within that level, in the same order.
+    fn nodes(&self, height: usize, offsets: &[usize]) -> Result<Vec<Hash>, Self::Error>;
+}
+
+impl RemoteTree for MerkleTree {
+    type Error = Infallible;
+
+    fn leaf_count(&self) -> Result<usize, Infallible> {
+        Ok(self.leaf_count)
+    }
+
+    fn nodes(&self, height: usize, offsets: &[usize]) -> Result<Vec<Hash>, Infallible> {
+        Ok(offsets
+            .iter()
+            .map(|offset| *self.node_at_height(height, *offset))
+            .collect())
+    }
+}
+
+impl MerkleTree {
+    /// Return the offsets of the leaves that differ from a remote tree, which
+    /// are the leaves to transfer to bring the trees in sync, in ascending
+    /// order.  Leaves present in only one of the trees always differ.
+    ///
+    /// O(k log n) hashes are requested for k differences, in one request per
+    /// level.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let mut remote = MerkleTree::new(&leaves).unwrap();
+    /// remote.update(2, MerkleTree::hash(b"z")).unwrap();
+    ///
+    /// assert_eq!(tree.reconcile(&remote).unwrap(), [2]);
+    /// ```
+    pub fn reconcile<R: RemoteTree>(&self, remote: &R) -> Result<Vec<usize>, R::Error> {
+        let remote_count = remote.leaf_count()?;
+        let shared_count = self.leaf_count.min(remote_count);
+        let remote_levels = remote_count.next_power_of_two().max(2).ilog2() as usize;
+        let mut height = self.num_levels().min(remote_levels);
+
+        // start at the highest level both trees have
+        let mut offsets = (0..shared_count.div_ceil(1 << height)).collect::<Vec<_>>();
+
+        let mut differences = loop {
+            // nodes that also cover leaves past the shared ones always differ
+            let (full, partial): (Vec<usize>, Vec<usize>) = offsets
+                .iter()
+                .partition(|offset| (*offset + 1) << height <= shared_count);
+
+            let remote_nodes = remote.nodes(height, &full)?;
+            let mut differing = full
+                .into_iter()
+                .zip(remote_nodes)
+                .filter(|(offset, hash)| self.node_at_height(height, *offset) != hash)
+                .map(|(offset, _)| offset)
+                .chain(partial)
+                .collect::<Vec<_>>();
+            differing.sort_unstable();
+
+            if height == 0 {
+                break differing;
+            }
+
+            height -= 1;
+            offsets = differing
+                .into_iter()
+                .flat_map(|offset| [offset * 2, offset * 2 + 1])
+                .filter(|child| *child << height < shared_count)
+                .collect();
+        };
+
+        differences.extend(shared_count..self.leaf_count.max(remote_count));
+        Ok(differences)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// A remote tree that records the requests made to it.
+    struct Recorder {
+        tree: MerkleTree,
+        requests: RefCell<Vec<(usize, Vec<usize>)>>,
+    }
+
+    impl RemoteTree for Recorder {
+        type Error = String;
+
+        fn leaf_count(&self) -> Result<usize, String> {
+            Ok(self.tree.leaf_count)
+        }
+
+        fn nodes(&self, height: usize, offsets: &[usize]) -> Result<Vec<Hash>, String> {
+            self.requests.borrow_mut().push((height, offsets.to_vec()));
+            let Ok(nodes) = RemoteTree::nodes(&self.tree, height, offsets);
+            Ok(nodes)
+        }
+    }
+
+    #[test]
+    fn requests_only_differing_subtrees() {
+        let leaves = (0..16u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let tree = MerkleTree::new(&leaves).unwrap();
+        let mut other = MerkleTree::new(&leaves).unwrap();
+        other.update(5, MerkleTree::hash(b"z")).unwrap();
+        other.push(MerkleTree::hash(b"y")).unwrap();
+
+        let remote = Recorder {
+            tree: other,
+            requests: RefCell::new(Vec::new()),
+        };
+
+        assert_eq!(tree.reconcile(&remote).unwrap(), [5, 16]);
+        assert_eq!(
+            remote.requests.into_inner(),
+            [
+                (4, vec![0]),
+                (3, vec![0, 1]),
+                (2, vec![0, 1]),
+                (1, vec![2, 3]),
+                (0, vec![4, 5])
+            ]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Zero-copy archives of trees with rkyv, so a persisted tree (e.g. a
+//! memory-mapped file) can be queried for its root and proofs without
+//! deserializing it first.
+//!
+//! Like the serde representation, an archive holds the flattened node layout,
+//! number of leaves and the `sorted_pairs` and `depth` options, but not the
+//! hash functions.  Whether the last node of an odd level is promoted (see
+//! `padding::PromoteOdd`) is recorded so proofs skip those levels; other
+//! padding strategies are read from the stored padding slots.
+
+use crate::builder::Config;
+use crate::error::{MerkleTreeError, Result};
+use crate::padding::PromoteOdd;
+use crate::{Hash, MerkleTree, Proof, EMPTY_NODE};
+use rkyv::rancor;
+use rkyv::util::AlignedVec;
+use rkyv::{Archive, Deserialize, Serialize};
+
+/// The archived form of a tree.  Use `access()` to read an archive in place.
+#[derive(Debug, Archive, Serialize, Deserialize)]
+pub struct TreeArchive {
+    leaf_count: u64,
+    nodes: Vec<Hash>,
+    sorted_pairs: bool,
+    depth: Option<u64>,
+    promote_odd: bool,
+}
+
+/// Check that bytes hold a well formed archive and read it in place.  The
+/// bytes must be aligned to 16 bytes, as those returned by `to_rkyv()` and
+/// memory maps are.
+///
+/// ```rust
+/// use merkle_tree::{archive, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+/// let tree = MerkleTree::new(&leaves).unwrap();
+/// let bytes = tree.to_rkyv();
+///
+/// let archived = archive::access(&bytes).unwrap();
+/// let proof = archived.proof_at(2).unwrap();
+/// assert_eq!(archived.root(), tree.root());
+/// assert!(MerkleTree::verify_with_root(&archived.root(), &proof, &leaves[2]));
+/// ```
+pub fn access(bytes: &[u8]) -> Result<&ArchivedTreeArchive> {
+    let archived = rkyv::access::<ArchivedTreeArchive, rancor::Error>(bytes)
+        .map_err(|error| MerkleTreeError::InvalidEncoding(error.to_string()))?;
+
+    let leaf_count = archived.leaf_count();
+    let nodes = archived.nodes.len();
+
+    // the layout must be a full binary tree with room for the leaves
+    if leaf_count == 0 || !(nodes + 1).is_power_of_two() || leaf_count > nodes.div_ceil(2) {
+        return Err(MerkleTreeError::InvalidEncoding(format!(
+            "{nodes} nodes can't hold {leaf_count} leaves"
+        )));
+    }
+
+    Ok(archived)
+}
+
+impl ArchivedTreeArchive {
+    /// The root hash of the archived tree.
+    pub fn root(&self) -> Hash {
+        self.nodes[0]
+    }
+
+    /// The number of leaves in the archived tree.
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count.to_native() as usize
+    }
+
+    /// The leaves of the archived tree, excluding any padding.
+    pub fn leaves(&self) -> &[Hash] {
+        let start = self.nodes.len() / 2;
+        &self.nodes[start..start + self.leaf_count()]
+    }
+
+    /// Generate a Merkle Proof for the leaf at a given offset, borrowing the
+    /// sibling hashes from the archive.
+    pub fn proof_at(&self, offset: usize) -> Result<Proof<'_>> {
+        let leaf_count = self.leaf_count();
+
+        if offset >= leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, leaf_count));
+        }
+
+        // neither padding of an archive uses empty subtrees
+        let nodes = self.nodes.as_slice();
+        let config = self.config();
+        let siblings =
+            MerkleTree::path_siblings(nodes, leaf_count, &config, &[], nodes.len() / 2 + offset);
+
+        Ok(siblings
+            .map(|(direction, sibling)| (direction, &nodes[sibling]))
+            .collect())
+    }
+
+    /// Copy the archived tree into a `MerkleTree`, with the default hash
+    /// functions.
+    pub fn to_tree(&self) -> MerkleTree {
+        MerkleTree::from_parts(self.nodes.to_vec(), self.leaf_count(), Default::default())
+            .with_config(self.config())
+    }
+
+    /// The options of the archived tree, with the default hash functions.
+    fn config(&self) -> Config {
+        let mut config = Config {
+            sorted_pairs: self.sorted_pairs,
+            depth: self.depth.as_ref().map(|depth| depth.to_native() as usize),
+            ..Config::default()
+        };
+
+        if self.promote_odd {
+            config.padding = &PromoteOdd;
+        }
+
+        config
+    }
+}
+
+impl MerkleTree {
+    /// Archive the tree with rkyv.  Read the archive in place with
+    /// `archive::access()`.
+    pub fn to_rkyv(&self) -> AlignedVec {
+        let archive = TreeArchive {
+            leaf_count: self.leaf_count as u64,
+            nodes: self.nodes.clone(),
+            sorted_pairs: self.config.sorted_pairs,
+            depth: self.config.depth.map(|depth| depth as u64),
+            promote_odd: self
+                .config
+                .padding
+                .sibling(&EMPTY_NODE, &EMPTY_NODE)
+                .is_none(),
+        };
+
+        rkyv::to_bytes::<rancor::Error>(&archive).expect("trees can always be archived")
+    }
+
+    /// Load a tree archived with `to_rkyv()`, copying its nodes.
+    pub fn from_rkyv(bytes: &[u8]) -> Result<MerkleTree> {
+        Ok(access(bytes)?.to_tree())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves() -> Vec<Hash> {
+        (0..7u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn proves_leaves_in_place() {
+        let leaves = leaves();
+
+        for builder in [
+            MerkleTree::builder(),
+            MerkleTree::builder().padding(&PromoteOdd),
+        ] {
+            let tree = builder.build(&leaves).unwrap();
+            let bytes = tree.to_rkyv();
+            let archived = access(&bytes).unwrap();
+
+            assert_eq!(archived.leaves(), leaves);
+            for offset in 0..leaves.len() {
+                assert_eq!(
+                    archived.proof_at(offset).unwrap(),
+                    tree.proof_at(offset).unwrap()
+                );
+            }
+            assert!(archived.proof_at(leaves.len()).is_err());
+
+            let loaded = MerkleTree::from_rkyv(&bytes).unwrap();
+            assert_eq!(loaded, tree);
+            assert!(loaded.integrity_check().is_ok());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_archives() {
+        let bytes = MerkleTree::new(&leaves()).unwrap().to_rkyv();
+        assert!(access(&bytes[..bytes.len() - 1]).is_err());
+
+        let archive = TreeArchive {
+            leaf_count: 9,
+            nodes: vec![EMPTY_NODE; 15],
+            sorted_pairs: false,
+            depth: None,
+            promote_odd: false,
+        };
+        let bytes = rkyv::to_bytes::<rancor::Error>(&archive).unwrap();
+        assert!(access(&bytes).is_err());
+    }
+}
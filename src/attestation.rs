@@ -0,0 +1,206 @@
+//! Roots signed with ed25519, behind the `ed25519` feature, so a service can
+//! publish authenticated roots and clients can bind proofs to a specific
+//! signed checkpoint of a tree.
+//!
+//! The signature covers the magic `MRKLATST`, the number of leaves and the
+//! timestamp (milliseconds since the Unix epoch) as little-endian `u64`s,
+//! then the root, so verifiers in other languages can rebuild the message
+//! from the fields.  An attestation is encoded as the number of leaves, the
+//! timestamp, the root and the signature, in 112 bytes.
+
+use crate::builder::MerkleTreeBuilder;
+use crate::error::{MerkleTreeError, Result};
+use crate::{Direction, Hash, MerkleTree};
+use ed25519_dalek::{Signature, Signer};
+use std::borrow::Borrow;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+/// Identifies the signed message of an attestation.
+const MAGIC: &[u8; 8] = b"MRKLATST";
+
+/// The length of an encoded attestation.
+pub const ATTESTATION_LEN: usize = 112;
+
+/// A root signed by the holder of a key, along with the number of leaves the
+/// tree had and when it was signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attestation {
+    pub root: Hash,
+    pub tree_size: u64,
+    pub timestamp: u64,
+    pub signature: [u8; 64],
+}
+
+impl Attestation {
+    /// Sign a root of a tree of `tree_size` leaves, at `timestamp`
+    /// milliseconds since the Unix epoch.
+    pub fn sign(root: Hash, tree_size: u64, timestamp: u64, key: &SigningKey) -> Attestation {
+        let message = message(&root, tree_size, timestamp);
+
+        Attestation {
+            root,
+            tree_size,
+            timestamp,
+            signature: key.sign(&message).to_bytes(),
+        }
+    }
+
+    /// The message the signature covers, see the `attestation` module.
+    pub fn message(&self) -> Vec<u8> {
+        message(&self.root, self.tree_size, self.timestamp)
+    }
+
+    /// Whether the attestation was signed by the holder of `key`.
+    pub fn verify(&self, key: &VerifyingKey) -> bool {
+        let signature = Signature::from_bytes(&self.signature);
+        key.verify_strict(&self.message(), &signature).is_ok()
+    }
+
+    /// Whether the attestation was signed by the holder of `key`, and the
+    /// proof of the leaf leads to its root, for a tree built with the default
+    /// options.
+    pub fn verify_proof<H: Borrow<Hash>>(
+        &self,
+        key: &VerifyingKey,
+        proof: &[(Direction, H)],
+        leaf: &Hash,
+    ) -> bool {
+        MerkleTree::builder().verify_attested(self, key, proof, leaf)
+    }
+
+    /// Encode the attestation, see the `attestation` module.
+    pub fn to_bytes(&self) -> [u8; ATTESTATION_LEN] {
+        let mut bytes = [0; ATTESTATION_LEN];
+        bytes[0..8].copy_from_slice(&self.tree_size.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[16..48].copy_from_slice(&self.root);
+        bytes[48..].copy_from_slice(&self.signature);
+        bytes
+    }
+
+    /// Decode an attestation encoded with `to_bytes()`.  The signature isn't
+    /// checked, see `verify()`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Attestation> {
+        let bytes: &[u8; ATTESTATION_LEN] = bytes.try_into().map_err(|_| {
+            MerkleTreeError::InvalidEncoding(format!(
+                "attestation of {} bytes, expected {ATTESTATION_LEN}",
+                bytes.len()
+            ))
+        })?;
+
+        Ok(Attestation {
+            tree_size: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            timestamp: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            root: bytes[16..48].try_into().unwrap(),
+            signature: bytes[48..].try_into().unwrap(),
+        })
+    }
+}
+
+fn message(root: &Hash, tree_size: u64, timestamp: u64) -> Vec<u8> {
+    [
+        &MAGIC[..],
+        &tree_size.to_le_bytes(),
+        &timestamp.to_le_bytes(),
+        root,
+    ]
+    .concat()
+}
+
+impl MerkleTree {
+    /// Sign the current root and number of leaves, timestamped now.
+    ///
+    /// ```rust
+    /// use merkle_tree::attestation::SigningKey;
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let key = SigningKey::from_bytes(&[7; 32]);
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// let attestation = tree.attest(&key);
+    /// let proof = tree.proof(&leaves[1]).unwrap();
+    /// assert!(attestation.verify_proof(&key.verifying_key(), &proof, &leaves[1]));
+    /// ```
+    pub fn attest(&self, key: &SigningKey) -> Attestation {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+
+        Attestation::sign(self.root(), self.leaf_count as u64, timestamp, key)
+    }
+}
+
+impl MerkleTreeBuilder {
+    /// Whether an attestation was signed by the holder of `key`, and the
+    /// proof of the leaf leads to its root, for a tree built with these
+    /// options.
+    pub fn verify_attested<H: Borrow<Hash>>(
+        &self,
+        attestation: &Attestation,
+        key: &VerifyingKey,
+        proof: &[(Direction, H)],
+        leaf: &Hash,
+    ) -> bool {
+        attestation.verify(key) && self.verify(&attestation.root, proof, leaf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binds_proofs_to_signed_roots() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        let leaves = (0..5u8).map(|i| MerkleTree::hash(&[i])).collect::<Vec<_>>();
+        let mut tree = MerkleTree::new(&leaves).unwrap();
+
+        let attestation = Attestation::sign(tree.root(), 5, 1_700_000_000_000, &key);
+        let proof = tree.proof(&leaves[3]).unwrap();
+
+        assert!(attestation.verify(&key.verifying_key()));
+        assert!(!attestation.verify(&other));
+        assert!(attestation.verify_proof(&key.verifying_key(), &proof, &leaves[3]));
+        assert!(!attestation.verify_proof(&key.verifying_key(), &proof, &leaves[2]));
+
+        // every field is covered by the signature
+        for tampered in [
+            Attestation {
+                tree_size: 6,
+                ..attestation
+            },
+            Attestation {
+                timestamp: 0,
+                ..attestation
+            },
+            Attestation {
+                root: leaves[0],
+                ..attestation
+            },
+        ] {
+            assert!(!tampered.verify(&key.verifying_key()));
+        }
+
+        // a proof of the tree after an update doesn't match the signed root
+        tree.update(0, MerkleTree::hash(b"x")).unwrap();
+        let proof = tree.proof(&leaves[3]).unwrap();
+        assert!(!attestation.verify_proof(&key.verifying_key(), &proof, &leaves[3]));
+        assert!(tree
+            .attest(&key)
+            .verify_proof(&key.verifying_key(), &proof, &leaves[3]));
+    }
+
+    #[test]
+    fn round_trips_bytes() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let attestation = Attestation::sign(MerkleTree::hash(b"root"), 9, 42, &key);
+        let bytes = attestation.to_bytes();
+
+        assert_eq!(Attestation::from_bytes(&bytes).unwrap(), attestation);
+        assert!(Attestation::from_bytes(&bytes[1..]).is_err());
+    }
+}
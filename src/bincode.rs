@@ -0,0 +1,81 @@
+//! Fast binary persistence of trees with bincode, e.g. for service
+//! checkpoints.
+//!
+//! The encoding is a little-endian `u32` format version followed by the
+//! bincode encoding of the tree's serde representation, see the
+//! `serialization` module.  Decoding an unknown version fails with
+//! `UnsupportedFormatVersion` rather than misreading the tree.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::MerkleTree;
+
+/// The version of the encoding written by `to_bincode()`.
+pub const FORMAT_VERSION: u32 = 1;
+
+impl MerkleTree {
+    /// Encode the tree with bincode, prefixed with the format version.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let loaded = MerkleTree::from_bincode(&tree.to_bincode()).unwrap();
+    ///
+    /// assert_eq!(loaded, tree);
+    /// ```
+    pub fn to_bincode(&self) -> Vec<u8> {
+        let mut bytes = FORMAT_VERSION.to_le_bytes().to_vec();
+        ::bincode::serialize_into(&mut bytes, self).expect("trees can always be encoded");
+        bytes
+    }
+
+    /// Decode a tree encoded with `to_bincode()`.  The layout is checked to be
+    /// well formed, but nodes aren't checked against their children, see
+    /// `integrity_check()`.
+    pub fn from_bincode(bytes: &[u8]) -> Result<MerkleTree> {
+        let Some((version, encoded)) = bytes.split_first_chunk::<4>() else {
+            return Err(MerkleTreeError::InvalidEncoding(
+                "missing format version".to_owned(),
+            ));
+        };
+
+        match u32::from_le_bytes(*version) {
+            FORMAT_VERSION => ::bincode::deserialize(encoded)
+                .map_err(|error| MerkleTreeError::InvalidEncoding(error.to_string())),
+            version => Err(MerkleTreeError::UnsupportedFormatVersion(version)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Hash;
+
+    #[test]
+    fn round_trips_trees() {
+        let leaves = (0..5u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let tree = MerkleTree::builder()
+            .sorted_pairs(true)
+            .build(&leaves)
+            .unwrap();
+        let bytes = tree.to_bincode();
+
+        assert_eq!(bytes[0..4], [1, 0, 0, 0]);
+        let loaded = MerkleTree::from_bincode(&bytes).unwrap();
+        assert_eq!(loaded, tree);
+        assert!(loaded.integrity_check().is_ok());
+
+        let mut future = bytes.clone();
+        future[0] = 2;
+        assert!(matches!(
+            MerkleTree::from_bincode(&future),
+            Err(MerkleTreeError::UnsupportedFormatVersion(2))
+        ));
+        assert!(MerkleTree::from_bincode(&bytes[0..3]).is_err());
+        assert!(MerkleTree::from_bincode(&bytes[0..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,738 @@
+//! Configure how a tree is constructed and hashed.
+//!
+//! Options that change the shape or hashes of a tree are collected by
+//! `MerkleTreeBuilder` and kept with the tree, so that every later mutation
+//! and proof is consistent with how the tree was built.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::padding::{DuplicateLast, PaddingStrategy};
+use crate::progress::{CancellationToken, Progress, ProgressListener};
+use crate::trace;
+use crate::{Direction, DuplicatePolicy, Hash, MerkleTree, EMPTY_NODE};
+use sha3::{Digest, Keccak256};
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The hash functions of a tree: `leaf` hashes raw data into a leaf, and
+/// `node` hashes a pair of children into their parent.  `id` is recorded
+/// when a tree is persisted, so that it's only loaded with the same hash
+/// functions.  Ids below 256 are reserved for the hashers of this crate.
+#[derive(Debug, Clone, Copy)]
+pub struct Hasher {
+    pub id: u32,
+    pub leaf: fn(&[u8]) -> Hash,
+    pub node: fn(&Hash, &Hash) -> Hash,
+}
+
+impl Hasher {
+    /// SHA3-256 of the data, and of the concatenation of both children.
+    pub const SHA3_256: Hasher = Hasher {
+        id: 1,
+        leaf: MerkleTree::hash,
+        node: MerkleTree::concat,
+    };
+
+    /// Keccak-256 (as used by Ethereum) of the data, and of the
+    /// concatenation of both children.
+    pub const KECCAK_256: Hasher = Hasher {
+        id: 2,
+        leaf: keccak_256,
+        node: keccak_256_node,
+    };
+}
+
+fn keccak_256(data: &[u8]) -> Hash {
+    Keccak256::digest(data).into()
+}
+
+fn keccak_256_node(left: &Hash, right: &Hash) -> Hash {
+    Keccak256::new()
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+impl Default for Hasher {
+    fn default() -> Hasher {
+        Hasher::SHA3_256
+    }
+}
+
+/// The deepest fixed depth a tree may be built with.  A fixed depth stores
+/// every node of the full depth, so a tree of this depth holds `2^25 - 1`
+/// nodes (1 GiB) even for a single leaf.
+pub const MAX_DEPTH: usize = 24;
+
+/// The options that a tree keeps after construction.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Config {
+    pub(crate) hasher: Hasher,
+    pub(crate) sorted_pairs: bool,
+    pub(crate) depth: Option<usize>,
+    pub(crate) padding: &'static dyn PaddingStrategy,
+    #[cfg(feature = "parallel")]
+    pub(crate) parallel_threshold: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            hasher: Hasher::default(),
+            sorted_pairs: false,
+            depth: None,
+            padding: &DuplicateLast,
+            #[cfg(feature = "parallel")]
+            parallel_threshold: crate::parallel::DEFAULT_PARALLEL_THRESHOLD,
+        }
+    }
+}
+
+impl Config {
+    /// Hash a pair of children into their parent.
+    pub(crate) fn combine(&self, left: &Hash, right: &Hash) -> Hash {
+        if self.sorted_pairs && right < left {
+            (self.hasher.node)(right, left)
+        } else {
+            (self.hasher.node)(left, right)
+        }
+    }
+
+    /// The hash of an empty subtree of each height up to `height`, if the
+    /// padding uses them.
+    pub(crate) fn empty_subtrees(&self, height: usize) -> Vec<Hash> {
+        if !self.padding.uses_empty_subtrees() {
+            return Vec::new();
+        }
+
+        let mut empty_subtrees = vec![EMPTY_NODE];
+
+        while empty_subtrees.len() <= height {
+            let below = empty_subtrees[empty_subtrees.len() - 1];
+            empty_subtrees.push(self.combine(&below, &below));
+        }
+
+        empty_subtrees
+    }
+
+    /// The number of leaves to size the flattened layout for, which is a power
+    /// of two (minimum of 2), or exactly the leaves of a fixed depth, provided
+    /// the padding strategy accepts padding that many leaves.
+    pub(crate) fn padded_count(&self, leaf_count: usize) -> Result<usize> {
+        let padded_count = leaf_count
+            .checked_next_power_of_two()
+            .ok_or(MerkleTreeError::CapacityOverflow(leaf_count))?
+            .max(2);
+
+        let padded_count = match self.depth {
+            None => padded_count,
+            // bounding the depth also keeps the shift from overflowing
+            Some(depth) if (1..=MAX_DEPTH).contains(&depth) && padded_count <= 1 << depth => {
+                1 << depth
+            }
+            Some(depth) => return Err(MerkleTreeError::DepthExceeded(depth, leaf_count)),
+        };
+
+        self.padding.check(leaf_count, padded_count)?;
+
+        Ok(padded_count)
+    }
+
+    /// Calculate the root hash implied by a Merkle Proof for a given leaf.
+    pub(crate) fn root_from_proof<H: Borrow<Hash>>(
+        &self,
+        proof: &[(Direction, H)],
+        leaf: &Hash,
+    ) -> Hash {
+        proof
+            .iter()
+            .fold(*leaf, |current_hash, (direction, hash)| match direction {
+                Direction::Left => self.combine(hash.borrow(), &current_hash),
+                Direction::Right => self.combine(&current_hash, hash.borrow()),
+            })
+    }
+}
+
+/// Collects the options for constructing a tree.
+///
+/// ```rust
+/// use merkle_tree::{builder::MerkleTreeBuilder, DuplicatePolicy, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"b"), MerkleTree::hash(b"a")];
+/// let builder = MerkleTreeBuilder::new()
+///     .sorted_pairs(true)
+///     .sort_leaves(true)
+///     .duplicate_policy(DuplicatePolicy::Reject)
+///     .capacity(1024);
+/// let tree = builder.build(&leaves).unwrap();
+///
+/// let proof = tree.proof(&leaves[0]).unwrap();
+/// assert!(builder.verify(&tree.root(), &proof, &leaves[0]));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MerkleTreeBuilder {
+    pub(crate) config: Config,
+    pub(crate) duplicate_policy: DuplicatePolicy,
+    sort_leaves: bool,
+    dedup: bool,
+    capacity: usize,
+    pub(crate) checkpoint_interval: usize,
+    progress: Option<ProgressListener>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl MerkleTreeBuilder {
+    /// Create a builder with the default options, which build the same tree
+    /// as `MerkleTree::new()`.
+    pub fn new() -> MerkleTreeBuilder {
+        MerkleTreeBuilder::default()
+    }
+
+    /// Hash nodes with the given hash functions rather than SHA3-256.
+    pub fn hasher(mut self, hasher: Hasher) -> MerkleTreeBuilder {
+        self.config.hasher = hasher;
+        self
+    }
+
+    /// Order each pair of children by value before hashing them, so that a
+    /// proof can be verified without knowing which side each sibling is on.
+    pub fn sorted_pairs(mut self, sorted_pairs: bool) -> MerkleTreeBuilder {
+        self.config.sorted_pairs = sorted_pairs;
+        self
+    }
+
+    /// Sort the leaves by value before building, so that the same set of
+    /// leaves always builds the same tree.  The built tree records where each
+    /// supplied leaf ended up, see `MerkleTree::sorted_positions()`.
+    pub fn sort_leaves(mut self, sort_leaves: bool) -> MerkleTreeBuilder {
+        self.sort_leaves = sort_leaves;
+        self
+    }
+
+    /// Remove leaves that are duplicates of an earlier leaf before building,
+    /// so that each value is committed once.  The built tree records which
+    /// leaves were removed, see `MerkleTree::removed_duplicates()`.
+    pub fn dedup(mut self, dedup: bool) -> MerkleTreeBuilder {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Set the policy for duplicate leaves.
+    pub fn duplicate_policy(mut self, duplicate_policy: DuplicatePolicy) -> MerkleTreeBuilder {
+        self.duplicate_policy = duplicate_policy;
+        self
+    }
+
+    /// Choose how the last node of a level with an odd number of nodes is
+    /// paired, see the `padding` module.  The default duplicates it.
+    pub fn padding(mut self, padding: &'static dyn PaddingStrategy) -> MerkleTreeBuilder {
+        self.config.padding = padding;
+        self
+    }
+
+    /// Pad the tree to a fixed depth (of at least 1) regardless of the number
+    /// of leaves, so every proof has `depth` siblings.  Building or growing
+    /// past `2^depth` leaves fails with `DepthExceeded`.  The flattened
+    /// layout stores every node of the full depth, so this allocates
+    /// `2^(depth + 1) - 1` nodes, and building with a depth over `MAX_DEPTH`
+    /// fails with `DepthExceeded` rather than allocating them.
+    pub fn depth(mut self, depth: usize) -> MerkleTreeBuilder {
+        self.config.depth = Some(depth);
+        self
+    }
+
+    /// Allocate node storage for at least this many leaves up front, so that
+    /// pushing up to that many leaves doesn't reallocate.  Building fails
+    /// with `CapacityOverflow` if the storage can't be allocated.
+    pub fn capacity(mut self, capacity: usize) -> MerkleTreeBuilder {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Checkpoint tree files built with `build_file()` every time `interval`
+    /// more leaves have been pushed, so an interrupted build can be resumed
+    /// with `resume_file()`.
+    pub fn checkpoint_interval(mut self, interval: usize) -> MerkleTreeBuilder {
+        self.checkpoint_interval = interval;
+        self
+    }
+
+    /// Call `callback` with the progress of a build every time `interval`
+    /// more leaves (rounded up to a power of two) have been hashed, e.g. to
+    /// report on a build of hundreds of millions of leaves.
+    pub fn on_progress<F>(mut self, interval: usize, callback: F) -> MerkleTreeBuilder
+    where
+        F: Fn(Progress) + Send + Sync + 'static,
+    {
+        self.progress = Some(ProgressListener {
+            interval,
+            callback: Arc::new(callback),
+        });
+        self
+    }
+
+    /// Abort a build with `Cancelled` once the token is cancelled.  The token
+    /// is checked between chunks of leaves, see `on_progress()`.
+    pub fn cancellation(mut self, token: CancellationToken) -> MerkleTreeBuilder {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Build a tree from the leaves with the collected options.  The node
+    /// array is allocated once, at its final size, and every level is hashed
+    /// in place, so construction copies the leaves once and nothing else.
+    pub fn build(&self, supplied: &[Hash]) -> Result<MerkleTree> {
+        let _timed = trace::operation("build", supplied.len());
+
+        if supplied.is_empty() {
+            return Err(MerkleTreeError::Empty);
+        }
+
+        let mut unique = Vec::new();
+        let mut removed_duplicates = Vec::new();
+
+        let leaves = if self.dedup {
+            let mut seen = HashSet::new();
+
+            for (offset, leaf) in supplied.iter().enumerate() {
+                if seen.insert(leaf) {
+                    unique.push(*leaf);
+                } else {
+                    removed_duplicates.push(offset);
+                }
+            }
+
+            &unique[..]
+        } else {
+            supplied
+        };
+
+        if self.duplicate_policy == DuplicatePolicy::Reject {
+            if let Some(duplicate) = MerkleTree::find_duplicate(leaves.iter()) {
+                return Err(MerkleTreeError::DuplicateLeaf(hex::encode(duplicate)));
+            }
+        }
+
+        // The flattened layout requires a full binary tree, so storage is
+        // sized for a power of two (minimum of 2) leaves.
+        let padded_count = self.config.padded_count(leaves.len())?;
+        let level_start = padded_count - 1;
+
+        let mut nodes = Vec::new();
+        MerkleTree::reserve_nodes(&mut nodes, self.capacity.max(padded_count))?;
+        nodes.resize(padded_count * 2 - 1, EMPTY_NODE);
+        nodes[level_start..level_start + leaves.len()].copy_from_slice(leaves);
+
+        let mut tree = MerkleTree::from_parts(nodes, leaves.len(), self.duplicate_policy)
+            .with_config(self.config);
+
+        if self.sort_leaves {
+            // a stable sort keeps duplicates in the order they were supplied
+            let mut order = (0..leaves.len()).collect::<Vec<_>>();
+            order.sort_by_key(|offset| leaves[*offset]);
+
+            let mut positions = vec![0; leaves.len()];
+            for (position, offset) in order.iter().enumerate() {
+                tree.nodes[level_start + position] = leaves[*offset];
+                positions[*offset] = position;
+            }
+
+            // removed duplicates map to the position of the leaf they repeat
+            if self.dedup {
+                let sorted = tree.leaves();
+                positions = supplied
+                    .iter()
+                    .map(|leaf| sorted.binary_search(leaf).expect("sorted leaf"))
+                    .collect();
+            }
+
+            tree.sorted_positions = Some(positions);
+        }
+
+        if self.dedup {
+            tree.removed_duplicates = Some(removed_duplicates);
+        }
+
+        if self.progress.is_some() || self.cancellation.is_some() {
+            tree.recompute_in_chunks(self.progress.as_ref(), self.cancellation.as_ref())?;
+        } else {
+            tree.recompute(0..tree.leaf_count);
+        }
+
+        tree.root_history = vec![tree.root()];
+
+        Ok(tree)
+    }
+
+    /// Build a tree from raw items, hashing each item into a leaf with the
+    /// configured leaf hash function.
+    pub fn build_from_data<I>(&self, items: I) -> Result<MerkleTree>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let leaves = items
+            .into_iter()
+            .map(|item| (self.config.hasher.leaf)(item.as_ref()))
+            .collect::<Vec<Hash>>();
+
+        self.build(&leaves)
+    }
+
+    /// Load a tree from the bytes of `MerkleTree::to_bytes()`, hashing with
+    /// these options.  The tree must have been hashed with a hasher of the
+    /// same id, and keeps the depth and `sorted_pairs` recorded with it, see
+    /// the `bytes` module.
+    pub fn load_bytes(&self, bytes: &[u8]) -> Result<MerkleTree> {
+        MerkleTree::decode_bytes(bytes, self.config, self.duplicate_policy)
+    }
+
+    /// Verify a Merkle Proof for a given leaf against the root of a tree
+    /// built with these options, without needing the tree.
+    pub fn verify<H: Borrow<Hash>>(
+        &self,
+        root: &Hash,
+        proof: &[(Direction, H)],
+        leaf: &Hash,
+    ) -> bool {
+        self.config.root_from_proof(proof, leaf) == *root
+    }
+}
+
+impl MerkleTree {
+    /// The offset each leaf was sorted to when the tree was built with
+    /// `sort_leaves`, indexed by the leaf's position in the supplied leaves.
+    /// This describes construction only, and isn't updated by mutations.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::builder().sort_leaves(true).build(&leaves).unwrap();
+    /// let positions = tree.sorted_positions().unwrap();
+    ///
+    /// assert!((0..3).all(|offset| tree[positions[offset]] == leaves[offset]));
+    /// assert!(MerkleTree::new(&leaves).unwrap().sorted_positions().is_none());
+    /// ```
+    pub fn sorted_positions(&self) -> Option<&[usize]> {
+        self.sorted_positions.as_deref()
+    }
+
+    /// Set the options of a tree and precompute any empty subtrees its
+    /// padding needs.
+    pub(crate) fn with_config(mut self, config: Config) -> MerkleTree {
+        self.config = config;
+        self.empty_subtrees = config.empty_subtrees(self.num_levels());
+        self
+    }
+
+    /// The offsets within the supplied leaves of the leaves that were removed
+    /// as duplicates of an earlier leaf when the tree was built with `dedup`.
+    /// This describes construction only, and isn't updated by mutations.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"a")];
+    /// let tree = MerkleTree::builder().dedup(true).build(&leaves).unwrap();
+    ///
+    /// assert_eq!(tree.leaves(), &leaves[0..2]);
+    /// assert_eq!(tree.removed_duplicates(), Some(&[2][..]));
+    /// ```
+    pub fn removed_duplicates(&self) -> Option<&[usize]> {
+        self.removed_duplicates.as_deref()
+    }
+
+    /// Start configuring a new tree.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let tree = MerkleTree::builder().sorted_pairs(true).build(&leaves).unwrap();
+    /// ```
+    pub fn builder() -> MerkleTreeBuilder {
+        MerkleTreeBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::padding::ZeroHash;
+    use sha3::{Digest, Keccak256};
+
+    fn leaves() -> Vec<Hash> {
+        (0..5u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    fn keccak_concat(left: &Hash, right: &Hash) -> Hash {
+        Keccak256::new()
+            .chain_update(left)
+            .chain_update(right)
+            .finalize()
+            .into()
+    }
+
+    #[test]
+    fn builds_the_same_tree_as_new_by_default() {
+        let leaves = leaves();
+        let tree = MerkleTree::builder().build(&leaves).unwrap();
+
+        assert_eq!(tree.nodes, MerkleTree::new(&leaves).unwrap().nodes);
+        assert!(MerkleTree::builder().build(&[]).is_err());
+    }
+
+    #[test]
+    fn hashes_nodes_with_the_configured_hasher() {
+        let leaves = leaves();
+        let builder = MerkleTree::builder().hasher(Hasher {
+            id: 256,
+            leaf: |data| Keccak256::digest(data).into(),
+            node: keccak_concat,
+        });
+        let mut tree = builder.build(&leaves[0..2]).unwrap();
+        assert_eq!(tree.root(), keccak_concat(&leaves[0], &leaves[1]));
+
+        for leaf in &leaves[2..] {
+            tree.push(*leaf).unwrap();
+        }
+        tree.update(1, leaves[4]).unwrap();
+        assert!(tree.integrity_check().is_ok());
+
+        let mut expected = leaves.clone();
+        expected[1] = leaves[4];
+        assert_eq!(tree.nodes, builder.build(&expected).unwrap().nodes);
+
+        let proof = tree.proof_at(3).unwrap();
+        assert!(tree.verify(&proof, &leaves[3]));
+        assert!(builder.verify(&tree.root(), &proof, &leaves[3]));
+        assert!(!MerkleTree::verify_with_root(
+            &tree.root(),
+            &proof,
+            &leaves[3]
+        ));
+    }
+
+    #[test]
+    fn hashes_data_with_the_configured_hasher() {
+        let items = ["a", "b", "c"];
+        let leaves = items.map(|item| Keccak256::digest(item).into());
+        let builder = MerkleTree::builder().hasher(Hasher {
+            id: 256,
+            leaf: |data| Keccak256::digest(data).into(),
+            node: keccak_concat,
+        });
+
+        assert_eq!(
+            builder.build_from_data(items).unwrap().nodes,
+            builder.build(&leaves).unwrap().nodes
+        );
+        assert!(builder.build_from_data(Vec::<&[u8]>::new()).is_err());
+    }
+
+    #[test]
+    fn sorts_pairs_before_hashing() {
+        let leaves = leaves();
+        let builder = MerkleTree::builder().sorted_pairs(true);
+        let tree = builder.build(&leaves).unwrap();
+
+        for (offset, leaf) in leaves.iter().enumerate() {
+            // the directions of a proof don't matter when pairs are sorted
+            let proof = tree
+                .proof_at(offset)
+                .unwrap()
+                .into_iter()
+                .map(|(_, hash)| (Direction::Right, hash))
+                .collect::<Vec<_>>();
+            assert!(builder.verify(&tree.root(), &proof, leaf));
+        }
+
+        let (low, high) = (leaves[0].min(leaves[1]), leaves[0].max(leaves[1]));
+        let pair = builder.build(&[high, low]).unwrap();
+        assert_eq!(pair.root(), MerkleTree::concat(&low, &high));
+    }
+
+    #[test]
+    fn sorts_leaves_before_building() {
+        let leaves = leaves();
+        let tree = MerkleTree::builder()
+            .sort_leaves(true)
+            .build(&leaves)
+            .unwrap();
+        let mut sorted = leaves.clone();
+        sorted.sort_unstable();
+
+        assert_eq!(tree.nodes, MerkleTree::new(&sorted).unwrap().nodes);
+
+        let positions = tree.sorted_positions().unwrap();
+        for (offset, leaf) in leaves.iter().enumerate() {
+            assert_eq!(tree[positions[offset]], *leaf);
+        }
+
+        // duplicates keep the order they were supplied in
+        let duplicates = [sorted[1], sorted[0], sorted[1]];
+        let tree = MerkleTree::builder()
+            .sort_leaves(true)
+            .build(&duplicates)
+            .unwrap();
+        assert_eq!(tree.sorted_positions().unwrap(), [1, 0, 2]);
+    }
+
+    #[test]
+    fn removes_duplicates_before_building() {
+        let leaves = leaves();
+        let supplied = [
+            leaves[3], leaves[1], leaves[3], leaves[0], leaves[1], leaves[3],
+        ];
+        let builder = MerkleTree::builder()
+            .dedup(true)
+            .duplicate_policy(DuplicatePolicy::Reject);
+
+        let tree = builder.build(&supplied).unwrap();
+        assert_eq!(
+            tree,
+            MerkleTree::new(&[leaves[3], leaves[1], leaves[0]]).unwrap()
+        );
+        assert_eq!(tree.removed_duplicates().unwrap(), [2, 4, 5]);
+        assert!(tree.sorted_positions().is_none());
+
+        let tree = builder.sort_leaves(true).build(&supplied).unwrap();
+        let positions = tree.sorted_positions().unwrap();
+        assert_eq!(tree.leaf_count(), 3);
+        assert!((0..supplied.len()).all(|offset| tree[positions[offset]] == supplied[offset]));
+
+        let tree = MerkleTree::builder().dedup(true).build(&leaves).unwrap();
+        assert!(tree.removed_duplicates().unwrap().is_empty());
+    }
+
+    #[test]
+    fn pads_with_empty_subtrees() {
+        let leaves = leaves();
+        let builder = MerkleTree::builder().padding(&ZeroHash);
+        let mut tree = builder.build(&leaves[0..3]).unwrap();
+
+        let empty = [0; 32];
+        let expected = MerkleTree::concat(
+            &MerkleTree::concat(&leaves[0], &leaves[1]),
+            &MerkleTree::concat(&leaves[2], &empty),
+        );
+        assert_eq!(tree.root(), expected);
+        assert!(tree.integrity_check().is_ok());
+
+        // the padding isn't provable as a copy of the last leaf
+        let mut proof = tree.proof_at(2).unwrap();
+        assert!(tree.verify(&proof, &leaves[2]));
+        proof[0] = (Direction::Left, &leaves[2]);
+        assert!(!tree.verify(&proof, &leaves[2]));
+
+        for leaf in &leaves[3..] {
+            tree.push(*leaf).unwrap();
+        }
+        tree.update(4, leaves[0]).unwrap();
+        tree.remove(1).unwrap();
+        assert!(tree.integrity_check().is_ok());
+
+        let expected = [leaves[0], leaves[2], leaves[3], leaves[0]];
+        assert_eq!(tree.nodes, builder.build(&expected).unwrap().nodes);
+
+        let (left, right) = builder.build(&leaves).unwrap().split_at(3).unwrap();
+        assert_eq!(left.nodes, builder.build(&leaves[0..3]).unwrap().nodes);
+        assert_eq!(right.nodes, builder.build(&leaves[3..]).unwrap().nodes);
+
+        let fixed = builder.depth(3).build(&leaves[0..1]).unwrap();
+        let mut root = leaves[0];
+        for empty_subtree in &fixed.empty_subtrees()[0..3] {
+            root = MerkleTree::concat(&root, empty_subtree);
+        }
+        assert_eq!(fixed.root(), root);
+    }
+
+    #[test]
+    fn pads_to_a_fixed_depth() {
+        let leaves = leaves();
+        let builder = MerkleTree::builder().depth(4);
+        let mut tree = builder.build(&leaves[0..3]).unwrap();
+
+        // a fixed depth tree is a natural tree with extra levels above the root
+        let mut expected = MerkleTree::new(&leaves[0..3]).unwrap().root();
+        for _ in 2..4 {
+            expected = MerkleTree::concat(&expected, &expected);
+        }
+        assert_eq!(tree.root(), expected);
+        assert_eq!(tree.num_levels(), 4);
+
+        tree.extend(&leaves[3..]).unwrap();
+        tree.truncate(1).unwrap();
+        tree.push(leaves[1]).unwrap();
+        assert_eq!(tree.num_levels(), 4);
+        assert_eq!(tree.nodes, builder.build(&leaves[0..2]).unwrap().nodes);
+
+        for offset in 0..2 {
+            assert_eq!(tree.proof_at(offset).unwrap().len(), 4);
+        }
+
+        let full = (0..16u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<_>>();
+        let mut tree = builder.build(&full).unwrap();
+        assert!(matches!(
+            tree.push(leaves[0]),
+            Err(MerkleTreeError::DepthExceeded(4, 17))
+        ));
+        assert!(tree.insert(0, leaves[0]).is_err());
+        assert!(tree.extend(&leaves).is_err());
+        assert!(tree.rebuild(&[&full[..], &leaves].concat()).is_err());
+        assert_eq!(tree.nodes, builder.build(&full).unwrap().nodes);
+
+        assert!(builder.build(&[&full[..], &full].concat()).is_err());
+
+        for depth in [0, MAX_DEPTH + 1, 64, usize::MAX] {
+            assert!(matches!(
+                MerkleTree::builder().depth(depth).build(&leaves[0..1]),
+                Err(MerkleTreeError::DepthExceeded(_, 1))
+            ));
+        }
+    }
+
+    #[test]
+    fn reserves_storage_for_the_capacity() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::builder()
+            .capacity(5)
+            .build(&leaves[0..1])
+            .unwrap();
+        let capacity = tree.nodes.capacity();
+        assert!(capacity >= 15);
+
+        for leaf in &leaves[1..] {
+            tree.push(*leaf).unwrap();
+        }
+
+        assert_eq!(tree.nodes.capacity(), capacity);
+        assert_eq!(tree.nodes, MerkleTree::new(&leaves).unwrap().nodes);
+    }
+
+    #[test]
+    fn allocates_the_nodes_once() {
+        let leaves = leaves();
+
+        for count in 1..=leaves.len() {
+            let tree = MerkleTree::new(&leaves[0..count]).unwrap();
+            let node_count = count.next_power_of_two().max(2) * 2 - 1;
+
+            assert_eq!(tree.nodes.len(), node_count);
+            assert_eq!(tree.nodes.capacity(), node_count);
+        }
+    }
+
+    #[test]
+    fn honors_the_duplicate_policy() {
+        let leaves = leaves();
+        let builder = MerkleTree::builder().duplicate_policy(DuplicatePolicy::Reject);
+
+        assert!(builder.build(&[leaves[0], leaves[0]]).is_err());
+        assert!(builder.build(&leaves).unwrap().push(leaves[0]).is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! A raw byte layout of trees for persistence and network transfer without
+//! any dependencies.
+//!
+//! Nodes are fixed size, so the flattened layout is already contiguous bytes,
+//! see `MerkleTree::as_bytes()`.  `MerkleTree::to_bytes()` prefixes it with a
+//! header of `HEADER_LEN` bytes, all integers little-endian:
+//!
+//! | Offset | Size | Field                                            |
+//! |--------|------|--------------------------------------------------|
+//! | 0      | 8    | number of leaves                                 |
+//! | 8      | 8    | number of nodes                                  |
+//! | 16     | 4    | fixed depth, or 0 if the tree has none           |
+//! | 20     | 4    | padding strategy id, see `PaddingStrategy::id()` |
+//! | 24     | 4    | hasher id, see `builder::Hasher`                 |
+//! | 28     | 4    | flags: bit 0 is set if pairs are sorted          |
+//!
+//! followed by the 32 bytes of each node in layout order.  The header is the
+//! size of a node, so nodes stay aligned.  The duplicate policy isn't
+//! recorded, and is taken from the builder loading the tree.
+
+use crate::builder::Config;
+use crate::error::{MerkleTreeError, Result};
+use crate::{DuplicatePolicy, Hash, MerkleTree};
+
+/// The length of the header preceding the nodes.
+pub const HEADER_LEN: usize = 32;
+
+/// The flag set if pairs of children are sorted before hashing.
+const SORTED_PAIRS: u32 = 1;
+
+impl MerkleTree {
+    /// The flattened layout as contiguous bytes, without a header.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.nodes.as_flattened()
+    }
+
+    /// The flattened layout prefixed with a header describing it.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let loaded = MerkleTree::from_bytes(&tree.to_bytes()).unwrap();
+    ///
+    /// assert_eq!(loaded, tree);
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.as_bytes().len());
+        bytes.extend_from_slice(&encode_header(
+            self.leaf_count,
+            self.nodes.len(),
+            &self.config,
+        ));
+        bytes.extend_from_slice(self.as_bytes());
+        bytes
+    }
+
+    /// Load a tree from the bytes of `to_bytes()` with the default options,
+    /// see `MerkleTreeBuilder::load_bytes()` to load trees built with other
+    /// options.  The layout is checked to be well formed, but nodes aren't
+    /// checked against their children, see `integrity_check()`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MerkleTree> {
+        MerkleTree::builder().load_bytes(bytes)
+    }
+
+    /// Decode the bytes of `to_bytes()`, hashing with the given options.
+    pub(crate) fn decode_bytes(
+        bytes: &[u8],
+        config: Config,
+        duplicate_policy: DuplicatePolicy,
+    ) -> Result<MerkleTree> {
+        let (nodes, leaf_count, config) = decode_layout(bytes, config)?;
+
+        Ok(
+            MerkleTree::from_parts(nodes.to_vec(), leaf_count, duplicate_policy)
+                .with_config(config),
+        )
+    }
+}
+
+/// The header of the bytes of `to_bytes()`.
+pub(crate) fn encode_header(
+    leaf_count: usize,
+    node_count: usize,
+    config: &Config,
+) -> [u8; HEADER_LEN] {
+    let flags = if config.sorted_pairs { SORTED_PAIRS } else { 0 };
+
+    let mut header = [0; HEADER_LEN];
+    header[0..8].copy_from_slice(&(leaf_count as u64).to_le_bytes());
+    header[8..16].copy_from_slice(&(node_count as u64).to_le_bytes());
+    header[16..20].copy_from_slice(&(config.depth.unwrap_or(0) as u32).to_le_bytes());
+    header[20..24].copy_from_slice(&config.padding.id().to_le_bytes());
+    header[24..28].copy_from_slice(&config.hasher.id.to_le_bytes());
+    header[28..32].copy_from_slice(&flags.to_le_bytes());
+    header
+}
+
+/// Check the header of the bytes of `to_bytes()` against the layout,
+/// returning the nodes borrowed from the bytes, the number of leaves and the
+/// options recorded with them.
+pub(crate) fn decode_layout(bytes: &[u8], config: Config) -> Result<(&[Hash], usize, Config)> {
+    let invalid = |reason: String| MerkleTreeError::InvalidEncoding(reason);
+
+    let Some((header, body)) = bytes.split_first_chunk::<HEADER_LEN>() else {
+        return Err(invalid(format!("missing {HEADER_LEN} byte header")));
+    };
+
+    let u64_at = |offset: usize| u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap());
+    let u32_at = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+    let flags = u32_at(28);
+
+    if flags & !SORTED_PAIRS != 0 {
+        return Err(invalid(format!("unknown flags {flags:#x}")));
+    }
+
+    let (nodes, rest) = body.as_chunks::<32>();
+
+    // the node count is checked against the bytes before it sizes anything
+    if nodes.len() as u64 != u64_at(8) || !rest.is_empty() {
+        return Err(invalid(format!(
+            "expected {} nodes, found {} bytes",
+            u64_at(8),
+            body.len()
+        )));
+    }
+
+    let (leaf_count, _, config) = check_header(
+        u64_at(0),
+        u64_at(8),
+        u32_at(16).into(),
+        u32_at(20),
+        u32_at(24),
+        flags & SORTED_PAIRS != 0,
+        config,
+    )?;
+
+    Ok((nodes, leaf_count, config))
+}
+
+/// Check the fields of a header against the layout a tree would have been
+/// built with, returning the number of leaves and nodes and the options
+/// recorded with them.
+pub(crate) fn check_header(
+    leaf_count: u64,
+    node_count: u64,
+    depth: u64,
+    padding: u32,
+    hasher: u32,
+    sorted_pairs: bool,
+    config: Config,
+) -> Result<(usize, usize, Config)> {
+    let invalid = |reason: String| MerkleTreeError::InvalidEncoding(reason);
+    let to_usize =
+        |field: u64| usize::try_from(field).map_err(|_| invalid(format!("{field} is too large")));
+
+    let leaf_count = to_usize(leaf_count)?;
+    let node_count = to_usize(node_count)?;
+    let depth = to_usize(depth)?;
+
+    if hasher != config.hasher.id {
+        return Err(MerkleTreeError::HasherMismatch(config.hasher.id, hasher));
+    }
+
+    if padding != config.padding.id() {
+        return Err(MerkleTreeError::PaddingMismatch(
+            config.padding.id(),
+            padding,
+        ));
+    }
+
+    let config = Config {
+        sorted_pairs,
+        depth: (depth > 0).then_some(depth),
+        ..config
+    };
+
+    // the layout must be the one the tree would have been built with, and
+    // every field is untrusted, so nothing may overflow
+    let padded_count = config
+        .padded_count(leaf_count)
+        .map_err(|error| invalid(error.to_string()))?;
+
+    if leaf_count == 0 || padded_count.checked_mul(2) != node_count.checked_add(1) {
+        return Err(invalid(format!(
+            "{node_count} nodes can't hold {leaf_count} leaves"
+        )));
+    }
+
+    Ok((leaf_count, node_count, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Hasher;
+    use crate::padding::ZeroHash;
+
+    fn leaves() -> Vec<Hash> {
+        (0..5u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn round_trips_trees() {
+        let leaves = leaves();
+        let builder = MerkleTree::builder().sorted_pairs(true).depth(4);
+        let tree = builder.build(&leaves).unwrap();
+        let bytes = tree.to_bytes();
+
+        assert_eq!(bytes.len(), HEADER_LEN + 31 * 32);
+        assert_eq!(&bytes[HEADER_LEN..], tree.as_bytes());
+        assert_eq!(bytes[0..8], 5u64.to_le_bytes());
+        assert_eq!(bytes[16..20], 4u32.to_le_bytes());
+        assert_eq!(bytes[20..24], 0u32.to_le_bytes());
+        assert_eq!(bytes[28..32], 1u32.to_le_bytes());
+
+        let mut loaded = MerkleTree::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded, tree);
+        assert_eq!(loaded.num_levels(), 4);
+        assert!(loaded.integrity_check().is_ok());
+
+        // the options are kept, so mutations hash the same way
+        let mut tree = tree;
+        tree.update(2, MerkleTree::hash(b"z")).unwrap();
+        loaded.update(2, MerkleTree::hash(b"z")).unwrap();
+        assert_eq!(loaded, tree);
+    }
+
+    #[test]
+    fn rejects_malformed_bytes() {
+        let bytes = MerkleTree::new(&leaves()).unwrap().to_bytes();
+
+        assert!(MerkleTree::from_bytes(&bytes[0..HEADER_LEN - 1]).is_err());
+        assert!(MerkleTree::from_bytes(&bytes[0..bytes.len() - 1]).is_err());
+        assert!(MerkleTree::from_bytes(&[bytes.as_slice(), &[0; 32]].concat()).is_err());
+
+        let mut leaf_count = bytes.clone();
+        leaf_count[0] = 9;
+        assert!(MerkleTree::from_bytes(&leaf_count).is_err());
+
+        let mut flags = bytes.clone();
+        flags[29] = 1;
+        assert!(MerkleTree::from_bytes(&flags).is_err());
+
+        // fields that would overflow the layout arithmetic
+        let header = |offset: usize, field: u64| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 8].copy_from_slice(&field.to_le_bytes());
+            bytes
+        };
+        for malformed in [
+            header(16, 63),
+            header(16, 64),
+            header(16, u32::MAX.into()),
+            header(0, u64::MAX),
+            header(0, 1 << 63),
+            header(8, u64::MAX),
+            header(8, u64::MAX / 32 + 1),
+        ] {
+            assert!(matches!(
+                MerkleTree::from_bytes(&malformed),
+                Err(MerkleTreeError::InvalidEncoding(_))
+            ));
+        }
+
+        let custom = MerkleTree::builder().hasher(Hasher {
+            id: 256,
+            ..Hasher::SHA3_256
+        });
+        assert!(matches!(
+            custom.load_bytes(&bytes),
+            Err(MerkleTreeError::HasherMismatch(256, 1))
+        ));
+
+        let zero_hash = MerkleTree::builder().padding(&ZeroHash);
+        assert!(matches!(
+            zero_hash.load_bytes(&bytes),
+            Err(MerkleTreeError::PaddingMismatch(1, 0))
+        ));
+    }
+}
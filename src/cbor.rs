@@ -0,0 +1,139 @@
+//! Deterministic CBOR encoding of proofs and roots.
+//!
+//! A root is encoded as a 32 byte string.  A proof is encoded as an array of
+//! `[direction, hash]` pairs, where `direction` is `0` for `Left` and `1` for
+//! `Right` and `hash` is a 32 byte string.  Only definite lengths and the
+//! shortest integer encodings are emitted, so the output is deterministic
+//! (RFC 8949, section 4.2).
+
+use crate::error::{MerkleTreeError, Result};
+use crate::{Direction, Hash, OwnedProof};
+use ciborium::value::Value;
+use std::borrow::Borrow;
+
+/// Encode a proof as deterministic CBOR.
+///
+/// ```rust
+/// use merkle_tree::{cbor, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+/// let tree = MerkleTree::new(&leaves).unwrap();
+/// let proof = tree.proof(&leaves[1]).unwrap();
+/// let decoded = cbor::decode_proof(&cbor::encode_proof(&proof)).unwrap();
+/// assert!(tree.verify(&decoded, &leaves[1]));
+/// ```
+pub fn encode_proof<H: Borrow<Hash>>(proof: &[(Direction, H)]) -> Vec<u8> {
+    let steps = proof
+        .iter()
+        .map(|(direction, hash)| {
+            let direction = match direction {
+                Direction::Left => 0,
+                Direction::Right => 1,
+            };
+
+            Value::Array(vec![
+                Value::Integer(direction.into()),
+                Value::Bytes(hash.borrow().to_vec()),
+            ])
+        })
+        .collect();
+
+    to_bytes(&Value::Array(steps))
+}
+
+/// Decode a proof previously encoded with `encode_proof()`.
+pub fn decode_proof(bytes: &[u8]) -> Result<OwnedProof> {
+    let Value::Array(steps) = from_bytes(bytes)? else {
+        return Err(invalid("proof must be an array"));
+    };
+
+    steps
+        .into_iter()
+        .map(|step| match step {
+            Value::Array(pair) => match <[Value; 2]>::try_from(pair) {
+                Ok([direction, Value::Bytes(hash)]) => {
+                    let direction = match direction.as_integer().map(i128::from) {
+                        Some(0) => Direction::Left,
+                        Some(1) => Direction::Right,
+                        _ => return Err(invalid("direction must be 0 or 1")),
+                    };
+
+                    Ok((direction, to_hash(hash)?))
+                }
+                _ => Err(invalid("proof step must be a [direction, hash] pair")),
+            },
+            _ => Err(invalid("proof step must be an array")),
+        })
+        .collect()
+}
+
+/// Encode a root hash as deterministic CBOR.
+///
+/// ```rust
+/// use merkle_tree::{cbor, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+/// let tree = MerkleTree::new(&leaves).unwrap();
+/// let root = cbor::decode_root(&cbor::encode_root(&tree.root())).unwrap();
+/// assert_eq!(root, tree.root());
+/// ```
+pub fn encode_root(root: &Hash) -> Vec<u8> {
+    to_bytes(&Value::Bytes(root.to_vec()))
+}
+
+/// Decode a root hash previously encoded with `encode_root()`.
+pub fn decode_root(bytes: &[u8]) -> Result<Hash> {
+    match from_bytes(bytes)? {
+        Value::Bytes(root) => to_hash(root),
+        _ => Err(invalid("root must be a byte string")),
+    }
+}
+
+fn to_bytes(value: &Value) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    // writing to a Vec cannot fail
+    ciborium::into_writer(value, &mut bytes).expect("CBOR encoding into a Vec");
+    bytes
+}
+
+fn from_bytes(bytes: &[u8]) -> Result<Value> {
+    ciborium::from_reader(bytes).map_err(|error| invalid(&error.to_string()))
+}
+
+fn to_hash(bytes: Vec<u8>) -> Result<Hash> {
+    Hash::try_from(bytes).map_err(|_| invalid("hash must be 32 bytes"))
+}
+
+fn invalid(message: &str) -> MerkleTreeError {
+    MerkleTreeError::InvalidEncoding(format!("CBOR {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn encodes_a_proof_deterministically() {
+        let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+        let tree = MerkleTree::new(&leaves).unwrap();
+        let proof = tree.proof(&leaves[1]).unwrap();
+        let encoded = encode_proof(&proof);
+
+        // array(1) [ array(2) [ 0, bytes(32) ] ]
+        assert_eq!(encoded[..5], [0x81, 0x82, 0x00, 0x58, 0x20]);
+        assert_eq!(encoded[5..], leaves[0]);
+        assert_eq!(
+            decode_proof(&encoded).unwrap(),
+            [(Direction::Left, leaves[0])]
+        );
+    }
+
+    #[test]
+    fn errors_when_decoding_an_invalid_proof() {
+        assert!(decode_proof(&[0xff]).is_err());
+        assert!(decode_proof(&encode_root(&MerkleTree::hash(b"a"))).is_err());
+        assert!(decode_root(&[0x43, 0x01, 0x02, 0x03]).is_err());
+    }
+}
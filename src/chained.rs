@@ -0,0 +1,114 @@
+//! Proofs that span nested commitments, where the root of one tree is a leaf
+//! of the next (e.g. leaf → subtree root → super-root).
+
+use crate::{Direction, Hash, MerkleTree, OwnedProof};
+use std::borrow::Borrow;
+
+/// A sequence of proofs from multiple trees, lowest tree first.  The root
+/// implied by each proof is the leaf of the next, and the root implied by the
+/// last proof is the top root.
+///
+/// ```rust
+/// use merkle_tree::{chained::ChainedProof, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+/// let subtree = MerkleTree::new(&leaves).unwrap();
+/// let tree = MerkleTree::new(&[subtree.root(), MerkleTree::hash(b"c")]).unwrap();
+///
+/// let proof = ChainedProof::new()
+///     .link(&subtree.proof_at(1).unwrap())
+///     .link(&tree.proof_at(0).unwrap());
+///
+/// assert!(proof.verify(&tree.root(), &leaves[1]));
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ChainedProof {
+    links: Vec<OwnedProof>,
+}
+
+impl ChainedProof {
+    /// Create a chain without any proofs.
+    pub fn new() -> ChainedProof {
+        ChainedProof::default()
+    }
+
+    /// Append the proof of the previous tree's root in the next tree up.
+    pub fn link<H: Borrow<Hash>>(mut self, proof: &[(Direction, H)]) -> ChainedProof {
+        let proof = proof
+            .iter()
+            .map(|(direction, hash)| (*direction, *hash.borrow()))
+            .collect();
+        self.links.push(proof);
+        self
+    }
+
+    /// The proofs of the chain, lowest tree first.
+    pub fn links(&self) -> &[OwnedProof] {
+        &self.links
+    }
+
+    /// Calculate the root of each tree in the chain, lowest tree first, from
+    /// the leaf of the lowest tree.  The last root is the top root.
+    pub fn roots(&self, leaf: &Hash) -> Vec<Hash> {
+        self.links
+            .iter()
+            .scan(*leaf, |hash, proof| {
+                *hash = MerkleTree::root_from_proof(proof, hash);
+                Some(*hash)
+            })
+            .collect()
+    }
+
+    /// Verify the whole chain for a leaf of the lowest tree against the top
+    /// root.  An empty chain never verifies.
+    pub fn verify(&self, root: &Hash, leaf: &Hash) -> bool {
+        self.roots(leaf).last() == Some(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::forest::Forest;
+
+    #[test]
+    fn verifies_a_chain_of_nested_trees() {
+        let leaves = (0..5u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let bottom = MerkleTree::new(&leaves).unwrap();
+        let middle = MerkleTree::new(&[leaves[0], bottom.root(), leaves[1]]).unwrap();
+        let top = MerkleTree::new(&[middle.root(), leaves[2]]).unwrap();
+
+        let proof = ChainedProof::new()
+            .link(&bottom.proof_at(3).unwrap())
+            .link(&middle.proof_at(1).unwrap())
+            .link(&top.proof_at(0).unwrap());
+
+        assert_eq!(
+            proof.roots(&leaves[3]),
+            [bottom.root(), middle.root(), top.root()]
+        );
+        assert!(proof.verify(&top.root(), &leaves[3]));
+        assert!(!proof.verify(&top.root(), &leaves[2]));
+        assert!(!proof.verify(&middle.root(), &leaves[3]));
+        assert!(!ChainedProof::new().verify(&leaves[3], &leaves[3]));
+    }
+
+    #[test]
+    fn chains_forest_proofs() {
+        let leaves = (0..3u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let mut forest = Forest::new();
+        forest
+            .insert_tree("a", MerkleTree::new(&leaves).unwrap())
+            .unwrap();
+        forest
+            .insert_tree("b", MerkleTree::new(&leaves).unwrap())
+            .unwrap();
+
+        let proof = forest.proof_at("b", 2).unwrap().into_chained("b");
+        assert!(proof.verify(&forest.root().unwrap(), &leaves[2]));
+    }
+}
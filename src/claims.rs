@@ -0,0 +1,203 @@
+//! Export a tree as an airdrop claims file, a single JSON document mapping
+//! each leaf to its proof, behind the `json` feature.
+//!
+//! ```json
+//! {
+//!   "root": "0x…",
+//!   "leafCount": 2,
+//!   "claims": {
+//!     "0x…": {
+//!       "index": 0,
+//!       "metadata": { "account": "alice", "amount": 100 },
+//!       "proof": ["0x…"],
+//!       "directions": ["right"]
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! Hashes are `0x` prefixed hex.  `metadata` is only present for trees with
+//! metadata, see `metadata::MetadataMerkleTree`.  `directions` gives the side
+//! of each proof hash, which verifiers of trees with sorted pairs (as airdrop
+//! contracts usually are) can ignore.  A leaf appearing more than once is
+//! claimed at its first offset.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::metadata::MetadataMerkleTree;
+use crate::{Direction, Hash, MerkleTree};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+impl MerkleTree {
+    /// The claims file of the tree, as a JSON value.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let claims = tree.to_claims_json();
+    ///
+    /// let claim = &claims["claims"][format!("0x{}", hex::encode(leaves[1]))];
+    /// assert_eq!(claim["index"], 1);
+    /// assert_eq!(claim["proof"][0], format!("0x{}", hex::encode(leaves[0])));
+    /// ```
+    pub fn to_claims_json(&self) -> Value {
+        claims_json(self, |_| None)
+    }
+
+    /// Write the claims file of the tree to `path`, replacing any existing
+    /// file.
+    pub fn export_claims_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_json(path, &self.to_claims_json())
+    }
+}
+
+impl<M: Serialize> MetadataMerkleTree<M> {
+    /// The claims file of the tree, with the metadata of each leaf, as a
+    /// JSON value.
+    pub fn to_claims_json(&self) -> Result<Value> {
+        let metadata = (0..self.tree().leaf_count())
+            .map(|offset| serde_json::to_value(self.metadata(offset)))
+            .collect::<serde_json::Result<Vec<Value>>>()
+            .map_err(|error| MerkleTreeError::InvalidEncoding(error.to_string()))?;
+
+        Ok(claims_json(self.tree(), |offset| {
+            Some(metadata[offset].clone())
+        }))
+    }
+
+    /// Write the claims file of the tree, with the metadata of each leaf, to
+    /// `path`, replacing any existing file.
+    pub fn export_claims_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_json(path, &self.to_claims_json()?)
+    }
+}
+
+fn claims_json<F>(tree: &MerkleTree, metadata: F) -> Value
+where
+    F: Fn(usize) -> Option<Value>,
+{
+    let mut claims = Map::new();
+
+    for (offset, leaf) in tree.leaves().iter().enumerate() {
+        let key = to_hex(leaf);
+
+        if claims.contains_key(&key) {
+            continue;
+        }
+
+        let proof = tree.proof_at(offset).expect("offset of a leaf");
+        let mut claim = Map::new();
+        claim.insert("index".to_owned(), json!(offset));
+
+        if let Some(metadata) = metadata(offset) {
+            claim.insert("metadata".to_owned(), metadata);
+        }
+
+        claim.insert(
+            "proof".to_owned(),
+            proof.iter().map(|(_, hash)| to_hex(hash)).collect(),
+        );
+        claim.insert(
+            "directions".to_owned(),
+            proof
+                .iter()
+                .map(|(direction, _)| match direction {
+                    Direction::Left => "left",
+                    Direction::Right => "right",
+                })
+                .collect(),
+        );
+
+        claims.insert(key, Value::Object(claim));
+    }
+
+    json!({
+        "root": to_hex(&tree.root()),
+        "leafCount": tree.leaf_count(),
+        "claims": claims,
+    })
+}
+
+fn to_hex(hash: &Hash) -> String {
+    format!("0x{}", hex::encode(hash))
+}
+
+fn write_json<P: AsRef<Path>>(path: P, value: &Value) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, value).map_err(std::io::Error::from)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_claims_with_metadata() {
+        let claims = [("alice", 100), ("bob", 250), ("carol", 75)];
+        let leaves = claims
+            .iter()
+            .map(|(account, amount)| {
+                let leaf = MerkleTree::hash(format!("{account}:{amount}").as_bytes());
+                (leaf, json!({ "account": account, "amount": amount }))
+            })
+            .collect::<Vec<_>>();
+        let tree = MetadataMerkleTree::new(leaves.clone()).unwrap();
+
+        let path =
+            std::env::temp_dir().join(format!("merkle-tree-{}.claims.json", std::process::id()));
+        tree.export_claims_json(&path).unwrap();
+        let exported = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let exported: Value = serde_json::from_str(&exported.unwrap()).unwrap();
+        assert_eq!(exported["root"], to_hex(&tree.tree().root()));
+        assert_eq!(exported["leafCount"], 3);
+
+        for (offset, (leaf, metadata)) in leaves.iter().enumerate() {
+            let claim = &exported["claims"][to_hex(leaf)];
+            assert_eq!(claim["index"], offset);
+            assert_eq!(&claim["metadata"], metadata);
+
+            // the proof verifies from its hex and directions
+            let proof = claim["proof"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .zip(claim["directions"].as_array().unwrap())
+                .map(|(hash, direction)| {
+                    let hash = MerkleTree::hash_from_hex(hash.as_str().unwrap()).unwrap();
+                    let direction = match direction.as_str().unwrap() {
+                        "left" => Direction::Left,
+                        _ => Direction::Right,
+                    };
+                    (direction, hash)
+                })
+                .collect::<Vec<_>>();
+            assert!(tree.tree().verify(&proof, leaf));
+        }
+    }
+
+    #[test]
+    fn claims_duplicates_at_their_first_offset() {
+        let leaves = [
+            MerkleTree::hash(b"a"),
+            MerkleTree::hash(b"b"),
+            MerkleTree::hash(b"a"),
+        ];
+        let claims = MerkleTree::new(&leaves).unwrap().to_claims_json();
+
+        assert_eq!(claims["claims"].as_object().unwrap().len(), 2);
+        assert_eq!(claims["claims"][to_hex(&leaves[0])]["index"], 0);
+        assert!(claims["claims"][to_hex(&leaves[0])]
+            .get("metadata")
+            .is_none());
+    }
+}
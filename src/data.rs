@@ -0,0 +1,141 @@
+//! Keep the original payload of each leaf alongside the tree, so leaves can
+//! be looked up and proven by payload rather than by hash.
+
+use crate::builder::MerkleTreeBuilder;
+use crate::error::Result;
+use crate::{Direction, Hash, MerkleTree, Proof};
+use std::borrow::Borrow;
+
+/// A tree that owns the payload each leaf was hashed from.
+///
+/// ```rust
+/// use merkle_tree::data::DataMerkleTree;
+///
+/// let tree = DataMerkleTree::new(vec!["alice", "bob", "carol"]).unwrap();
+///
+/// let proof = tree.proof_for(&"bob").unwrap();
+/// assert!(tree.verify(&proof, &"bob"));
+/// assert_eq!(tree.get(2), Some(&"carol"));
+/// ```
+#[derive(Debug)]
+pub struct DataMerkleTree<T> {
+    tree: MerkleTree,
+    items: Vec<T>,
+}
+
+impl<T: AsRef<[u8]>> DataMerkleTree<T> {
+    /// Create a new tree, hashing each payload into a leaf.
+    pub fn new(items: Vec<T>) -> Result<DataMerkleTree<T>> {
+        Self::with_builder(&MerkleTree::builder(), items)
+    }
+
+    /// Create a new tree with the options of a builder, hashing each payload
+    /// into a leaf with the builder's leaf hash function.  Leaf sorting
+    /// isn't applied, so payloads stay in step with their leaves.
+    pub fn with_builder(builder: &MerkleTreeBuilder, items: Vec<T>) -> Result<DataMerkleTree<T>> {
+        let tree = builder.clone().sort_leaves(false).build_from_data(&items)?;
+
+        Ok(DataMerkleTree { tree, items })
+    }
+
+    /// Create a new tree from the payloads yielded by an iterator.  This is
+    /// the fallible equivalent of `collect()`, since a tree can't be empty.
+    pub fn try_from_iter<I: IntoIterator<Item = T>>(items: I) -> Result<DataMerkleTree<T>> {
+        Self::new(items.into_iter().collect())
+    }
+
+    /// The underlying tree.
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// The payloads of the leaves, in leaf order.
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Return the payload of the leaf at the given offset.
+    pub fn get(&self, offset: usize) -> Option<&T> {
+        self.items.get(offset)
+    }
+
+    /// Hash a payload into a leaf as the tree does.
+    pub fn leaf(&self, item: &T) -> Hash {
+        (self.tree.config.hasher.leaf)(item.as_ref())
+    }
+
+    /// Return the root hash of the tree.
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    /// Replace the payload of an existing leaf.
+    pub fn update(&mut self, offset: usize, item: T) -> Result<()> {
+        self.tree.update(offset, self.leaf(&item))?;
+        self.items[offset] = item;
+
+        Ok(())
+    }
+
+    /// Append a payload and return the new root hash.
+    pub fn push(&mut self, item: T) -> Result<Hash> {
+        let root = self.tree.push(self.leaf(&item))?;
+        self.items.push(item);
+
+        Ok(root)
+    }
+
+    /// Generate a Merkle Proof for a payload.
+    pub fn proof_for(&self, item: &T) -> Result<Proof<'_>> {
+        self.tree.proof(&self.leaf(item))
+    }
+
+    /// Generate a Merkle Proof for the leaf at a given offset.
+    pub fn proof_at(&self, offset: usize) -> Result<Proof<'_>> {
+        self.tree.proof_at(offset)
+    }
+
+    /// Verify a Merkle Proof for a payload.
+    pub fn verify<H: Borrow<Hash>>(&self, proof: &[(Direction, H)], item: &T) -> bool {
+        self.tree.verify(proof, &self.leaf(item))
+    }
+
+    /// Iterate over every payload with its proof, in leaf order, e.g. to
+    /// export a claims file.
+    pub fn proofs(&self) -> impl Iterator<Item = (&T, Proof<'_>)> + '_ {
+        self.items.iter().enumerate().map(|(offset, item)| {
+            (
+                item,
+                self.tree
+                    .proof_from_index(self.tree.get_index_from_offset(offset)),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proves_leaves_by_payload() {
+        let items = (0..5u8).map(|i| vec![i]).collect::<Vec<_>>();
+        let mut tree = DataMerkleTree::try_from_iter(items[0..4].iter().cloned()).unwrap();
+        tree.push(items[4].clone()).unwrap();
+        tree.update(1, vec![9]).unwrap();
+
+        assert_eq!(
+            tree.root(),
+            MerkleTree::from_data(tree.items()).unwrap().root()
+        );
+        assert_eq!(tree.get(1), Some(&vec![9]));
+        assert!(tree.proof_for(&items[1]).is_err());
+        assert!(DataMerkleTree::<Vec<u8>>::try_from_iter([]).is_err());
+
+        for (offset, (item, proof)) in tree.proofs().enumerate() {
+            assert_eq!(proof, tree.proof_for(item).unwrap());
+            assert_eq!(proof, tree.proof_at(offset).unwrap());
+            assert!(tree.verify(&proof, item));
+        }
+    }
+}
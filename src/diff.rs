@@ -0,0 +1,76 @@
+//! Compare two trees to find the leaves that differ.
+
+use crate::{Hash, MerkleTree};
+
+impl MerkleTree {
+    /// Return the offsets of the leaves whose values differ between two trees,
+    /// in ascending order.  Both trees are walked top-down and subtrees with
+    /// equal hashes are skipped, so this is O(k log n) for k differences.
+    /// Leaves present in only one of the trees always differ.  This is
+    /// `reconcile()` with both trees held locally.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let mut other = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// other.update(1, MerkleTree::hash(b"z")).unwrap();
+    /// other.push(MerkleTree::hash(b"d")).unwrap();
+    ///
+    /// assert_eq!(tree.diff(&other), [1, 3]);
+    /// ```
+    pub fn diff(&self, other: &MerkleTree) -> Vec<usize> {
+        let Ok(differences) = self.reconcile(other);
+        differences
+    }
+
+    /// Get the node at a height above the leaves (leaves are height 0) and
+    /// an offset within that level.
+    pub(crate) fn node_at_height(&self, height: usize, offset: usize) -> &Hash {
+        let level = self.num_levels() - height;
+        &self.nodes[Self::get_level_start(level) + offset]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Hash, MerkleTree};
+
+    fn leaves() -> Vec<Hash> {
+        (0..21u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn finds_the_leaves_that_differ() {
+        let leaves = leaves();
+        let tree = MerkleTree::new(&leaves).unwrap();
+        let mut other = MerkleTree::new(&leaves).unwrap();
+        assert!(tree.diff(&other).is_empty());
+
+        for offset in [20, 3, 4, 11] {
+            other.update(offset, MerkleTree::hash(b"z")).unwrap();
+        }
+
+        assert_eq!(tree.diff(&other), [3, 4, 11, 20]);
+        assert_eq!(other.diff(&tree), [3, 4, 11, 20]);
+    }
+
+    #[test]
+    fn finds_the_leaves_that_differ_between_trees_of_different_sizes() {
+        let leaves = leaves();
+        let tree = MerkleTree::new(&leaves).unwrap();
+        let mut other = MerkleTree::new(&leaves[0..5]).unwrap();
+        other.update(2, MerkleTree::hash(b"z")).unwrap();
+
+        let expected = [2].into_iter().chain(5..21).collect::<Vec<_>>();
+        assert_eq!(tree.diff(&other), expected);
+        assert_eq!(other.diff(&tree), expected);
+
+        // the last shared leaf is duplicated in the smaller tree
+        let other = MerkleTree::new(&[leaves[0], leaves[1], leaves[2], leaves[2]]).unwrap();
+        let tree = MerkleTree::new(&leaves[0..3]).unwrap();
+        assert_eq!(tree.diff(&other), [3]);
+    }
+}
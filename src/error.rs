@@ -5,9 +5,81 @@ pub enum MerkleTreeError {
     #[error("Cannot find leaf: {0}")]
     CannotFindLeaf(String),
 
+    #[error("Leaf {0} appears more than once, use an offset-based lookup")]
+    AmbiguousLeaf(String),
+
+    #[error("Cannot remove every leaf from the tree")]
+    CannotRemoveLastLeaf,
+
+    #[error("Cannot join trees: {0}")]
+    CannotJoin(String),
+
+    #[error("Cannot take the frontier of a tree: {0}")]
+    CannotTakeFrontier(String),
+
+    #[error("Cannot fit {1} leaves in a tree of depth {0}")]
+    DepthExceeded(usize, usize),
+
+    #[error("Cannot reserve storage for {0} leaves")]
+    CapacityOverflow(usize),
+
+    #[error("Cannot hold {0} leaves without padding")]
+    PaddingRequired(usize),
+
+    #[error("Construction was cancelled")]
+    Cancelled,
+
     #[error("Cannot initialize with zero leaves")]
     Empty,
 
+    #[error("Expected {0} leaves, but {1} were supplied")]
+    LeafCountMismatch(usize, usize),
+
+    #[error("Duplicate leaf: {0}")]
+    DuplicateLeaf(String),
+
+    #[error("Node at index {0} is inconsistent with its children")]
+    InconsistentNode(usize),
+
+    #[error("Generalized index {0} is not part of the tree")]
+    InvalidGeneralizedIndex(usize),
+
+    #[error("Invalid hex hash: {0}")]
+    InvalidHex(String),
+
+    #[error("Invalid encoding: {0}")]
+    InvalidEncoding(String),
+
+    #[error("Tree was hashed with hasher {1}, expected hasher {0}")]
+    HasherMismatch(u32, u32),
+
+    #[error("Tree was padded with strategy {1}, expected strategy {0}")]
+    PaddingMismatch(u32, u32),
+
+    #[error("Unsupported format version: {0}")]
+    UnsupportedFormatVersion(u32),
+
+    #[error("Unsupported format flags: {0:#x}")]
+    UnsupportedFormatFlags(u32),
+
+    #[error("Checksum doesn't match the contents")]
+    ChecksumMismatch,
+
+    #[error("Node {1} of level {0} is missing from storage")]
+    MissingNode(usize, usize),
+
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Unknown tree: {0}")]
+    UnknownTree(String),
+
+    #[error("Unknown version: {0}")]
+    UnknownVersion(usize),
+
     #[error("Offset {0} out of bounds (leaf length is {1}")]
     OffsetOutOfBounds(usize, usize),
 }
@@ -0,0 +1,363 @@
+//! A versioned file format for persisting trees, which is stable across
+//! releases and documented here so files can be audited and read by other
+//! implementations.
+//!
+//! All integers are little-endian:
+//!
+//! | Offset | Size   | Field                                            |
+//! |--------|--------|--------------------------------------------------|
+//! | 0      | 8      | magic, `MRKLTREE`                                |
+//! | 8      | 4      | format version, currently `FORMAT_VERSION`       |
+//! | 12     | 4      | flags: bit 0 is set if the body is compressed    |
+//! | 16     | n      | body: the header and nodes of the `bytes` module |
+//! | 16 + n | 32     | SHA3-256 checksum of every preceding byte        |
+//!
+//! The tree's header records the hasher and padding strategy ids, leaf
+//! count, node count, depth and whether pairs are sorted.  With the `compression` feature, the body
+//! may be compressed as a single zstd frame, see `save_compressed()`, which
+//! `load()` detects and decompresses.  The checksum always uses SHA3-256,
+//! whatever the tree was hashed with, and covers the body as stored.
+//!
+//! Loading a file of an unknown version fails with
+//! `UnsupportedFormatVersion`, and one with flags this version doesn't know,
+//! or a compressed file without the `compression` feature, fails with
+//! `UnsupportedFormatFlags`, rather than misreading the tree.
+
+use crate::builder::MerkleTreeBuilder;
+use crate::error::{MerkleTreeError, Result};
+use crate::{Hash, MerkleTree};
+use sha3::{Digest, Sha3_256};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// The bytes every tree file starts with.
+pub const MAGIC: [u8; 8] = *b"MRKLTREE";
+
+/// The version of the file format written by `save()`.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The length of the file header preceding the tree's bytes.
+pub(crate) const FILE_HEADER_LEN: usize = 16;
+
+/// The flag set if the body is compressed with zstd.
+pub(crate) const COMPRESSED: u32 = 1;
+
+/// The flags this build can read.
+const KNOWN_FLAGS: u32 = if cfg!(feature = "compression") {
+    COMPRESSED
+} else {
+    0
+};
+
+/// The length of the trailing checksum.
+pub(crate) const CHECKSUM_LEN: usize = 32;
+
+impl MerkleTree {
+    /// Write the tree in the file format of this module.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// let mut file = Vec::new();
+    /// tree.save(&mut file).unwrap();
+    /// assert_eq!(MerkleTree::load(file.as_slice()).unwrap(), tree);
+    /// ```
+    pub fn save<W: Write>(&self, writer: W) -> Result<()> {
+        write_file(writer, 0, &self.to_bytes())
+    }
+
+    /// Write the tree to a file at `path`, replacing any existing file.
+    pub fn save_to_path<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.save(BufWriter::new(File::create(path)?))
+    }
+
+    /// Write the tree with its body compressed by zstd at the given level
+    /// (1 to 22, or 0 for zstd's default).  Trees of similar leaves compress
+    /// well, and `load()` decompresses them transparently.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = vec![MerkleTree::hash(b"a"); 1000];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// let (mut plain, mut compressed) = (Vec::new(), Vec::new());
+    /// tree.save(&mut plain).unwrap();
+    /// tree.save_compressed(&mut compressed, 3).unwrap();
+    ///
+    /// assert!(compressed.len() < plain.len() / 10);
+    /// assert_eq!(MerkleTree::load(compressed.as_slice()).unwrap(), tree);
+    /// ```
+    #[cfg(feature = "compression")]
+    pub fn save_compressed<W: Write>(&self, writer: W, level: i32) -> Result<()> {
+        let body = zstd::bulk::compress(&self.to_bytes(), level)?;
+        write_file(writer, COMPRESSED, &body)
+    }
+
+    /// Write the tree compressed to a file at `path`, replacing any existing
+    /// file.
+    #[cfg(feature = "compression")]
+    pub fn save_compressed_to_path<P: AsRef<Path>>(&self, path: P, level: i32) -> Result<()> {
+        self.save_compressed(BufWriter::new(File::create(path)?), level)
+    }
+
+    /// Read a tree written by `save()` with the default options, see
+    /// `MerkleTreeBuilder::load()` to load trees built with other options.
+    pub fn load<R: Read>(reader: R) -> Result<MerkleTree> {
+        MerkleTree::builder().load(reader)
+    }
+
+    /// Read a tree from a file written by `save_to_path()` with the default
+    /// options.
+    pub fn load_from_path<P: AsRef<Path>>(path: P) -> Result<MerkleTree> {
+        MerkleTree::builder().load_from_path(path)
+    }
+}
+
+impl MerkleTreeBuilder {
+    /// Read a tree written by `MerkleTree::save()`, hashing with these
+    /// options.  The checksum and layout are checked, but nodes aren't
+    /// checked against their children, see `integrity_check()`.
+    pub fn load<R: Read>(&self, mut reader: R) -> Result<MerkleTree> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        #[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+        let (flags, body) = split_file(&bytes)?;
+        verify_checksum(&bytes)?;
+
+        #[cfg(feature = "compression")]
+        if flags & COMPRESSED != 0 {
+            return self.load_bytes(&zstd::stream::decode_all(body)?);
+        }
+
+        self.load_bytes(body)
+    }
+
+    /// Read a tree from a file written by `MerkleTree::save_to_path()`,
+    /// hashing with these options.
+    pub fn load_from_path<P: AsRef<Path>>(&self, path: P) -> Result<MerkleTree> {
+        self.load(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Check the file header, returning its flags and the body.  The checksum
+/// isn't checked, see `verify_checksum()`.
+pub(crate) fn split_file(bytes: &[u8]) -> Result<(u32, &[u8])> {
+    let not_a_tree = || MerkleTreeError::InvalidEncoding("not a tree file".to_owned());
+
+    let Some((header, rest)) = bytes.split_first_chunk::<FILE_HEADER_LEN>() else {
+        return Err(not_a_tree());
+    };
+
+    if header[0..8] != MAGIC {
+        return Err(not_a_tree());
+    }
+
+    let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    let flags = u32::from_le_bytes(header[12..16].try_into().unwrap());
+
+    if version != FORMAT_VERSION {
+        return Err(MerkleTreeError::UnsupportedFormatVersion(version));
+    }
+
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(MerkleTreeError::UnsupportedFormatFlags(flags));
+    }
+
+    let Some((body, _)) = rest.split_last_chunk::<CHECKSUM_LEN>() else {
+        return Err(MerkleTreeError::InvalidEncoding(
+            "missing checksum".to_owned(),
+        ));
+    };
+
+    Ok((flags, body))
+}
+
+/// Check the checksum of a file against the rest of its contents.
+pub(crate) fn verify_checksum(bytes: &[u8]) -> Result<()> {
+    let Some((contents, checksum)) = bytes.split_last_chunk::<CHECKSUM_LEN>() else {
+        return Err(MerkleTreeError::InvalidEncoding(
+            "missing checksum".to_owned(),
+        ));
+    };
+
+    let expected: Hash = Sha3_256::digest(contents).into();
+
+    if *checksum != expected {
+        return Err(MerkleTreeError::ChecksumMismatch);
+    }
+
+    Ok(())
+}
+
+/// The file header of a file with the given flags.
+pub(crate) fn file_header(flags: u32) -> [u8; FILE_HEADER_LEN] {
+    let mut header = [0; FILE_HEADER_LEN];
+    header[0..8].copy_from_slice(&MAGIC);
+    header[8..12].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header[12..16].copy_from_slice(&flags.to_le_bytes());
+    header
+}
+
+/// Write the file header, the body and the checksum of both.
+fn write_file<W: Write>(mut writer: W, flags: u32, body: &[u8]) -> Result<()> {
+    let mut checksum = Sha3_256::new();
+    let mut write = |bytes: &[u8]| {
+        checksum.update(bytes);
+        writer.write_all(bytes)
+    };
+
+    write(&file_header(flags))?;
+    write(body)?;
+
+    let checksum: Hash = checksum.finalize().into();
+    writer.write_all(&checksum)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::padding::{PaddingStrategy, PromoteOdd, ZeroHash};
+
+    fn tree() -> MerkleTree {
+        let leaves = (0..5u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+
+        MerkleTree::builder()
+            .sorted_pairs(true)
+            .build(&leaves)
+            .unwrap()
+    }
+
+    fn saved(tree: &MerkleTree) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        tree.save(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn saves_and_loads_files() {
+        let tree = tree();
+        let path = std::env::temp_dir().join(format!("merkle-tree-{}.tree", std::process::id()));
+
+        tree.save_to_path(&path).unwrap();
+        let loaded = MerkleTree::load_from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, tree);
+        assert!(loaded.integrity_check().is_ok());
+        assert!(MerkleTree::load_from_path(&path).is_err());
+    }
+
+    #[test]
+    fn documents_the_layout() {
+        let tree = tree();
+        let bytes = saved(&tree);
+
+        assert_eq!(bytes[0..8], *b"MRKLTREE");
+        assert_eq!(bytes[8..12], [1, 0, 0, 0]);
+        assert_eq!(bytes[12..16], [0; 4]);
+        assert_eq!(bytes[16..bytes.len() - 32], tree.to_bytes());
+        assert_eq!(
+            bytes[bytes.len() - 32..],
+            MerkleTree::hash(&bytes[..bytes.len() - 32])
+        );
+    }
+
+    #[test]
+    fn loads_with_the_padding_strategy_it_was_saved_with() {
+        let leaves = (0..5u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+
+        for padding in [&ZeroHash as &'static dyn PaddingStrategy, &PromoteOdd] {
+            let builder = MerkleTree::builder().padding(padding);
+            let tree = builder.build(&leaves).unwrap();
+            let bytes = saved(&tree);
+
+            assert!(matches!(
+                MerkleTree::load(bytes.as_slice()),
+                Err(MerkleTreeError::PaddingMismatch(0, id)) if id == padding.id()
+            ));
+
+            // later mutations pad the way the tree was built
+            let mut loaded = builder.load(bytes.as_slice()).unwrap();
+            loaded.push(MerkleTree::hash(b"z")).unwrap();
+            let mut grown = leaves.clone();
+            grown.push(MerkleTree::hash(b"z"));
+            assert_eq!(loaded.root(), builder.build(&grown).unwrap().root());
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_or_corrupted_files() {
+        let bytes = saved(&tree());
+
+        let mut future = bytes.clone();
+        future[8] = 2;
+        assert!(matches!(
+            MerkleTree::load(future.as_slice()),
+            Err(MerkleTreeError::UnsupportedFormatVersion(2))
+        ));
+
+        let mut flags = bytes.clone();
+        flags[12] = 2;
+        assert!(matches!(
+            MerkleTree::load(flags.as_slice()),
+            Err(MerkleTreeError::UnsupportedFormatFlags(2))
+        ));
+
+        let mut corrupted = bytes.clone();
+        corrupted[100] ^= 1;
+        assert!(matches!(
+            MerkleTree::load(corrupted.as_slice()),
+            Err(MerkleTreeError::ChecksumMismatch)
+        ));
+
+        assert!(MerkleTree::load(&bytes[1..]).is_err());
+        assert!(MerkleTree::load(&bytes[..bytes.len() - 1]).is_err());
+        assert!(MerkleTree::load(&bytes[..20]).is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compresses_the_body() {
+        let tree = MerkleTree::new(&vec![MerkleTree::hash(b"a"); 100]).unwrap();
+        let mut bytes = Vec::new();
+        tree.save_compressed(&mut bytes, 0).unwrap();
+
+        assert_eq!(bytes[12..16], [1, 0, 0, 0]);
+        assert!(bytes.len() < saved(&tree).len() / 10);
+        assert_eq!(MerkleTree::load(bytes.as_slice()).unwrap(), tree);
+
+        // the checksum covers the compressed body
+        let last = bytes.len() - 33;
+        bytes[last] ^= 1;
+        assert!(matches!(
+            MerkleTree::load(bytes.as_slice()),
+            Err(MerkleTreeError::ChecksumMismatch)
+        ));
+    }
+
+    #[cfg(not(feature = "compression"))]
+    #[test]
+    fn rejects_compressed_files() {
+        let mut bytes = saved(&tree());
+        bytes[12] = 1;
+
+        assert!(matches!(
+            MerkleTree::load(bytes.as_slice()),
+            Err(MerkleTreeError::UnsupportedFormatFlags(1))
+        ));
+    }
+}
@@ -0,0 +1,271 @@
+//! Many named trees committed to by a single forest root.
+//!
+//! Each tree is committed in a parent tree, ordered by name, as the leaf
+//! `concat(hash(name), root)`, which binds the tree's root to its name.  A
+//! proof of a leaf in one tree is the leaf's proof within the tree followed
+//! by the tree's proof within the parent tree.
+
+use crate::chained::ChainedProof;
+use crate::error::{MerkleTreeError, Result};
+use crate::{Direction, Hash, MerkleTree, OwnedProof};
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+/// A set of named trees, e.g. one per tenant, under a single forest root.
+///
+/// ```rust
+/// use merkle_tree::{forest::Forest, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+/// let mut forest = Forest::new();
+/// forest.insert_tree("alice", MerkleTree::new(&leaves).unwrap()).unwrap();
+/// forest.insert_tree("bob", MerkleTree::new(&leaves[0..1]).unwrap()).unwrap();
+///
+/// let proof = forest.proof_at("alice", 1).unwrap();
+/// assert!(Forest::verify(&forest.root().unwrap(), "alice", &proof, &leaves[1]));
+/// assert!(!Forest::verify(&forest.root().unwrap(), "bob", &proof, &leaves[1]));
+/// ```
+#[derive(Debug, Default)]
+pub struct Forest {
+    trees: BTreeMap<String, MerkleTree>,
+
+    /// The tree committing to every named tree, once there is at least one.
+    parent: Option<MerkleTree>,
+}
+
+/// A proof of a leaf in one of the trees of a forest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForestProof {
+    /// The proof of the leaf within its tree.
+    pub tree: OwnedProof,
+
+    /// The proof of the tree within the forest.
+    pub forest: OwnedProof,
+}
+
+impl ForestProof {
+    /// Convert to a `ChainedProof` of two links for a tree with the given
+    /// name.  Committing the tree's root under its name is a proof step of
+    /// its own, so it's prepended to the proof within the forest.
+    pub fn into_chained(self, name: &str) -> ChainedProof {
+        let name_step = [(Direction::Left, MerkleTree::hash(name.as_bytes()))];
+        let forest = [&name_step[..], &self.forest].concat();
+
+        ChainedProof::new().link(&self.tree).link(&forest)
+    }
+}
+
+impl Forest {
+    /// Create an empty forest.
+    pub fn new() -> Forest {
+        Forest::default()
+    }
+
+    /// Add a named tree to the forest, replacing any tree with the same name,
+    /// and return the new forest root.
+    pub fn insert_tree(&mut self, name: &str, tree: MerkleTree) -> Result<Hash> {
+        let leaf = Self::forest_leaf(name, &tree.root());
+        let offset = self.offset(name);
+        let replaced = self.trees.insert(name.to_owned(), tree).is_some();
+
+        match self.parent.as_mut() {
+            Some(parent) if replaced => parent.update(offset, leaf)?,
+            Some(parent) => _ = parent.insert(offset, leaf)?,
+            None => self.parent = Some(MerkleTree::new(&[leaf])?),
+        }
+
+        self.root()
+    }
+
+    /// Remove a named tree from the forest and return it.
+    pub fn remove_tree(&mut self, name: &str) -> Result<MerkleTree> {
+        let offset = self.offset(name);
+        let tree = self
+            .trees
+            .remove(name)
+            .ok_or_else(|| MerkleTreeError::UnknownTree(name.to_owned()))?;
+
+        if self.trees.is_empty() {
+            self.parent = None;
+        } else if let Some(parent) = self.parent.as_mut() {
+            parent.remove(offset)?;
+        }
+
+        Ok(tree)
+    }
+
+    /// Return a named tree.
+    pub fn tree(&self, name: &str) -> Option<&MerkleTree> {
+        self.trees.get(name)
+    }
+
+    /// Iterate over the names of the trees, in the order they are committed.
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.trees.keys().map(String::as_str)
+    }
+
+    /// Mutate a named tree, then recommit its root.  The result of the
+    /// mutation is returned, and the tree is recommitted even if it failed.
+    ///
+    /// ```rust
+    /// use merkle_tree::{forest::Forest, MerkleTree};
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let mut forest = Forest::new();
+    /// forest.insert_tree("alice", MerkleTree::new(&leaves[0..1]).unwrap()).unwrap();
+    /// let old_root = forest.root().unwrap();
+    ///
+    /// forest.modify_tree("alice", |tree| tree.push(leaves[1])).unwrap().unwrap();
+    /// assert_ne!(forest.root().unwrap(), old_root);
+    /// ```
+    pub fn modify_tree<T>(
+        &mut self,
+        name: &str,
+        mutation: impl FnOnce(&mut MerkleTree) -> T,
+    ) -> Result<T> {
+        let offset = self.offset(name);
+        let tree = self
+            .trees
+            .get_mut(name)
+            .ok_or_else(|| MerkleTreeError::UnknownTree(name.to_owned()))?;
+
+        let result = mutation(tree);
+        let leaf = Self::forest_leaf(name, &tree.root());
+
+        if let Some(parent) = self.parent.as_mut() {
+            if parent.leaves()[offset] != leaf {
+                parent.update(offset, leaf)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Return the forest root, which commits to every tree.
+    pub fn root(&self) -> Result<Hash> {
+        self.parent
+            .as_ref()
+            .map(|parent| parent.root())
+            .ok_or(MerkleTreeError::Empty)
+    }
+
+    /// Generate a proof of the leaf at a given offset of a named tree.
+    pub fn proof_at(&self, name: &str, offset: usize) -> Result<ForestProof> {
+        let tree = self
+            .trees
+            .get(name)
+            .ok_or_else(|| MerkleTreeError::UnknownTree(name.to_owned()))?;
+        let parent = self.parent.as_ref().ok_or(MerkleTreeError::Empty)?;
+
+        Ok(ForestProof {
+            tree: to_owned_proof(tree.proof_at(offset)?),
+            forest: to_owned_proof(parent.proof_at(self.offset(name))?),
+        })
+    }
+
+    /// Verify a proof of a leaf in a named tree against a forest root.
+    pub fn verify(root: &Hash, name: &str, proof: &ForestProof, leaf: &Hash) -> bool {
+        let tree_root = MerkleTree::root_from_proof(&proof.tree, leaf);
+        let forest_leaf = Self::forest_leaf(name, &tree_root);
+        MerkleTree::verify_with_root(root, &proof.forest, &forest_leaf)
+    }
+
+    /// The leaf committing to a named tree in the parent tree.
+    fn forest_leaf(name: &str, root: &Hash) -> Hash {
+        MerkleTree::concat(&MerkleTree::hash(name.as_bytes()), root)
+    }
+
+    /// The offset of a name within the parent tree, which is where it is (or
+    /// would be) in name order.
+    fn offset(&self, name: &str) -> usize {
+        let before = (Bound::Unbounded, Bound::Excluded(name));
+        self.trees.range::<str, _>(before).count()
+    }
+}
+
+fn to_owned_proof(proof: crate::Proof<'_>) -> OwnedProof {
+    proof
+        .into_iter()
+        .map(|(direction, hash)| (direction, *hash))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves() -> Vec<Hash> {
+        (0..5u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn proves_leaves_across_both_levels() {
+        let leaves = leaves();
+        let mut forest = Forest::new();
+        assert!(forest.root().is_err());
+
+        for (count, name) in [(1, "carol"), (5, "alice"), (3, "bob")] {
+            forest
+                .insert_tree(name, MerkleTree::new(&leaves[0..count]).unwrap())
+                .unwrap();
+        }
+
+        assert_eq!(
+            forest.names().collect::<Vec<_>>(),
+            ["alice", "bob", "carol"]
+        );
+        let expected = MerkleTree::new(&[
+            Forest::forest_leaf("alice", &MerkleTree::new(&leaves).unwrap().root()),
+            Forest::forest_leaf("bob", &MerkleTree::new(&leaves[0..3]).unwrap().root()),
+            Forest::forest_leaf("carol", &MerkleTree::new(&leaves[0..1]).unwrap().root()),
+        ])
+        .unwrap();
+        assert_eq!(forest.root().unwrap(), expected.root());
+
+        let root = forest.root().unwrap();
+        let proof = forest.proof_at("bob", 2).unwrap();
+        assert!(Forest::verify(&root, "bob", &proof, &leaves[2]));
+        assert!(!Forest::verify(&root, "bob", &proof, &leaves[1]));
+        assert!(!Forest::verify(&root, "alice", &proof, &leaves[2]));
+        assert!(forest.proof_at("dave", 0).is_err());
+
+        forest
+            .modify_tree("bob", |tree| tree.update(2, leaves[4]))
+            .unwrap()
+            .unwrap();
+        assert!(!Forest::verify(
+            &forest.root().unwrap(),
+            "bob",
+            &proof,
+            &leaves[2]
+        ));
+        let proof = forest.proof_at("bob", 2).unwrap();
+        assert!(Forest::verify(
+            &forest.root().unwrap(),
+            "bob",
+            &proof,
+            &leaves[4]
+        ));
+    }
+
+    #[test]
+    fn removes_trees() {
+        let leaves = leaves();
+        let mut forest = Forest::new();
+        forest
+            .insert_tree("a", MerkleTree::new(&leaves).unwrap())
+            .unwrap();
+        let root = forest.root().unwrap();
+
+        forest
+            .insert_tree("b", MerkleTree::new(&leaves).unwrap())
+            .unwrap();
+        assert_eq!(forest.remove_tree("b").unwrap().leaf_count, 5);
+        assert_eq!(forest.root().unwrap(), root);
+
+        assert!(forest.remove_tree("b").is_err());
+        forest.remove_tree("a").unwrap();
+        assert!(forest.root().is_err());
+        assert!(forest.tree("a").is_none());
+    }
+}
@@ -0,0 +1,270 @@
+//! An append-only representation that stores only the right frontier of the
+//! tree: the root of each complete subtree along its right edge.
+//!
+//! A tree of `n` leaves has one complete subtree for each set bit of `n`, so
+//! the frontier holds at most `log2(n) + 1` hashes no matter how many leaves
+//! have been appended.  Old leaves can't be proven, but appending and
+//! calculating the root are both O(log n), and roots are identical to those
+//! of a `MerkleTree` holding the same leaves.  A frontier taken from a tree
+//! keeps its hash functions, `sorted_pairs` and padding strategy.
+
+use crate::builder::Config;
+use crate::error::{MerkleTreeError, Result};
+use crate::{Hash, MerkleTree, EMPTY_NODE};
+
+/// The right frontier of an append-only tree.
+///
+/// ```rust
+/// use merkle_tree::{frontier::MerkleFrontier, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+/// let mut frontier = MerkleFrontier::new();
+///
+/// for leaf in leaves {
+///     frontier.push(leaf);
+/// }
+///
+/// assert_eq!(frontier.root().unwrap(), MerkleTree::new(&leaves).unwrap().root());
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MerkleFrontier {
+    /// The root of the complete subtree at each height, if bit `height` of
+    /// the leaf count is set.
+    subtrees: Vec<Option<Hash>>,
+    leaf_count: usize,
+    config: Config,
+    empty_subtrees: Vec<Hash>,
+}
+
+impl MerkleFrontier {
+    /// Create an empty frontier with the default options.
+    pub fn new() -> MerkleFrontier {
+        MerkleFrontier::default()
+    }
+
+    /// Append a leaf and return the new root hash.  Complete subtrees of the
+    /// same height are merged, like carrying when incrementing a binary
+    /// counter.
+    ///
+    /// O(log n)
+    pub fn push(&mut self, leaf: Hash) -> Hash {
+        let mut carry = leaf;
+        let mut height = 0;
+
+        while let Some(Some(subtree)) = self.subtrees.get_mut(height).map(Option::take) {
+            carry = self.config.combine(&subtree, &carry);
+            height += 1;
+        }
+
+        if height == self.subtrees.len() {
+            self.subtrees.push(None);
+        }
+
+        self.subtrees[height] = Some(carry);
+        self.leaf_count += 1;
+
+        let depth = self.depth();
+        if self.config.padding.uses_empty_subtrees() && self.empty_subtrees.len() <= depth {
+            self.empty_subtrees = self.config.empty_subtrees(depth);
+        }
+
+        self.calculate_root()
+    }
+
+    /// Return the hash root of the tree, which has to hold at least one leaf.
+    ///
+    /// O(log n)
+    pub fn root(&self) -> Result<Hash> {
+        if self.leaf_count == 0 {
+            return Err(MerkleTreeError::Empty);
+        }
+
+        Ok(self.calculate_root())
+    }
+
+    /// Return the number of leaves appended so far.
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// The number of levels above the leaves.
+    fn depth(&self) -> usize {
+        self.leaf_count.next_power_of_two().max(2).ilog2() as usize
+    }
+
+    /// The parent of the partial subtree at `height`, which is the last node
+    /// of its level and has no complete subtree to its left, so it's padded.
+    fn pad_parent(&self, last: &Hash, height: usize) -> Hash {
+        match self.config.padding_for(&self.empty_subtrees, last, height) {
+            Some(sibling) => self.config.combine(last, &sibling),
+            None => *last,
+        }
+    }
+
+    /// Fold the subtrees from the lowest up, padding the partial subtree at
+    /// every height that has no complete subtree to its left.
+    fn calculate_root(&self) -> Hash {
+        let depth = self.depth();
+        let mut subtrees = self
+            .subtrees
+            .iter()
+            .enumerate()
+            .filter_map(|(height, subtree)| Some((height, subtree.as_ref()?)));
+
+        let (mut height, mut root) = match subtrees.next() {
+            Some((height, subtree)) => (height, *subtree),
+            None => return EMPTY_NODE,
+        };
+
+        for (subtree_height, subtree) in subtrees {
+            while height < subtree_height {
+                root = self.pad_parent(&root, height);
+                height += 1;
+            }
+
+            root = self.config.combine(subtree, &root);
+            height += 1;
+        }
+
+        while height < depth {
+            root = self.pad_parent(&root, height);
+            height += 1;
+        }
+
+        root
+    }
+}
+
+impl TryFrom<&MerkleTree> for MerkleFrontier {
+    type Error = MerkleTreeError;
+
+    /// Take the frontier of an existing tree, e.g. to keep appending to it
+    /// without holding every node.  Fails with `CannotTakeFrontier` for a
+    /// tree of a fixed depth, which a frontier can't grow past, or padded
+    /// with a strategy that refuses to pad, since a frontier pads every tree
+    /// it passes through.
+    fn try_from(tree: &MerkleTree) -> Result<MerkleFrontier> {
+        let config = tree.config;
+
+        if let Some(depth) = config.depth {
+            return Err(MerkleTreeError::CannotTakeFrontier(format!(
+                "the tree has a fixed depth of {depth}"
+            )));
+        }
+
+        if config.padding.check(1, 2).is_err() {
+            return Err(MerkleTreeError::CannotTakeFrontier(format!(
+                "{:?} doesn't pad trees",
+                config.padding
+            )));
+        }
+
+        let leaf_count = tree.leaf_count;
+        let subtrees = (0..usize::BITS - leaf_count.leading_zeros())
+            .map(|height| {
+                let height = height as usize;
+                ((leaf_count >> height) & 1 == 1)
+                    .then(|| *tree.node_at_height(height, (leaf_count >> height) - 1))
+            })
+            .collect();
+
+        Ok(MerkleFrontier {
+            subtrees,
+            leaf_count,
+            config,
+            empty_subtrees: tree.empty_subtrees.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Hasher;
+    use crate::padding::{PromoteOdd, RequirePowerOfTwo, ZeroHash};
+
+    fn leaves() -> Vec<Hash> {
+        (0..21u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn matches_the_root_of_a_merkle_tree() {
+        let leaves = leaves();
+        let mut frontier = MerkleFrontier::new();
+        assert!(frontier.root().is_err());
+
+        for count in 1..=leaves.len() {
+            let root = frontier.push(leaves[count - 1]);
+            let expected = MerkleTree::new(&leaves[0..count]).unwrap();
+
+            assert_eq!(root, expected.root());
+            assert_eq!(frontier.root().unwrap(), expected.root());
+            assert_eq!(frontier.leaf_count(), count);
+            assert!(frontier.subtrees.len() <= count.ilog2() as usize + 1);
+        }
+    }
+
+    #[test]
+    fn continues_from_the_frontier_of_a_tree() {
+        let leaves = leaves();
+
+        for count in 1..leaves.len() {
+            let tree = MerkleTree::new(&leaves[0..count]).unwrap();
+            let mut frontier = MerkleFrontier::try_from(&tree).unwrap();
+            assert_eq!(frontier.root().unwrap(), tree.root());
+
+            for leaf in &leaves[count..] {
+                frontier.push(*leaf);
+            }
+
+            assert_eq!(
+                frontier.root().unwrap(),
+                MerkleTree::new(&leaves).unwrap().root()
+            );
+        }
+    }
+
+    #[test]
+    fn continues_with_the_options_of_a_tree() {
+        let leaves = leaves();
+        let builders = [
+            MerkleTree::builder().hasher(Hasher::KECCAK_256),
+            MerkleTree::builder().sorted_pairs(true),
+            MerkleTree::builder().padding(&ZeroHash),
+            MerkleTree::builder().padding(&PromoteOdd),
+        ];
+
+        for builder in builders {
+            for count in 1..leaves.len() {
+                let tree = builder.clone().build(&leaves[0..count]).unwrap();
+                let mut frontier = MerkleFrontier::try_from(&tree).unwrap();
+                assert_eq!(frontier.root().unwrap(), tree.root());
+
+                for (pushed, leaf) in leaves.iter().enumerate().skip(count) {
+                    let root = frontier.push(*leaf);
+                    let expected = builder.clone().build(&leaves[0..=pushed]).unwrap();
+                    assert_eq!(root, expected.root());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_options_it_cant_grow_with() {
+        let leaves = leaves();
+        let fixed_depth = MerkleTree::builder().depth(8).build(&leaves).unwrap();
+        let full = MerkleTree::builder()
+            .padding(&RequirePowerOfTwo)
+            .build(&leaves[0..16])
+            .unwrap();
+
+        assert!(matches!(
+            MerkleFrontier::try_from(&fixed_depth),
+            Err(MerkleTreeError::CannotTakeFrontier(_))
+        ));
+        assert!(matches!(
+            MerkleFrontier::try_from(&full),
+            Err(MerkleTreeError::CannotTakeFrontier(_))
+        ));
+    }
+}
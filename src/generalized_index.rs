@@ -0,0 +1,176 @@
+//! Proofs addressed by SSZ generalized indices.
+//!
+//! A generalized index numbers every node of the tree: the root is `1` and the
+//! children of node `g` are `2g` and `2g + 1`.  Leaves of a tree with `2^d`
+//! (padded) leaves are therefore `2^d..2^(d + 1)`.  This is the flattened
+//! layout shifted by one, so both leaf and internal positions can be proven
+//! with the same API.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::{Hash, MerkleTree, Proof};
+use std::borrow::Borrow;
+
+impl MerkleTree {
+    /// Get the generalized index of the leaf at the given offset.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// assert_eq!(tree.generalized_index(2), 6);
+    /// ```
+    pub fn generalized_index(&self, offset: usize) -> usize {
+        self.get_index_from_offset(offset) + 1
+    }
+
+    /// Get the hash of the node at a generalized index.
+    pub fn node_at_generalized_index(&self, generalized_index: usize) -> Result<Hash> {
+        let index = self.index_from_generalized_index(generalized_index)?;
+        Ok(self.nodes[index])
+    }
+
+    /// Generate a Merkle Proof for the node at a generalized index, which may
+    /// be a leaf or an internal node.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// // the parent of the first two leaves
+    /// let node = tree.node_at_generalized_index(2).unwrap();
+    /// let proof = tree.proof_at_generalized_index(2).unwrap();
+    /// assert!(tree.verify(&proof, &node));
+    /// ```
+    pub fn proof_at_generalized_index(&self, generalized_index: usize) -> Result<Proof<'_>> {
+        let index = self.index_from_generalized_index(generalized_index)?;
+        Ok(self.proof_from_index(index))
+    }
+
+    /// Verify an SSZ-style branch (sibling hashes only, leaf first) for a node
+    /// at a generalized index.  The side of each sibling is derived from the
+    /// bits of the generalized index.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let generalized_index = tree.generalized_index(2);
+    /// let branch = tree
+    ///     .proof_at_generalized_index(generalized_index)
+    ///     .unwrap()
+    ///     .into_iter()
+    ///     .map(|(_, hash)| hash)
+    ///     .collect::<Vec<_>>();
+    ///
+    /// assert!(MerkleTree::verify_generalized_index(&tree.root(), generalized_index, &leaves[2], &branch));
+    /// ```
+    pub fn verify_generalized_index<H: Borrow<Hash>>(
+        root: &Hash,
+        generalized_index: usize,
+        node: &Hash,
+        branch: &[H],
+    ) -> bool {
+        // the branch must contain exactly one sibling per level below the root
+        if generalized_index == 0 || branch.len() != generalized_index.ilog2() as usize {
+            return false;
+        }
+
+        let mut current_hash = *node;
+
+        for (level, hash) in branch.iter().enumerate() {
+            current_hash = if (generalized_index >> level) & 1 == 1 {
+                Self::concat(hash.borrow(), &current_hash)
+            } else {
+                Self::concat(&current_hash, hash.borrow())
+            };
+        }
+
+        current_hash == *root
+    }
+
+    /// Convert a generalized index to an array index, checking that it refers
+    /// to a node derived from real leaves rather than padding.
+    fn index_from_generalized_index(&self, generalized_index: usize) -> Result<usize> {
+        if generalized_index == 0 || generalized_index > self.nodes.len() {
+            return Err(MerkleTreeError::InvalidGeneralizedIndex(generalized_index));
+        }
+
+        let index = generalized_index - 1;
+        let level = Self::get_level(index);
+
+        if index - Self::get_level_start(level) >= self.level_width(level) {
+            return Err(MerkleTreeError::InvalidGeneralizedIndex(generalized_index));
+        }
+
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Direction, MerkleTree};
+
+    #[test]
+    fn proves_leaves_and_internal_nodes_by_generalized_index() {
+        let leaves = (0..8u8).map(|i| MerkleTree::hash(&[i])).collect::<Vec<_>>();
+        let tree = MerkleTree::new(&leaves).unwrap();
+
+        for generalized_index in 1..16 {
+            let node = tree.node_at_generalized_index(generalized_index).unwrap();
+            let proof = tree.proof_at_generalized_index(generalized_index).unwrap();
+            let branch = proof.iter().map(|(_, hash)| *hash).collect::<Vec<_>>();
+
+            assert!(tree.verify(&proof, &node));
+            assert!(MerkleTree::verify_generalized_index(
+                &tree.root(),
+                generalized_index,
+                &node,
+                &branch
+            ));
+        }
+
+        // generalized index 5 is the right child of the left child of the root
+        let proof = tree.proof_at_generalized_index(5).unwrap();
+        assert_eq!(proof[0].0, Direction::Left);
+        assert_eq!(proof[1].0, Direction::Right);
+        assert_eq!(tree.generalized_index(3), 11);
+    }
+
+    #[test]
+    fn rejects_invalid_generalized_indices() {
+        let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+        let tree = MerkleTree::new(&leaves).unwrap();
+        let branch = [leaves[0]];
+
+        assert!(tree.proof_at_generalized_index(0).is_err());
+        assert!(tree.proof_at_generalized_index(4).is_err());
+
+        assert!(MerkleTree::verify_generalized_index(
+            &tree.root(),
+            3,
+            &leaves[1],
+            &branch
+        ));
+        assert!(!MerkleTree::verify_generalized_index(
+            &tree.root(),
+            2,
+            &leaves[1],
+            &branch
+        ));
+        assert!(!MerkleTree::verify_generalized_index(
+            &tree.root(),
+            6,
+            &leaves[1],
+            &branch
+        ));
+
+        // generalized index 7 is padding in a tree of three leaves
+        let tree = MerkleTree::new(&[leaves[0], leaves[1], leaves[0]]).unwrap();
+        assert!(tree.proof_at_generalized_index(6).is_ok());
+        assert!(tree.proof_at_generalized_index(7).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+//! Notify listeners whenever a mutation produces a new root.
+
+use crate::{Hash, MerkleTree};
+use std::fmt;
+
+type Listener = Box<dyn FnMut(&Hash, &Hash, usize) + Send + Sync>;
+
+/// The listeners registered with `MerkleTree::on_root_change()`.
+#[derive(Default)]
+pub(crate) struct RootListeners(Vec<Listener>);
+
+impl RootListeners {
+    pub(crate) fn notify(&mut self, old_root: &Hash, new_root: &Hash, version: usize) {
+        for listener in self.0.iter_mut() {
+            listener(old_root, new_root, version);
+        }
+    }
+}
+
+/// Listeners belong to the tree they were registered with, so a clone of a
+/// tree starts without any.
+impl Clone for RootListeners {
+    fn clone(&self) -> RootListeners {
+        RootListeners::default()
+    }
+}
+
+impl fmt::Debug for RootListeners {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RootListeners({})", self.0.len())
+    }
+}
+
+impl MerkleTree {
+    /// Register a listener that is called with the old root, the new root and
+    /// the new version after every successful mutation, so new roots can be
+    /// published without polling.  Failed mutations don't call listeners.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    /// use std::sync::mpsc;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let mut tree = MerkleTree::new(&leaves).unwrap();
+    /// let (sender, receiver) = mpsc::channel();
+    ///
+    /// tree.on_root_change(move |_old_root, new_root, version| {
+    ///     sender.send((*new_root, version)).unwrap();
+    /// });
+    ///
+    /// tree.push(MerkleTree::hash(b"c")).unwrap();
+    /// assert_eq!(receiver.try_recv().unwrap(), (tree.root(), 1));
+    /// ```
+    pub fn on_root_change<F>(&mut self, listener: F)
+    where
+        F: FnMut(&Hash, &Hash, usize) + Send + Sync + 'static,
+    {
+        self.root_listeners.0.push(Box::new(listener));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Hash, MerkleTree};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn notifies_listeners_of_every_new_root() {
+        let leaves = (0..4u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let mut tree = MerkleTree::new(&leaves[0..2]).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        for _ in 0..2 {
+            let events = events.clone();
+            tree.on_root_change(move |old_root, new_root, version| {
+                events.lock().unwrap().push((*old_root, *new_root, version));
+            });
+        }
+
+        tree.push(leaves[2]).unwrap();
+        tree.update(0, leaves[3]).unwrap();
+        assert!(tree.update(5, leaves[3]).is_err());
+
+        let expected = [
+            (tree.root_at(0).unwrap(), tree.root_at(1).unwrap(), 1),
+            (tree.root_at(1).unwrap(), tree.root_at(2).unwrap(), 2),
+        ];
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], events[1]);
+        assert_eq!([events[0], events[2]], expected);
+    }
+}
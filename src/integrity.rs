@@ -0,0 +1,211 @@
+//! Export and load the flattened node layout, and check and repair trees
+//! whose internal nodes may have been corrupted, e.g. after being loaded from
+//! external storage.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::{DuplicatePolicy, Hash, MerkleTree, EMPTY_NODE};
+
+impl MerkleTree {
+    /// The flattened node layout of the tree, root first, including padding.
+    pub fn nodes(&self) -> &[Hash] {
+        &self.nodes
+    }
+
+    /// Load a tree from a node layout previously exported with `nodes()`,
+    /// without rehashing it.  The number of nodes must match `leaf_count`.
+    /// With `verify`, the layout is also checked with `integrity_check()`,
+    /// which should be done for nodes from untrusted storage.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// let loaded = MerkleTree::from_nodes(tree.nodes().to_vec(), 3, true).unwrap();
+    /// assert_eq!(loaded.root(), tree.root());
+    /// ```
+    pub fn from_nodes(nodes: Vec<Hash>, leaf_count: usize, verify: bool) -> Result<MerkleTree> {
+        if leaf_count == 0 {
+            return Err(MerkleTreeError::Empty);
+        }
+
+        let expected = leaf_count.next_power_of_two().max(2) * 2 - 1;
+
+        if nodes.len() != expected {
+            return Err(MerkleTreeError::InvalidEncoding(format!(
+                "expected {expected} nodes for {leaf_count} leaves, found {}",
+                nodes.len()
+            )));
+        }
+
+        let tree = Self::from_parts(nodes, leaf_count, DuplicatePolicy::default());
+
+        if verify {
+            tree.integrity_check()?;
+        }
+
+        Ok(tree)
+    }
+
+    /// Recalculate every internal node from the current leaves and reset any
+    /// padding slots, returning the root hash.  A new version is only
+    /// recorded if the root changed.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let mut tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// assert_eq!(tree.rehash(), MerkleTree::new(&leaves).unwrap().root());
+    /// assert_eq!(tree.version(), 0);
+    /// ```
+    ///
+    /// O(n)
+    pub fn rehash(&mut self) -> Hash {
+        let old_root = self.root();
+
+        for level in 0..=self.num_levels() {
+            let start = Self::get_level_start(level);
+            let width = self.level_width(level);
+            self.nodes[start + width..Self::get_level_start(level + 1)].fill(EMPTY_NODE);
+        }
+
+        self.recompute(0..self.leaf_count);
+
+        if self.root() != old_root {
+            self.record_root();
+        }
+
+        self.root()
+    }
+
+    /// Check that every internal node is the hash of its children, that
+    /// padding matches the tree's padding strategy and that unused slots are
+    /// empty.  The index of the first inconsistent node, from the leaves
+    /// up, is returned as `InconsistentNode`.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// assert!(tree.integrity_check().is_ok());
+    /// ```
+    ///
+    /// O(n)
+    pub fn integrity_check(&self) -> Result<()> {
+        let num_levels = self.num_levels();
+
+        for level in (0..=num_levels).rev() {
+            let start = Self::get_level_start(level);
+            let width = self.level_width(level);
+
+            for offset in 0..1 << level {
+                let index = start + offset;
+
+                let expected = if offset < width {
+                    // leaves can hold any value
+                    if level == num_levels {
+                        continue;
+                    }
+
+                    if offset * 2 + 1 == self.level_width(level + 1) {
+                        self.pad_parent(&self.nodes[index * 2 + 1], num_levels - level - 1)
+                    } else {
+                        self.config
+                            .combine(&self.nodes[index * 2 + 1], &self.nodes[index * 2 + 2])
+                    }
+                } else if offset == width && !width.is_multiple_of(2) {
+                    self.padding_for(&self.nodes[index - 1], num_levels - level)
+                        .unwrap_or(EMPTY_NODE)
+                } else {
+                    EMPTY_NODE
+                };
+
+                if self.nodes[index] != expected {
+                    return Err(MerkleTreeError::InconsistentNode(index));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::MerkleTreeError;
+    use crate::{Hash, MerkleTree};
+
+    #[test]
+    fn repairs_corrupted_nodes() {
+        let leaves = (0..11u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let expected = MerkleTree::new(&leaves).unwrap();
+        let mut tree = MerkleTree::new(&leaves).unwrap();
+
+        for index in 0..tree.nodes.len() - tree.num_leaves() {
+            tree.nodes[index] = MerkleTree::hash(&index.to_le_bytes());
+        }
+
+        // corrupt a padding leaf too
+        let last = tree.nodes.len() - 1;
+        tree.nodes[last] = MerkleTree::hash(b"z");
+
+        assert_eq!(tree.rehash(), expected.root());
+        assert_eq!(tree.nodes, expected.nodes);
+        assert_eq!(tree.version(), 1);
+    }
+
+    #[test]
+    fn loads_exported_nodes() {
+        let leaves = (0..11u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let tree = MerkleTree::new(&leaves).unwrap();
+        let mut nodes = tree.nodes().to_vec();
+
+        let loaded = MerkleTree::from_nodes(nodes.clone(), 11, true).unwrap();
+        assert_eq!(loaded.nodes, tree.nodes);
+        assert_eq!(loaded.leaf_count, 11);
+
+        assert!(MerkleTree::from_nodes(nodes.clone(), 8, true).is_err());
+        assert!(MerkleTree::from_nodes(nodes.clone(), 17, true).is_err());
+        assert!(MerkleTree::from_nodes(Vec::new(), 0, false).is_err());
+
+        nodes[3] = MerkleTree::hash(b"z");
+        assert!(MerkleTree::from_nodes(nodes.clone(), 11, true).is_err());
+        assert!(MerkleTree::from_nodes(nodes, 11, false).is_ok());
+    }
+
+    #[test]
+    fn finds_the_first_inconsistent_node() {
+        let leaves = (0..11u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let mut tree = MerkleTree::new(&leaves).unwrap();
+        assert!(tree.integrity_check().is_ok());
+
+        // the duplicate of the last leaf, then the parent of the first leaves
+        for index in [26, 7] {
+            tree.rehash();
+            tree.nodes[index] = MerkleTree::hash(b"z");
+            assert!(matches!(
+                tree.integrity_check(),
+                Err(MerkleTreeError::InconsistentNode(inconsistent)) if inconsistent == index
+            ));
+        }
+
+        // changing a leaf makes its parent inconsistent
+        tree.rehash();
+        tree.nodes[15] = MerkleTree::hash(b"z");
+        assert!(matches!(
+            tree.integrity_check(),
+            Err(MerkleTreeError::InconsistentNode(7))
+        ));
+    }
+}
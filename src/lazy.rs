@@ -0,0 +1,193 @@
+//! Defer recalculating branches until a root or proof is needed.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::replication::Mutation;
+use crate::{Hash, MerkleTree, Proof};
+
+/// A tree whose `update()` only writes the leaf and marks its branch dirty.
+/// Dirty branches are recalculated the next time the root or a proof is
+/// requested, so a burst of updates to nearby leaves recalculates each
+/// shared parent once.
+///
+/// ```rust
+/// use merkle_tree::{lazy::LazyMerkleTree, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+/// let mut tree = LazyMerkleTree::new(&leaves).unwrap();
+/// let (x, y) = (MerkleTree::hash(b"x"), MerkleTree::hash(b"y"));
+///
+/// tree.update(0, x).unwrap();
+/// tree.update(1, y).unwrap();
+/// assert!(tree.is_dirty());
+///
+/// assert_eq!(tree.root(), MerkleTree::new(&[x, y, leaves[2]]).unwrap().root());
+/// assert!(!tree.is_dirty());
+/// ```
+#[derive(Debug)]
+pub struct LazyMerkleTree {
+    tree: MerkleTree,
+    dirty: Vec<usize>,
+}
+
+impl LazyMerkleTree {
+    /// Create a new lazy tree.  Seed with all of the leaves.
+    pub fn new(leaves: &[Hash]) -> Result<LazyMerkleTree> {
+        Ok(LazyMerkleTree::from(MerkleTree::new(leaves)?))
+    }
+
+    /// Update the value of an existing leaf without recalculating its branch.
+    ///
+    /// O(1), or O(n) with the `Reject` duplicate policy
+    pub fn update(&mut self, offset: usize, value: Hash) -> Result<()> {
+        let tree = &mut self.tree;
+
+        if offset >= tree.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, tree.leaf_count));
+        }
+
+        tree.ensure_unique(&value, Some(offset))?;
+
+        // the root isn't recorded until the flush, so the index of leaves
+        // must follow the change here
+        let mut lookup = std::mem::take(&mut tree.lookup);
+
+        let position = tree.get_index_from_offset(offset);
+        let old = std::mem::replace(&mut tree.nodes[position], value);
+        self.dirty.push(offset);
+
+        lookup.replace(tree.leaves(), offset, Some(&old), value);
+        tree.lookup = lookup;
+
+        Ok(())
+    }
+
+    /// Whether any updates are waiting to be applied to their branches.
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Recalculate every dirty branch, visiting each affected node exactly
+    /// once.  The tree's root history records one root per flush.
+    pub fn flush(&mut self) {
+        if self.is_dirty() {
+            let dirty = std::mem::take(&mut self.dirty);
+            let tree = &mut self.tree;
+
+            if tree.log.is_some() {
+                let leaves = tree.leaves();
+                let updates = dirty
+                    .iter()
+                    .map(|offset| (*offset, leaves[*offset]))
+                    .collect();
+                tree.log_mutation(|| Mutation::UpdateBatch(updates));
+            }
+
+            tree.recompute_offsets(dirty);
+            tree.record_root();
+        }
+    }
+
+    /// Return the hash root of the tree, recalculating dirty branches first.
+    pub fn root(&mut self) -> Hash {
+        self.tree().root()
+    }
+
+    /// Generate a Merkle Proof for the first occurrence of a leaf,
+    /// recalculating dirty branches first.
+    pub fn proof(&mut self, leaf: &Hash) -> Result<Proof<'_>> {
+        self.tree().proof(leaf)
+    }
+
+    /// Generate a Merkle Proof for the leaf at a given offset, recalculating
+    /// dirty branches first.
+    pub fn proof_at(&mut self, offset: usize) -> Result<Proof<'_>> {
+        self.tree().proof_at(offset)
+    }
+
+    /// Borrow the underlying tree, recalculating dirty branches first.
+    pub fn tree(&mut self) -> &MerkleTree {
+        self.flush();
+        &self.tree
+    }
+
+    /// Take the underlying tree, recalculating dirty branches first.
+    pub fn into_inner(mut self) -> MerkleTree {
+        self.flush();
+        self.tree
+    }
+}
+
+impl From<MerkleTree> for LazyMerkleTree {
+    fn from(tree: MerkleTree) -> LazyMerkleTree {
+        LazyMerkleTree {
+            tree,
+            dirty: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DuplicatePolicy;
+
+    fn leaves() -> Vec<Hash> {
+        (0..13u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn recalculates_dirty_branches_on_demand() {
+        let leaves = leaves();
+        let mut tree = LazyMerkleTree::new(&leaves).unwrap();
+        let mut expected = leaves.clone();
+
+        for offset in [12, 3, 2, 3, 7] {
+            let value = MerkleTree::hash(&[offset as u8, 0xff]);
+            tree.update(offset, value).unwrap();
+            expected[offset] = value;
+        }
+
+        assert!(tree.update(13, leaves[0]).is_err());
+
+        let proof = tree.proof_at(12).unwrap();
+        let expected = MerkleTree::new(&expected).unwrap();
+        assert!(expected.verify(&proof, &MerkleTree::hash(&[12, 0xff])));
+
+        let tree = tree.into_inner();
+        assert_eq!(tree.nodes, expected.nodes);
+        assert_eq!(tree.version(), 1);
+    }
+
+    #[test]
+    fn honors_the_duplicate_policy() {
+        let leaves = leaves();
+        let tree = MerkleTree::new_with_policy(&leaves, DuplicatePolicy::Reject).unwrap();
+        let mut tree = LazyMerkleTree::from(tree);
+
+        assert!(tree.update(0, leaves[1]).is_err());
+        assert!(!tree.is_dirty());
+        assert_eq!(tree.root(), MerkleTree::new(&leaves).unwrap().root());
+    }
+
+    #[test]
+    fn checks_duplicates_against_unflushed_updates() {
+        let leaves = leaves();
+        let x = MerkleTree::hash(b"x");
+        let tree = MerkleTree::new_with_policy(&leaves, DuplicatePolicy::Reject).unwrap();
+        let mut tree = LazyMerkleTree::from(tree);
+
+        // index the leaves before the updates
+        assert!(tree.tree().contains(&leaves[0]));
+
+        tree.update(0, x).unwrap();
+        assert!(tree.update(1, x).is_err());
+        assert!(tree.update(2, leaves[0]).is_ok());
+        assert!(tree.update(3, leaves[0]).is_err());
+        assert!(tree.is_dirty());
+
+        let tree = tree.into_inner();
+        assert_eq!(tree.leaf_index(&x).unwrap(), 0);
+        assert_eq!(tree.leaf_index(&leaves[0]).unwrap(), 2);
+        assert!(!tree.contains(&leaves[2]));
+    }
+}
@@ -1,22 +1,169 @@
+pub mod anti_entropy;
+#[cfg(feature = "rkyv")]
+pub mod archive;
+#[cfg(feature = "ed25519")]
+pub mod attestation;
+#[cfg(feature = "bincode")]
+pub mod bincode;
+pub mod builder;
+pub mod bytes;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+pub mod chained;
+#[cfg(feature = "json")]
+pub mod claims;
+pub mod data;
+mod diff;
 pub mod error;
+pub mod file;
+pub mod forest;
+pub mod frontier;
+mod generalized_index;
+mod hooks;
+mod integrity;
+pub mod lazy;
+pub mod lines;
+mod lookup;
+pub mod manifest;
+pub mod map;
+#[cfg(feature = "json")]
+pub mod merkletreejs;
+pub mod metadata;
+mod multi_buffer;
+#[cfg(feature = "json")]
+pub mod openzeppelin;
+pub mod ots;
+pub mod out_of_core;
+pub mod padding;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod persistent;
+pub mod progress;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+#[cfg(feature = "rekor")]
+pub mod rekor;
+mod render;
+pub mod replication;
+#[cfg(feature = "rfc6962")]
+pub mod rfc6962;
+#[cfg(feature = "rocksdb")]
+pub mod rocksdb;
+#[cfg(feature = "cose")]
+pub mod scitt;
+#[cfg(feature = "serde")]
+pub mod serialization;
+pub mod set;
+mod shard;
+#[cfg(feature = "sled")]
+pub mod sled;
+pub mod snapshot;
+#[cfg(feature = "ssz")]
+pub mod ssz;
+pub mod stats;
+#[cfg(feature = "sth")]
+pub mod sth;
+pub mod storage;
+pub mod subscription;
+mod trace;
+pub mod transaction;
+mod traversal;
+pub mod versioned;
+#[cfg(feature = "mmap")]
+pub mod view;
+pub mod wal;
+#[cfg(any(feature = "bytemuck", feature = "zerocopy"))]
+pub mod zero_copy;
 
 use error::{MerkleTreeError, Result};
+use replication::Mutation;
 use sha3::{Digest, Sha3_256};
+use smallvec::SmallVec;
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::ops::{Index, Range};
+
+/// A Merkle Tree stored as a flattened binary tree.
+///
+/// The nodes are kept in one `Vec<Hash>` by default, or in any
+/// `storage::NodeStore`, e.g. a database for trees larger than RAM, see the
+/// `storage` module.
+///
+/// Cloning a tree copies its nodes, options, root history and mutation log,
+/// but not the listeners, subscriptions or write-ahead log registered with
+/// it.  Trees are equal when they hold the same nodes, regardless of their
+/// history.
+#[derive(Debug, Clone)]
+pub struct MerkleTree<S = Vec<Hash>> {
+    nodes: S,
+    leaf_count: usize,
+    duplicate_policy: DuplicatePolicy,
+    config: builder::Config,
+    empty_subtrees: Vec<Hash>,
+    sorted_positions: Option<Vec<usize>>,
+    removed_duplicates: Option<Vec<usize>>,
+    root_history: Vec<Hash>,
+    root_listeners: hooks::RootListeners,
+    subscribers: subscription::Subscribers,
+    log: Option<Vec<Mutation>>,
+    lookup: lookup::LeafLookup,
+    wal: wal::AttachedLog,
+}
+
+impl PartialEq for MerkleTree {
+    fn eq(&self, other: &MerkleTree) -> bool {
+        self.leaf_count == other.leaf_count && self.nodes == other.nodes
+    }
+}
+
+impl Eq for MerkleTree {}
+
+/// Index the leaves of the tree by offset.  Panics if the offset is past the
+/// last leaf, use `get()` to check.
+impl Index<usize> for MerkleTree {
+    type Output = Hash;
+
+    fn index(&self, offset: usize) -> &Hash {
+        &self.leaves()[offset]
+    }
+}
 
-#[derive(Debug)]
-pub struct MerkleTree(Vec<Hash>);
 pub type Hash = [u8; 32];
 pub type Proof<'a> = Vec<(Direction, &'a Hash)>;
+pub type OwnedProof = Vec<(Direction, Hash)>;
+/// An owned proof kept inline for trees of up to 2^32 leaves, so generating
+/// one with `inline_proof_at()` doesn't allocate.
+pub type InlineProof = SmallVec<[(Direction, Hash); 32]>;
 
-#[derive(Debug, PartialEq)]
+/// Placeholder for slots of the flattened layout that aren't part of the tree.
+const EMPTY_NODE: Hash = [0; 32];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Left,
     Right,
 }
 
+/// How the tree treats leaves that share the same hash.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DuplicatePolicy {
+    /// Allow duplicates.  Value-based lookups resolve to the first match.
+    #[default]
+    FirstMatch,
+
+    /// Refuse to hold duplicate leaves, both at construction and on update.
+    Reject,
+
+    /// Allow duplicates, but value-based lookups of a duplicated leaf error so
+    /// that callers must use the offset-based APIs (e.g. `proof_at()`).
+    RequireIndex,
+}
+
 impl MerkleTree {
-    /// Create a new MerkleTree.  Seed with all of the leaves. If there are an
-    /// odd number of leaves, duplicate the last leaf and append to the vec.
+    /// Create a new MerkleTree.  Seed with all of the leaves. If any level of
+    /// the tree has an odd number of nodes, the last node is paired with a
+    /// duplicate of itself.
     ///
     /// ```rust
     /// use merkle_tree::MerkleTree;
@@ -25,38 +172,124 @@ impl MerkleTree {
     /// let tree = MerkleTree::new(&leaves).unwrap();
     /// ```
     pub fn new(leaves: &[Hash]) -> Result<MerkleTree> {
-        if leaves.is_empty() {
-            return Err(MerkleTreeError::Empty);
-        }
+        Self::builder().build(leaves)
+    }
 
-        // take ownership of leaves
-        let mut nodes = leaves.to_owned();
+    /// Create a new MerkleTree from raw items, hashing each item into a leaf.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let tree = MerkleTree::from_data(["a", "b", "c"]).unwrap();
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    ///
+    /// assert_eq!(tree.root(), MerkleTree::new(&leaves).unwrap().root());
+    /// ```
+    pub fn from_data<I>(items: I) -> Result<MerkleTree>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        Self::builder().build_from_data(items)
+    }
 
-        // There are an odd number of leaves.  Duplicate the last leaf and
-        // append to the vec.
-        if nodes.len() % 2 == 1 {
-            let last_leaf = nodes[nodes.len() - 1];
-            nodes.extend_from_slice(&[last_leaf]);
-        }
+    /// Create a new MerkleTree from the leaves yielded by an iterator, e.g. at
+    /// the end of an iterator pipeline.  This is the fallible equivalent of
+    /// `collect()`, since a tree can't be empty.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let tree = MerkleTree::try_from_iter(["a", "b"].iter().map(|item| MerkleTree::hash(item.as_bytes()))).unwrap();
+    /// assert_eq!(tree.root(), MerkleTree::from_data(["a", "b"]).unwrap().root());
+    ///
+    /// assert!(MerkleTree::try_from_iter(std::iter::empty()).is_err());
+    /// ```
+    pub fn try_from_iter<I: IntoIterator<Item = Hash>>(leaves: I) -> Result<MerkleTree> {
+        Self::new(&leaves.into_iter().collect::<Vec<Hash>>())
+    }
 
-        let depth = Self::num_levels_from_leaves(&nodes);
-        let mut last_index = nodes.len();
+    /// Create a new MerkleTree with node storage allocated for at least
+    /// `capacity` leaves, so that pushing up to that many leaves doesn't
+    /// reallocate.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let tree = MerkleTree::with_capacity(&leaves, 1000).unwrap();
+    ///
+    /// assert_eq!(tree.capacity(), 1024);
+    /// ```
+    pub fn with_capacity(leaves: &[Hash], capacity: usize) -> Result<MerkleTree> {
+        Self::builder().capacity(capacity).build(leaves)
+    }
 
-        // Add the concatenated branches to the end of the vec.  We can avoid
-        // recursion since we can derive the number of levels in the tree.
-        //
-        // O(log n)
-        for _i in 0..depth {
-            let combined = nodes[0..last_index]
-                .chunks_exact(2)
-                .map(|chunk| Self::concat(&chunk[0], &chunk[1]))
-                .collect::<Vec<Hash>>();
+    /// Create a new MerkleTree from hex encoded leaves, with or without a
+    /// `0x` prefix.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let tree = MerkleTree::from_hex_leaves([hex::encode(leaves[0]), format!("0x{}", hex::encode(leaves[1]))]).unwrap();
+    ///
+    /// assert_eq!(tree, MerkleTree::new(&leaves).unwrap());
+    /// ```
+    pub fn from_hex_leaves<I>(leaves: I) -> Result<MerkleTree>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let leaves = leaves
+            .into_iter()
+            .map(|leaf| Self::hash_from_hex(leaf.as_ref()))
+            .collect::<Result<Vec<Hash>>>()?;
 
-            last_index = combined.len();
-            nodes = [combined, nodes].concat();
-        }
+        Self::new(&leaves)
+    }
+
+    /// Create a new MerkleTree with an explicit policy for duplicate leaves.
+    ///
+    /// ```rust
+    /// use merkle_tree::{DuplicatePolicy, MerkleTree};
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"a")];
+    /// let tree = MerkleTree::new_with_policy(&leaves, DuplicatePolicy::Reject);
+    /// assert!(tree.is_err());
+    /// ```
+    pub fn new_with_policy(
+        leaves: &[Hash],
+        duplicate_policy: DuplicatePolicy,
+    ) -> Result<MerkleTree> {
+        Self::builder()
+            .duplicate_policy(duplicate_policy)
+            .build(leaves)
+    }
 
-        Ok(MerkleTree(nodes))
+    /// Assemble a tree from an already calculated flattened layout.
+    pub(crate) fn from_parts(
+        nodes: Vec<Hash>,
+        leaf_count: usize,
+        duplicate_policy: DuplicatePolicy,
+    ) -> MerkleTree {
+        let root_history = vec![nodes[0]];
+
+        MerkleTree {
+            nodes,
+            leaf_count,
+            duplicate_policy,
+            config: builder::Config::default(),
+            empty_subtrees: Vec::new(),
+            sorted_positions: None,
+            removed_duplicates: None,
+            root_history,
+            root_listeners: hooks::RootListeners::default(),
+            subscribers: subscription::Subscribers::default(),
+            log: None,
+            lookup: lookup::LeafLookup::default(),
+            wal: wal::AttachedLog::default(),
+        }
     }
 
     /// Update the value of an existing leaf and recalculate the root hash
@@ -84,34 +317,786 @@ impl MerkleTree {
     /// assert!(tree.verify(&proof, &new_leaf));
     /// ```
     pub fn update(&mut self, offset: usize, value: Hash) -> Result<()> {
-        if offset > self.num_leaves() - 1 {
-            return Err(MerkleTreeError::OffsetOutOfBounds(
-                offset,
-                self.num_leaves(),
-            ));
+        if offset >= self.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, self.leaf_count));
         }
 
-        let mut position = self.get_index_from_offset(offset);
-        let mut hash = value;
+        self.ensure_unique(&value, Some(offset))?;
 
-        // update the leaf's value
-        self.0[position] = hash;
+        // the index of leaves follows the change, rather than being rebuilt
+        let mut lookup = std::mem::take(&mut self.lookup);
 
-        // recalculate the hashes of the leaf's branch
-        while position > 0 {
-            hash = if position % 2 == 0 {
-                Self::concat(&self.0[position - 1], &hash)
-            } else {
-                Self::concat(&hash, &self.0[position])
+        // update the leaf's value and recalculate the hashes of its branch
+        let position = self.get_index_from_offset(offset);
+        let old = std::mem::replace(&mut self.nodes[position], value);
+        self.recompute(offset..offset + 1);
+        self.log_mutation(|| Mutation::Update { offset, value });
+        self.record_root();
+
+        lookup.replace(self.leaves(), offset, Some(&old), value);
+        self.lookup = lookup;
+
+        Ok(())
+    }
+
+    /// Update the value of an existing leaf, found by its current value rather
+    /// than its offset, and return the offset of the updated leaf.  If the
+    /// value isn't a leaf, `CannotFindLeaf` is returned.  If the value appears
+    /// more than once, the duplicate policy decides: `FirstMatch` updates the
+    /// first occurrence and `RequireIndex` returns `AmbiguousLeaf`.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let mut tree = MerkleTree::new(&leaves).unwrap();
+    /// let new_leaf = MerkleTree::hash(b"c");
+    ///
+    /// assert_eq!(tree.update_by_value(&leaves[1], new_leaf).unwrap(), 1);
+    /// assert!(tree.update_by_value(&leaves[1], new_leaf).is_err());
+    /// ```
+    pub fn update_by_value(&mut self, old_leaf: &Hash, new_leaf: Hash) -> Result<usize> {
+        let offset = self.leaf_index(old_leaf)?;
+        self.update(offset, new_leaf)?;
+
+        Ok(offset)
+    }
+
+    /// Update the values of many existing leaves at once.  Each affected node
+    /// is recalculated exactly once, no matter how many updated leaves share
+    /// it.  No leaves are changed if any of the offsets are out of bounds.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let mut tree = MerkleTree::new(&leaves).unwrap();
+    /// let (x, y) = (MerkleTree::hash(b"x"), MerkleTree::hash(b"y"));
+    /// tree.update_batch(&[(0, x), (2, y)]).unwrap();
+    ///
+    /// assert_eq!(tree.root(), MerkleTree::new(&[x, leaves[1], y]).unwrap().root());
+    /// ```
+    pub fn update_batch(&mut self, updates: &[(usize, Hash)]) -> Result<()> {
+        let _timed = trace::operation("update_batch", updates.len());
+
+        if let Some((offset, _)) = updates
+            .iter()
+            .find(|(offset, _)| *offset >= self.leaf_count)
+        {
+            return Err(MerkleTreeError::OffsetOutOfBounds(*offset, self.leaf_count));
+        }
+
+        if self.duplicate_policy == DuplicatePolicy::Reject {
+            let mut leaves = self.leaves().to_vec();
+
+            for (offset, value) in updates.iter() {
+                leaves[*offset] = *value;
+            }
+
+            if let Some(duplicate) = Self::find_duplicate(leaves.iter()) {
+                return Err(MerkleTreeError::DuplicateLeaf(hex::encode(duplicate)));
+            }
+        }
+
+        for (offset, value) in updates.iter() {
+            let position = self.get_index_from_offset(*offset);
+            self.nodes[position] = *value;
+        }
+
+        self.recompute_offsets(updates.iter().map(|(offset, _)| *offset).collect());
+        self.log_mutation(|| Mutation::UpdateBatch(updates.to_vec()));
+        self.record_root();
+
+        Ok(())
+    }
+
+    /// Exchange the values of two leaves.  The branches of both leaves are
+    /// recalculated in a single pass, so shared ancestors are hashed once.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let mut tree = MerkleTree::new(&leaves).unwrap();
+    /// tree.swap(0, 2).unwrap();
+    ///
+    /// assert_eq!(tree.root(), MerkleTree::new(&[leaves[2], leaves[1], leaves[0]]).unwrap().root());
+    /// ```
+    pub fn swap(&mut self, offset1: usize, offset2: usize) -> Result<()> {
+        if let Some(offset) = [offset1, offset2]
+            .into_iter()
+            .find(|offset| *offset >= self.leaf_count)
+        {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, self.leaf_count));
+        }
+
+        let position1 = self.get_index_from_offset(offset1);
+        let position2 = self.get_index_from_offset(offset2);
+        self.nodes.swap(position1, position2);
+        self.recompute_offsets(vec![offset1, offset2]);
+        self.log_mutation(|| Mutation::Swap(offset1, offset2));
+        self.record_root();
+
+        Ok(())
+    }
+
+    /// Append a leaf to the end of the tree and return the new root hash.
+    /// Only the branch of the new leaf is recalculated, so this is O(log n),
+    /// except when the tree is full and the storage needs to double.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let mut tree = MerkleTree::new(&leaves[0..1]).unwrap();
+    /// let root = tree.push(leaves[1]).unwrap();
+    ///
+    /// assert_eq!(root, MerkleTree::new(&leaves).unwrap().root());
+    /// ```
+    pub fn push(&mut self, leaf: Hash) -> Result<Hash> {
+        self.ensure_unique(&leaf, None)?;
+        self.make_room(self.leaf_count + 1)?;
+
+        // the index of leaves follows the change, rather than being rebuilt
+        let mut lookup = std::mem::take(&mut self.lookup);
+
+        let position = self.get_index_from_offset(self.leaf_count);
+        self.leaf_count += 1;
+        self.nodes[position] = leaf;
+        self.recompute(self.leaf_count - 1..self.leaf_count);
+        self.log_mutation(|| Mutation::Push(leaf));
+        self.record_root();
+
+        lookup.replace(self.leaves(), self.leaf_count - 1, None, leaf);
+        self.lookup = lookup;
+
+        Ok(self.root())
+    }
+
+    /// Remove the last leaf of the tree and return it.  This undoes a `push()`,
+    /// including any duplication of the new last node on odd levels.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let mut tree = MerkleTree::new(&leaves[0..2]).unwrap();
+    /// let old_root = tree.root();
+    ///
+    /// tree.push(leaves[2]).unwrap();
+    /// assert_eq!(tree.pop().unwrap(), leaves[2]);
+    /// assert_eq!(tree.root(), old_root);
+    /// ```
+    pub fn pop(&mut self) -> Result<Hash> {
+        if self.leaf_count == 1 {
+            return Err(MerkleTreeError::CannotRemoveLastLeaf);
+        }
+
+        self.config.padded_count(self.leaf_count - 1)?;
+
+        let leaf = self.nodes[self.get_index_from_offset(self.leaf_count - 1)];
+        self.truncate_leaves(self.leaf_count - 1);
+        self.recompute(self.leaf_count - 1..self.leaf_count);
+        self.log_mutation(|| Mutation::Pop);
+        self.record_root();
+
+        Ok(leaf)
+    }
+
+    /// Append many leaves to the end of the tree and return the new root hash.
+    /// Branches shared by the new leaves are recalculated only once, so this
+    /// is O(k + log n) for k leaves, plus any doubling of the storage.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let mut tree = MerkleTree::new(&leaves[0..1]).unwrap();
+    /// let root = tree.extend(&leaves[1..]).unwrap();
+    ///
+    /// assert_eq!(root, MerkleTree::new(&leaves).unwrap().root());
+    /// ```
+    pub fn extend(&mut self, leaves: &[Hash]) -> Result<Hash> {
+        let _timed = trace::operation("extend", leaves.len());
+
+        if self.duplicate_policy == DuplicatePolicy::Reject {
+            if let Some(duplicate) = Self::find_duplicate(self.leaves().iter().chain(leaves)) {
+                return Err(MerkleTreeError::DuplicateLeaf(hex::encode(duplicate)));
+            }
+        }
+
+        let start = self.leaf_count;
+        let end = start + leaves.len();
+        self.make_room(end)?;
+
+        let position = self.get_index_from_offset(start);
+        self.nodes[position..position + leaves.len()].copy_from_slice(leaves);
+        self.leaf_count = end;
+        self.recompute(start..end);
+        self.log_mutation(|| Mutation::Extend(leaves.to_vec()));
+        self.record_root();
+
+        Ok(self.root())
+    }
+
+    /// Insert a leaf at the given offset, shifting all subsequent leaves to
+    /// the right, and return the new root hash.  Every branch above the
+    /// shifted leaves is recalculated, so this is O(n - offset).
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let mut tree = MerkleTree::new(&[leaves[0], leaves[2]]).unwrap();
+    /// let root = tree.insert(1, leaves[1]).unwrap();
+    ///
+    /// assert_eq!(root, MerkleTree::new(&leaves).unwrap().root());
+    /// ```
+    pub fn insert(&mut self, offset: usize, leaf: Hash) -> Result<Hash> {
+        if offset > self.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, self.leaf_count));
+        }
+
+        self.ensure_unique(&leaf, None)?;
+        self.make_room(self.leaf_count + 1)?;
+
+        let position = self.get_index_from_offset(offset);
+        let end = self.get_index_from_offset(self.leaf_count);
+        self.nodes.copy_within(position..end, position + 1);
+        self.nodes[position] = leaf;
+        self.leaf_count += 1;
+        self.recompute(offset..self.leaf_count);
+        self.log_mutation(|| Mutation::Insert { offset, leaf });
+        self.record_root();
+
+        Ok(self.root())
+    }
+
+    /// Remove the leaf at the given offset and return it.  Removal shifts all
+    /// subsequent leaves to the left rather than leaving a hole, so offsets
+    /// past the removed leaf decrease by one and the tree is identical to one
+    /// created without the leaf.  Every branch above the shifted leaves is
+    /// recalculated, so this is O(n - offset).
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let mut tree = MerkleTree::new(&leaves).unwrap();
+    /// let removed = tree.remove(1).unwrap();
+    ///
+    /// assert_eq!(removed, leaves[1]);
+    /// assert_eq!(tree.root(), MerkleTree::new(&[leaves[0], leaves[2]]).unwrap().root());
+    /// ```
+    pub fn remove(&mut self, offset: usize) -> Result<Hash> {
+        if offset >= self.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, self.leaf_count));
+        }
+
+        if self.leaf_count == 1 {
+            return Err(MerkleTreeError::CannotRemoveLastLeaf);
+        }
+
+        self.config.padded_count(self.leaf_count - 1)?;
+
+        let position = self.get_index_from_offset(offset);
+        let end = self.get_index_from_offset(self.leaf_count);
+        let leaf = self.nodes[position];
+
+        self.nodes.copy_within(position + 1..end, position);
+        self.truncate_leaves(self.leaf_count - 1);
+        self.recompute(offset.min(self.leaf_count - 1)..self.leaf_count);
+        self.log_mutation(|| Mutation::Remove(offset));
+        self.record_root();
+
+        Ok(leaf)
+    }
+
+    /// Shrink the tree to the first `leaf_count` leaves and return the new root
+    /// hash, which is the same root the tree had at that leaf count.  Only the
+    /// branch of the new last leaf is recalculated.  Truncating to a count
+    /// greater than or equal to the current one has no effect.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let mut tree = MerkleTree::new(&leaves[0..2]).unwrap();
+    /// let old_root = tree.root();
+    ///
+    /// tree.push(leaves[2]).unwrap();
+    /// assert_eq!(tree.truncate(2).unwrap(), old_root);
+    /// ```
+    pub fn truncate(&mut self, leaf_count: usize) -> Result<Hash> {
+        if leaf_count == 0 {
+            return Err(MerkleTreeError::CannotRemoveLastLeaf);
+        }
+
+        if leaf_count < self.leaf_count {
+            self.config.padded_count(leaf_count)?;
+            self.truncate_leaves(leaf_count);
+            self.recompute(leaf_count - 1..leaf_count);
+            self.log_mutation(|| Mutation::Truncate(leaf_count));
+            self.record_root();
+        }
+
+        Ok(self.root())
+    }
+
+    /// Replace every leaf of the tree and return the new root hash.  The
+    /// existing node allocation is reused, so rebuilding a tree of the same
+    /// (or a smaller) size does not allocate.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let mut tree = MerkleTree::new(&leaves[0..2]).unwrap();
+    ///
+    /// let root = tree.rebuild(&leaves[1..3]).unwrap();
+    /// assert_eq!(root, MerkleTree::new(&leaves[1..3]).unwrap().root());
+    /// ```
+    ///
+    /// O(n)
+    pub fn rebuild(&mut self, leaves: &[Hash]) -> Result<Hash> {
+        let _timed = trace::operation("rebuild", leaves.len());
+
+        if leaves.is_empty() {
+            return Err(MerkleTreeError::Empty);
+        }
+
+        if self.duplicate_policy == DuplicatePolicy::Reject {
+            if let Some(duplicate) = Self::find_duplicate(leaves.iter()) {
+                return Err(MerkleTreeError::DuplicateLeaf(hex::encode(duplicate)));
+            }
+        }
+
+        let padded_count = self.config.padded_count(leaves.len())?;
+        let level_start = padded_count - 1;
+
+        self.nodes.clear();
+        self.nodes.resize(padded_count * 2 - 1, EMPTY_NODE);
+        self.nodes[level_start..level_start + leaves.len()].copy_from_slice(leaves);
+        self.leaf_count = leaves.len();
+        self.recompute(0..self.leaf_count);
+        self.log_mutation(|| Mutation::Rebuild(leaves.to_vec()));
+        self.record_root();
+
+        Ok(self.root())
+    }
+
+    /// Recalculate the hashes of every branch above a range of leaf offsets,
+    /// visiting each affected node exactly once.  If the last node of a level
+    /// with an odd number of nodes is affected, it is paired with the padding
+    /// the tree was built with.
+    ///
+    /// O(log n + k) for a range of k leaves
+    pub(crate) fn recompute(&mut self, offsets: Range<usize>) {
+        let Range { mut start, mut end } = offsets;
+
+        for level in (1..=self.num_levels()).rev() {
+            if start >= end {
+                break;
+            }
+
+            let level_start = Self::get_level_start(level);
+            let parent_start = Self::get_level_start(level - 1);
+            let width = self.level_width(level);
+
+            let height = self.num_levels() - level;
+
+            if !width.is_multiple_of(2) && end == width {
+                self.nodes[level_start + width] = self
+                    .padding_for(&self.nodes[level_start + width - 1], height)
+                    .unwrap_or(EMPTY_NODE);
+            }
+
+            start /= 2;
+            end = end.div_ceil(2);
+
+            // the last parent of a level with an odd number of nodes is padded
+            let paired = start..end.min(width / 2);
+            self.combine_pairs(level, paired.clone());
+
+            if end > paired.end {
+                let parent = paired.end;
+                self.nodes[parent_start + parent] =
+                    self.pad_parent(&self.nodes[level_start + parent * 2], height);
+            }
+        }
+    }
+
+    /// Hash each parent in a range of offsets from its two children, which
+    /// are at `level`.  With the `parallel` feature, ranges of at least the
+    /// parallel threshold are hashed on the rayon thread pool.
+    fn combine_pairs(&mut self, level: usize, parents: Range<usize>) {
+        #[cfg(feature = "parallel")]
+        if parents.len() >= self.config.parallel_threshold {
+            return self.combine_pairs_parallel(level, parents);
+        }
+
+        let level_start = Self::get_level_start(level);
+        let parent_start = Self::get_level_start(level - 1);
+
+        // parents come before their children in the layout
+        let (above, below) = self.nodes.split_at_mut(level_start);
+        self.config.combine_all(
+            &below[parents.start * 2..parents.end * 2],
+            &mut above[parent_start + parents.start..parent_start + parents.end],
+        );
+    }
+
+    /// Add levels above the root until there's room for `leaf_count` leaves,
+    /// or error without changing the tree if it has a fixed depth that is
+    /// too small.
+    pub(crate) fn make_room(&mut self, leaf_count: usize) -> Result<()> {
+        let padded_count = self.config.padded_count(leaf_count)?;
+
+        while self.num_leaves() < padded_count {
+            self.grow();
+        }
+
+        Ok(())
+    }
+
+    /// Double the leaf capacity of the tree by moving every level down one
+    /// level, making the current root the left child of a new root.  Levels
+    /// are moved in place, so storage is only reallocated if it wasn't
+    /// reserved up front.
+    ///
+    /// O(n)
+    fn grow(&mut self) {
+        let num_levels = self.num_levels();
+        self.nodes.resize(self.nodes.len() * 2 + 1, EMPTY_NODE);
+
+        // move the lowest level first, which only overwrites levels that have
+        // already been moved
+        for level in (0..=num_levels).rev() {
+            let start = Self::get_level_start(level);
+            let new_start = Self::get_level_start(level + 1);
+            let width = 1 << level;
+
+            self.nodes.copy_within(start..start + width, new_start);
+            self.nodes[new_start + width..new_start + width * 2].fill(EMPTY_NODE);
+        }
+
+        self.nodes[0] = EMPTY_NODE;
+
+        if self.config.padding.uses_empty_subtrees() {
+            self.empty_subtrees = self.config.empty_subtrees(self.num_levels());
+        }
+    }
+
+    /// Recalculate the hashes of every branch above a set of leaf offsets that
+    /// aren't necessarily contiguous, visiting each affected node exactly once.
+    ///
+    /// O(k log n) for k offsets
+    fn recompute_offsets(&mut self, mut offsets: Vec<usize>) {
+        offsets.sort_unstable();
+        offsets.dedup();
+
+        for level in (1..=self.num_levels()).rev() {
+            let Some(last) = offsets.last() else {
+                break;
             };
 
-            position = Self::get_parent_index(position);
-            self.0[position] = hash;
+            let level_start = Self::get_level_start(level);
+            let parent_start = Self::get_level_start(level - 1);
+            let width = self.level_width(level);
+
+            let height = self.num_levels() - level;
+
+            if !width.is_multiple_of(2) && *last == width - 1 {
+                self.nodes[level_start + width] = self
+                    .padding_for(&self.nodes[level_start + width - 1], height)
+                    .unwrap_or(EMPTY_NODE);
+            }
+
+            // siblings share a parent, so the sorted offsets stay sorted
+            offsets.iter_mut().for_each(|offset| *offset /= 2);
+            offsets.dedup();
+
+            for parent in offsets.iter() {
+                let left = level_start + parent * 2;
+                self.nodes[parent_start + parent] = if parent * 2 + 1 == width {
+                    self.pad_parent(&self.nodes[left], height)
+                } else {
+                    self.config
+                        .combine(&self.nodes[left], &self.nodes[left + 1])
+                };
+            }
+        }
+    }
+
+    /// Drop the leaves past `leaf_count`, clearing their slots and the slots
+    /// of any nodes that no longer derive from real leaves, then remove the
+    /// levels above the root that are no longer needed.  The caller is
+    /// responsible for recalculating the branch of the new last leaf.
+    fn truncate_leaves(&mut self, leaf_count: usize) {
+        let num_levels = self.num_levels();
+
+        for level in 1..=num_levels {
+            let start = Self::get_level_start(level);
+            let old_width = self.level_width(level);
+            let new_width = leaf_count.div_ceil(1 << (num_levels - level));
+            let end = (old_width + 1).min(1 << level);
+
+            self.nodes[start + new_width..start + end].fill(EMPTY_NODE);
+        }
+
+        self.leaf_count = leaf_count;
+
+        // the root's right subtree is empty, so make its left child the root
+        let padded_count = self
+            .config
+            .padded_count(leaf_count)
+            .expect("fewer leaves fit");
+
+        while self.num_leaves() > padded_count {
+            self.shrink();
+        }
+    }
+
+    /// Halve the leaf capacity of the tree by moving every level up one
+    /// level, making the left child of the root the new root.
+    ///
+    /// O(n)
+    fn shrink(&mut self) {
+        for level in 0..self.num_levels() {
+            let start = Self::get_level_start(level);
+            let old_start = Self::get_level_start(level + 1);
+            let width = 1 << level;
+
+            self.nodes.copy_within(old_start..old_start + width, start);
+        }
+
+        self.nodes.truncate(self.nodes.len() / 2);
+    }
+
+    /// Find the first leaf that has already been seen.
+    ///
+    /// O(n)
+    pub(crate) fn find_duplicate<'a>(
+        mut leaves: impl Iterator<Item = &'a Hash>,
+    ) -> Option<&'a Hash> {
+        let mut seen = HashSet::new();
+        leaves.find(|leaf| !seen.insert(*leaf))
+    }
+
+    /// Error if the policy rejects duplicates and the value is already a leaf,
+    /// ignoring the leaf at `skip_offset`.
+    fn ensure_unique(&self, value: &Hash, skip_offset: Option<usize>) -> Result<()> {
+        if self.duplicate_policy != DuplicatePolicy::Reject {
+            return Ok(());
+        }
+
+        if let Some(occurrences) = self.lookup.find(self.leaves(), value) {
+            if occurrences.count > 1 || Some(occurrences.first) != skip_offset {
+                return Err(MerkleTreeError::DuplicateLeaf(hex::encode(value)));
+            }
         }
 
         Ok(())
     }
 
+    /// Return the leaf at the given offset, or `None` if the offset is past
+    /// the last leaf.  Padding is never returned.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// assert_eq!(tree.leaf(2), Some(leaves[2]));
+    /// assert_eq!(tree.leaf(3), None);
+    /// ```
+    pub fn leaf(&self, offset: usize) -> Option<Hash> {
+        self.leaves().get(offset).copied()
+    }
+
+    /// Return a reference to the leaf at the given offset, or `None` if the
+    /// offset is past the last leaf.  This is the non-panicking equivalent of
+    /// indexing the tree (`tree[offset]`).
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// assert_eq!(tree[2], leaves[2]);
+    /// assert_eq!(tree.get(2), Some(&leaves[2]));
+    /// assert_eq!(tree.get(3), None);
+    /// ```
+    pub fn get(&self, offset: usize) -> Option<&Hash> {
+        self.leaves().get(offset)
+    }
+
+    /// Whether a value is one of the leaves of the tree.  Padding never
+    /// matches.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// assert!(tree.contains(&leaves[1]));
+    /// assert!(!tree.contains(&MerkleTree::hash(b"c")));
+    /// ```
+    ///
+    /// O(1), after indexing the leaves once
+    pub fn contains(&self, leaf: &Hash) -> bool {
+        self.lookup.find(self.leaves(), leaf).is_some()
+    }
+
+    /// The number of leaves in the tree, excluding any padding.
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// The number of leaves in the tree, excluding any padding.  This is the
+    /// same as `leaf_count()`.
+    pub fn len(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Whether the tree has no leaves, which is never the case since a tree
+    /// can't be created or left without leaves.
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+
+    /// The number of nodes in the flattened layout, including padding.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The number of leaves the tree can hold without reallocating its node
+    /// storage.
+    pub fn capacity(&self) -> usize {
+        // a full tree of n leaves needs 2n - 1 nodes
+        let leaves = self.nodes.capacity().div_ceil(2);
+        1 << leaves.ilog2()
+    }
+
+    /// Allocate node storage for at least `additional` more leaves, so that
+    /// pushing up to that many leaves doesn't reallocate.  Growing the tree
+    /// past a power of two still moves the existing levels, but within the
+    /// reserved storage.  Fails with `CapacityOverflow`, leaving the tree
+    /// unchanged, if the storage can't be allocated.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let mut tree = MerkleTree::new(&[MerkleTree::hash(b"a")]).unwrap();
+    /// tree.reserve(100).unwrap();
+    ///
+    /// assert!(tree.capacity() >= 101);
+    /// ```
+    pub fn reserve(&mut self, additional: usize) -> Result<()> {
+        let leaf_count = self
+            .leaf_count
+            .checked_add(additional)
+            .ok_or(MerkleTreeError::CapacityOverflow(usize::MAX))?;
+
+        Self::reserve_nodes(&mut self.nodes, leaf_count)
+    }
+
+    /// Reserve node storage for a full tree of at least `leaf_count` leaves,
+    /// failing with `CapacityOverflow` rather than panicking or aborting if
+    /// that many nodes can't be allocated.
+    pub(crate) fn reserve_nodes(nodes: &mut Vec<Hash>, leaf_count: usize) -> Result<()> {
+        let node_count = leaf_count
+            .checked_next_power_of_two()
+            .and_then(|leaves| leaves.max(2).checked_mul(2))
+            .ok_or(MerkleTreeError::CapacityOverflow(leaf_count))?
+            - 1;
+
+        nodes
+            .try_reserve_exact(node_count.saturating_sub(nodes.len()))
+            .map_err(|_| MerkleTreeError::CapacityOverflow(leaf_count))
+    }
+
+    /// Release storage beyond what the tree needs, e.g. after truncating a
+    /// large tree or reserving more than was used.  Levels that are no longer
+    /// needed are removed as leaves are removed, so this releases the excess
+    /// capacity of the node storage, empty subtrees, root history and
+    /// mutation log.  Pushing after shrinking reallocates.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = (0..100u8).map(|i| MerkleTree::hash(&[i])).collect::<Vec<_>>();
+    /// let mut tree = MerkleTree::new(&leaves).unwrap();
+    /// tree.truncate(3).unwrap();
+    /// tree.shrink_to_fit();
+    ///
+    /// assert_eq!(tree.capacity(), 4);
+    /// assert_eq!(tree.stats().node_capacity, 7);
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+
+        if !self.empty_subtrees.is_empty() {
+            self.empty_subtrees.truncate(self.num_levels() + 1);
+            self.empty_subtrees.shrink_to_fit();
+        }
+
+        self.root_history.shrink_to_fit();
+
+        if let Some(log) = &mut self.log {
+            log.shrink_to_fit();
+        }
+    }
+
+    /// Whether padding was added because the number of leaves isn't a power
+    /// of two, so that the last node of some level is paired with padding
+    /// (by default, a duplicate of itself).
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    ///
+    /// assert!(!MerkleTree::new(&leaves[0..2]).unwrap().is_padded());
+    /// assert!(MerkleTree::new(&leaves).unwrap().is_padded());
+    /// ```
+    pub fn is_padded(&self) -> bool {
+        self.leaf_count != self.num_leaves()
+    }
+
+    /// Return the hash root of the tree as lowercase hex, without a `0x`
+    /// prefix.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let tree = MerkleTree::from_data(["a", "b"]).unwrap();
+    ///
+    /// assert_eq!(MerkleTree::hash_from_hex(&tree.root_hex()).unwrap(), tree.root());
+    /// ```
+    pub fn root_hex(&self) -> String {
+        hex::encode(self.root())
+    }
+
+    /// Whether two trees have the same root, which is a single comparison
+    /// rather than one of every node.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// assert!(tree.same_root(&tree.clone()));
+    /// assert!(!tree.same_root(&MerkleTree::new(&leaves[0..1]).unwrap()));
+    /// ```
+    pub fn same_root(&self, other: &MerkleTree) -> bool {
+        self.root() == other.root()
+    }
+
     /// Return the hash root of the tree.
     ///
     /// ```rust
@@ -123,29 +1108,110 @@ impl MerkleTree {
     /// assert_eq!(&tree.root(), expected);
     /// ```
     pub fn root(&self) -> Hash {
-        self.0[0]
+        self.nodes[0]
+    }
+
+    /// The current version of the tree.  The tree starts at version 0 and
+    /// every successful mutation produces a new version.
+    pub fn version(&self) -> usize {
+        self.root_history.len() - 1
+    }
+
+    /// Return the hash root the tree had at a given version, so that proofs
+    /// can be verified against historical roots with `verify_with_root()`.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let mut tree = MerkleTree::new(&leaves).unwrap();
+    /// let proof = tree
+    ///     .proof(&leaves[1])
+    ///     .unwrap()
+    ///     .into_iter()
+    ///     .map(|(direction, hash)| (direction, *hash))
+    ///     .collect::<Vec<_>>();
+    ///
+    /// tree.update(1, MerkleTree::hash(b"c")).unwrap();
+    /// assert_eq!(tree.version(), 1);
+    ///
+    /// let old_root = tree.root_at(0).unwrap();
+    /// assert!(MerkleTree::verify_with_root(&old_root, &proof, &leaves[1]));
+    /// assert!(!tree.verify(&proof, &leaves[1]));
+    /// ```
+    pub fn root_at(&self, version: usize) -> Option<Hash> {
+        self.root_history.get(version).copied()
+    }
+
+    /// Iterate over every `(version, root)` the tree has had, oldest first.
+    pub fn root_history(&self) -> impl Iterator<Item = (usize, Hash)> + '_ {
+        self.root_history.iter().copied().enumerate()
+    }
+
+    /// Record the current root as a new version and notify the listeners
+    /// registered with `on_root_change()` and the proof subscribers.  Every
+    /// mutation records a root, so the index of leaves is dropped here.
+    fn record_root(&mut self) {
+        self.lookup.clear();
+
+        let old_root = self.root_history[self.root_history.len() - 1];
+        self.root_history.push(self.root());
+        self.root_listeners
+            .notify(&old_root, &self.root(), self.version());
+        self.notify_subscribers();
     }
 
     /// Using the full size of the array, calculate the number of levels.
     pub fn num_levels(&self) -> usize {
-        Self::num_levels_from_leaves(&self.0)
+        Self::num_levels_from_leaves(&self.nodes)
     }
 
     /// Using the number of leaves, calculate the number of levels.
     /// This will always be an even number.
     /// This is zero-based, so a single level tree will have zero levels.
     pub fn num_levels_from_leaves(leaves: &[Hash]) -> usize {
-        (leaves.len() as f32).log2().floor() as usize
+        leaves.len().checked_ilog2().unwrap_or(0) as usize
     }
 
     /// Using the position of a leaf, calcualte the array index.
     pub fn get_index_from_offset(&self, offset: usize) -> usize {
-        self.0.len() - self.num_leaves() + offset
+        self.nodes.len() - self.num_leaves() + offset
     }
 
     /// Calculate the number of leaves in the tree from the number of levels.
     fn num_leaves(&self) -> usize {
-        2_usize.pow(self.num_levels() as u32)
+        1 << self.num_levels()
+    }
+
+    /// The leaves that were supplied by the caller, excluding any padding.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// assert_eq!(tree.leaves(), leaves);
+    /// ```
+    pub fn leaves(&self) -> &[Hash] {
+        let start = self.get_index_from_offset(0);
+        &self.nodes[start..start + self.leaf_count]
+    }
+
+    /// Get the level of the node at an array index.  The root is level 0.
+    pub fn get_level(index: usize) -> usize {
+        (index + 1).ilog2() as usize
+    }
+
+    /// Get the array index of the first node of a level.
+    pub fn get_level_start(level: usize) -> usize {
+        (1 << level) - 1
+    }
+
+    /// The number of nodes at a level that are derived from real leaves,
+    /// excluding padding.
+    fn level_width(&self, level: usize) -> usize {
+        self.leaf_count.div_ceil(1 << (self.num_levels() - level))
     }
 
     /// Get the array index of the parent node.
@@ -157,6 +1223,51 @@ impl MerkleTree {
         }
     }
 
+    /// Get the array index of the node sharing a parent with the node at an
+    /// array index.  The root has no sibling.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// assert_eq!(MerkleTree::sibling_index(3), Some(4));
+    /// assert_eq!(MerkleTree::sibling_index(4), Some(3));
+    /// assert_eq!(MerkleTree::sibling_index(0), None);
+    /// ```
+    pub fn sibling_index(index: usize) -> Option<usize> {
+        match index {
+            0 => None,
+            _ if index.is_multiple_of(2) => Some(index - 1),
+            _ => Some(index + 1),
+        }
+    }
+
+    /// Get the array indices of the nodes on the path from the leaf at an
+    /// offset up to the root, leaf first.  The nodes of a proof of the leaf
+    /// are the siblings of every index but the root's.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// assert_eq!(tree.path_indices(2).unwrap(), [5, 2, 0]);
+    /// ```
+    pub fn path_indices(&self, offset: usize) -> Result<Vec<usize>> {
+        if offset >= self.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, self.leaf_count));
+        }
+
+        let mut path = vec![self.get_index_from_offset(offset)];
+
+        // O(log n)
+        while path[path.len() - 1] > 0 {
+            path.push(Self::get_parent_index(path[path.len() - 1]));
+        }
+
+        Ok(path)
+    }
+
     /// Generate a Merkle Proof for a given leaf.
     ///
     /// ```rust
@@ -168,60 +1279,260 @@ impl MerkleTree {
     /// let proof = tree.proof(&leaf).unwrap();
     /// assert_eq!(proof, [(Direction::Left, &MerkleTree::hash(b"a"))]);
     /// ```
-    pub fn proof(&self, leaf: &Hash) -> Result<Proof> {
-        let mut proof = Proof::new();
+    pub fn proof(&self, leaf: &Hash) -> Result<Proof<'_>> {
+        let offset = self.leaf_index(leaf)?;
+        Ok(self.proof_from_index(self.get_index_from_offset(offset)))
+    }
 
-        // O(n)
-        // I tried out Rayon (par_iter().position_any()), but it was +69490%
-        // slower than this approach.
-        let mut position = self
-            .0
-            .iter()
-            .position(|current_leaf| *current_leaf == *leaf)
+    /// Find the offset of the first occurrence of a leaf by value, e.g. to
+    /// pass to `update()`.  With the `RequireIndex` duplicate policy, a leaf
+    /// that appears more than once returns `AmbiguousLeaf`.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// assert_eq!(tree.leaf_index(&leaves[1]).unwrap(), 1);
+    /// assert!(tree.leaf_index(&MerkleTree::hash(b"c")).is_err());
+    /// ```
+    pub fn leaf_index(&self, leaf: &Hash) -> Result<usize> {
+        // O(1), after indexing the leaves once (O(n)), since the index
+        // follows updates and pushes
+        let occurrences = self
+            .lookup
+            .find(self.leaves(), leaf)
             .ok_or_else(|| MerkleTreeError::CannotFindLeaf(hex::encode(leaf)))?;
 
-        // O(log n)
-        for _ in 0..self.num_levels() {
-            let corresponding_hash = if position % 2 == 0 {
-                (Direction::Left, &self.0[position - 1])
+        if self.duplicate_policy == DuplicatePolicy::RequireIndex && occurrences.count > 1 {
+            return Err(MerkleTreeError::AmbiguousLeaf(hex::encode(leaf)));
+        }
+
+        Ok(occurrences.first)
+    }
+
+    /// Find the offsets of every occurrence of a leaf by value, in ascending
+    /// order.  A value that isn't a leaf has no offsets.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"a")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// assert_eq!(tree.indices_of(&leaves[0]), [0, 2]);
+    /// ```
+    ///
+    /// O(n)
+    pub fn indices_of(&self, leaf: &Hash) -> Vec<usize> {
+        self.leaves()
+            .iter()
+            .enumerate()
+            .filter(|(_, current_leaf)| *current_leaf == leaf)
+            .map(|(offset, _)| offset)
+            .collect()
+    }
+
+    /// Generate a Merkle Proof for the leaf at a given offset.
+    ///
+    /// ```rust
+    /// use merkle_tree::{MerkleTree, Direction};
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let proof = tree.proof_at(1).unwrap();
+    /// assert_eq!(proof, [(Direction::Left, &MerkleTree::hash(b"a"))]);
+    /// ```
+    pub fn proof_at(&self, offset: usize) -> Result<Proof<'_>> {
+        if offset >= self.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, self.leaf_count));
+        }
+
+        Ok(self.proof_from_index(self.get_index_from_offset(offset)))
+    }
+
+    /// Generate a Merkle Proof for the leaf at a given offset without
+    /// allocating, copying the sibling hashes into an `InlineProof`, e.g. in
+    /// hot request paths.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let mut tree = MerkleTree::new(&leaves).unwrap();
+    /// let proof = tree.inline_proof_at(1).unwrap();
+    /// assert!(tree.verify(&proof, &leaves[1]));
+    ///
+    /// tree.update(0, MerkleTree::hash(b"c")).unwrap();
+    /// assert!(!tree.verify(&proof, &leaves[1]));
+    /// ```
+    pub fn inline_proof_at(&self, offset: usize) -> Result<InlineProof> {
+        if offset >= self.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, self.leaf_count));
+        }
+
+        let index = self.get_index_from_offset(offset);
+
+        Ok(self
+            .proof_path(index)
+            .map(|(direction, sibling)| (direction, self.nodes[sibling]))
+            .collect())
+    }
+
+    /// Generate a Merkle Proof for every occurrence of a given leaf, paired
+    /// with the offset of that occurrence.  Padding leaves are never included.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"a")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let proofs = tree.proof_all(&leaves[0]).unwrap();
+    /// assert_eq!(proofs.len(), 2);
+    /// assert_eq!(proofs[0].0, 0);
+    /// assert_eq!(proofs[1].0, 2);
+    /// assert!(tree.verify(&proofs[1].1, &leaves[0]));
+    /// ```
+    pub fn proof_all(&self, leaf: &Hash) -> Result<Vec<(usize, Proof<'_>)>> {
+        let proofs = self
+            .indices_of(leaf)
+            .into_iter()
+            .map(|offset| {
+                let proof = self.proof_from_index(self.get_index_from_offset(offset));
+                (offset, proof)
+            })
+            .collect::<Vec<_>>();
+
+        if proofs.is_empty() {
+            return Err(MerkleTreeError::CannotFindLeaf(hex::encode(leaf)));
+        }
+
+        Ok(proofs)
+    }
+
+    /// Walk from the node at the given array index up to the root, collecting
+    /// the sibling hashes along the way.  A node promoted past a level by its
+    /// padding strategy has no sibling at that level.
+    pub(crate) fn proof_from_index(&self, index: usize) -> Proof<'_> {
+        let _timed = trace::leaf_operation("proof", self.leaf_count);
+
+        let mut proof = Proof::with_capacity(self.num_levels());
+        proof.extend(
+            self.proof_path(index)
+                .map(|(direction, sibling)| (direction, &self.nodes[sibling])),
+        );
+
+        proof
+    }
+
+    /// The siblings on the path from the node at an array index up to the
+    /// root, as the direction and array index of each, see `path_siblings()`.
+    ///
+    /// O(log n)
+    pub(crate) fn proof_path(&self, index: usize) -> impl Iterator<Item = (Direction, usize)> + '_ {
+        Self::path_siblings(
+            &self.nodes,
+            self.leaf_count,
+            &self.config,
+            &self.empty_subtrees,
+            index,
+        )
+    }
+
+    /// The siblings on the path from the node at an array index of a layout
+    /// of `leaf_count` leaves up to the root, as the direction and array
+    /// index of each.  A padding sibling is skipped where the padding
+    /// strategy promotes the node instead.  Shared by trees and views of
+    /// tree files, so every proof is generated the same way.
+    pub(crate) fn path_siblings<'a>(
+        nodes: &'a [Hash],
+        leaf_count: usize,
+        config: &'a builder::Config,
+        empty_subtrees: &'a [Hash],
+        index: usize,
+    ) -> impl Iterator<Item = (Direction, usize)> + 'a {
+        let num_levels = Self::num_levels_from_leaves(nodes);
+        let mut position = index;
+
+        std::iter::from_fn(move || loop {
+            let sibling = Self::sibling_index(position)?;
+            let direction = if sibling < position {
+                Direction::Left
             } else {
-                (Direction::Right, &self.0[position + 1])
+                Direction::Right
             };
 
-            proof.push(corresponding_hash);
+            let level = Self::get_level(sibling);
+            let height = num_levels - level;
+            let width = leaf_count.div_ceil(1 << height);
+            let is_padding = sibling - Self::get_level_start(level) >= width;
+            let node = &nodes[position];
+
             position = Self::get_parent_index(position);
-        }
 
-        Ok(proof)
+            if !is_padding || config.padding_for(empty_subtrees, node, height).is_some() {
+                return Some((direction, sibling));
+            }
+        })
+    }
+
+    /// Verify a Merkle Proof for a given leaf.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let leaf = leaves[1];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let proof = tree.proof(&leaf).unwrap();
+    /// assert!(tree.verify(&proof, &leaf));
+    /// ```
+    ///
+    /// Both borrowed (`Proof`) and owned (`OwnedProof`) proofs are accepted.
+    pub fn verify<H: Borrow<Hash>>(&self, proof: &[(Direction, H)], leaf: &Hash) -> bool {
+        self.config.root_from_proof(proof, leaf) == self.root()
     }
 
-    /// Verify a Merkle Proof for a given leaf.
+    /// Verify a Merkle Proof for a given leaf against a root hash, without
+    /// needing the tree.  This assumes the default hashing, so proofs of
+    /// trees built with other options are verified with
+    /// `MerkleTreeBuilder::verify()`.
     ///
     /// ```rust
     /// use merkle_tree::MerkleTree;
     ///
     /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
-    /// let leaf = leaves[1];
     /// let tree = MerkleTree::new(&leaves).unwrap();
-    /// let proof = tree.proof(&leaf).unwrap();
-    /// assert!(tree.verify(&proof, &leaf));
+    /// let proof = tree.proof(&leaves[1]).unwrap();
+    /// assert!(MerkleTree::verify_with_root(&tree.root(), &proof, &leaves[1]));
     /// ```
-    pub fn verify(&self, proof: &Proof, leaf: &Hash) -> bool {
-        let root_hash = self.root();
-        let mut current_hash = *leaf;
-
-        for (hash_direction, hash) in proof.iter() {
-            current_hash = match hash_direction {
-                Direction::Left => Self::concat(hash, &current_hash),
-                Direction::Right => Self::concat(&current_hash, hash),
-            };
-        }
+    pub fn verify_with_root<H: Borrow<Hash>>(
+        root: &Hash,
+        proof: &[(Direction, H)],
+        leaf: &Hash,
+    ) -> bool {
+        Self::root_from_proof(proof, leaf) == *root
+    }
 
-        current_hash == root_hash
+    /// Calculate the root hash implied by a Merkle Proof for a given leaf.
+    /// The proof is valid if this is the root of the tree, so this can be
+    /// used to chain proofs where the root of one tree is a leaf of another.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let proof = tree.proof(&leaves[1]).unwrap();
+    /// assert_eq!(MerkleTree::root_from_proof(&proof, &leaves[1]), tree.root());
+    /// ```
+    pub fn root_from_proof<H: Borrow<Hash>>(proof: &[(Direction, H)], leaf: &Hash) -> Hash {
+        builder::Config::default().root_from_proof(proof, leaf)
     }
 
-    /// Hash a byte array.
-    /// TODO(ddimaria): Allow the user to select/inject the hash function
+    /// Hash a byte array with SHA3-256.  Use `MerkleTree::builder()` to
+    /// build a tree with a different hash function.
     ///
     /// ```rust
     /// use merkle_tree::MerkleTree;
@@ -233,17 +1544,40 @@ impl MerkleTree {
         Sha3_256::digest(data).into()
     }
 
-    /// Concatenate
-    pub fn concat(hash1: &Hash, hash2: &Hash) -> Hash {
-        let mut combined = [0; 64];
-        combined[..32].copy_from_slice(hash1);
-        combined[32..].copy_from_slice(hash2);
+    /// Decode a hash from 64 hex characters, with or without a `0x` prefix.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let hash = MerkleTree::hash(b"a");
+    ///
+    /// assert_eq!(MerkleTree::hash_from_hex(&format!("0x{}", hex::encode(hash))).unwrap(), hash);
+    /// assert!(MerkleTree::hash_from_hex("0x1234").is_err());
+    /// ```
+    pub fn hash_from_hex(hex: &str) -> Result<Hash> {
+        let digits = hex.strip_prefix("0x").unwrap_or(hex);
+        let mut hash = EMPTY_NODE;
+
+        hex::decode_to_slice(digits, &mut hash)
+            .map_err(|error| MerkleTreeError::InvalidHex(format!("{hex}: {error}")))?;
+
+        Ok(hash)
+    }
 
-        Self::hash(&combined)
+    /// Concatenate, hashing both hashes as one message without copying them
+    /// together first
+    pub fn concat(hash1: &Hash, hash2: &Hash) -> Hash {
+        Sha3_256::new()
+            .chain_update(hash1)
+            .chain_update(hash2)
+            .finalize()
+            .into()
     }
 }
 
 #[cfg(test)]
+// the original tests predate clippy being run over every target
+#[allow(clippy::needless_range_loop)]
 mod tests {
     use super::*;
 
@@ -309,6 +1643,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_borrow)]
     fn gets_the_root_hash_of_odd_leaves() {
         let leaves: &[Hash] = &leaves()[0..15];
         let tree = MerkleTree::new(&leaves).unwrap();
@@ -318,6 +1653,485 @@ mod tests {
         assert_eq!(tree.root(), root_hash(&even_leaves));
     }
 
+    #[test]
+    fn gets_the_root_hash_by_duplicating_the_last_node_of_odd_levels() {
+        let leaves = &leaves()[0..6];
+        let tree = MerkleTree::new(leaves).unwrap();
+        let left = MerkleTree::concat(
+            &MerkleTree::concat(&leaves[0], &leaves[1]),
+            &MerkleTree::concat(&leaves[2], &leaves[3]),
+        );
+        let right = MerkleTree::concat(&leaves[4], &leaves[5]);
+
+        assert_eq!(
+            tree.root(),
+            MerkleTree::concat(&left, &MerkleTree::concat(&right, &right))
+        );
+    }
+
+    #[test]
+    fn pushes_leaves_to_match_a_new_tree() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new(&leaves[0..1]).unwrap();
+
+        for count in 2..=leaves.len() {
+            let root = tree.push(leaves[count - 1]).unwrap();
+            let expected = MerkleTree::new(&leaves[0..count]).unwrap();
+
+            assert_eq!(root, expected.root());
+            assert_eq!(tree.num_levels(), expected.num_levels());
+
+            for leaf in leaves[0..count].iter() {
+                let proof = tree.proof(leaf).unwrap();
+                assert!(tree.verify(&proof, leaf));
+            }
+        }
+    }
+
+    #[test]
+    fn updates_a_batch_of_leaves() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new(&leaves[0..13]).unwrap();
+        let updates = [
+            (12, MerkleTree::hash(b"x")),
+            (0, MerkleTree::hash(b"y")),
+            (5, MerkleTree::hash(b"z")),
+            (4, MerkleTree::hash(b"w")),
+            (5, MerkleTree::hash(b"v")),
+        ];
+        tree.update_batch(&updates).unwrap();
+
+        let mut expected = leaves[0..13].to_vec();
+        for (offset, value) in updates.iter() {
+            expected[*offset] = *value;
+        }
+        assert_eq!(tree.nodes, MerkleTree::new(&expected).unwrap().nodes);
+
+        // nothing is applied when an offset is out of bounds
+        let root = tree.root();
+        assert!(tree
+            .update_batch(&[(1, leaves[0]), (13, leaves[0])])
+            .is_err());
+        assert_eq!(tree.root(), root);
+    }
+
+    #[test]
+    fn updates_a_leaf_by_value() {
+        let mut leaves = leaves();
+        let new_leaf = MerkleTree::hash(b"z");
+        let mut tree = MerkleTree::new(&leaves).unwrap();
+
+        assert_eq!(tree.update_by_value(&leaves[6], new_leaf).unwrap(), 6);
+        assert!(tree.verify(&tree.proof_at(6).unwrap(), &new_leaf));
+        assert!(matches!(
+            tree.update_by_value(&leaves[6], new_leaf),
+            Err(MerkleTreeError::CannotFindLeaf(_))
+        ));
+
+        leaves[9] = leaves[2];
+        let mut tree = MerkleTree::new_with_policy(&leaves, DuplicatePolicy::RequireIndex).unwrap();
+        assert!(matches!(
+            tree.update_by_value(&leaves[2], new_leaf),
+            Err(MerkleTreeError::AmbiguousLeaf(_))
+        ));
+    }
+
+    #[test]
+    fn records_the_root_of_every_version() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new(&leaves[0..4]).unwrap();
+        let mut expected = vec![tree.root()];
+
+        tree.push(leaves[4]).unwrap();
+        expected.push(tree.root());
+        tree.update(0, leaves[5]).unwrap();
+        expected.push(tree.root());
+        tree.swap(0, 1).unwrap();
+        expected.push(tree.root());
+        tree.truncate(10).unwrap();
+        assert!(tree.update(10, leaves[5]).is_err());
+
+        assert_eq!(tree.version(), 3);
+        assert_eq!(
+            tree.root_at(0),
+            Some(MerkleTree::new(&leaves[0..4]).unwrap().root())
+        );
+        assert_eq!(tree.root_at(4), None);
+        assert_eq!(
+            tree.root_history().collect::<Vec<_>>(),
+            expected.into_iter().enumerate().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn pops_leaves_to_undo_pushes() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new(&leaves[0..1]).unwrap();
+        let mut snapshots = vec![tree.nodes.clone()];
+
+        for leaf in leaves[1..].iter() {
+            tree.push(*leaf).unwrap();
+            snapshots.push(tree.nodes.clone());
+        }
+
+        for count in (1..leaves.len()).rev() {
+            snapshots.pop();
+            assert_eq!(tree.pop().unwrap(), leaves[count]);
+            assert_eq!(Some(&tree.nodes), snapshots.last());
+        }
+
+        assert!(tree.pop().is_err());
+    }
+
+    #[test]
+    fn swaps_two_leaves() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new(&leaves[0..11]).unwrap();
+        tree.swap(1, 10).unwrap();
+        tree.swap(4, 4).unwrap();
+
+        let mut expected = leaves[0..11].to_vec();
+        expected.swap(1, 10);
+        assert_eq!(tree.nodes, MerkleTree::new(&expected).unwrap().nodes);
+
+        assert!(tree.swap(0, 11).is_err());
+        assert!(tree.swap(11, 0).is_err());
+    }
+
+    #[test]
+    fn extends_leaves_to_match_a_new_tree() {
+        let leaves = leaves();
+
+        for start in 1..leaves.len() {
+            for end in start..=leaves.len() {
+                let mut tree = MerkleTree::new(&leaves[0..start]).unwrap();
+                let root = tree.extend(&leaves[start..end]).unwrap();
+                let expected = MerkleTree::new(&leaves[0..end]).unwrap();
+
+                assert_eq!(root, expected.root());
+                assert_eq!(tree.nodes, expected.nodes);
+            }
+        }
+
+        let mut tree = MerkleTree::new_with_policy(&leaves[0..4], DuplicatePolicy::Reject).unwrap();
+        assert!(tree.extend(&[leaves[5], leaves[5]]).is_err());
+        assert!(tree.extend(&[leaves[5], leaves[0]]).is_err());
+        assert!(tree.extend(&[leaves[5], leaves[6]]).is_ok());
+    }
+
+    #[test]
+    fn inserts_leaves_to_match_a_new_tree() {
+        let leaves = leaves();
+
+        for offset in 0..=9 {
+            let mut tree = MerkleTree::new(&leaves[0..9]).unwrap();
+            let new_leaf = MerkleTree::hash(b"z");
+            let root = tree.insert(offset, new_leaf).unwrap();
+
+            let mut expected = leaves[0..9].to_vec();
+            expected.insert(offset, new_leaf);
+            assert_eq!(root, MerkleTree::new(&expected).unwrap().root());
+
+            let proof = tree.proof_at(offset).unwrap();
+            assert!(tree.verify(&proof, &new_leaf));
+        }
+
+        let mut tree = MerkleTree::new(&leaves).unwrap();
+        assert!(tree.insert(17, leaves[0]).is_err());
+    }
+
+    #[test]
+    fn removes_leaves_to_match_a_new_tree() {
+        let leaves = leaves();
+
+        for offset in 0..9 {
+            let mut tree = MerkleTree::new(&leaves[0..9]).unwrap();
+            assert_eq!(tree.remove(offset).unwrap(), leaves[offset]);
+
+            let mut expected = leaves[0..9].to_vec();
+            expected.remove(offset);
+            let expected = MerkleTree::new(&expected).unwrap();
+            assert_eq!(tree.root(), expected.root());
+            assert_eq!(tree.num_levels(), expected.num_levels());
+        }
+
+        let mut tree = MerkleTree::new(&leaves).unwrap();
+
+        for count in (1..leaves.len()).rev() {
+            tree.remove(count).unwrap();
+
+            let expected = MerkleTree::new(&leaves[0..count]).unwrap();
+            assert_eq!(tree.root(), expected.root());
+            assert_eq!(tree.num_levels(), expected.num_levels());
+
+            for leaf in leaves[0..count].iter() {
+                let proof = tree.proof(leaf).unwrap();
+                assert!(tree.verify(&proof, leaf));
+            }
+        }
+
+        assert!(tree.remove(1).is_err());
+        assert!(matches!(
+            tree.remove(0),
+            Err(MerkleTreeError::CannotRemoveLastLeaf)
+        ));
+    }
+
+    #[test]
+    fn truncates_leaves_to_match_a_new_tree() {
+        let leaves = leaves();
+
+        for count in 1..=leaves.len() {
+            let mut tree = MerkleTree::new(&leaves).unwrap();
+            let root = tree.truncate(count).unwrap();
+            let expected = MerkleTree::new(&leaves[0..count]).unwrap();
+
+            assert_eq!(root, expected.root());
+            assert_eq!(tree.nodes, expected.nodes);
+            assert_eq!(tree.leaf_count, count);
+        }
+
+        let mut tree = MerkleTree::new(&leaves).unwrap();
+        assert!(tree.truncate(0).is_err());
+        assert_eq!(tree.truncate(20).unwrap(), root_hash(&leaves));
+    }
+
+    #[test]
+    fn gets_leaves_by_offset() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new(&leaves[0..5]).unwrap();
+
+        for (offset, leaf) in leaves[0..5].iter().enumerate() {
+            assert_eq!(tree.leaf(offset), Some(*leaf));
+        }
+
+        // the duplicate of the last leaf is padding
+        assert_eq!(tree.leaf(5), None);
+
+        tree.pop().unwrap();
+        assert_eq!(tree.leaf(4), None);
+    }
+
+    #[test]
+    fn finds_the_offsets_of_a_leaf() {
+        let leaves = leaves();
+        let duplicated = [leaves[0], leaves[1], leaves[0], leaves[2], leaves[0]];
+        let tree = MerkleTree::new(&duplicated).unwrap();
+
+        assert_eq!(tree.leaf_index(&leaves[0]).unwrap(), 0);
+        assert_eq!(tree.leaf_index(&leaves[2]).unwrap(), 3);
+        assert_eq!(tree.indices_of(&leaves[0]), [0, 2, 4]);
+        assert!(tree.leaf_index(&leaves[3]).is_err());
+        assert!(tree.indices_of(&leaves[3]).is_empty());
+
+        let tree = MerkleTree::new_with_policy(&duplicated, DuplicatePolicy::RequireIndex).unwrap();
+        assert!(tree.leaf_index(&leaves[0]).is_err());
+        assert_eq!(tree.leaf_index(&leaves[1]).unwrap(), 1);
+    }
+
+    #[test]
+    fn reports_the_size_of_the_tree() {
+        let leaves = leaves();
+
+        for count in 1..=leaves.len() {
+            let tree = MerkleTree::new(&leaves[0..count]).unwrap();
+            let capacity = count.next_power_of_two().max(2);
+
+            assert_eq!(tree.leaf_count(), count);
+            assert_eq!(tree.len(), count);
+            assert!(!tree.is_empty());
+            assert_eq!(tree.node_count(), capacity * 2 - 1);
+            assert_eq!(tree.is_padded(), count != capacity);
+        }
+    }
+
+    #[test]
+    fn gets_the_path_and_sibling_indices_of_a_leaf() {
+        let leaves = leaves();
+        let tree = MerkleTree::new(&leaves[0..5]).unwrap();
+
+        for offset in 0..5 {
+            let path = tree.path_indices(offset).unwrap();
+            let siblings = path[..path.len() - 1]
+                .iter()
+                .map(|index| &tree.nodes[MerkleTree::sibling_index(*index).unwrap()])
+                .collect::<Vec<_>>();
+            let proof = tree.proof_at(offset).unwrap();
+
+            assert_eq!(path.len(), tree.num_levels() + 1);
+            assert_eq!(path[path.len() - 1], 0);
+            assert_eq!(
+                siblings,
+                proof.iter().map(|(_, hash)| *hash).collect::<Vec<_>>()
+            );
+        }
+
+        assert!(tree.path_indices(5).is_err());
+    }
+
+    #[test]
+    fn reserves_storage_for_pushes() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::with_capacity(&leaves[0..1], 3).unwrap();
+        assert_eq!(tree.capacity(), 4);
+
+        tree.reserve(leaves.len() - 1).unwrap();
+        let capacity = tree.nodes.capacity();
+        assert!(tree.capacity() >= leaves.len());
+
+        for leaf in &leaves[1..] {
+            tree.push(*leaf).unwrap();
+        }
+
+        assert_eq!(tree.nodes.capacity(), capacity);
+        assert_eq!(tree.nodes, MerkleTree::new(&leaves).unwrap().nodes);
+    }
+
+    #[test]
+    fn errors_when_the_capacity_cant_be_allocated() {
+        let leaves = leaves();
+
+        for capacity in [usize::MAX, 1 << 58] {
+            assert!(matches!(
+                MerkleTree::with_capacity(&leaves, capacity),
+                Err(MerkleTreeError::CapacityOverflow(_))
+            ));
+        }
+
+        let mut tree = MerkleTree::new(&leaves).unwrap();
+        assert!(tree.reserve(usize::MAX).is_err());
+        assert!(tree.reserve(1 << 58).is_err());
+        assert_eq!(tree, MerkleTree::new(&leaves).unwrap());
+    }
+
+    #[test]
+    fn releases_excess_storage() {
+        let leaves = leaves();
+        let builder = MerkleTree::builder().padding(&padding::ZeroHash);
+        let mut tree = builder.clone().capacity(64).build(&leaves).unwrap();
+        tree.truncate(2).unwrap();
+        tree.shrink_to_fit();
+
+        assert_eq!(tree.nodes.capacity(), 3);
+        assert_eq!(tree.empty_subtrees().len(), 2);
+        assert_eq!(tree.capacity(), 2);
+
+        tree.extend(&leaves[2..]).unwrap();
+        assert_eq!(tree.nodes, builder.build(&leaves).unwrap().nodes);
+    }
+
+    #[test]
+    fn clones_and_compares_trees() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new(&leaves[0..5]).unwrap();
+        tree.on_root_change(|_, _, _| {});
+        let _subscription = tree.subscribe(0).unwrap();
+        tree.push(leaves[5]).unwrap();
+
+        let mut clone = tree.clone();
+        assert_eq!(clone, tree);
+        assert_eq!(clone.root_history, tree.root_history);
+        assert_eq!(clone, MerkleTree::new(&leaves[0..6]).unwrap());
+        assert!(clone.same_root(&tree));
+
+        clone.update(0, leaves[6]).unwrap();
+        assert_ne!(clone, tree);
+        assert!(!clone.same_root(&tree));
+        assert_eq!(tree.leaves()[0], leaves[0]);
+    }
+
+    #[test]
+    fn converts_hashes_to_and_from_hex() {
+        let leaves = leaves();
+        let tree = MerkleTree::new(&leaves[0..3]).unwrap();
+        let hex_leaves = leaves[0..3].iter().map(hex::encode).collect::<Vec<_>>();
+
+        assert_eq!(MerkleTree::from_hex_leaves(&hex_leaves).unwrap(), tree);
+        assert_eq!(tree.root_hex(), hex::encode(tree.root()));
+
+        for invalid in [
+            "",
+            "0x",
+            &hex_leaves[0][1..],
+            &hex_leaves[0].replace('a', "g"),
+        ] {
+            assert!(matches!(
+                MerkleTree::hash_from_hex(invalid),
+                Err(MerkleTreeError::InvalidHex(_))
+            ));
+        }
+        assert!(MerkleTree::from_hex_leaves(["0x00"]).is_err());
+    }
+
+    #[test]
+    fn indexes_leaves_by_offset() {
+        let leaves = leaves();
+        let tree = MerkleTree::new(&leaves[0..5]).unwrap();
+
+        for (offset, leaf) in leaves[0..5].iter().enumerate() {
+            assert_eq!(tree[offset], *leaf);
+            assert_eq!(tree.get(offset), Some(leaf));
+        }
+
+        assert_eq!(tree.get(5), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn panics_indexing_past_the_last_leaf() {
+        let leaves = leaves();
+        let tree = MerkleTree::new(&leaves[0..5]).unwrap();
+        let _ = tree[5];
+    }
+
+    #[test]
+    fn checks_whether_a_leaf_is_present() {
+        let leaves = leaves();
+        let tree = MerkleTree::new(&leaves[0..5]).unwrap();
+
+        assert!(leaves[0..5].iter().all(|leaf| tree.contains(leaf)));
+        assert!(!tree.contains(&leaves[5]));
+
+        // neither internal nodes nor padding are leaves
+        assert!(!tree.contains(&tree.root()));
+        assert!(!tree.contains(&[0; 32]));
+    }
+
+    #[test]
+    fn rebuilds_a_tree_in_place() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new(&leaves).unwrap();
+        let capacity = tree.nodes.capacity();
+
+        for count in (1..=leaves.len()).rev() {
+            let root = tree.rebuild(&leaves[leaves.len() - count..]).unwrap();
+            let expected = MerkleTree::new(&leaves[leaves.len() - count..]).unwrap();
+
+            assert_eq!(root, expected.root());
+            assert_eq!(tree.nodes, expected.nodes);
+            assert_eq!(tree.nodes.capacity(), capacity);
+        }
+
+        assert!(tree.rebuild(&[]).is_err());
+        assert_eq!(tree.leaf_count, 1);
+    }
+
+    #[test]
+    fn sets_a_leaf_value_that_is_a_left_child() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new(&leaves[0..11]).unwrap();
+        let new_leaf = MerkleTree::hash(b"z");
+        tree.update(2, new_leaf).unwrap();
+
+        let mut expected = leaves[0..11].to_vec();
+        expected[2] = new_leaf;
+        assert_eq!(tree.root(), MerkleTree::new(&expected).unwrap().root());
+
+        tree.update(10, leaves[0]).unwrap();
+        expected[10] = leaves[0];
+        assert_eq!(tree.root(), MerkleTree::new(&expected).unwrap().root());
+    }
+
     #[test]
     fn gets_the_parent_index() {
         assert_eq!(MerkleTree::get_parent_index(0), 0);
@@ -439,6 +2253,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn gets_inline_proofs() {
+        let leaves = leaves();
+        let tree = MerkleTree::new(&leaves[0..11]).unwrap();
+
+        for offset in 0..11 {
+            let proof = tree.inline_proof_at(offset).unwrap();
+            let expected = tree.proof_at(offset).unwrap();
+
+            assert!(!proof.spilled());
+            assert!(proof
+                .iter()
+                .map(|(direction, hash)| (*direction, hash))
+                .eq(expected));
+        }
+
+        assert!(tree.inline_proof_at(11).is_err());
+    }
+
     #[test]
     fn errors_when_getting_a_proof_for_a_non_existent_leaf() {
         let leaves = leaves();
@@ -448,6 +2281,74 @@ mod tests {
         assert!(proof.is_err());
     }
 
+    #[test]
+    fn gets_a_proof_and_verifies_for_all_leaves_of_a_non_power_of_two_tree() {
+        let leaves = &leaves()[0..5];
+        let tree = MerkleTree::new(leaves).unwrap();
+
+        for leaf in leaves.iter() {
+            let proof = tree.proof(leaf).unwrap();
+            assert!(tree.verify(&proof, leaf));
+        }
+    }
+
+    #[test]
+    fn gets_proofs_for_all_occurrences_of_a_leaf() {
+        let mut leaves = leaves();
+        leaves[3] = leaves[9];
+        leaves[12] = leaves[9];
+        let tree = MerkleTree::new(&leaves).unwrap();
+        let proofs = tree.proof_all(&leaves[9]).unwrap();
+
+        let offsets = proofs.iter().map(|(offset, _)| *offset).collect::<Vec<_>>();
+        assert_eq!(offsets, [3, 9, 12]);
+
+        for (_, proof) in proofs.iter() {
+            assert!(tree.verify(proof, &leaves[9]));
+        }
+    }
+
+    #[test]
+    fn does_not_get_proofs_for_padding_leaves() {
+        let leaves = &leaves()[0..5];
+        let tree = MerkleTree::new(leaves).unwrap();
+        let proofs = tree.proof_all(&leaves[4]).unwrap();
+
+        assert_eq!(proofs.len(), 1);
+        assert_eq!(proofs[0].0, 4);
+        assert!(tree.proof_all(&MerkleTree::hash(b"z")).is_err());
+    }
+
+    #[test]
+    fn rejects_duplicate_leaves_with_the_reject_policy() {
+        let mut leaves = leaves();
+        assert!(MerkleTree::new_with_policy(&leaves, DuplicatePolicy::Reject).is_ok());
+
+        leaves[7] = leaves[2];
+        let tree = MerkleTree::new_with_policy(&leaves, DuplicatePolicy::Reject);
+        assert!(matches!(tree, Err(MerkleTreeError::DuplicateLeaf(_))));
+
+        let leaves = self::leaves();
+        let mut tree = MerkleTree::new_with_policy(&leaves, DuplicatePolicy::Reject).unwrap();
+        assert!(tree.update(7, leaves[2]).is_err());
+        assert!(tree.update(7, leaves[7]).is_ok());
+    }
+
+    #[test]
+    fn requires_an_offset_for_duplicate_leaves_with_the_require_index_policy() {
+        let mut leaves = leaves();
+        leaves[7] = leaves[2];
+        let tree = MerkleTree::new_with_policy(&leaves, DuplicatePolicy::RequireIndex).unwrap();
+
+        let proof = tree.proof(&leaves[2]);
+        assert!(matches!(proof, Err(MerkleTreeError::AmbiguousLeaf(_))));
+        assert!(tree.proof(&leaves[3]).is_ok());
+
+        let proof = tree.proof_at(7).unwrap();
+        assert!(tree.verify(&proof, &leaves[2]));
+        assert!(tree.proof_at(16).is_err());
+    }
+
     #[test]
     fn does_not_verify_a_proof_for_a_non_existent_leaf() {
         let leaves = leaves();
@@ -0,0 +1,243 @@
+//! Build trees from newline-delimited records, as produced by most CLI and
+//! ETL tools, and import and export leaves as lists of hex hashes.
+//!
+//! A hash list has one leaf per line in hex, so it can be produced and
+//! consumed by shell pipelines.  Anything after the hash on a line is
+//! ignored, so the output of `sha256sum` and similar tools can be imported
+//! as is, using the digests as leaves.
+
+use crate::builder::MerkleTreeBuilder;
+use crate::error::{MerkleTreeError, Result};
+use crate::MerkleTree;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+
+/// How each line of a newline-delimited input encodes its record.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineFormat {
+    /// The bytes of the line are the record.
+    #[default]
+    Raw,
+
+    /// The line is the record in hex, with or without a `0x` prefix.
+    Hex,
+}
+
+impl MerkleTree {
+    /// Build a tree with the default options from newline-delimited records,
+    /// hashing each record into a leaf.  Line endings (`\n` or `\r\n`) aren't
+    /// part of a record, and blank lines are skipped.
+    ///
+    /// ```rust
+    /// use merkle_tree::lines::LineFormat;
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let input = "alice,100\nbob,250\r\ncarol,75\n";
+    /// let tree = MerkleTree::from_reader(input.as_bytes(), LineFormat::Raw).unwrap();
+    ///
+    /// assert_eq!(tree.leaf_count(), 3);
+    /// assert_eq!(tree[1], MerkleTree::hash(b"bob,250"));
+    /// ```
+    pub fn from_reader<R: Read>(reader: R, format: LineFormat) -> Result<MerkleTree> {
+        MerkleTree::builder().build_from_reader(reader, format)
+    }
+
+    /// Build a tree with the default options from a list of hex hashes, one
+    /// leaf per line, see the `lines` module.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// // e.g. the output of `sha256sum *.parquet`
+    /// let digests = "\
+    /// 5d41402abc4b2a76b9719d911017c592ae3a5c3a1b7e1b0ba4a2d3ea8ecd1b33  a.parquet
+    /// 0x7d793037a0760186574b0282f2f435e7a3e1c1a5f8d1d7e41c7c5a1f8f1e0e12 *b.parquet
+    /// ";
+    /// let tree = MerkleTree::from_hash_list(digests.as_bytes()).unwrap();
+    ///
+    /// let mut exported = Vec::new();
+    /// tree.write_hash_list(&mut exported).unwrap();
+    /// assert_eq!(MerkleTree::from_hash_list(exported.as_slice()).unwrap(), tree);
+    /// ```
+    pub fn from_hash_list<R: Read>(reader: R) -> Result<MerkleTree> {
+        MerkleTree::builder().build_from_hash_list(reader)
+    }
+
+    /// Write the leaves as a list of hex hashes, one per line.
+    pub fn write_hash_list<W: Write>(&self, writer: W) -> Result<()> {
+        let mut writer = BufWriter::new(writer);
+
+        for leaf in self.leaves() {
+            writeln!(writer, "{}", hex::encode(leaf))?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+}
+
+impl MerkleTreeBuilder {
+    /// Build a tree from newline-delimited records, hashing each record into
+    /// a leaf with the configured leaf hash function, see
+    /// `MerkleTree::from_reader()`.
+    pub fn build_from_reader<R: Read>(&self, reader: R, format: LineFormat) -> Result<MerkleTree> {
+        let mut records = Vec::new();
+
+        for_each_line(reader, |number, line| {
+            let record = match format {
+                LineFormat::Raw => line.to_vec(),
+                LineFormat::Hex => {
+                    let line = line.trim_ascii();
+                    let digits = line.strip_prefix(b"0x").unwrap_or(line);
+
+                    hex::decode(digits).map_err(|error| {
+                        MerkleTreeError::InvalidHex(format!("line {number}: {error}"))
+                    })?
+                }
+            };
+
+            records.push((self.config.hasher.leaf)(&record));
+            Ok(())
+        })?;
+
+        self.build(&records)
+    }
+
+    /// Build a tree from a list of hex hashes, one leaf per line, see the
+    /// `lines` module.
+    pub fn build_from_hash_list<R: Read>(&self, reader: R) -> Result<MerkleTree> {
+        let mut leaves = Vec::new();
+
+        for_each_line(reader, |number, line| {
+            let hash = std::str::from_utf8(line)
+                .ok()
+                .and_then(|line| line.split_whitespace().next())
+                .unwrap_or_default();
+
+            // coreutils escapes the lines of file names with backslashes
+            let hash = hash.strip_prefix('\\').unwrap_or(hash);
+
+            match MerkleTree::hash_from_hex(hash) {
+                Ok(hash) => leaves.push(hash),
+                Err(_) if line.trim_ascii().is_empty() => {}
+                Err(_) => {
+                    return Err(MerkleTreeError::InvalidHex(format!(
+                        "line {number}: {hash}"
+                    )))
+                }
+            }
+
+            Ok(())
+        })?;
+
+        self.build(&leaves)
+    }
+}
+
+/// Call `f` with the number (from 1) and contents of each non-blank line,
+/// without its line ending.
+fn for_each_line<R, F>(reader: R, mut f: F) -> Result<()>
+where
+    R: Read,
+    F: FnMut(usize, &[u8]) -> Result<()>,
+{
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    let mut number = 0;
+
+    loop {
+        line.clear();
+
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+
+        number += 1;
+
+        let contents = line.strip_suffix(b"\n").unwrap_or(&line);
+        let contents = contents.strip_suffix(b"\r").unwrap_or(contents);
+
+        if !contents.is_empty() {
+            f(number, contents)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Hasher;
+    use crate::Hash;
+
+    #[test]
+    fn hashes_each_line() {
+        let records = ["alice", "bob", "carol", "dave"];
+        let expected = MerkleTree::builder()
+            .hasher(Hasher::KECCAK_256)
+            .build_from_data(records)
+            .unwrap();
+
+        let raw = "alice\r\nbob\n\ncarol\ndave";
+        let hex = records
+            .iter()
+            .enumerate()
+            .map(|(i, record)| {
+                let prefix = if i % 2 == 0 { "0x" } else { "" };
+                format!("  {prefix}{} \n", hex::encode(record))
+            })
+            .collect::<String>();
+
+        let builder = MerkleTree::builder().hasher(Hasher::KECCAK_256);
+
+        for (input, format) in [(raw.to_owned(), LineFormat::Raw), (hex, LineFormat::Hex)] {
+            let tree = builder.build_from_reader(input.as_bytes(), format).unwrap();
+            assert_eq!(tree, expected);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        let error = MerkleTree::from_reader("00\n0g\n".as_bytes(), LineFormat::Hex).unwrap_err();
+        assert!(error.to_string().contains("line 2"));
+
+        assert!(matches!(
+            MerkleTree::from_reader("\n\r\n".as_bytes(), LineFormat::Raw),
+            Err(MerkleTreeError::Empty)
+        ));
+    }
+
+    #[test]
+    fn round_trips_hash_lists() {
+        let leaves = (0..5u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let tree = MerkleTree::new(&leaves).unwrap();
+
+        let mut exported = Vec::new();
+        tree.write_hash_list(&mut exported).unwrap();
+        let exported = String::from_utf8(exported).unwrap();
+
+        assert_eq!(exported.lines().count(), 5);
+        assert_eq!(
+            exported.lines().next(),
+            Some(hex::encode(leaves[0]).as_str())
+        );
+        assert_eq!(
+            MerkleTree::from_hash_list(exported.as_bytes()).unwrap(),
+            tree
+        );
+
+        // sha256sum output, including an escaped file name and a blank line
+        let sha256sum = format!(
+            "{}  a.txt\n\\{} *b\\\\c.txt\n\n{}\t-\r\n",
+            hex::encode(leaves[0]),
+            hex::encode(leaves[1]),
+            hex::encode(leaves[2]),
+        );
+        let imported = MerkleTree::from_hash_list(sha256sum.as_bytes()).unwrap();
+        assert_eq!(imported.leaves(), &leaves[..3]);
+
+        let error = MerkleTree::from_hash_list(format!("{exported}abc\n").as_bytes()).unwrap_err();
+        assert!(error.to_string().contains("line 6"));
+    }
+}
@@ -0,0 +1,181 @@
+//! Find leaves by value without scanning every leaf.
+//!
+//! The index is built from the leaves the first time a value-based lookup
+//! (e.g. `proof()`) needs it, then kept in sync by `update()` and `push()`,
+//! and by `LazyMerkleTree::update()`, which doesn't record a root until its
+//! branches are flushed.  Every other mutation drops it when it records its
+//! root, to be rebuilt by the next lookup, since they can move many leaves
+//! at once.  Anything that writes leaves must do one or the other before the
+//! index is next read.
+
+use crate::Hash;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Where a leaf value occurs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Occurrences {
+    pub(crate) first: usize,
+    pub(crate) count: usize,
+}
+
+/// The index of a tree's leaves by value.
+#[derive(Default)]
+pub(crate) struct LeafLookup(OnceLock<HashMap<Hash, Occurrences>>);
+
+impl LeafLookup {
+    /// Where a value occurs among the leaves, indexing them first if needed.
+    ///
+    /// O(1), or O(n) to build the index
+    pub(crate) fn find(&self, leaves: &[Hash], leaf: &Hash) -> Option<Occurrences> {
+        self.0.get_or_init(|| index(leaves)).get(leaf).copied()
+    }
+
+    /// Drop the index, after leaves were changed other than by `replace()`.
+    pub(crate) fn clear(&mut self) {
+        self.0.take();
+    }
+
+    /// Record that the leaf at `offset` changed from `old` (`None` for a new
+    /// leaf) to `new`, where `leaves` are the leaves after the change.  Does
+    /// nothing if the index hasn't been built.
+    ///
+    /// O(1), or O(n) when the first of several occurrences of `old` changes
+    pub(crate) fn replace(
+        &mut self,
+        leaves: &[Hash],
+        offset: usize,
+        old: Option<&Hash>,
+        new: Hash,
+    ) {
+        let Some(index) = self.0.get_mut() else {
+            return;
+        };
+
+        if let Some(old) = old {
+            match index.get_mut(old) {
+                Some(occurrences) if occurrences.count > 1 => {
+                    occurrences.count -= 1;
+
+                    if occurrences.first == offset {
+                        occurrences.first = leaves
+                            .iter()
+                            .position(|leaf| leaf == old)
+                            .expect("another occurrence");
+                    }
+                }
+                _ => {
+                    index.remove(old);
+                }
+            }
+        }
+
+        let occurrences = index.entry(new).or_insert(Occurrences {
+            first: offset,
+            count: 0,
+        });
+        occurrences.first = occurrences.first.min(offset);
+        occurrences.count += 1;
+    }
+
+    /// The number of entries the index has room for, to estimate its memory.
+    pub(crate) fn capacity(&self) -> usize {
+        self.0.get().map_or(0, HashMap::capacity)
+    }
+}
+
+fn index(leaves: &[Hash]) -> HashMap<Hash, Occurrences> {
+    let mut index = HashMap::with_capacity(leaves.len());
+
+    for (offset, leaf) in leaves.iter().enumerate() {
+        index
+            .entry(*leaf)
+            .or_insert(Occurrences {
+                first: offset,
+                count: 0,
+            })
+            .count += 1;
+    }
+
+    index
+}
+
+/// A clone of a tree rebuilds the index when it's first needed, rather than
+/// copying one that might never be used.
+impl Clone for LeafLookup {
+    fn clone(&self) -> LeafLookup {
+        LeafLookup::default()
+    }
+}
+
+impl fmt::Debug for LeafLookup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LeafLookup({})", self.0.get().map_or(0, HashMap::len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DuplicatePolicy, MerkleTree};
+
+    #[test]
+    fn stays_in_sync_with_the_leaves() {
+        let leaves = (0..6u8).map(|i| MerkleTree::hash(&[i])).collect::<Vec<_>>();
+        let mut tree = MerkleTree::new(&leaves[..4]).unwrap();
+        let check = |tree: &MerkleTree| {
+            for leaf in &leaves {
+                let expected = tree.leaves().iter().position(|current| current == leaf);
+                assert_eq!(tree.leaf_index(leaf).ok(), expected);
+            }
+        };
+
+        check(&tree);
+        tree.push(leaves[4]).unwrap();
+        check(&tree);
+        tree.update(0, leaves[5]).unwrap();
+        check(&tree);
+
+        // duplicates, and moving the first occurrence
+        tree.update(1, leaves[4]).unwrap();
+        check(&tree);
+        tree.update(1, leaves[2]).unwrap();
+        check(&tree);
+        tree.push(leaves[0]).unwrap();
+        check(&tree);
+
+        // mutations that move leaves rebuild the index
+        tree.remove(0).unwrap();
+        check(&tree);
+        tree.insert(1, leaves[3]).unwrap();
+        check(&tree);
+        tree.swap(0, 4).unwrap();
+        check(&tree);
+        tree.extend(&leaves[..2]).unwrap();
+        check(&tree);
+        tree.truncate(3).unwrap();
+        check(&tree);
+
+        let clone = tree.clone();
+        check(&clone);
+    }
+
+    #[test]
+    fn finds_duplicates() {
+        let (a, b) = (MerkleTree::hash(b"a"), MerkleTree::hash(b"b"));
+        let mut tree = MerkleTree::new_with_policy(&[a, b], DuplicatePolicy::RequireIndex).unwrap();
+        assert_eq!(tree.leaf_index(&a).unwrap(), 0);
+
+        tree.push(a).unwrap();
+        assert!(tree.leaf_index(&a).is_err());
+
+        tree.update(0, b).unwrap();
+        assert_eq!(tree.leaf_index(&a).unwrap(), 2);
+
+        let mut tree = MerkleTree::new_with_policy(&[a, b], DuplicatePolicy::Reject).unwrap();
+        assert!(tree.contains(&a));
+        assert!(tree.push(a).is_err());
+        assert!(tree.update(1, a).is_err());
+        assert!(tree.update(0, a).is_ok());
+    }
+}
@@ -0,0 +1,361 @@
+//! Manifests of large files split into fixed-size chunks, so a downloader
+//! can verify each chunk as it arrives rather than the whole file at the end.
+//!
+//! Each chunk is hashed as a leaf, in order, and the manifest records the
+//! chunk size, the root, and the offset, length, hash and proof of every
+//! chunk.  Only the last chunk may be shorter than the chunk size.  A
+//! downloader that trusts the root can then check any chunk on its own with
+//! `ChunkManifest::verify_chunk()`.
+//!
+//! Behind the `json` feature, a manifest can be exchanged as
+//!
+//! ```json
+//! {
+//!   "root": "0x…",
+//!   "chunkSize": 1048576,
+//!   "fileSize": 3000000,
+//!   "chunks": [
+//!     { "offset": 0, "length": 1048576, "hash": "0x…", "proof": ["0x…"], "directions": ["right"] }
+//!   ]
+//! }
+//! ```
+
+use crate::builder::MerkleTreeBuilder;
+use crate::error::Result;
+use crate::{Hash, MerkleTree, OwnedProof};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// A chunk of a file: its position, the hash of its bytes, and its proof.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub offset: u64,
+    pub length: usize,
+    pub hash: Hash,
+    pub proof: OwnedProof,
+}
+
+/// The chunks of a file and the root of the tree of their hashes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkManifest {
+    pub root: Hash,
+    pub chunk_size: usize,
+    pub file_size: u64,
+    pub chunks: Vec<Chunk>,
+}
+
+impl ChunkManifest {
+    /// Split a stream into chunks of `chunk_size` bytes (at least 1) and
+    /// hash them with the default options.
+    ///
+    /// ```rust
+    /// use merkle_tree::manifest::ChunkManifest;
+    ///
+    /// let file = (0..10_000u32).map(|i| i as u8).collect::<Vec<_>>();
+    /// let manifest = ChunkManifest::from_reader(file.as_slice(), 4096).unwrap();
+    ///
+    /// assert_eq!(manifest.chunks.len(), 3);
+    /// assert_eq!(manifest.chunks[2].length, 10_000 - 2 * 4096);
+    ///
+    /// // each chunk is verified as it's downloaded
+    /// for (index, bytes) in file.chunks(4096).enumerate() {
+    ///     assert!(manifest.verify_chunk(index, bytes));
+    /// }
+    /// assert!(!manifest.verify_chunk(0, &file[1..4097]));
+    /// ```
+    pub fn from_reader<R: Read>(reader: R, chunk_size: usize) -> Result<ChunkManifest> {
+        MerkleTree::builder().chunk_manifest(reader, chunk_size)
+    }
+
+    /// Split the file at `path` into chunks of `chunk_size` bytes (at least
+    /// 1) and hash them with the default options.
+    pub fn from_path<P: AsRef<Path>>(path: P, chunk_size: usize) -> Result<ChunkManifest> {
+        let file = BufReader::new(File::open(path)?);
+        ChunkManifest::from_reader(file, chunk_size)
+    }
+
+    /// Whether `bytes` are the chunk at `index` of a manifest hashed with the
+    /// default options.
+    pub fn verify_chunk(&self, index: usize, bytes: &[u8]) -> bool {
+        MerkleTree::builder().verify_chunk(self, index, bytes)
+    }
+}
+
+impl MerkleTreeBuilder {
+    /// Split a stream into chunks of `chunk_size` bytes (at least 1) and
+    /// hash them with these options.  Only the hashing options apply: chunks
+    /// keep their order and repeated chunks are kept.  An empty stream has no
+    /// chunks, so can't have a manifest.
+    pub fn chunk_manifest<R: Read>(
+        &self,
+        mut reader: R,
+        chunk_size: usize,
+    ) -> Result<ChunkManifest> {
+        let chunk_size = chunk_size.max(1);
+        let mut leaves = Vec::new();
+        let mut lengths = Vec::new();
+        let mut buffer = Vec::with_capacity(chunk_size);
+
+        loop {
+            buffer.clear();
+            (&mut reader)
+                .take(chunk_size as u64)
+                .read_to_end(&mut buffer)?;
+
+            if buffer.is_empty() {
+                break;
+            }
+
+            leaves.push((self.config.hasher.leaf)(&buffer));
+            lengths.push(buffer.len());
+
+            if buffer.len() < chunk_size {
+                break;
+            }
+        }
+
+        let mut builder = MerkleTreeBuilder::new();
+        builder.config = self.config;
+        let tree = builder.build(&leaves)?;
+        let mut offset = 0;
+        let mut chunks = Vec::with_capacity(leaves.len());
+
+        for (index, (hash, length)) in leaves.into_iter().zip(lengths).enumerate() {
+            let proof = tree.proof_at(index)?;
+
+            chunks.push(Chunk {
+                offset,
+                length,
+                hash,
+                proof: proof
+                    .into_iter()
+                    .map(|(direction, hash)| (direction, *hash))
+                    .collect(),
+            });
+            offset += length as u64;
+        }
+
+        Ok(ChunkManifest {
+            root: tree.root(),
+            chunk_size,
+            file_size: offset,
+            chunks,
+        })
+    }
+
+    /// Whether `bytes` are the chunk at `index` of a manifest hashed with
+    /// these options.
+    pub fn verify_chunk(&self, manifest: &ChunkManifest, index: usize, bytes: &[u8]) -> bool {
+        let Some(chunk) = manifest.chunks.get(index) else {
+            return false;
+        };
+
+        let hash = (self.config.hasher.leaf)(bytes);
+
+        bytes.len() == chunk.length
+            && hash == chunk.hash
+            && self.verify(&manifest.root, &chunk.proof, &hash)
+    }
+}
+
+#[cfg(feature = "json")]
+mod json {
+    use super::{Chunk, ChunkManifest};
+    use crate::error::{MerkleTreeError, Result};
+    use crate::{Direction, Hash, MerkleTree};
+    use serde_json::{json, Value};
+
+    impl ChunkManifest {
+        /// The manifest as a JSON value, see the `manifest` module.
+        pub fn to_json(&self) -> Value {
+            let chunks = self
+                .chunks
+                .iter()
+                .map(|chunk| {
+                    json!({
+                        "offset": chunk.offset,
+                        "length": chunk.length,
+                        "hash": to_hex(&chunk.hash),
+                        "proof": chunk.proof.iter().map(|(_, hash)| to_hex(hash)).collect::<Vec<_>>(),
+                        "directions": chunk
+                            .proof
+                            .iter()
+                            .map(|(direction, _)| match direction {
+                                Direction::Left => "left",
+                                Direction::Right => "right",
+                            })
+                            .collect::<Vec<_>>(),
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            json!({
+                "root": to_hex(&self.root),
+                "chunkSize": self.chunk_size,
+                "fileSize": self.file_size,
+                "chunks": chunks,
+            })
+        }
+
+        /// Read a manifest from its JSON value.  Its chunks are checked to
+        /// tile the file, but not against the root.
+        pub fn from_json(value: &Value) -> Result<ChunkManifest> {
+            let chunk_size = field(value, "chunkSize")?;
+            let file_size = field(value, "fileSize")?;
+            let mut offset = 0;
+            let mut chunks = Vec::new();
+
+            let Some(values) = value["chunks"].as_array() else {
+                return Err(invalid("missing chunks"));
+            };
+
+            for (index, chunk) in values.iter().enumerate() {
+                let length = field(chunk, "length")?;
+                let last = index + 1 == values.len();
+
+                if field(chunk, "offset")? != offset
+                    || length == 0
+                    || length > chunk_size
+                    || (!last && length != chunk_size)
+                {
+                    return Err(invalid(&format!("chunk {index} doesn't tile the file")));
+                }
+
+                let (Some(proof), Some(directions)) =
+                    (chunk["proof"].as_array(), chunk["directions"].as_array())
+                else {
+                    return Err(invalid(&format!("chunk {index} is missing its proof")));
+                };
+
+                if proof.len() != directions.len() {
+                    return Err(invalid(&format!("chunk {index} has a malformed proof")));
+                }
+
+                let proof = proof
+                    .iter()
+                    .zip(directions)
+                    .map(|(hash, direction)| {
+                        let direction = match direction.as_str() {
+                            Some("left") => Direction::Left,
+                            Some("right") => Direction::Right,
+                            _ => return Err(invalid("direction must be left or right")),
+                        };
+                        Ok((direction, from_hex(hash)?))
+                    })
+                    .collect::<Result<_>>()?;
+
+                chunks.push(Chunk {
+                    offset,
+                    length: length as usize,
+                    hash: from_hex(&chunk["hash"])?,
+                    proof,
+                });
+                offset += length;
+            }
+
+            if chunks.is_empty() || offset != file_size {
+                return Err(invalid("chunks don't cover the file"));
+            }
+
+            Ok(ChunkManifest {
+                root: from_hex(&value["root"])?,
+                chunk_size: chunk_size as usize,
+                file_size,
+                chunks,
+            })
+        }
+    }
+
+    fn field(value: &Value, name: &str) -> Result<u64> {
+        value[name]
+            .as_u64()
+            .ok_or_else(|| invalid(&format!("missing {name}")))
+    }
+
+    fn to_hex(hash: &Hash) -> String {
+        format!("0x{}", hex::encode(hash))
+    }
+
+    fn from_hex(value: &Value) -> Result<Hash> {
+        let hex = value
+            .as_str()
+            .ok_or_else(|| invalid("hash must be a string"))?;
+        MerkleTree::hash_from_hex(hex)
+    }
+
+    fn invalid(reason: &str) -> MerkleTreeError {
+        MerkleTreeError::InvalidEncoding(format!("manifest {reason}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Hasher;
+
+    fn file(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn verifies_chunks_incrementally() {
+        for (len, chunk_size) in [(1, 64), (64, 64), (1000, 64), (1000, 1), (256, 0)] {
+            let file = file(len);
+            let manifest = ChunkManifest::from_reader(file.as_slice(), chunk_size).unwrap();
+            let chunks = file.chunks(chunk_size.max(1)).collect::<Vec<_>>();
+
+            assert_eq!(manifest.chunks.len(), chunks.len());
+            assert_eq!(manifest.file_size, len as u64);
+
+            for (index, bytes) in chunks.iter().enumerate() {
+                assert_eq!(
+                    manifest.chunks[index].offset,
+                    (index * manifest.chunk_size) as u64
+                );
+                assert!(manifest.verify_chunk(index, bytes));
+            }
+
+            assert!(!manifest.verify_chunk(chunks.len(), chunks[0]));
+            assert!(!manifest.verify_chunk(0, &[0xff]));
+        }
+
+        assert!(ChunkManifest::from_reader([].as_slice(), 64).is_err());
+    }
+
+    #[test]
+    fn keeps_repeated_chunks_in_order() {
+        let file = [[0; 16], [1; 16], [0; 16]].concat();
+        let builder = MerkleTree::builder()
+            .hasher(Hasher::KECCAK_256)
+            .sort_leaves(true)
+            .dedup(true);
+        let manifest = builder.chunk_manifest(file.as_slice(), 16).unwrap();
+
+        assert_eq!(manifest.chunks.len(), 3);
+        assert_eq!(manifest.chunks[0].hash, manifest.chunks[2].hash);
+
+        for (index, bytes) in file.chunks(16).enumerate() {
+            assert!(builder.verify_chunk(&manifest, index, bytes));
+        }
+        assert!(!ChunkManifest::verify_chunk(&manifest, 1, &file[16..32]));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn round_trips_through_json() {
+        let file = file(300);
+        let manifest = ChunkManifest::from_reader(file.as_slice(), 128).unwrap();
+        let json = manifest.to_json();
+
+        assert_eq!(ChunkManifest::from_json(&json).unwrap(), manifest);
+
+        let mut gap = json.clone();
+        gap["chunks"][1]["offset"] = 129.into();
+        assert!(ChunkManifest::from_json(&gap).is_err());
+
+        let mut short = json.clone();
+        short["fileSize"] = 299.into();
+        assert!(ChunkManifest::from_json(&short).is_err());
+    }
+}
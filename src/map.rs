@@ -0,0 +1,209 @@
+//! Commit to a set of key-value pairs, e.g. a configuration, where each key
+//! has a deterministic position in the tree.
+//!
+//! Entries are ordered by the hash of their key, so the same entries always
+//! build the same tree regardless of the order they were inserted in.  Each
+//! leaf is the `concat` of the key hash and the value hash, so a proof
+//! commits to both.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::{Direction, Hash, MerkleTree, Proof};
+use std::borrow::Borrow;
+
+/// A tree of key-value pairs, ordered by key hash.
+///
+/// ```rust
+/// use merkle_tree::map::MerkleMap;
+///
+/// let mut map = MerkleMap::new([("timeout", "30"), ("retries", "3")]).unwrap();
+/// map.insert("region", "eu-west-1").unwrap();
+///
+/// let proof = map.prove(&"retries").unwrap();
+/// assert!(map.verify(&proof, &"retries", &"3"));
+/// assert_eq!(map.get(&"region"), Some(&"eu-west-1"));
+/// ```
+#[derive(Debug)]
+pub struct MerkleMap<K, V> {
+    tree: MerkleTree,
+    key_hashes: Vec<Hash>,
+    entries: Vec<(K, V)>,
+}
+
+impl<K: AsRef<[u8]>, V: AsRef<[u8]>> MerkleMap<K, V> {
+    /// Create a new map from key-value pairs.  A key that appears more than
+    /// once keeps its last value.
+    pub fn new<I: IntoIterator<Item = (K, V)>>(entries: I) -> Result<MerkleMap<K, V>> {
+        let mut entries = entries
+            .into_iter()
+            .map(|(key, value)| (MerkleTree::hash(key.as_ref()), (key, value)))
+            .collect::<Vec<_>>();
+
+        if entries.is_empty() {
+            return Err(MerkleTreeError::Empty);
+        }
+
+        // a stable sort keeps repeated keys in the order they were supplied,
+        // so the last one is kept
+        entries.sort_by_key(|(key_hash, _)| *key_hash);
+        entries.reverse();
+        entries.dedup_by_key(|(key_hash, _)| *key_hash);
+        entries.reverse();
+
+        let (key_hashes, entries): (Vec<Hash>, Vec<(K, V)>) = entries.into_iter().unzip();
+        let leaves = key_hashes
+            .iter()
+            .zip(&entries)
+            .map(|(key_hash, (_, value))| Self::leaf_from_key_hash(key_hash, value))
+            .collect::<Vec<_>>();
+
+        Ok(MerkleMap {
+            tree: MerkleTree::new(&leaves)?,
+            key_hashes,
+            entries,
+        })
+    }
+
+    /// The leaf committing to a key-value pair.
+    pub fn leaf(key: &K, value: &V) -> Hash {
+        Self::leaf_from_key_hash(&MerkleTree::hash(key.as_ref()), value)
+    }
+
+    fn leaf_from_key_hash(key_hash: &Hash, value: &V) -> Hash {
+        MerkleTree::concat(key_hash, &MerkleTree::hash(value.as_ref()))
+    }
+
+    /// The offset of the leaf of a key, or the offset it would be inserted at.
+    fn position(&self, key: &K) -> std::result::Result<usize, usize> {
+        self.key_hashes
+            .binary_search(&MerkleTree::hash(key.as_ref()))
+    }
+
+    /// The underlying tree.
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Return the root hash of the map.
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    /// The number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// A map always holds at least one entry.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The entries of the map, ordered by key hash.
+    pub fn entries(&self) -> &[(K, V)] {
+        &self.entries
+    }
+
+    /// Return the value of a key.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let offset = self.position(key).ok()?;
+        Some(&self.entries[offset].1)
+    }
+
+    /// Whether the map holds a key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.position(key).is_ok()
+    }
+
+    /// Set the value of a key and return the previous value, if any.  A new
+    /// key is inserted at the position of its key hash, which shifts the
+    /// leaves after it.
+    pub fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        match self.position(&key) {
+            Ok(offset) => {
+                let leaf = Self::leaf_from_key_hash(&self.key_hashes[offset], &value);
+                self.tree.update(offset, leaf)?;
+
+                let (_, old) = std::mem::replace(&mut self.entries[offset], (key, value));
+                Ok(Some(old))
+            }
+            Err(offset) => {
+                let key_hash = MerkleTree::hash(key.as_ref());
+                self.tree
+                    .insert(offset, Self::leaf_from_key_hash(&key_hash, &value))?;
+                self.key_hashes.insert(offset, key_hash);
+                self.entries.insert(offset, (key, value));
+
+                Ok(None)
+            }
+        }
+    }
+
+    /// Generate a Merkle Proof for the entry of a key.
+    pub fn prove(&self, key: &K) -> Result<Proof<'_>> {
+        match self.position(key) {
+            Ok(offset) => self.tree.proof_at(offset),
+            Err(_) => Err(MerkleTreeError::CannotFindLeaf(hex::encode(
+                MerkleTree::hash(key.as_ref()),
+            ))),
+        }
+    }
+
+    /// Verify a Merkle Proof for a key-value pair against the map's root.
+    pub fn verify<H: Borrow<Hash>>(&self, proof: &[(Direction, H)], key: &K, value: &V) -> bool {
+        Self::verify_with_root(&self.root(), proof, key, value)
+    }
+
+    /// Verify a Merkle Proof for a key-value pair against a root hash,
+    /// without needing the map.
+    pub fn verify_with_root<H: Borrow<Hash>>(
+        root: &Hash,
+        proof: &[(Direction, H)],
+        key: &K,
+        value: &V,
+    ) -> bool {
+        MerkleTree::verify_with_root(root, proof, &Self::leaf(key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_the_same_map_in_any_order() {
+        let entries = (0..5u8).map(|i| (vec![i], vec![i * 2])).collect::<Vec<_>>();
+        let mut map = MerkleMap::new(entries[0..2].to_vec()).unwrap();
+
+        for (key, value) in entries[2..].iter().rev() {
+            assert_eq!(map.insert(key.clone(), value.clone()).unwrap(), None);
+        }
+
+        let expected = MerkleMap::new(entries.clone()).unwrap();
+        assert_eq!(map.root(), expected.root());
+        assert_eq!(map.len(), 5);
+        assert!(MerkleMap::<Vec<u8>, Vec<u8>>::new([]).is_err());
+
+        for (key, value) in &entries {
+            assert_eq!(map.get(key), Some(value));
+            let proof = map.prove(key).unwrap();
+            assert!(map.verify(&proof, key, value));
+            assert!(!map.verify(&proof, key, &vec![9]));
+        }
+
+        assert!(map.prove(&vec![9]).is_err());
+        assert!(!map.contains_key(&vec![9]));
+    }
+
+    #[test]
+    fn replaces_the_value_of_a_key() {
+        let mut map = MerkleMap::new([("a", "1"), ("b", "2"), ("a", "3")]).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&"a"), Some(&"3"));
+
+        assert_eq!(map.insert("b", "4").unwrap(), Some("2"));
+        assert_eq!(
+            map.root(),
+            MerkleMap::new([("b", "4"), ("a", "3")]).unwrap().root()
+        );
+    }
+}
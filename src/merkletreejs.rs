@@ -0,0 +1,280 @@
+//! Import and export trees and proofs in the JSON shapes of the merkletreejs
+//! JavaScript library, behind the `json` feature.
+//!
+//! A tree is the output of `MerkleTree.marshalTree()`: its options, root,
+//! layers (leaves first) and leaves, all hashes `0x` prefixed hex.  A proof
+//! is the output of `MerkleTree.marshalProof()`, an array of
+//! `{ "position": "left" | "right", "data": "0x…" }` steps.
+//!
+//! merkletreejs promotes the last node of an odd layer unless `duplicateOdd`
+//! is set, which map to `padding::PromoteOdd` and `padding::DuplicateLast`,
+//! and `sortPairs` and `sortLeaves` map to the builder options of the same
+//! names.  Hash functions are code rather than data, so trees are decoded
+//! with the hasher of a builder, e.g. `Hasher::KECCAK_256`, and the root is
+//! checked to be the one recorded.  Bitcoin trees, complete trees and
+//! `fillDefaultHash` aren't supported.
+
+use crate::builder::MerkleTreeBuilder;
+use crate::error::{MerkleTreeError, Result};
+use crate::padding::{DuplicateLast, PromoteOdd};
+use crate::{Direction, Hash, MerkleTree, OwnedProof};
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+
+/// The options block of a marshalled tree.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Options {
+    pub complete: bool,
+    pub is_bitcoin_tree: bool,
+    pub hash_leaves: bool,
+    pub sort_leaves: bool,
+    pub sort_pairs: bool,
+    pub sort: bool,
+    pub fill_default_hash: Option<String>,
+    pub duplicate_odd: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Tree {
+    #[serde(default)]
+    options: Options,
+    root: String,
+    #[serde(default)]
+    layers: Vec<Vec<String>>,
+    leaves: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Step {
+    position: String,
+    data: String,
+}
+
+/// Encode a tree as merkletreejs marshals it.  Trees padded other than by
+/// promoting or duplicating the last node of a level can't be encoded.
+///
+/// ```rust
+/// use merkle_tree::builder::Hasher;
+/// use merkle_tree::{merkletreejs, MerkleTree};
+///
+/// let builder = MerkleTree::builder().hasher(Hasher::KECCAK_256).sorted_pairs(true);
+/// let leaves = [b"a", b"b", b"c"].map(|data| (Hasher::KECCAK_256.leaf)(data));
+/// let tree = builder.build(&leaves).unwrap();
+///
+/// let json = merkletreejs::encode_tree(&tree).unwrap();
+/// assert_eq!(merkletreejs::decode_tree(&builder, &json).unwrap(), tree);
+/// ```
+pub fn encode_tree(tree: &MerkleTree) -> Result<String> {
+    let probe = (MerkleTree::hash(b"last"), MerkleTree::hash(b"empty"));
+    let duplicate_odd = match tree.config.padding.sibling(&probe.0, &probe.1) {
+        None => false,
+        Some(sibling) if sibling == probe.0 => true,
+        Some(_) => {
+            return Err(MerkleTreeError::InvalidEncoding(
+                "merkletreejs can only promote or duplicate odd nodes".to_owned(),
+            ))
+        }
+    };
+
+    let sort_leaves = tree.sorted_positions().is_some();
+    let sort_pairs = tree.config.sorted_pairs;
+
+    let layers = (0..=tree.num_levels())
+        .rev()
+        .map(|level| {
+            let start = MerkleTree::get_level_start(level);
+            tree.nodes[start..start + tree.level_width(level)]
+                .iter()
+                .map(to_hex)
+                .collect()
+        })
+        .collect();
+
+    let marshalled = Tree {
+        options: Options {
+            sort_leaves,
+            sort_pairs,
+            sort: sort_leaves && sort_pairs,
+            duplicate_odd,
+            ..Options::default()
+        },
+        root: to_hex(&tree.root()),
+        layers,
+        leaves: tree.leaves().iter().map(to_hex).collect(),
+    };
+
+    serde_json::to_string_pretty(&marshalled)
+        .map_err(|error| MerkleTreeError::InvalidEncoding(error.to_string()))
+}
+
+/// Decode a tree marshalled by merkletreejs, hashing with the hasher of
+/// `builder` and taking the other options from the tree.  The leaves are
+/// rebuilt and must have the recorded root.
+pub fn decode_tree(builder: &MerkleTreeBuilder, json: &str) -> Result<MerkleTree> {
+    let marshalled = serde_json::from_str::<Tree>(json)
+        .map_err(|error| MerkleTreeError::InvalidEncoding(error.to_string()))?;
+    let options = &marshalled.options;
+
+    if options.complete || options.is_bitcoin_tree || options.fill_default_hash.is_some() {
+        return Err(MerkleTreeError::InvalidEncoding(
+            "complete trees, bitcoin trees and fillDefaultHash aren't supported".to_owned(),
+        ));
+    }
+
+    let leaves = marshalled
+        .leaves
+        .iter()
+        .map(|leaf| MerkleTree::hash_from_hex(leaf))
+        .collect::<Result<Vec<Hash>>>()?;
+
+    let tree = builder
+        .clone()
+        .sort_leaves(options.sort_leaves || options.sort)
+        .sorted_pairs(options.sort_pairs || options.sort)
+        .padding(if options.duplicate_odd {
+            &DuplicateLast
+        } else {
+            &PromoteOdd
+        })
+        .build(&leaves)?;
+
+    if tree.root() != MerkleTree::hash_from_hex(&marshalled.root)? {
+        return Err(MerkleTreeError::InvalidEncoding(
+            "leaves don't have the recorded root".to_owned(),
+        ));
+    }
+
+    Ok(tree)
+}
+
+/// Encode a proof as merkletreejs marshals it.
+pub fn encode_proof<H: Borrow<Hash>>(proof: &[(Direction, H)]) -> String {
+    let steps = proof
+        .iter()
+        .map(|(direction, hash)| Step {
+            position: match direction {
+                Direction::Left => "left",
+                Direction::Right => "right",
+            }
+            .to_owned(),
+            data: to_hex(hash.borrow()),
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::to_string(&steps).expect("proofs are always encodable")
+}
+
+/// Decode a proof marshalled by merkletreejs.
+pub fn decode_proof(json: &str) -> Result<OwnedProof> {
+    let steps = serde_json::from_str::<Vec<Step>>(json)
+        .map_err(|error| MerkleTreeError::InvalidEncoding(error.to_string()))?;
+
+    steps
+        .iter()
+        .map(|step| {
+            let direction = match step.position.as_str() {
+                "left" => Direction::Left,
+                "right" => Direction::Right,
+                position => {
+                    return Err(MerkleTreeError::InvalidEncoding(format!(
+                        "unknown position {position}"
+                    )))
+                }
+            };
+
+            Ok((direction, MerkleTree::hash_from_hex(&step.data)?))
+        })
+        .collect()
+}
+
+fn to_hex(hash: &Hash) -> String {
+    format!("0x{}", hex::encode(hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Hasher;
+    use serde_json::Value;
+
+    fn keccak(data: &[u8]) -> Hash {
+        (Hasher::KECCAK_256.leaf)(data)
+    }
+
+    fn builder() -> MerkleTreeBuilder {
+        MerkleTree::builder().hasher(Hasher::KECCAK_256)
+    }
+
+    /// A tree marshalled the way merkletreejs does, with the layers its
+    /// algorithm builds: an odd last node is promoted unhashed.
+    fn marshalled() -> (String, Hash) {
+        let [a, b, c] = [b"a", b"b", b"c"].map(|data| keccak(data));
+        let ab = (Hasher::KECCAK_256.node)(&a, &b);
+        let root = (Hasher::KECCAK_256.node)(&ab, &c);
+
+        let json = serde_json::json!({
+            "options": {
+                "complete": false,
+                "isBitcoinTree": false,
+                "hashLeaves": false,
+                "sortLeaves": false,
+                "sortPairs": false,
+                "sort": false,
+                "fillDefaultHash": null,
+                "duplicateOdd": false
+            },
+            "root": to_hex(&root),
+            "layers": [[to_hex(&a), to_hex(&b), to_hex(&c)], [to_hex(&ab), to_hex(&c)], [to_hex(&root)]],
+            "leaves": [to_hex(&a), to_hex(&b), to_hex(&c)]
+        });
+
+        (json.to_string(), root)
+    }
+
+    #[test]
+    fn reads_and_writes_merkletreejs_trees() {
+        let (json, root) = marshalled();
+        let tree = decode_tree(&builder(), &json).unwrap();
+        assert_eq!(tree.root(), root);
+
+        // encoding gives back the same document
+        let encoded = encode_tree(&tree).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&encoded).unwrap(),
+            serde_json::from_str::<Value>(&json).unwrap()
+        );
+
+        // the promoted leaf has no sibling at the second layer
+        let proof = tree.proof_at(2).unwrap();
+        assert_eq!(proof.len(), 1);
+        let decoded = decode_proof(&encode_proof(&proof)).unwrap();
+        assert!(tree.verify(&decoded, &keccak(b"c")));
+
+        // the hash functions must match the recorded root
+        assert!(decode_tree(&MerkleTree::builder(), &json).is_err());
+    }
+
+    #[test]
+    fn maps_options_to_the_builder() {
+        let leaves = [b"d", b"b", b"c", b"a", b"e"].map(|data| keccak(data));
+
+        for builder in [
+            builder().sorted_pairs(true),
+            builder().padding(&DuplicateLast).sort_leaves(true),
+        ] {
+            let tree = builder.build(&leaves).unwrap();
+            let json = encode_tree(&tree).unwrap();
+            assert_eq!(
+                decode_tree(&MerkleTree::builder().hasher(Hasher::KECCAK_256), &json).unwrap(),
+                tree
+            );
+        }
+
+        let json = marshalled()
+            .0
+            .replace("\"isBitcoinTree\":false", "\"isBitcoinTree\":true");
+        assert!(decode_tree(&builder(), &json).is_err());
+        assert!(decode_proof(r#"[{"position":"up","data":"0x00"}]"#).is_err());
+    }
+}
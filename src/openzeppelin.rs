@@ -0,0 +1,609 @@
+//! Trees compatible with OpenZeppelin's `StandardMerkleTree`, including its
+//! `standard-v1` dump format, behind the `json` feature.
+//!
+//! A standard tree's leaves are values of Solidity types, each hashed as
+//! `keccak256(keccak256(abi.encode(value)))` so a leaf can't be mistaken for
+//! a pair of nodes, and its pairs are sorted and hashed with Keccak-256.
+//! Unlike a `MerkleTree`, it isn't padded to a power of two: `n` leaves make
+//! a complete binary tree of `2n - 1` nodes, with the root at index 0, the
+//! children of `i` at `2i + 1` and `2i + 2`, and the leaves last, in reverse
+//! order.  Leaves are sorted by hash before building, as OpenZeppelin does
+//! by default.
+//!
+//! A dump is a JSON document:
+//!
+//! ```json
+//! {
+//!   "format": "standard-v1",
+//!   "leafEncoding": ["address", "uint256"],
+//!   "tree": ["0x…", "0x…", "0x…"],
+//!   "values": [{ "value": ["0x1111111111111111111111111111111111111111", "5000"], "treeIndex": 1 }]
+//! }
+//! ```
+//!
+//! Values are encoded from the JSON types OpenZeppelin's tooling uses:
+//! addresses, fixed size bytes and dynamic bytes as `0x` prefixed hex, integers
+//! as JSON numbers or decimal or `0x` prefixed hex strings, booleans and
+//! strings as themselves.  The elementary types `address`, `bool`, `uintN`,
+//! `intN`, `bytesN`, `bytes` and `string` are supported, but not arrays or
+//! tuples.
+
+use crate::builder::{Config, Hasher};
+use crate::error::{MerkleTreeError, Result};
+use crate::{Hash, MerkleTree, EMPTY_NODE};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The format of the dumps this module reads and writes.
+pub const FORMAT: &str = "standard-v1";
+
+/// A tree of ABI encoded values, as built by OpenZeppelin's
+/// `StandardMerkleTree.of()`.
+///
+/// ```rust
+/// use merkle_tree::openzeppelin::StandardMerkleTree;
+/// use serde_json::json;
+///
+/// let values = vec![
+///     vec![json!("0x1111111111111111111111111111111111111111"), json!("5000000000000000000")],
+///     vec![json!("0x2222222222222222222222222222222222222222"), json!("2500000000000000000")],
+/// ];
+/// let tree = StandardMerkleTree::of(values, &["address", "uint256"]).unwrap();
+///
+/// let proof = tree.proof(1).unwrap();
+/// let leaf = tree.leaf_hash(&tree.values()[1]).unwrap();
+/// assert!(StandardMerkleTree::verify(&tree.root(), &leaf, &proof));
+///
+/// let loaded = StandardMerkleTree::load(&tree.dump()).unwrap();
+/// assert_eq!(loaded.root(), tree.root());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct StandardMerkleTree {
+    tree: Vec<Hash>,
+    values: Vec<Vec<Value>>,
+    tree_indices: Vec<usize>,
+    leaf_encoding: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Dump {
+    format: String,
+    leaf_encoding: Vec<String>,
+    tree: Vec<String>,
+    values: Vec<Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    value: Vec<Value>,
+    tree_index: usize,
+}
+
+impl StandardMerkleTree {
+    /// Build a tree of values, each a list of JSON values of the types in
+    /// `leaf_encoding`.
+    pub fn of<S: AsRef<str>>(
+        values: Vec<Vec<Value>>,
+        leaf_encoding: &[S],
+    ) -> Result<StandardMerkleTree> {
+        if values.is_empty() {
+            return Err(MerkleTreeError::Empty);
+        }
+
+        let leaf_encoding = leaf_encoding
+            .iter()
+            .map(|kind| kind.as_ref().to_owned())
+            .collect::<Vec<_>>();
+
+        let leaves = values
+            .iter()
+            .map(|value| leaf_hash(&leaf_encoding, value))
+            .collect::<Result<Vec<Hash>>>()?;
+
+        // a stable sort, as OpenZeppelin's
+        let mut order = (0..leaves.len()).collect::<Vec<_>>();
+        order.sort_by_key(|offset| leaves[*offset]);
+
+        let mut tree = vec![EMPTY_NODE; 2 * leaves.len() - 1];
+        let mut tree_indices = vec![0; leaves.len()];
+
+        for (position, offset) in order.into_iter().enumerate() {
+            let tree_index = tree.len() - 1 - position;
+            tree[tree_index] = leaves[offset];
+            tree_indices[offset] = tree_index;
+        }
+
+        for index in (0..tree.len() - leaves.len()).rev() {
+            tree[index] = hash_pair(&tree[index * 2 + 1], &tree[index * 2 + 2]);
+        }
+
+        Ok(StandardMerkleTree {
+            tree,
+            values,
+            tree_indices,
+            leaf_encoding,
+        })
+    }
+
+    /// Load a tree from a `standard-v1` dump, checking that every leaf is the
+    /// hash of its value and every node the hash of its children.
+    pub fn load(json: &str) -> Result<StandardMerkleTree> {
+        let dump = serde_json::from_str::<Dump>(json).map_err(invalid)?;
+
+        if dump.format != FORMAT {
+            return Err(MerkleTreeError::InvalidEncoding(format!(
+                "unknown format {}",
+                dump.format
+            )));
+        }
+
+        let tree = dump
+            .tree
+            .iter()
+            .map(|node| MerkleTree::hash_from_hex(node))
+            .collect::<Result<Vec<Hash>>>()?;
+
+        if dump.values.is_empty() || tree.len() != 2 * dump.values.len() - 1 {
+            return Err(MerkleTreeError::InvalidEncoding(format!(
+                "{} nodes can't hold {} values",
+                tree.len(),
+                dump.values.len()
+            )));
+        }
+
+        let first_leaf = tree.len() - dump.values.len();
+
+        for (index, node) in tree.iter().enumerate().take(first_leaf) {
+            if *node != hash_pair(&tree[index * 2 + 1], &tree[index * 2 + 2]) {
+                return Err(MerkleTreeError::InconsistentNode(index));
+            }
+        }
+
+        for entry in &dump.values {
+            let leaf = tree
+                .get(entry.tree_index)
+                .filter(|_| entry.tree_index >= first_leaf);
+
+            if leaf != Some(&leaf_hash(&dump.leaf_encoding, &entry.value)?) {
+                return Err(MerkleTreeError::InconsistentNode(entry.tree_index));
+            }
+        }
+
+        let (values, tree_indices) = dump
+            .values
+            .into_iter()
+            .map(|entry| (entry.value, entry.tree_index))
+            .unzip();
+
+        Ok(StandardMerkleTree {
+            tree,
+            values,
+            tree_indices,
+            leaf_encoding: dump.leaf_encoding,
+        })
+    }
+
+    /// Dump the tree in the `standard-v1` format.
+    pub fn dump(&self) -> String {
+        let dump = Dump {
+            format: FORMAT.to_owned(),
+            leaf_encoding: self.leaf_encoding.clone(),
+            tree: self.tree.iter().map(to_hex).collect(),
+            values: self
+                .values
+                .iter()
+                .zip(&self.tree_indices)
+                .map(|(value, tree_index)| Entry {
+                    value: value.clone(),
+                    tree_index: *tree_index,
+                })
+                .collect(),
+        };
+
+        serde_json::to_string_pretty(&dump).expect("dumps are always encodable")
+    }
+
+    /// The root hash of the tree.
+    pub fn root(&self) -> Hash {
+        self.tree[0]
+    }
+
+    /// The Solidity types of each value.
+    pub fn leaf_encoding(&self) -> &[String] {
+        &self.leaf_encoding
+    }
+
+    /// The values, in the order they were supplied.
+    pub fn values(&self) -> &[Vec<Value>] {
+        &self.values
+    }
+
+    /// The leaf hash of a value of the tree's types.
+    pub fn leaf_hash(&self, value: &[Value]) -> Result<Hash> {
+        leaf_hash(&self.leaf_encoding, value)
+    }
+
+    /// Generate a proof for the value at an offset of `values()`, the
+    /// siblings of its leaf from the bottom up.
+    pub fn proof(&self, offset: usize) -> Result<Vec<Hash>> {
+        let mut index =
+            *self
+                .tree_indices
+                .get(offset)
+                .ok_or(MerkleTreeError::OffsetOutOfBounds(
+                    offset,
+                    self.values.len(),
+                ))?;
+        let mut proof = Vec::new();
+
+        while let Some(sibling) = MerkleTree::sibling_index(index) {
+            proof.push(self.tree[sibling]);
+            index = MerkleTree::get_parent_index(index);
+        }
+
+        Ok(proof)
+    }
+
+    /// Verify a proof of a leaf hash against a root, as OpenZeppelin's
+    /// `MerkleProof.verify()` does.
+    pub fn verify(root: &Hash, leaf: &Hash, proof: &[Hash]) -> bool {
+        proof
+            .iter()
+            .fold(*leaf, |node, sibling| hash_pair(&node, sibling))
+            == *root
+    }
+}
+
+/// Hash a value of the given types as a leaf.
+fn leaf_hash<S: AsRef<str>>(leaf_encoding: &[S], value: &[Value]) -> Result<Hash> {
+    let keccak = Hasher::KECCAK_256.leaf;
+    Ok(keccak(&keccak(&abi_encode(leaf_encoding, value)?)))
+}
+
+/// Hash a pair of nodes, sorted.
+fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+    let config = Config {
+        hasher: Hasher::KECCAK_256,
+        sorted_pairs: true,
+        ..Config::default()
+    };
+
+    config.combine(left, right)
+}
+
+/// ABI encode values as a tuple of the given types, as `abi.encode()` does:
+/// a 32 byte head per value, holding static values in place and the offset
+/// of dynamic values, which follow the heads.
+fn abi_encode<S: AsRef<str>>(types: &[S], values: &[Value]) -> Result<Vec<u8>> {
+    if types.len() != values.len() {
+        return Err(MerkleTreeError::InvalidEncoding(format!(
+            "{} values for {} types",
+            values.len(),
+            types.len()
+        )));
+    }
+
+    let mut heads = Vec::with_capacity(types.len() * 32);
+    let mut tails = Vec::new();
+
+    for (kind, value) in types.iter().zip(values) {
+        match encode_value(kind.as_ref(), value)? {
+            Encoded::Static(word) => heads.extend_from_slice(&word),
+            Encoded::Dynamic(bytes) => {
+                let offset = types.len() * 32 + tails.len();
+                heads.extend_from_slice(&uint_word(offset as u64));
+                tails.extend_from_slice(&uint_word(bytes.len() as u64));
+                tails.extend_from_slice(&bytes);
+                tails.resize(tails.len().next_multiple_of(32), 0);
+            }
+        }
+    }
+
+    heads.extend_from_slice(&tails);
+    Ok(heads)
+}
+
+enum Encoded {
+    Static(Hash),
+    Dynamic(Vec<u8>),
+}
+
+fn encode_value(kind: &str, value: &Value) -> Result<Encoded> {
+    let mismatch = || MerkleTreeError::InvalidEncoding(format!("{value} isn't a {kind}"));
+
+    let encoded = match kind {
+        "address" => {
+            let mut word = EMPTY_NODE;
+            decode_hex(value.as_str().ok_or_else(mismatch)?, &mut word[12..])?;
+            Encoded::Static(word)
+        }
+        "bool" => {
+            let value = value.as_bool().ok_or_else(mismatch)?;
+            Encoded::Static(uint_word(value as u64))
+        }
+        "string" => {
+            let value = value.as_str().ok_or_else(mismatch)?;
+            Encoded::Dynamic(value.as_bytes().to_vec())
+        }
+        "bytes" => {
+            let value = value.as_str().ok_or_else(mismatch)?;
+            let digits = value.strip_prefix("0x").ok_or_else(mismatch)?;
+            Encoded::Dynamic(hex::decode(digits).map_err(|_| mismatch())?)
+        }
+        _ => {
+            if let Some(size) = parse_size(kind, "bytes") {
+                if !(1..=32).contains(&size) {
+                    return Err(unsupported(kind));
+                }
+
+                let mut word = EMPTY_NODE;
+                decode_hex(value.as_str().ok_or_else(mismatch)?, &mut word[..size])?;
+                Encoded::Static(word)
+            } else if let Some(bits) = parse_size(kind, "uint") {
+                let (negative, word) = parse_integer(value).ok_or_else(mismatch)?;
+
+                if negative || bit_length(&word) > bits {
+                    return Err(mismatch());
+                }
+
+                Encoded::Static(word)
+            } else if let Some(bits) = parse_size(kind, "int") {
+                let (negative, magnitude) = parse_integer(value).ok_or_else(mismatch)?;
+                let limit = bits - 1;
+
+                // -2^(bits - 1) <= value < 2^(bits - 1)
+                let fits = if negative {
+                    bit_length(&magnitude) <= limit || is_power_of_two(&magnitude, limit)
+                } else {
+                    bit_length(&magnitude) <= limit
+                };
+
+                if !fits {
+                    return Err(mismatch());
+                }
+
+                Encoded::Static(if negative {
+                    negate(&magnitude)
+                } else {
+                    magnitude
+                })
+            } else {
+                return Err(unsupported(kind));
+            }
+        }
+    };
+
+    Ok(encoded)
+}
+
+/// The size of a type like `uint256` or `bytes32`, defaulting to 256 bits
+/// for `uint` and `int`.
+fn parse_size(kind: &str, prefix: &str) -> Option<usize> {
+    let size = kind.strip_prefix(prefix)?;
+
+    if size.is_empty() && prefix != "bytes" {
+        return Some(256);
+    }
+
+    let size = size.parse::<usize>().ok()?;
+
+    if prefix == "bytes" {
+        return Some(size);
+    }
+
+    (size > 0 && size <= 256 && size.is_multiple_of(8)).then_some(size)
+}
+
+/// Parse an integer from a JSON number, or a decimal or `0x` prefixed hex
+/// string, into its sign and big-endian magnitude.
+fn parse_integer(value: &Value) -> Option<(bool, Hash)> {
+    let text = match value {
+        Value::Number(number) => number.to_string(),
+        Value::String(text) => text.clone(),
+        _ => return None,
+    };
+
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text.as_str()),
+    };
+
+    let (radix, digits) = match digits.strip_prefix("0x") {
+        Some(digits) => (16, digits),
+        None => (10, digits),
+    };
+
+    if digits.is_empty() {
+        return None;
+    }
+
+    let mut word = EMPTY_NODE;
+
+    for digit in digits.chars() {
+        let mut carry = digit.to_digit(radix)?;
+
+        for byte in word.iter_mut().rev() {
+            let product = *byte as u32 * radix + carry;
+            *byte = product as u8;
+            carry = product >> 8;
+        }
+
+        if carry != 0 {
+            return None;
+        }
+    }
+
+    Some((negative, word))
+}
+
+/// The number of bits needed to hold a big-endian word.
+fn bit_length(word: &Hash) -> usize {
+    word.iter().position(|byte| *byte != 0).map_or(0, |first| {
+        (32 - first) * 8 - word[first].leading_zeros() as usize
+    })
+}
+
+fn is_power_of_two(word: &Hash, exponent: usize) -> bool {
+    let mut power = EMPTY_NODE;
+    power[31 - exponent / 8] = 1 << (exponent % 8);
+    *word == power
+}
+
+/// The two's complement of a big-endian word.
+fn negate(word: &Hash) -> Hash {
+    let mut negated = word.map(|byte| !byte);
+
+    for byte in negated.iter_mut().rev() {
+        let (sum, overflow) = byte.overflowing_add(1);
+        *byte = sum;
+
+        if !overflow {
+            break;
+        }
+    }
+
+    negated
+}
+
+fn uint_word(value: u64) -> Hash {
+    let mut word = EMPTY_NODE;
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn decode_hex(value: &str, bytes: &mut [u8]) -> Result<()> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+
+    hex::decode_to_slice(digits, bytes)
+        .map_err(|error| MerkleTreeError::InvalidHex(format!("{value}: {error}")))
+}
+
+fn unsupported(kind: &str) -> MerkleTreeError {
+    MerkleTreeError::InvalidEncoding(format!("unsupported type {kind}"))
+}
+
+fn invalid(error: serde_json::Error) -> MerkleTreeError {
+    MerkleTreeError::InvalidEncoding(error.to_string())
+}
+
+fn to_hex(hash: &Hash) -> String {
+    format!("0x{}", hex::encode(hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn values() -> Vec<Vec<Value>> {
+        vec![
+            vec![
+                json!("0x1111111111111111111111111111111111111111"),
+                json!("5000000000000000000"),
+            ],
+            vec![
+                json!("0x2222222222222222222222222222222222222222"),
+                json!("2500000000000000000"),
+            ],
+            vec![
+                json!("0x3333333333333333333333333333333333333333"),
+                json!(42),
+            ],
+        ]
+    }
+
+    #[test]
+    fn abi_encodes_values() {
+        let encoded = abi_encode(
+            &["address", "uint256", "string", "int8", "bytes4", "bool"],
+            &[
+                json!("0x1111111111111111111111111111111111111111"),
+                json!("0x100"),
+                json!("hi"),
+                json!(-1),
+                json!("0xdeadbeef"),
+                json!(true),
+            ],
+        )
+        .unwrap();
+
+        let words = encoded.as_chunks::<32>().0;
+        assert_eq!(words.len(), 8);
+        assert_eq!(words[0][..12], [0; 12]);
+        assert_eq!(words[0][12..], [0x11; 20]);
+        assert_eq!(words[1], uint_word(256));
+        assert_eq!(words[2], uint_word(6 * 32));
+        assert_eq!(words[3], [0xff; 32]);
+        assert_eq!(words[4][..4], [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(words[5], uint_word(1));
+        assert_eq!(words[6], uint_word(2));
+        assert_eq!(words[7][..3], *b"hi\0");
+
+        assert!(abi_encode(&["uint8"], &[json!(256)]).is_err());
+        assert!(abi_encode(&["int8"], &[json!(-128)]).is_ok());
+        assert!(abi_encode(&["int8"], &[json!(-129)]).is_err());
+        assert!(abi_encode(&["int8"], &[json!(128)]).is_err());
+        assert!(abi_encode(&["uint256[]"], &[json!([1])]).is_err());
+        assert!(abi_encode(&["uint256"], &[json!(1), json!(2)]).is_err());
+    }
+
+    #[test]
+    fn matches_openzeppelin() {
+        // the example of OpenZeppelin's merkle-tree README
+        let tree = StandardMerkleTree::of(values()[..2].to_vec(), &["address", "uint256"]).unwrap();
+
+        assert_eq!(
+            to_hex(&tree.root()),
+            "0xd4dee0beab2d53f2cc83e567171bd2820e49898130a22622b10ead383e90bd77"
+        );
+    }
+
+    #[test]
+    fn builds_the_standard_layout() {
+        let tree = StandardMerkleTree::of(values(), &["address", "uint256"]).unwrap();
+        let leaves = values()
+            .iter()
+            .map(|value| tree.leaf_hash(value).unwrap())
+            .collect::<Vec<_>>();
+
+        // leaves are sorted, and stored last in reverse order
+        let mut sorted = leaves.clone();
+        sorted.sort();
+        assert_eq!(tree.tree[2..], [sorted[2], sorted[1], sorted[0]]);
+        assert_eq!(tree.tree[1], hash_pair(&sorted[0], &sorted[1]));
+        assert_eq!(tree.root(), hash_pair(&tree.tree[1], &sorted[2]));
+
+        for (offset, leaf) in leaves.iter().enumerate() {
+            let proof = tree.proof(offset).unwrap();
+            assert!(StandardMerkleTree::verify(&tree.root(), leaf, &proof));
+        }
+        assert!(tree.proof(3).is_err());
+    }
+
+    #[test]
+    fn dumps_and_loads_trees() {
+        let tree = StandardMerkleTree::of(values(), &["address", "uint256"]).unwrap();
+        let dump = tree.dump();
+
+        let parsed = serde_json::from_str::<Value>(&dump).unwrap();
+        assert_eq!(parsed["format"], "standard-v1");
+        assert_eq!(parsed["leafEncoding"], json!(["address", "uint256"]));
+        assert_eq!(parsed["tree"][0], to_hex(&tree.root()));
+        assert_eq!(
+            parsed["values"][2]["value"],
+            json!(["0x3333333333333333333333333333333333333333", 42])
+        );
+
+        assert_eq!(StandardMerkleTree::load(&dump).unwrap(), tree);
+
+        let mut tampered = parsed.clone();
+        tampered["values"][0]["value"][1] = json!("1");
+        assert!(StandardMerkleTree::load(&tampered.to_string()).is_err());
+
+        let mut tampered = parsed;
+        tampered["format"] = json!("simple-v1");
+        assert!(StandardMerkleTree::load(&tampered.to_string()).is_err());
+    }
+}
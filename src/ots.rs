@@ -0,0 +1,227 @@
+//! Export proofs as OpenTimestamps operations, so leaves committed in a tree
+//! can be anchored by timestamping its root, and verified by OTS tooling.
+//!
+//! A proof becomes, for each sibling, an append or prepend of the sibling
+//! followed by the tree's node hash, which takes the leaf to the root.  A
+//! timestamp of the root (e.g. from `ots stamp` of the root, or a pending
+//! attestation from a calendar that the root was submitted to) then follows,
+//! making a timestamp of the leaf.  Only trees whose hasher has an OTS
+//! operation can be exported: `Hasher::KECCAK_256`, and the SHA-256 hasher
+//! of the `ssz` feature.
+//!
+//! Each leaf must be the hash of its data (as `build_from_data()` hashes
+//! them), since the detached timestamp file of a leaf records the leaf as
+//! the digest of the timestamped file.
+
+use crate::builder::{Config, Hasher, MerkleTreeBuilder};
+use crate::error::{MerkleTreeError, Result};
+use crate::{Direction, Hash, MerkleTree};
+use std::borrow::Borrow;
+
+/// The header of a detached timestamp file, before its version.
+const HEADER_MAGIC: &[u8] = b"\x00OpenTimestamps\x00\x00Proof\x00\xbf\x89\xe2\xe8\x84\xe8\x92\x94";
+
+/// The version of detached timestamp files written.
+const VERSION: u8 = 1;
+
+const APPEND: u8 = 0xf0;
+const PREPEND: u8 = 0xf1;
+#[cfg(feature = "ssz")]
+const SHA256: u8 = 0x08;
+const KECCAK256: u8 = 0x67;
+
+/// Precedes an attestation of a timestamp.
+const ATTESTATION: u8 = 0x00;
+
+/// The tag of an attestation that the digest was submitted to a calendar.
+const PENDING_ATTESTATION: [u8; 8] = [0x83, 0xdf, 0xe3, 0x0d, 0x2e, 0xf9, 0x0c, 0x8e];
+
+impl Config {
+    /// The OTS operation of the hasher.
+    fn ots_hash_op(&self) -> Result<u8> {
+        match self.hasher.id {
+            id if id == Hasher::KECCAK_256.id => Ok(KECCAK256),
+            #[cfg(feature = "ssz")]
+            id if id == Hasher::SHA_256.id => Ok(SHA256),
+            id => Err(MerkleTreeError::InvalidEncoding(format!(
+                "OpenTimestamps has no operation for hasher {id}"
+            ))),
+        }
+    }
+}
+
+impl MerkleTreeBuilder {
+    /// The OTS operations taking a leaf to the root of a tree built with
+    /// these options, along its proof.
+    pub fn ots_operations<H: Borrow<Hash>>(
+        &self,
+        proof: &[(Direction, H)],
+        leaf: &Hash,
+    ) -> Result<Vec<u8>> {
+        operations(&self.config, proof, leaf)
+    }
+}
+
+impl MerkleTree {
+    /// The OTS operations taking the leaf at an offset to the root.
+    pub fn ots_operations(&self, offset: usize) -> Result<Vec<u8>> {
+        operations(&self.config, &self.proof_at(offset)?, &self[offset])
+    }
+
+    /// The detached timestamp file (`.ots`) of the leaf at an offset, given
+    /// a serialized timestamp of the root.
+    ///
+    /// ```rust
+    /// use merkle_tree::builder::Hasher;
+    /// use merkle_tree::{ots, MerkleTree};
+    ///
+    /// let tree = MerkleTree::builder()
+    ///     .hasher(Hasher::KECCAK_256)
+    ///     .build_from_data(["a", "b", "c"])
+    ///     .unwrap();
+    ///
+    /// // once the root has been submitted to a calendar
+    /// let root_timestamp = ots::pending_attestation("https://alice.btc.calendar.opentimestamps.org");
+    /// let file = tree.ots_timestamp(1, &root_timestamp).unwrap();
+    /// assert!(file.starts_with(b"\x00OpenTimestamps\x00\x00Proof\x00"));
+    /// ```
+    pub fn ots_timestamp(&self, offset: usize, root_timestamp: &[u8]) -> Result<Vec<u8>> {
+        let hash_op = self.config.ots_hash_op()?;
+        let operations = self.ots_operations(offset)?;
+
+        Ok([
+            HEADER_MAGIC,
+            &[VERSION, hash_op],
+            &self[offset],
+            &operations,
+            root_timestamp,
+        ]
+        .concat())
+    }
+}
+
+/// A serialized timestamp attesting that a digest was submitted to the
+/// calendar at `uri`, to be upgraded later with `ots upgrade`.
+pub fn pending_attestation(uri: &str) -> Vec<u8> {
+    let mut payload = Vec::new();
+    put_bytes(&mut payload, uri.as_bytes());
+
+    let mut timestamp = vec![ATTESTATION];
+    timestamp.extend_from_slice(&PENDING_ATTESTATION);
+    put_bytes(&mut timestamp, &payload);
+    timestamp
+}
+
+fn operations<H: Borrow<Hash>>(
+    config: &Config,
+    proof: &[(Direction, H)],
+    leaf: &Hash,
+) -> Result<Vec<u8>> {
+    let hash_op = config.ots_hash_op()?;
+    let mut operations = Vec::with_capacity(proof.len() * 35);
+    let mut current = *leaf;
+
+    for (direction, sibling) in proof {
+        let sibling = sibling.borrow();
+
+        // with sorted pairs, the side is decided by the hashes themselves
+        let left = match direction {
+            _ if config.sorted_pairs => sibling < &current,
+            Direction::Left => true,
+            Direction::Right => false,
+        };
+
+        operations.push(if left { PREPEND } else { APPEND });
+        put_bytes(&mut operations, sibling);
+        operations.push(hash_op);
+
+        current = if left {
+            config.combine(sibling, &current)
+        } else {
+            config.combine(&current, sibling)
+        };
+    }
+
+    Ok(operations)
+}
+
+/// Write bytes prefixed by their length as a varuint.
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    let mut len = bytes.len();
+
+    while len >= 0x80 {
+        out.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+
+    out.push(len as u8);
+    out.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha3::{Digest, Keccak256};
+
+    /// Evaluate a linear timestamp as OTS tooling would, returning the
+    /// commitment its attestation is for and the attestation's tag.
+    fn evaluate(mut timestamp: &[u8], mut message: Vec<u8>) -> (Vec<u8>, [u8; 8]) {
+        let bytes = |timestamp: &mut &[u8]| {
+            let len = timestamp[0] as usize;
+            let (bytes, rest) = timestamp[1..].split_at(len);
+            *timestamp = rest;
+            bytes.to_vec()
+        };
+
+        loop {
+            let (tag, rest) = timestamp.split_first().unwrap();
+            timestamp = rest;
+
+            match *tag {
+                APPEND => message.extend(bytes(&mut timestamp)),
+                PREPEND => message = [bytes(&mut timestamp), message].concat(),
+                KECCAK256 => message = Keccak256::digest(&message).to_vec(),
+                ATTESTATION => return (message, timestamp[..8].try_into().unwrap()),
+                tag => panic!("unexpected tag {tag:#x}"),
+            }
+        }
+    }
+
+    #[test]
+    fn takes_each_leaf_to_the_root() {
+        let data = (0..7u8).map(|i| vec![i; 3]).collect::<Vec<_>>();
+        let root_timestamp = pending_attestation("https://calendar.example");
+
+        for sorted_pairs in [false, true] {
+            let tree = MerkleTree::builder()
+                .hasher(Hasher::KECCAK_256)
+                .sorted_pairs(sorted_pairs)
+                .build_from_data(&data)
+                .unwrap();
+
+            for (offset, data) in data.iter().enumerate() {
+                let file = tree.ots_timestamp(offset, &root_timestamp).unwrap();
+                let (header, rest) = file.split_at(HEADER_MAGIC.len() + 2);
+                assert_eq!(header, [HEADER_MAGIC, &[VERSION, KECCAK256]].concat());
+
+                // the file digest is the hash of the data
+                let (digest, timestamp) = rest.split_at(32);
+                assert_eq!(digest, Keccak256::digest(data).as_slice());
+
+                let (commitment, tag) = evaluate(timestamp, digest.to_vec());
+                assert_eq!(commitment, tree.root());
+                assert_eq!(tag, PENDING_ATTESTATION);
+            }
+        }
+    }
+
+    #[test]
+    fn requires_a_hasher_with_an_operation() {
+        let tree = MerkleTree::builder().build_from_data(["a", "b"]).unwrap();
+        assert!(tree.ots_operations(0).is_err());
+
+        let mut long = Vec::new();
+        put_bytes(&mut long, &[0; 300]);
+        assert_eq!(long[..2], [0xac, 0x02]);
+    }
+}
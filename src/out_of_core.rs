@@ -0,0 +1,573 @@
+//! Out-of-core construction of tree files larger than RAM.
+//!
+//! `MerkleTreeBuilder::build_file()` writes a tree file in the format of the
+//! `file` module from leaves pushed one at a time, e.g. streamed from disk.
+//! The number of leaves is fixed up front, which fixes the layout, so every
+//! node is written to its place in the file as soon as it's hashed.  Only
+//! the last unpaired node of each level and a small write buffer per level
+//! are kept in memory, O(log n) in all, and the finished file can be loaded,
+//! mapped or queried like any other.
+//!
+//! Options that need every leaf at once (sorting, deduplication and
+//! rejecting duplicates) don't apply to files built this way.
+//!
+//! Long builds can be checkpointed, see `FileBuilder::checkpoint()`.  A
+//! checkpoint writes every buffered node to the file and syncs it, then
+//! records the number of leaves pushed and the unpaired nodes in a file next
+//! to it, with `.checkpoint` appended to its name.  After an interruption,
+//! `MerkleTreeBuilder::resume_file()` picks up from the last checkpoint, and
+//! pushing continues from the leaf at `pushed()`; nodes written since the
+//! checkpoint are simply written again.  The checkpoint file is removed once
+//! the tree is finished.
+//!
+//! A checkpoint is the magic `MRKLCKPT`, the number of leaves and the number
+//! pushed, then for each level below the root a byte set if it has an
+//! unpaired node and that node (or 32 zeros), followed by a SHA3-256 checksum
+//! of every preceding byte, with all integers little-endian `u64`s.
+
+use crate::builder::{Config, MerkleTreeBuilder};
+use crate::bytes::{encode_header, HEADER_LEN};
+use crate::error::{MerkleTreeError, Result};
+use crate::file::{file_header, CHECKSUM_LEN, FILE_HEADER_LEN};
+use crate::{Hash, MerkleTree, EMPTY_NODE};
+use sha3::{Digest, Sha3_256};
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// The number of nodes of a level buffered before they're written.
+pub const BUFFER_LEN: usize = 1024;
+
+/// The bytes every checkpoint starts with.
+const CHECKPOINT_MAGIC: [u8; 8] = *b"MRKLCKPT";
+
+/// Builds a tree file from leaves pushed one at a time.  Call `finish()` once
+/// every leaf has been pushed.
+///
+/// ```rust
+/// use merkle_tree::MerkleTree;
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+/// let path = std::env::temp_dir().join("merkle-tree-out-of-core-example.tree");
+///
+/// let mut builder = MerkleTree::builder().build_file(&path, leaves.len()).unwrap();
+/// for leaf in leaves {
+///     builder.push(leaf).unwrap();
+/// }
+/// let root = builder.finish().unwrap();
+///
+/// let tree = MerkleTree::load_from_path(&path).unwrap();
+/// assert_eq!(tree, MerkleTree::new(&leaves).unwrap());
+/// assert_eq!(root, tree.root());
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct FileBuilder {
+    file: File,
+    path: PathBuf,
+    checkpoint_interval: usize,
+    config: Config,
+    leaf_count: usize,
+    num_levels: usize,
+    pushed: usize,
+    /// The last node of each height above the leaves that is still waiting
+    /// for its sibling.
+    unpaired: Vec<Option<Hash>>,
+    /// Nodes of each height not yet written, and the index of the first.
+    buffers: Vec<(usize, Vec<Hash>)>,
+    empty_subtrees: Vec<Hash>,
+}
+
+impl MerkleTreeBuilder {
+    /// Start building a tree of `leaf_count` leaves in a file at `path`,
+    /// replacing any existing file, see the `out_of_core` module.
+    pub fn build_file<P: AsRef<Path>>(&self, path: P, leaf_count: usize) -> Result<FileBuilder> {
+        if leaf_count == 0 {
+            return Err(MerkleTreeError::Empty);
+        }
+
+        let headers = self.file_headers(leaf_count)?;
+        let node_count = self.config.padded_count(leaf_count)? * 2 - 1;
+
+        // unwritten slots read back as zeros, which are empty nodes
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        file.write_all(&headers)?;
+        file.set_len(node_offset(node_count) + CHECKSUM_LEN as u64)?;
+
+        // a checkpoint of a previous build of the file no longer applies
+        remove_if_exists(&checkpoint_path(path.as_ref()))?;
+
+        self.file_builder(file, path.as_ref(), leaf_count)
+    }
+
+    /// Resume building a tree file from its last checkpoint, with the options
+    /// it was started with.  Push leaves from the one at `pushed()`.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = (0..10u8).map(|i| MerkleTree::hash(&[i])).collect::<Vec<_>>();
+    /// let path = std::env::temp_dir().join("merkle-tree-resume-example.tree");
+    /// let builder = MerkleTree::builder().checkpoint_interval(4);
+    ///
+    /// let mut file = builder.build_file(&path, leaves.len()).unwrap();
+    /// for leaf in &leaves[..6] {
+    ///     file.push(*leaf).unwrap();
+    /// }
+    /// drop(file);
+    ///
+    /// let mut file = builder.resume_file(&path).unwrap();
+    /// for leaf in &leaves[file.pushed()..] {
+    ///     file.push(*leaf).unwrap();
+    /// }
+    /// assert_eq!(file.finish().unwrap(), MerkleTree::new(&leaves).unwrap().root());
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn resume_file<P: AsRef<Path>>(&self, path: P) -> Result<FileBuilder> {
+        let mut file = File::options().read(true).write(true).open(&path)?;
+        let checkpoint = std::fs::read(checkpoint_path(path.as_ref()))?;
+        let invalid = |reason: &str| MerkleTreeError::InvalidEncoding(reason.to_owned());
+
+        let (contents, checksum) = checkpoint
+            .split_last_chunk::<32>()
+            .ok_or_else(|| invalid("truncated checkpoint"))?;
+
+        if Sha3_256::digest(contents).as_slice() != checksum {
+            return Err(MerkleTreeError::ChecksumMismatch);
+        }
+
+        let (header, unpaired) = contents
+            .split_first_chunk::<24>()
+            .ok_or_else(|| invalid("truncated checkpoint"))?;
+        let u64_at = |offset: usize| {
+            u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap()) as usize
+        };
+
+        if header[0..8] != CHECKPOINT_MAGIC {
+            return Err(invalid("not a checkpoint"));
+        }
+
+        let (leaf_count, pushed) = (u64_at(8), u64_at(16));
+        let headers = self.file_headers(leaf_count)?;
+        let mut found = vec![0; headers.len()];
+        file.read_exact(&mut found)?;
+
+        if found != headers {
+            return Err(invalid("file was started with other options"));
+        }
+
+        let mut builder = self.file_builder(file, path.as_ref(), leaf_count)?;
+        let (unpaired, rest) = unpaired.as_chunks::<33>();
+
+        if pushed > leaf_count || unpaired.len() != builder.num_levels || !rest.is_empty() {
+            return Err(invalid("checkpoint doesn't match the file"));
+        }
+
+        builder.pushed = pushed;
+        builder.unpaired = unpaired
+            .iter()
+            .map(|node| (node[0] != 0).then(|| node[1..].try_into().unwrap()))
+            .collect();
+
+        Ok(builder)
+    }
+
+    /// The file header and tree header of a file of `leaf_count` leaves.
+    fn file_headers(&self, leaf_count: usize) -> Result<Vec<u8>> {
+        let node_count = self.config.padded_count(leaf_count)? * 2 - 1;
+        let tree_header = encode_header(leaf_count, node_count, &self.config);
+
+        Ok([&file_header(0)[..], &tree_header].concat())
+    }
+
+    fn file_builder(&self, file: File, path: &Path, leaf_count: usize) -> Result<FileBuilder> {
+        let num_levels = self.config.padded_count(leaf_count)?.ilog2() as usize;
+
+        Ok(FileBuilder {
+            file,
+            path: path.to_owned(),
+            checkpoint_interval: self.checkpoint_interval,
+            config: self.config,
+            leaf_count,
+            num_levels,
+            pushed: 0,
+            unpaired: vec![None; num_levels],
+            buffers: vec![(0, Vec::new()); num_levels + 1],
+            empty_subtrees: self.config.empty_subtrees(num_levels),
+        })
+    }
+
+    /// Build a tree file at `output` from a file of leaves, each 32 bytes,
+    /// returning the root.
+    ///
+    /// ```rust,no_run
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let root = MerkleTree::builder()
+    ///     .build_file_from_path("/path/to/leaves", "/path/to/tree.file")
+    ///     .unwrap();
+    /// ```
+    pub fn build_file_from_path<P, Q>(&self, leaves: P, output: Q) -> Result<Hash>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let leaves = File::open(leaves)?;
+        let length = leaves.metadata()?.len() as usize;
+
+        if !length.is_multiple_of(size_of::<Hash>()) {
+            return Err(MerkleTreeError::InvalidEncoding(format!(
+                "{length} bytes aren't a whole number of leaves"
+            )));
+        }
+
+        let mut builder = self.build_file(output, length / size_of::<Hash>())?;
+        builder.read_leaves(BufReader::new(leaves))?;
+        builder.finish()
+    }
+}
+
+impl FileBuilder {
+    /// The number of leaves pushed so far.
+    pub fn pushed(&self) -> usize {
+        self.pushed
+    }
+
+    /// Push the next leaf.
+    pub fn push(&mut self, leaf: Hash) -> Result<()> {
+        if self.pushed == self.leaf_count {
+            return Err(MerkleTreeError::LeafCountMismatch(
+                self.leaf_count,
+                self.pushed + 1,
+            ));
+        }
+
+        self.pushed += 1;
+        self.add(0, self.pushed - 1, leaf)?;
+
+        if self.checkpoint_interval > 0 && self.pushed.is_multiple_of(self.checkpoint_interval) {
+            self.checkpoint()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write every buffered node and sync the file, then record how far the
+    /// build has got, so it can be resumed from here with
+    /// `MerkleTreeBuilder::resume_file()`.
+    pub fn checkpoint(&mut self) -> Result<()> {
+        for height in 0..=self.num_levels {
+            self.flush(height)?;
+        }
+
+        self.file.sync_data()?;
+
+        let mut checkpoint = Vec::with_capacity(24 + self.num_levels * 33 + 32);
+        checkpoint.extend_from_slice(&CHECKPOINT_MAGIC);
+        checkpoint.extend_from_slice(&(self.leaf_count as u64).to_le_bytes());
+        checkpoint.extend_from_slice(&(self.pushed as u64).to_le_bytes());
+
+        for node in &self.unpaired {
+            checkpoint.push(node.is_some() as u8);
+            checkpoint.extend_from_slice(&node.unwrap_or(EMPTY_NODE));
+        }
+
+        let checksum: Hash = Sha3_256::digest(&checkpoint).into();
+        checkpoint.extend_from_slice(&checksum);
+
+        // replace the previous checkpoint atomically
+        let path = checkpoint_path(&self.path);
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+
+        let mut file = File::create(&temporary)?;
+        file.write_all(&checkpoint)?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, &path)?;
+
+        Ok(())
+    }
+
+    /// Push every leaf read from `reader`, each 32 bytes, until it ends.
+    pub fn read_leaves<R: Read>(&mut self, mut reader: R) -> Result<()> {
+        let mut leaf = Hash::default();
+
+        loop {
+            match reader.read_exact(&mut leaf) {
+                Ok(()) => self.push(leaf)?,
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+
+    /// Pad the last node of each level, write the remaining nodes and the
+    /// checksum, and return the root.  Every leaf must have been pushed.
+    pub fn finish(mut self) -> Result<Hash> {
+        if self.pushed != self.leaf_count {
+            return Err(MerkleTreeError::LeafCountMismatch(
+                self.leaf_count,
+                self.pushed,
+            ));
+        }
+
+        // each padded parent may complete a pair, or be padded, above it
+        for height in 0..self.num_levels {
+            if let Some(last) = self.unpaired[height].take() {
+                let index = self.level_width(height);
+                let parent = match self
+                    .config
+                    .padding
+                    .sibling(&last, self.empty_subtree(height))
+                {
+                    Some(sibling) => {
+                        self.write(height, index, sibling)?;
+                        self.config.combine(&last, &sibling)
+                    }
+                    None => last,
+                };
+
+                self.add(height + 1, index / 2, parent)?;
+            }
+        }
+
+        let root = self.buffers[self.num_levels].1[0];
+
+        for height in 0..=self.num_levels {
+            self.flush(height)?;
+        }
+
+        self.write_checksum()?;
+        remove_if_exists(&checkpoint_path(&self.path))?;
+
+        Ok(root)
+    }
+
+    /// Add a node, and its parent once its sibling has been added.
+    fn add(&mut self, height: usize, index: usize, node: Hash) -> Result<()> {
+        self.write(height, index, node)?;
+
+        if height == self.num_levels {
+            return Ok(());
+        }
+
+        if index.is_multiple_of(2) {
+            self.unpaired[height] = Some(node);
+            return Ok(());
+        }
+
+        let left = self.unpaired[height]
+            .take()
+            .expect("left sibling was added");
+        let parent = self.config.combine(&left, &node);
+        self.add(height + 1, index / 2, parent)
+    }
+
+    /// Buffer a node to be written, writing the buffer once it's full.
+    fn write(&mut self, height: usize, index: usize, node: Hash) -> Result<()> {
+        let (start, nodes) = &mut self.buffers[height];
+
+        if nodes.is_empty() {
+            *start = index;
+        }
+
+        nodes.push(node);
+
+        if nodes.len() == BUFFER_LEN {
+            self.flush(height)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the buffered nodes of a height to their place in the file.
+    fn flush(&mut self, height: usize) -> Result<()> {
+        let (start, nodes) = &mut self.buffers[height];
+
+        if nodes.is_empty() {
+            return Ok(());
+        }
+
+        let level = self.num_levels - height;
+        let index = MerkleTree::get_level_start(level) + *start;
+
+        self.file.seek(SeekFrom::Start(node_offset(index)))?;
+        self.file.write_all(nodes.as_flattened())?;
+        nodes.clear();
+
+        Ok(())
+    }
+
+    /// Checksum every byte written, reading the file back, and append the
+    /// checksum.
+    fn write_checksum(&mut self) -> Result<()> {
+        let length = self.file.metadata()?.len() - CHECKSUM_LEN as u64;
+        let mut checksum = Sha3_256::new();
+        let mut contents = BufReader::new(&self.file);
+
+        contents.seek(SeekFrom::Start(0))?;
+        std::io::copy(&mut contents.take(length), &mut checksum)?;
+
+        let checksum: Hash = checksum.finalize().into();
+        self.file.seek(SeekFrom::Start(length))?;
+        self.file.write_all(&checksum)?;
+        self.file.sync_all()?;
+
+        Ok(())
+    }
+
+    /// The number of nodes of a height above the leaves, excluding padding.
+    fn level_width(&self, height: usize) -> usize {
+        self.leaf_count.div_ceil(1 << height)
+    }
+
+    fn empty_subtree(&self, height: usize) -> &Hash {
+        self.empty_subtrees.get(height).unwrap_or(&EMPTY_NODE)
+    }
+}
+
+/// The path of the checkpoint of a tree file.
+fn checkpoint_path(path: &Path) -> PathBuf {
+    let mut checkpoint = path.as_os_str().to_owned();
+    checkpoint.push(".checkpoint");
+    checkpoint.into()
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(error) if error.kind() != ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+/// The offset of the node at an array index in a tree file.
+fn node_offset(index: usize) -> u64 {
+    (FILE_HEADER_LEN + HEADER_LEN + index * size_of::<Hash>()) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::padding::{PromoteOdd, ZeroHash};
+
+    fn leaves(count: u32) -> Vec<Hash> {
+        (0..count)
+            .map(|i| MerkleTree::hash(&i.to_le_bytes()))
+            .collect()
+    }
+
+    fn path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "merkle-tree-out-of-core-{name}-{}.tree",
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn builds_the_same_tree_as_in_memory() {
+        let output = path("same");
+
+        for builder in [
+            MerkleTree::builder(),
+            MerkleTree::builder().sorted_pairs(true).depth(5),
+            MerkleTree::builder().padding(&ZeroHash),
+            MerkleTree::builder().padding(&PromoteOdd),
+        ] {
+            for count in [1, 2, 3, 5, 8, 13] {
+                let leaves = leaves(count);
+                let mut file = builder.build_file(&output, leaves.len()).unwrap();
+
+                for leaf in &leaves {
+                    file.push(*leaf).unwrap();
+                }
+
+                let tree = builder.build(&leaves).unwrap();
+                assert_eq!(file.finish().unwrap(), tree.root());
+                assert_eq!(builder.load_from_path(&output).unwrap(), tree);
+            }
+        }
+
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn streams_leaves_from_a_file() {
+        let (input, output) = (path("leaves"), path("streamed"));
+        let leaves = leaves(BUFFER_LEN as u32 * 3 + 7);
+        std::fs::write(&input, leaves.as_flattened()).unwrap();
+
+        let builder = MerkleTree::builder();
+        let root = builder.build_file_from_path(&input, &output).unwrap();
+        let tree = MerkleTree::load_from_path(&output).unwrap();
+
+        assert_eq!(root, tree.root());
+        assert_eq!(tree, MerkleTree::new(&leaves).unwrap());
+
+        // a partial leaf is rejected
+        std::fs::write(&input, &leaves.as_flattened()[..40]).unwrap();
+        assert!(builder.build_file_from_path(&input, &output).is_err());
+
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn requires_every_leaf() {
+        let output = path("count");
+        let builder = MerkleTree::builder();
+
+        let mut file = builder.build_file(&output, 2).unwrap();
+        file.push(MerkleTree::hash(b"a")).unwrap();
+        assert_eq!(file.pushed(), 1);
+        assert!(matches!(
+            file.finish(),
+            Err(MerkleTreeError::LeafCountMismatch(2, 1))
+        ));
+
+        let mut file = builder.build_file(&output, 1).unwrap();
+        file.push(MerkleTree::hash(b"a")).unwrap();
+        assert!(file.push(MerkleTree::hash(b"b")).is_err());
+
+        assert!(builder.build_file(&output, 0).is_err());
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn resumes_from_checkpoints() {
+        let output = path("resume");
+        let leaves = leaves(1000);
+        let builder = MerkleTree::builder()
+            .sorted_pairs(true)
+            .checkpoint_interval(100);
+
+        // interrupted between checkpoints, with nodes still buffered
+        let mut file = builder.build_file(&output, leaves.len()).unwrap();
+        for leaf in &leaves[..350] {
+            file.push(*leaf).unwrap();
+        }
+        drop(file);
+
+        assert!(MerkleTree::builder().resume_file(&output).is_err());
+
+        let mut file = builder.resume_file(&output).unwrap();
+        assert_eq!(file.pushed(), 300);
+        for leaf in &leaves[file.pushed()..] {
+            file.push(*leaf).unwrap();
+        }
+        file.finish().unwrap();
+
+        assert_eq!(
+            builder.load_from_path(&output).unwrap(),
+            builder.build(&leaves).unwrap()
+        );
+        assert!(!checkpoint_path(&output).exists());
+        assert!(builder.resume_file(&output).is_err());
+
+        std::fs::remove_file(&output).unwrap();
+    }
+}
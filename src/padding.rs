@@ -0,0 +1,256 @@
+//! Strategies for the last node of a level with an odd number of nodes.
+//!
+//! Ecosystems disagree on how to handle odd nodes: Bitcoin duplicates them,
+//! sparse and incremental trees pair them with empty subtrees, Certificate
+//! Transparency promotes them up a level unhashed, and some verifiers only
+//! accept full trees.  The strategy is chosen with
+//! `MerkleTreeBuilder::padding()` and kept with the tree.
+
+use crate::builder::Config;
+use crate::error::{MerkleTreeError, Result};
+use crate::{Hash, MerkleTree, EMPTY_NODE};
+use std::fmt;
+
+/// How the last node of a level with an odd number of nodes is paired.
+pub trait PaddingStrategy: fmt::Debug + Send + Sync {
+    /// Recorded when a tree is persisted, so that it's only loaded with the
+    /// same strategy.  Ids below 256 are reserved for the strategies of this
+    /// crate.
+    fn id(&self) -> u32;
+
+    /// Check that `leaf_count` leaves may be held in a layout sized for
+    /// `padded_count` leaves.  Every mutation that changes the number of
+    /// leaves checks first.
+    fn check(&self, leaf_count: usize, padded_count: usize) -> Result<()> {
+        let _ = (leaf_count, padded_count);
+        Ok(())
+    }
+
+    /// Whether `sibling()` needs the hashes of empty subtrees, so that they
+    /// are precomputed.
+    fn uses_empty_subtrees(&self) -> bool {
+        false
+    }
+
+    /// The sibling to pair the last node of an odd level with, given the hash
+    /// of an empty subtree of the same height (all zeros unless
+    /// `uses_empty_subtrees()`).  `None` promotes the node to the level above
+    /// unhashed, which leaves it without a sibling in proofs.
+    fn sibling(&self, last: &Hash, empty_subtree: &Hash) -> Option<Hash>;
+}
+
+/// Pair the node with a duplicate of itself, as Bitcoin does.  The duplicate
+/// has the same proof as a real node, so the last leaf can also be proven at
+/// the padded offset.  This is the default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DuplicateLast;
+
+impl PaddingStrategy for DuplicateLast {
+    fn id(&self) -> u32 {
+        0
+    }
+
+    fn sibling(&self, last: &Hash, _empty_subtree: &Hash) -> Option<Hash> {
+        Some(*last)
+    }
+}
+
+/// Pair the node with the hash of an empty subtree of the same height, where
+/// an empty leaf is all zeros and an empty subtree is the hash of two empty
+/// subtrees one level down.  Padding is never a real leaf.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ZeroHash;
+
+impl PaddingStrategy for ZeroHash {
+    fn id(&self) -> u32 {
+        1
+    }
+
+    fn uses_empty_subtrees(&self) -> bool {
+        true
+    }
+
+    fn sibling(&self, _last: &Hash, empty_subtree: &Hash) -> Option<Hash> {
+        Some(*empty_subtree)
+    }
+}
+
+/// Promote the node to the level above unhashed, so a tree of `n` leaves is
+/// the RFC 6962 (Certificate Transparency) tree of those leaves, and proofs
+/// of leaves in the last subtree are shorter.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PromoteOdd;
+
+impl PaddingStrategy for PromoteOdd {
+    fn id(&self) -> u32 {
+        2
+    }
+
+    fn sibling(&self, _last: &Hash, _empty_subtree: &Hash) -> Option<Hash> {
+        None
+    }
+}
+
+/// Refuse to pad: the number of leaves must fill the tree, so it must be a
+/// power of two (at least 2), or exactly `2^depth` for a fixed depth.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RequirePowerOfTwo;
+
+impl PaddingStrategy for RequirePowerOfTwo {
+    fn id(&self) -> u32 {
+        3
+    }
+
+    fn check(&self, leaf_count: usize, padded_count: usize) -> Result<()> {
+        if leaf_count != padded_count {
+            return Err(MerkleTreeError::PaddingRequired(leaf_count));
+        }
+
+        Ok(())
+    }
+
+    fn sibling(&self, _last: &Hash, _empty_subtree: &Hash) -> Option<Hash> {
+        unreachable!("a tree without padding has no odd levels")
+    }
+}
+
+impl MerkleTree {
+    /// The hashes of empty subtrees by height, where height 0 is an empty
+    /// leaf, for padding strategies that use them (e.g. `ZeroHash`).  Empty
+    /// otherwise.
+    ///
+    /// ```rust
+    /// use merkle_tree::{padding::ZeroHash, MerkleTree};
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::builder().padding(&ZeroHash).build(&leaves).unwrap();
+    /// let empty_subtrees = tree.empty_subtrees();
+    ///
+    /// assert_eq!(empty_subtrees[0], [0; 32]);
+    /// assert_eq!(empty_subtrees[1], MerkleTree::concat(&[0; 32], &[0; 32]));
+    /// ```
+    pub fn empty_subtrees(&self) -> &[Hash] {
+        &self.empty_subtrees
+    }
+
+    /// The sibling the last node of a level with an odd number of nodes is
+    /// paired with, where the level is `height` above the leaves, or `None`
+    /// if the node is promoted.
+    pub(crate) fn padding_for(&self, last: &Hash, height: usize) -> Option<Hash> {
+        self.config.padding_for(&self.empty_subtrees, last, height)
+    }
+
+    /// The parent of the last node of a level with an odd number of nodes,
+    /// where the level is `height` above the leaves.
+    pub(crate) fn pad_parent(&self, last: &Hash, height: usize) -> Hash {
+        match self.padding_for(last, height) {
+            Some(sibling) => self.config.combine(last, &sibling),
+            None => *last,
+        }
+    }
+}
+
+impl Config {
+    /// The padding sibling of the last node of a level, see
+    /// `MerkleTree::padding_for()`, given the empty subtrees of the tree.
+    pub(crate) fn padding_for(
+        &self,
+        empty_subtrees: &[Hash],
+        last: &Hash,
+        height: usize,
+    ) -> Option<Hash> {
+        let empty_subtree = empty_subtrees.get(height).unwrap_or(&EMPTY_NODE);
+        self.padding.sibling(last, empty_subtree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Direction;
+
+    fn leaves() -> Vec<Hash> {
+        (0..7u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    /// The RFC 6962 root of a list of leaves, which splits them at the
+    /// largest power of two smaller than their number.
+    fn rfc6962_root(leaves: &[Hash]) -> Hash {
+        if leaves.len() == 1 {
+            return leaves[0];
+        }
+
+        let split = 1 << (leaves.len() - 1).ilog2();
+        MerkleTree::concat(
+            &rfc6962_root(&leaves[..split]),
+            &rfc6962_root(&leaves[split..]),
+        )
+    }
+
+    #[test]
+    fn promotes_odd_nodes() {
+        let leaves = leaves();
+        let builder = MerkleTree::builder().padding(&PromoteOdd);
+
+        for count in 2..=leaves.len() {
+            let tree = builder.build(&leaves[0..count]).unwrap();
+            assert_eq!(tree.root(), rfc6962_root(&leaves[0..count]));
+            assert!(tree.integrity_check().is_ok());
+
+            for (offset, leaf) in leaves[0..count].iter().enumerate() {
+                let proof = tree.proof_at(offset).unwrap();
+                assert!(tree.verify(&proof, leaf));
+                assert!(builder.verify(&tree.root(), &proof, leaf));
+            }
+        }
+
+        // the last of five leaves is promoted twice, so its proof is the root
+        // of the first four
+        let tree = builder.build(&leaves[0..5]).unwrap();
+        let first_four = MerkleTree::new(&leaves[0..4]).unwrap().root();
+        assert_eq!(tree.proof_at(4).unwrap(), [(Direction::Left, &first_four)]);
+
+        let mut tree = builder.build(&leaves[0..2]).unwrap();
+        tree.extend(&leaves[2..5]).unwrap();
+        tree.remove(1).unwrap();
+        tree.push(leaves[5]).unwrap();
+        let expected = [leaves[0], leaves[2], leaves[3], leaves[4], leaves[5]];
+        assert_eq!(tree.nodes, builder.build(&expected).unwrap().nodes);
+
+        let (left, right) = tree.split_at(3).unwrap();
+        assert_eq!(left.nodes, builder.build(&expected[0..3]).unwrap().nodes);
+        assert_eq!(right.nodes, builder.build(&expected[3..]).unwrap().nodes);
+    }
+
+    #[test]
+    fn requires_trees_without_padding() {
+        let leaves = leaves();
+        let builder = MerkleTree::builder().padding(&RequirePowerOfTwo);
+
+        assert!(matches!(
+            builder.build(&leaves[0..3]),
+            Err(MerkleTreeError::PaddingRequired(3))
+        ));
+        assert!(builder.build(&leaves[0..1]).is_err());
+        assert!(builder.clone().depth(3).build(&leaves[0..4]).is_err());
+
+        let mut tree = builder.build(&leaves[0..4]).unwrap();
+        assert_eq!(tree, MerkleTree::new(&leaves[0..4]).unwrap());
+        assert!(tree.push(leaves[4]).is_err());
+        assert!(tree.pop().is_err());
+        assert!(tree.remove(0).is_err());
+        assert!(tree.truncate(3).is_err());
+        assert!(tree.split_at(3).is_err());
+        assert_eq!(
+            tree.truncate(2).unwrap(),
+            MerkleTree::new(&leaves[0..2]).unwrap().root()
+        );
+        assert_eq!(
+            tree.extend(&leaves[4..6]).unwrap(),
+            builder
+                .build(&[&leaves[0..2], &leaves[4..6]].concat())
+                .unwrap()
+                .root()
+        );
+    }
+}
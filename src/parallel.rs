@@ -0,0 +1,173 @@
+//! Hash the levels of large trees on the rayon thread pool, behind the
+//! `parallel` feature.
+//!
+//! Every parent of a level only depends on its two children, so a level is
+//! split into chunks hashed on separate threads.  Levels with fewer parents
+//! to hash than the parallel threshold (see
+//! `MerkleTreeBuilder::parallel_threshold()`) are hashed on the calling
+//! thread, as the hand-off to the pool costs more than it saves for small
+//! levels, so building a large tree is parallel near its leaves and serial
+//! near its root.  The tree is the same either way.
+//!
+//! Proofs of many leaves (e.g. for a claims file) are generated concurrently
+//! with `MerkleTree::proofs_for()`.
+
+use crate::builder::MerkleTreeBuilder;
+use crate::error::{MerkleTreeError, Result};
+use crate::{trace, MerkleTree, Proof};
+use rayon::prelude::*;
+use std::ops::Range;
+
+/// The number of parents a level must have to hash before it's hashed in
+/// parallel, unless the builder sets another.
+pub const DEFAULT_PARALLEL_THRESHOLD: usize = 1 << 14;
+
+/// The number of parents hashed by each task.
+const CHUNK: usize = 1 << 10;
+
+impl MerkleTreeBuilder {
+    /// Hash the levels of the tree in parallel wherever at least `threshold`
+    /// parents are hashed at once, when building and by mutations that
+    /// rehash many leaves (e.g. `extend()` and `rebuild()`).  `usize::MAX`
+    /// hashes every level on the calling thread.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = (0..10_000u32).map(|i| MerkleTree::hash(&i.to_le_bytes())).collect::<Vec<_>>();
+    /// let tree = MerkleTree::builder().parallel_threshold(1024).build(&leaves).unwrap();
+    ///
+    /// assert_eq!(tree.root(), MerkleTree::new(&leaves).unwrap().root());
+    /// ```
+    pub fn parallel_threshold(mut self, threshold: usize) -> MerkleTreeBuilder {
+        self.config.parallel_threshold = threshold;
+        self
+    }
+}
+
+impl MerkleTree {
+    /// Hash each parent in a range of offsets from its two children, which
+    /// are at `level`, on the rayon thread pool.
+    pub(crate) fn combine_pairs_parallel(&mut self, level: usize, parents: Range<usize>) {
+        let level_start = Self::get_level_start(level);
+        let parent_start = Self::get_level_start(level - 1);
+        let config = self.config;
+
+        // parents come before their children in the layout
+        let (above, below) = self.nodes.split_at_mut(level_start);
+        let children = &below[parents.start * 2..parents.end * 2];
+
+        above[parent_start + parents.start..parent_start + parents.end]
+            .par_chunks_mut(CHUNK)
+            .zip(children.par_chunks(CHUNK * 2))
+            .for_each(|(parents, children)| config.combine_all(children, parents));
+    }
+
+    /// Generate the proofs of the leaves at many offsets concurrently, in the
+    /// order of the offsets.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = (0..100u8).map(|i| MerkleTree::hash(&[i])).collect::<Vec<_>>();
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// let proofs = tree.proofs_for(&[3, 97, 42]).unwrap();
+    /// assert!(tree.verify(&proofs[1], &leaves[97]));
+    /// assert!(tree.proofs_for(&[100]).is_err());
+    /// ```
+    pub fn proofs_for(&self, offsets: &[usize]) -> Result<Vec<Proof<'_>>> {
+        let _timed = trace::operation("proofs_for", offsets.len());
+
+        if let Some(offset) = offsets.iter().find(|offset| **offset >= self.leaf_count) {
+            return Err(MerkleTreeError::OffsetOutOfBounds(*offset, self.leaf_count));
+        }
+
+        let proofs = offsets
+            .par_iter()
+            .map(|offset| self.proof_from_index(self.get_index_from_offset(*offset)))
+            .collect();
+
+        Ok(proofs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::padding::{PromoteOdd, ZeroHash};
+    use crate::{Hash, MerkleTree};
+
+    fn leaves(count: u32) -> Vec<Hash> {
+        (0..count)
+            .map(|i| MerkleTree::hash(&i.to_le_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn builds_the_same_tree_as_serial_hashing() {
+        let leaves = leaves(1000);
+
+        for count in [1, 2, 3, 8, 255, 1000] {
+            for builder in [
+                MerkleTree::builder(),
+                MerkleTree::builder().sorted_pairs(true),
+                MerkleTree::builder().padding(&PromoteOdd),
+                MerkleTree::builder().padding(&ZeroHash),
+            ] {
+                let serial = builder
+                    .clone()
+                    .parallel_threshold(usize::MAX)
+                    .build(&leaves[..count])
+                    .unwrap();
+                let parallel = builder
+                    .parallel_threshold(1)
+                    .build(&leaves[..count])
+                    .unwrap();
+
+                assert_eq!(parallel.nodes, serial.nodes);
+            }
+        }
+    }
+
+    #[test]
+    fn mutates_the_same_tree_as_serial_hashing() {
+        let leaves = leaves(600);
+        let builder = MerkleTree::builder().parallel_threshold(4);
+        let mut tree = builder.build(&leaves[..100]).unwrap();
+
+        tree.extend(&leaves[100..500]).unwrap();
+        tree.insert(3, leaves[500]).unwrap();
+        tree.remove(7).unwrap();
+        tree.rebuild(&leaves[..300]).unwrap();
+        tree.extend(&leaves[300..]).unwrap();
+
+        assert_eq!(tree.nodes, MerkleTree::new(&leaves).unwrap().nodes);
+        assert!(tree.integrity_check().is_ok());
+    }
+
+    #[test]
+    fn generates_the_same_proofs_as_proof_at() {
+        let leaves = leaves(37);
+
+        for builder in [
+            MerkleTree::builder(),
+            MerkleTree::builder().padding(&PromoteOdd),
+            MerkleTree::builder().padding(&ZeroHash),
+            MerkleTree::builder().depth(8),
+        ] {
+            for count in [1, 2, 5, 37] {
+                let tree = builder.build(&leaves[..count]).unwrap();
+                let offsets = (0..count).rev().chain([0, 0]).collect::<Vec<_>>();
+                let proofs = tree.proofs_for(&offsets).unwrap();
+
+                for (offset, proof) in offsets.iter().zip(&proofs) {
+                    assert_eq!(*proof, tree.proof_at(*offset).unwrap());
+                }
+            }
+        }
+
+        let tree = MerkleTree::new(&leaves).unwrap();
+        assert!(tree.proofs_for(&[]).unwrap().is_empty());
+        assert!(tree.proofs_for(&[0, 37]).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+//! A persistent tree whose nodes are reference counted and shared between
+//! copies.
+//!
+//! Cloning a tree is O(1) and a mutation only copies the branch from the
+//! changed leaf up to the root (path copying), so many slightly different
+//! copies can be held and read concurrently.  Roots are identical to those
+//! of a `MerkleTree` holding the same leaves.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::{Direction, Hash, MerkleTree, OwnedProof};
+use std::borrow::Borrow;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub(crate) enum Node {
+    Leaf(Hash),
+
+    /// A right child of `None` means the right subtree holds no leaves, in
+    /// which case the left child is paired with a duplicate of itself.
+    Branch {
+        hash: Hash,
+        left: Arc<Node>,
+        right: Option<Arc<Node>>,
+    },
+}
+
+impl Node {
+    fn hash(&self) -> &Hash {
+        match self {
+            Node::Leaf(hash) => hash,
+            Node::Branch { hash, .. } => hash,
+        }
+    }
+
+    fn branch(left: Arc<Node>, right: Option<Arc<Node>>) -> Arc<Node> {
+        let hash = MerkleTree::concat(left.hash(), right.as_ref().unwrap_or(&left).hash());
+        Arc::new(Node::Branch { hash, left, right })
+    }
+
+    /// Build a subtree of the given height from its leaves.
+    fn from_leaves(height: usize, leaves: &[Hash]) -> Option<Arc<Node>> {
+        if leaves.is_empty() {
+            return None;
+        }
+
+        if height == 0 {
+            return Some(Arc::new(Node::Leaf(leaves[0])));
+        }
+
+        let half = (1 << (height - 1)).min(leaves.len());
+        let left = Self::from_leaves(height - 1, &leaves[..half])?;
+        let right = Self::from_leaves(height - 1, &leaves[half..]);
+
+        Some(Self::branch(left, right))
+    }
+
+    /// Return a copy of a subtree of the given height with the leaf at
+    /// `offset` set, sharing every node that isn't on the leaf's branch.
+    fn with_leaf(node: Option<&Arc<Node>>, height: usize, offset: usize, leaf: Hash) -> Arc<Node> {
+        if height == 0 {
+            return Arc::new(Node::Leaf(leaf));
+        }
+
+        let (left, right) = match node.map(Arc::as_ref) {
+            Some(Node::Branch { left, right, .. }) => (Some(left), right.as_ref()),
+            _ => (None, None),
+        };
+
+        let half = 1 << (height - 1);
+
+        if offset < half {
+            let left = Self::with_leaf(left, height - 1, offset, leaf);
+            Self::branch(left, right.cloned())
+        } else {
+            // leaves are contiguous, so a right subtree always has a left one
+            let left = left
+                .expect("left subtree of a non-empty right subtree")
+                .clone();
+            let right = Self::with_leaf(right, height - 1, offset - half, leaf);
+            Self::branch(left, Some(right))
+        }
+    }
+}
+
+/// A tree that can be cloned in O(1), with every clone sharing the nodes it
+/// hasn't changed.
+///
+/// ```rust
+/// use merkle_tree::{persistent::PersistentMerkleTree, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+/// let tree = PersistentMerkleTree::new(&leaves).unwrap();
+///
+/// let mut copy = tree.clone();
+/// copy.update(1, MerkleTree::hash(b"c")).unwrap();
+///
+/// assert_eq!(tree.root(), MerkleTree::new(&leaves).unwrap().root());
+/// assert_ne!(copy.root(), tree.root());
+/// ```
+#[derive(Debug, Clone)]
+pub struct PersistentMerkleTree {
+    pub(crate) root: Arc<Node>,
+    leaf_count: usize,
+    depth: usize,
+}
+
+impl PersistentMerkleTree {
+    /// Create a new persistent tree.
+    pub fn new(leaves: &[Hash]) -> Result<PersistentMerkleTree> {
+        if leaves.is_empty() {
+            return Err(MerkleTreeError::Empty);
+        }
+
+        let depth = leaves.len().next_power_of_two().max(2).ilog2() as usize;
+        let root = Node::from_leaves(depth, leaves).ok_or(MerkleTreeError::Empty)?;
+
+        Ok(PersistentMerkleTree {
+            root,
+            leaf_count: leaves.len(),
+            depth,
+        })
+    }
+
+    /// Update the leaf at the given offset.  Only the leaf's branch is
+    /// copied; every other node is shared with clones of this tree.
+    ///
+    /// O(log n)
+    pub fn update(&mut self, offset: usize, value: Hash) -> Result<()> {
+        if offset >= self.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, self.leaf_count));
+        }
+
+        self.root = Node::with_leaf(Some(&self.root), self.depth, offset, value);
+        Ok(())
+    }
+
+    /// Append a leaf to the end of the tree.
+    ///
+    /// O(log n)
+    pub fn push(&mut self, leaf: Hash) {
+        // there's no room for another leaf, so add a level above the root
+        if self.leaf_count == 1 << self.depth {
+            self.root = Node::branch(self.root.clone(), None);
+            self.depth += 1;
+        }
+
+        self.root = Node::with_leaf(Some(&self.root), self.depth, self.leaf_count, leaf);
+        self.leaf_count += 1;
+    }
+
+    /// Return the hash root of the tree.
+    pub fn root(&self) -> Hash {
+        *self.root.hash()
+    }
+
+    /// Return the number of leaves in the tree.
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Return the leaf at the given offset.
+    ///
+    /// O(log n)
+    pub fn leaf(&self, offset: usize) -> Option<&Hash> {
+        if offset >= self.leaf_count {
+            return None;
+        }
+
+        let mut node = &self.root;
+
+        for height in (0..self.depth).rev() {
+            let Node::Branch { left, right, .. } = node.as_ref() else {
+                return None;
+            };
+
+            node = if (offset >> height) & 1 == 0 {
+                left
+            } else {
+                right.as_ref()?
+            };
+        }
+
+        Some(node.hash())
+    }
+
+    /// Generate a Merkle Proof for the leaf at a given offset.
+    pub fn proof_at(&self, offset: usize) -> Result<OwnedProof> {
+        if offset >= self.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, self.leaf_count));
+        }
+
+        let mut proof = OwnedProof::with_capacity(self.depth);
+        let mut node = &self.root;
+
+        for height in (0..self.depth).rev() {
+            let Node::Branch { left, right, .. } = node.as_ref() else {
+                break;
+            };
+
+            node = if (offset >> height) & 1 == 0 {
+                let sibling = right.as_ref().unwrap_or(left);
+                proof.push((Direction::Right, *sibling.hash()));
+                left
+            } else {
+                let right = right.as_ref().expect("right subtree holding the leaf");
+                proof.push((Direction::Left, *left.hash()));
+                right
+            };
+        }
+
+        // the proof is ordered from the leaf up to the root
+        proof.reverse();
+        Ok(proof)
+    }
+
+    /// Generate a Merkle Proof for the first occurrence of a leaf.
+    ///
+    /// O(n log n)
+    pub fn proof(&self, leaf: &Hash) -> Result<OwnedProof> {
+        let offset = (0..self.leaf_count)
+            .find(|offset| self.leaf(*offset) == Some(leaf))
+            .ok_or_else(|| MerkleTreeError::CannotFindLeaf(hex::encode(leaf)))?;
+
+        self.proof_at(offset)
+    }
+
+    /// Verify a Merkle Proof for a given leaf against the root of the tree.
+    pub fn verify<H: Borrow<Hash>>(&self, proof: &[(Direction, H)], leaf: &Hash) -> bool {
+        MerkleTree::verify_with_root(&self.root(), proof, leaf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves() -> Vec<Hash> {
+        (0..11u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn matches_the_roots_and_proofs_of_a_merkle_tree() {
+        let leaves = leaves();
+        let mut tree = PersistentMerkleTree::new(&leaves[0..1]).unwrap();
+
+        for count in 2..=leaves.len() {
+            tree.push(leaves[count - 1]);
+            let expected = MerkleTree::new(&leaves[0..count]).unwrap();
+
+            assert_eq!(tree.root(), expected.root());
+            assert_eq!(tree.leaf_count(), count);
+
+            for (offset, leaf) in leaves[0..count].iter().enumerate() {
+                let proof = tree.proof_at(offset).unwrap();
+                assert!(expected.verify(&proof, leaf));
+                assert!(tree.verify(&proof, leaf));
+            }
+        }
+    }
+
+    #[test]
+    fn clones_share_unchanged_nodes() {
+        let leaves = leaves();
+        let tree = PersistentMerkleTree::new(&leaves[0..8]).unwrap();
+        let mut copy = tree.clone();
+        copy.update(7, MerkleTree::hash(b"z")).unwrap();
+
+        let (Node::Branch { left: old, .. }, Node::Branch { left: new, .. }) =
+            (tree.root.as_ref(), copy.root.as_ref())
+        else {
+            panic!("roots are branches");
+        };
+
+        assert!(Arc::ptr_eq(old, new));
+        assert!(tree.proof(&MerkleTree::hash(b"z")).is_err());
+        assert!(copy.proof(&MerkleTree::hash(b"z")).is_ok());
+        assert!(copy.update(8, leaves[8]).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+//! Report the progress of building a large tree, and cancel it.
+//!
+//! Building hashes the leaves in chunks, calling the progress callback after
+//! each chunk and checking the cancellation token before the next, so a
+//! build of hundreds of millions of leaves can be followed and aborted.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::MerkleTree;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How far a build has got, passed to the callback registered with
+/// `MerkleTreeBuilder::on_progress()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of leaves whose branches have been hashed.
+    pub leaves_hashed: usize,
+
+    /// The number of leaves being built.
+    pub leaf_count: usize,
+}
+
+/// A flag shared between a build and whoever may cancel it.  Clones share
+/// the same flag, so a clone can be moved to another thread to cancel.
+///
+/// ```rust
+/// use merkle_tree::{progress::CancellationToken, MerkleTree};
+///
+/// let token = CancellationToken::new();
+/// let builder = MerkleTree::builder().cancellation(token.clone());
+/// token.cancel();
+///
+/// assert!(builder.build(&[MerkleTree::hash(b"a")]).is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token that hasn't been cancelled.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Cancel every build using this token.  Builds check the token between
+    /// chunks of leaves, so a build in progress stops shortly after.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The callback registered with `MerkleTreeBuilder::on_progress()`, and how
+/// many leaves to hash between calls.
+#[derive(Clone)]
+pub(crate) struct ProgressListener {
+    pub(crate) interval: usize,
+    pub(crate) callback: Arc<dyn Fn(Progress) + Send + Sync>,
+}
+
+impl fmt::Debug for ProgressListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ProgressListener(every {} leaves)", self.interval)
+    }
+}
+
+/// The number of leaves hashed between checks of the cancellation token when
+/// no progress interval is given.
+const DEFAULT_INTERVAL: usize = 1 << 16;
+
+impl MerkleTree {
+    /// Hash every branch of a newly built tree a chunk of leaves at a time,
+    /// reporting progress after each chunk and erroring with `Cancelled` if
+    /// the token is cancelled.  Each chunk also hashes the path from its
+    /// subtree to the root, which costs O(log n) per chunk.
+    pub(crate) fn recompute_in_chunks(
+        &mut self,
+        progress: Option<&ProgressListener>,
+        cancellation: Option<&CancellationToken>,
+    ) -> Result<()> {
+        let interval = progress.map_or(DEFAULT_INTERVAL, |progress| progress.interval);
+
+        // chunks of a power of two leaves share the most internal nodes
+        let chunk = interval.max(1).next_power_of_two();
+        let mut start = 0;
+
+        while start < self.leaf_count {
+            if cancellation.is_some_and(CancellationToken::is_cancelled) {
+                return Err(MerkleTreeError::Cancelled);
+            }
+
+            let end = (start + chunk).min(self.leaf_count);
+            self.recompute(start..end);
+            start = end;
+
+            if let Some(progress) = progress {
+                (progress.callback)(Progress {
+                    leaves_hashed: end,
+                    leaf_count: self.leaf_count,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Hash;
+    use std::sync::Mutex;
+
+    fn leaves() -> Vec<Hash> {
+        (0..21u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn reports_progress_in_chunks() {
+        let leaves = leaves();
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+
+        let tree = MerkleTree::builder()
+            .on_progress(5, move |progress| sink.lock().unwrap().push(progress))
+            .build(&leaves)
+            .unwrap();
+
+        assert_eq!(tree.nodes, MerkleTree::new(&leaves).unwrap().nodes);
+
+        let hashed = reports
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|progress| progress.leaves_hashed)
+            .collect::<Vec<_>>();
+        assert_eq!(hashed, [8, 16, 21]);
+    }
+
+    #[test]
+    fn cancels_the_build() {
+        let leaves = leaves();
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+
+        let result = MerkleTree::builder()
+            .cancellation(token)
+            .on_progress(4, move |progress| {
+                if progress.leaves_hashed >= 8 {
+                    canceller.cancel();
+                }
+            })
+            .build(&leaves);
+
+        assert!(matches!(result, Err(MerkleTreeError::Cancelled)));
+    }
+}
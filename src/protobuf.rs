@@ -0,0 +1,589 @@
+//! Protobuf encoding of proofs, roots and tree snapshots.
+//!
+//! A snapshot is streamed as a `SnapshotChunk` holding its header, followed
+//! by chunks holding pages of at most a chosen number of nodes of the
+//! layout of `MerkleTree::to_bytes()`, so a whole tree can be shipped to a
+//! follower (e.g. as a gRPC stream) in messages of bounded size.
+//!
+//! The messages in `pb` mirror `proto/merkle_tree.proto`, which can be used to
+//! generate verifiers in other languages.  They are written out by hand so that
+//! building the crate doesn't require `protoc`.
+
+use crate::builder::{Config, MerkleTreeBuilder};
+use crate::bytes::check_header;
+use crate::error::{MerkleTreeError, Result};
+use crate::{Direction, DuplicatePolicy, Hash, MerkleTree, OwnedProof};
+use prost::Message;
+use std::borrow::Borrow;
+use std::io::{ErrorKind, Read, Write};
+
+/// The longest a header chunk encodes to: the tag and length of the header,
+/// then three `uint64` fields, two `uint32`s, a `bool` and a 32 byte root,
+/// each with its tag.
+const MAX_HEADER_CHUNK_LEN: usize = 2 + 3 * 11 + 2 * 6 + 2 + 34;
+
+/// The most a page chunk encodes to beyond its nodes: the tag and length of
+/// the page, the `uint64` offset and its tag, and the tag and length of the
+/// nodes.
+const MAX_PAGE_FRAMING_LEN: usize = 11 + 11 + 11;
+
+/// Messages from `proto/merkle_tree.proto` (package `merkle_tree.v1`).
+pub mod pb {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Root {
+        #[prost(bytes = "vec", tag = "1")]
+        pub hash: Vec<u8>,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Direction {
+        Left = 0,
+        Right = 1,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ProofStep {
+        #[prost(enumeration = "Direction", tag = "1")]
+        pub direction: i32,
+        #[prost(bytes = "vec", tag = "2")]
+        pub hash: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Proof {
+        #[prost(message, repeated, tag = "1")]
+        pub steps: Vec<ProofStep>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SnapshotHeader {
+        #[prost(uint64, tag = "1")]
+        pub leaf_count: u64,
+        #[prost(uint64, tag = "2")]
+        pub node_count: u64,
+        #[prost(uint64, tag = "3")]
+        pub depth: u64,
+        #[prost(uint32, tag = "4")]
+        pub hasher: u32,
+        #[prost(bool, tag = "5")]
+        pub sorted_pairs: bool,
+        #[prost(bytes = "vec", tag = "6")]
+        pub root: Vec<u8>,
+        #[prost(uint32, tag = "7")]
+        pub padding: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct NodePage {
+        #[prost(uint64, tag = "1")]
+        pub offset: u64,
+        #[prost(bytes = "vec", tag = "2")]
+        pub nodes: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SnapshotChunk {
+        #[prost(oneof = "snapshot_chunk::Chunk", tags = "1, 2")]
+        pub chunk: Option<snapshot_chunk::Chunk>,
+    }
+
+    pub mod snapshot_chunk {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Chunk {
+            #[prost(message, tag = "1")]
+            Header(super::SnapshotHeader),
+            #[prost(message, tag = "2")]
+            Page(super::NodePage),
+        }
+    }
+}
+
+/// Convert a proof into its protobuf message.
+pub fn proof_to_message<H: Borrow<Hash>>(proof: &[(Direction, H)]) -> pb::Proof {
+    let steps = proof
+        .iter()
+        .map(|(direction, hash)| {
+            let direction = match direction {
+                Direction::Left => pb::Direction::Left,
+                Direction::Right => pb::Direction::Right,
+            };
+
+            pb::ProofStep {
+                direction: direction.into(),
+                hash: hash.borrow().to_vec(),
+            }
+        })
+        .collect();
+
+    pb::Proof { steps }
+}
+
+/// Convert a protobuf message back into a proof.
+pub fn proof_from_message(message: pb::Proof) -> Result<OwnedProof> {
+    message
+        .steps
+        .into_iter()
+        .map(|step| {
+            let direction = match pb::Direction::try_from(step.direction) {
+                Ok(pb::Direction::Left) => Direction::Left,
+                Ok(pb::Direction::Right) => Direction::Right,
+                Err(_) => return Err(invalid("direction must be LEFT or RIGHT")),
+            };
+
+            Ok((direction, to_hash(step.hash)?))
+        })
+        .collect()
+}
+
+/// Encode a proof as a protobuf `Proof` message.
+///
+/// ```rust
+/// use merkle_tree::{protobuf, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+/// let tree = MerkleTree::new(&leaves).unwrap();
+/// let proof = tree.proof(&leaves[1]).unwrap();
+/// let decoded = protobuf::decode_proof(&protobuf::encode_proof(&proof)).unwrap();
+/// assert!(tree.verify(&decoded, &leaves[1]));
+/// ```
+pub fn encode_proof<H: Borrow<Hash>>(proof: &[(Direction, H)]) -> Vec<u8> {
+    proof_to_message(proof).encode_to_vec()
+}
+
+/// Decode a proof previously encoded with `encode_proof()`.
+pub fn decode_proof(bytes: &[u8]) -> Result<OwnedProof> {
+    let message = pb::Proof::decode(bytes).map_err(|error| invalid(&error.to_string()))?;
+    proof_from_message(message)
+}
+
+/// Encode a root hash as a protobuf `Root` message.
+///
+/// ```rust
+/// use merkle_tree::{protobuf, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+/// let tree = MerkleTree::new(&leaves).unwrap();
+/// let root = protobuf::decode_root(&protobuf::encode_root(&tree.root())).unwrap();
+/// assert_eq!(root, tree.root());
+/// ```
+pub fn encode_root(root: &Hash) -> Vec<u8> {
+    pb::Root {
+        hash: root.to_vec(),
+    }
+    .encode_to_vec()
+}
+
+/// Decode a root hash previously encoded with `encode_root()`.
+pub fn decode_root(bytes: &[u8]) -> Result<Hash> {
+    let message = pb::Root::decode(bytes).map_err(|error| invalid(&error.to_string()))?;
+    to_hash(message.hash)
+}
+
+/// The chunks of a snapshot of a tree: its header, then its nodes in pages
+/// of at most `page_len` nodes (at least one), so no chunk encodes to more
+/// than `32 * page_len` bytes plus a few bytes of framing.
+///
+/// ```rust
+/// use merkle_tree::{protobuf, MerkleTree};
+///
+/// let leaves = (0..100u8).map(|i| MerkleTree::hash(&[i])).collect::<Vec<_>>();
+/// let tree = MerkleTree::new(&leaves).unwrap();
+///
+/// let mut decoder = protobuf::SnapshotDecoder::new();
+/// for chunk in protobuf::snapshot_chunks(&tree, 64) {
+///     // e.g. sent to a follower as a gRPC stream
+///     decoder.push(chunk).unwrap();
+/// }
+/// assert_eq!(decoder.finish().unwrap(), tree);
+/// ```
+pub fn snapshot_chunks(
+    tree: &MerkleTree,
+    page_len: usize,
+) -> impl Iterator<Item = pb::SnapshotChunk> + '_ {
+    use pb::snapshot_chunk::Chunk;
+
+    let header = pb::SnapshotHeader {
+        leaf_count: tree.leaf_count as u64,
+        node_count: tree.nodes.len() as u64,
+        depth: tree.config.depth.unwrap_or(0) as u64,
+        hasher: tree.config.hasher.id,
+        sorted_pairs: tree.config.sorted_pairs,
+        root: tree.root().to_vec(),
+        padding: tree.config.padding.id(),
+    };
+    let page_len = page_len.max(1);
+    let pages = tree
+        .nodes
+        .chunks(page_len)
+        .enumerate()
+        .map(move |(page, nodes)| {
+            Chunk::Page(pb::NodePage {
+                offset: (page * page_len) as u64,
+                nodes: nodes.as_flattened().to_vec(),
+            })
+        });
+
+    std::iter::once(Chunk::Header(header))
+        .chain(pages)
+        .map(|chunk| pb::SnapshotChunk { chunk: Some(chunk) })
+}
+
+/// Write the chunks of a snapshot of a tree to a stream, each prefixed by
+/// its length as a varint, and read back with `SnapshotDecoder::read()`.
+pub fn write_snapshot<W: Write>(tree: &MerkleTree, page_len: usize, mut writer: W) -> Result<()> {
+    for chunk in snapshot_chunks(tree, page_len) {
+        writer.write_all(&chunk.encode_length_delimited_to_vec())?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Rebuilds a tree from the chunks of a snapshot, received in order.
+///
+/// The snapshot must have been hashed with a hasher of the same id as the
+/// decoder's options, and keeps the depth and `sorted_pairs` recorded in its
+/// header, as with `MerkleTreeBuilder::load_bytes()`.  The layout is checked
+/// to be well formed and to have the root of the header, but nodes aren't
+/// checked against their children, see `integrity_check()`.
+#[derive(Debug)]
+pub struct SnapshotDecoder {
+    config: Config,
+    duplicate_policy: DuplicatePolicy,
+    header: Option<(usize, usize, Config, Hash)>,
+    nodes: Vec<Hash>,
+}
+
+impl Default for SnapshotDecoder {
+    fn default() -> SnapshotDecoder {
+        MerkleTree::builder().snapshot_decoder()
+    }
+}
+
+impl SnapshotDecoder {
+    /// Decode a snapshot with the default options.
+    pub fn new() -> SnapshotDecoder {
+        SnapshotDecoder::default()
+    }
+
+    /// Accept the next chunk of the snapshot.
+    pub fn push(&mut self, chunk: pb::SnapshotChunk) -> Result<()> {
+        use pb::snapshot_chunk::Chunk;
+
+        match (chunk.chunk, &self.header) {
+            (Some(Chunk::Header(header)), None) => {
+                let (leaf_count, node_count, config) = check_header(
+                    header.leaf_count,
+                    header.node_count,
+                    header.depth,
+                    header.padding,
+                    header.hasher,
+                    header.sorted_pairs,
+                    self.config,
+                )?;
+                let root = to_hash(header.root)?;
+                self.header = Some((leaf_count, node_count, config, root));
+            }
+            (Some(Chunk::Page(page)), Some((_, node_count, ..))) => {
+                if page.offset != self.nodes.len() as u64 {
+                    return Err(invalid(&format!(
+                        "page at node {} where node {} was expected",
+                        page.offset,
+                        self.nodes.len()
+                    )));
+                }
+
+                let (nodes, rest) = page.nodes.as_chunks::<32>();
+
+                if nodes.is_empty()
+                    || !rest.is_empty()
+                    || nodes.len() > node_count - self.nodes.len()
+                {
+                    return Err(invalid(&format!(
+                        "page of {} bytes doesn't fit the snapshot",
+                        page.nodes.len()
+                    )));
+                }
+
+                self.nodes.extend_from_slice(nodes);
+            }
+            (Some(Chunk::Header(_)), Some(_)) => return Err(invalid("snapshot has two headers")),
+            (Some(Chunk::Page(_)), None) => return Err(invalid("page before snapshot header")),
+            (None, _) => return Err(invalid("empty snapshot chunk")),
+        }
+
+        Ok(())
+    }
+
+    /// The longest the next chunk can encode to: a header, or a page of every
+    /// node that hasn't been received yet.
+    fn max_chunk_len(&self) -> usize {
+        match self.header {
+            None => MAX_HEADER_CHUNK_LEN,
+            Some((_, node_count, ..)) => (node_count - self.nodes.len())
+                .saturating_mul(32)
+                .saturating_add(MAX_PAGE_FRAMING_LEN),
+        }
+    }
+
+    /// Whether every node of the snapshot has been received.
+    pub fn is_complete(&self) -> bool {
+        matches!(self.header, Some((_, node_count, ..)) if self.nodes.len() == node_count)
+    }
+
+    /// Read the length-prefixed chunks of `write_snapshot()` from a stream
+    /// until the snapshot is complete, and return its tree.
+    pub fn read<R: Read>(mut self, mut reader: R) -> Result<MerkleTree> {
+        while !self.is_complete() {
+            let mut delimiter = Vec::with_capacity(10);
+
+            loop {
+                let mut byte = [0];
+                reader
+                    .read_exact(&mut byte)
+                    .map_err(|error| match error.kind() {
+                        ErrorKind::UnexpectedEof => invalid("snapshot ended early"),
+                        _ => error.into(),
+                    })?;
+                delimiter.push(byte[0]);
+
+                if byte[0] < 0x80 || delimiter.len() == 10 {
+                    break;
+                }
+            }
+
+            let len = prost::decode_length_delimiter(delimiter.as_slice())
+                .map_err(|error| invalid(&error.to_string()))?;
+
+            // the length is untrusted, so it's bounded by what the snapshot
+            // can still hold, and the chunk is only allocated as it's read
+            if len > self.max_chunk_len() {
+                return Err(invalid(&format!(
+                    "chunk of {len} bytes is longer than the snapshot allows"
+                )));
+            }
+
+            let mut bytes = Vec::new();
+            (&mut reader).take(len as u64).read_to_end(&mut bytes)?;
+
+            if bytes.len() != len {
+                return Err(invalid("snapshot ended early"));
+            }
+
+            let chunk = pb::SnapshotChunk::decode(bytes.as_slice())
+                .map_err(|error| invalid(&error.to_string()))?;
+            self.push(chunk)?;
+        }
+
+        self.finish()
+    }
+
+    /// The tree of the snapshot, once every node has been received.
+    pub fn finish(self) -> Result<MerkleTree> {
+        let Some((leaf_count, node_count, config, root)) = self.header else {
+            return Err(invalid("snapshot has no header"));
+        };
+
+        if self.nodes.len() != node_count {
+            return Err(invalid(&format!(
+                "snapshot has {} of {node_count} nodes",
+                self.nodes.len()
+            )));
+        }
+
+        if self.nodes[0] != root {
+            return Err(invalid("snapshot nodes don't have the root of its header"));
+        }
+
+        Ok(
+            MerkleTree::from_parts(self.nodes, leaf_count, self.duplicate_policy)
+                .with_config(config),
+        )
+    }
+}
+
+impl MerkleTreeBuilder {
+    /// Decode a snapshot streamed with `protobuf::snapshot_chunks()`,
+    /// hashing with these options.
+    pub fn snapshot_decoder(&self) -> SnapshotDecoder {
+        SnapshotDecoder {
+            config: self.config,
+            duplicate_policy: self.duplicate_policy,
+            header: None,
+            nodes: Vec::new(),
+        }
+    }
+}
+
+fn to_hash(bytes: Vec<u8>) -> Result<Hash> {
+    Hash::try_from(bytes).map_err(|_| invalid("hash must be 32 bytes"))
+}
+
+fn invalid(message: &str) -> MerkleTreeError {
+    MerkleTreeError::InvalidEncoding(format!("protobuf {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn round_trips_a_proof() {
+        let leaves = [
+            MerkleTree::hash(b"a"),
+            MerkleTree::hash(b"b"),
+            MerkleTree::hash(b"c"),
+        ];
+        let tree = MerkleTree::new(&leaves).unwrap();
+        let proof = tree.proof(&leaves[2]).unwrap();
+        let decoded = decode_proof(&encode_proof(&proof)).unwrap();
+
+        assert_eq!(decoded.len(), proof.len());
+        assert!(tree.verify(&decoded, &leaves[2]));
+    }
+
+    #[test]
+    fn errors_when_decoding_an_invalid_proof() {
+        let message = pb::Proof {
+            steps: vec![pb::ProofStep {
+                direction: 7,
+                hash: MerkleTree::hash(b"a").to_vec(),
+            }],
+        };
+        assert!(decode_proof(&message.encode_to_vec()).is_err());
+        assert!(decode_root(
+            &pb::Root {
+                hash: vec![1, 2, 3]
+            }
+            .encode_to_vec()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn streams_snapshots() {
+        let leaves = (0..37u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<_>>();
+        let builder = MerkleTree::builder().sorted_pairs(true).depth(7);
+        let tree = builder.build(&leaves).unwrap();
+
+        for page_len in [0, 1, 10, 255, 1000] {
+            let mut bytes = Vec::new();
+            write_snapshot(&tree, page_len, &mut bytes).unwrap();
+            let decoded = builder.snapshot_decoder().read(bytes.as_slice()).unwrap();
+
+            assert_eq!(decoded, tree);
+            assert_eq!(
+                decoded.proof(&leaves[5]).unwrap(),
+                tree.proof(&leaves[5]).unwrap()
+            );
+        }
+
+        let mut bytes = Vec::new();
+        write_snapshot(&tree, 16, &mut bytes).unwrap();
+        assert!(SnapshotDecoder::new()
+            .read(&bytes[..bytes.len() - 1])
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_hostile_chunk_lengths() {
+        use pb::snapshot_chunk::Chunk;
+
+        let longest_header = pb::SnapshotChunk {
+            chunk: Some(Chunk::Header(pb::SnapshotHeader {
+                leaf_count: u64::MAX,
+                node_count: u64::MAX,
+                depth: u64::MAX,
+                hasher: u32::MAX,
+                sorted_pairs: true,
+                root: vec![0; 32],
+                padding: u32::MAX,
+            })),
+        };
+        assert_eq!(longest_header.encoded_len(), MAX_HEADER_CHUNK_LEN);
+
+        let tree = MerkleTree::new(&[MerkleTree::hash(b"a"), MerkleTree::hash(b"b")]).unwrap();
+        let mut snapshot = Vec::new();
+        write_snapshot(&tree, 1, &mut snapshot).unwrap();
+        let header_len = snapshot[0] as usize + 1;
+
+        // a length that would allocate far more than the stream holds, in
+        // place of the header and of a page
+        for (prefix, len) in [(0, usize::MAX >> 8), (0, 1 << 20), (header_len, 1 << 40)] {
+            let mut stream = snapshot[..prefix].to_vec();
+            prost::encode_length_delimiter(len, &mut stream).unwrap();
+            stream.extend_from_slice(&[0; 8]);
+
+            assert!(matches!(
+                SnapshotDecoder::new().read(stream.as_slice()),
+                Err(MerkleTreeError::InvalidEncoding(_))
+            ));
+        }
+
+        // a page longer than the nodes left, and one cut short
+        let mut stream = snapshot[..header_len].to_vec();
+        prost::encode_length_delimiter(3 * 32 + MAX_PAGE_FRAMING_LEN + 1, &mut stream).unwrap();
+        assert!(SnapshotDecoder::new().read(stream.as_slice()).is_err());
+
+        let mut stream = snapshot[..header_len].to_vec();
+        prost::encode_length_delimiter(40, &mut stream).unwrap();
+        stream.extend_from_slice(&[0; 39]);
+        assert!(SnapshotDecoder::new().read(stream.as_slice()).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_snapshots() {
+        use pb::snapshot_chunk::Chunk;
+
+        let leaves = (0..5u8).map(|i| MerkleTree::hash(&[i])).collect::<Vec<_>>();
+        let tree = MerkleTree::new(&leaves).unwrap();
+        let chunks = snapshot_chunks(&tree, 4).collect::<Vec<_>>();
+
+        let decode = |chunks: &[pb::SnapshotChunk]| {
+            let mut decoder = SnapshotDecoder::new();
+            for chunk in chunks {
+                decoder.push(chunk.clone())?;
+            }
+            decoder.finish()
+        };
+
+        assert_eq!(decode(&chunks).unwrap(), tree);
+
+        // missing, reordered or repeated chunks
+        assert!(decode(&chunks[..chunks.len() - 1]).is_err());
+        assert!(decode(&chunks[1..]).is_err());
+        assert!(decode(&[&chunks[..2], &chunks[3..4], &chunks[2..3]].concat()).is_err());
+        assert!(decode(&[&chunks[..1], &chunks[..]].concat()).is_err());
+        assert!(decode(&[&chunks[..], &chunks[1..2]].concat()).is_err());
+
+        // a header for another layout, hasher or root
+        let with_header = |edit: fn(&mut pb::SnapshotHeader)| {
+            let mut chunks = chunks.clone();
+            if let Some(Chunk::Header(header)) = &mut chunks[0].chunk {
+                edit(header);
+            }
+            decode(&chunks)
+        };
+        assert!(with_header(|header| header.leaf_count = 9).is_err());
+        assert!(with_header(|header| header.leaf_count = u64::MAX).is_err());
+        assert!(with_header(|header| header.node_count = u64::MAX).is_err());
+        assert!(with_header(|header| header.depth = 63).is_err());
+        assert!(with_header(|header| header.depth = 64).is_err());
+        assert!(with_header(|header| header.hasher = 2).is_err());
+        assert!(with_header(|header| header.padding = 1).is_err());
+        assert!(with_header(|header| header.root = vec![0; 32]).is_err());
+        assert!(with_header(|header| header.root = vec![0; 3]).is_err());
+
+        // a truncated page
+        let mut truncated = chunks.clone();
+        if let Some(Chunk::Page(page)) = &mut truncated[1].chunk {
+            page.nodes.pop();
+        }
+        assert!(decode(&truncated).is_err());
+    }
+}
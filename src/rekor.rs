@@ -0,0 +1,341 @@
+//! Offline verification of Sigstore Rekor inclusion proofs, behind the
+//! `rekor` feature.
+//!
+//! Rekor is an RFC 6962 log, so its proofs are checked with the semantics of
+//! the `rfc6962` module.  A log entry, as returned by `rekor-cli get` or the
+//! Rekor API, looks like
+//!
+//! ```json
+//! {
+//!   "<uuid>": {
+//!     "body": "<base64 canonicalized entry>",
+//!     "logIndex": 123456789,
+//!     "verification": {
+//!       "inclusionProof": {
+//!         "logIndex": 1234567,
+//!         "treeSize": 2345678,
+//!         "rootHash": "<hex>",
+//!         "hashes": ["<hex>"],
+//!         "checkpoint": "rekor.sigstore.dev - …\n2345678\n<base64 root>\n\n— …\n"
+//!       }
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! The leaf of an entry is the RFC 6962 leaf hash of its decoded body, and
+//! the proof's `logIndex` is the entry's index in the tree the proof is from
+//! (which differs from the entry's `logIndex` once the log has been
+//! sharded).  When present, the checkpoint must be for the proof's tree
+//! size and root.  Signatures (of the checkpoint and the signed entry
+//! timestamp) aren't checked, as they need the log's public key.
+
+use crate::builder::Hasher;
+use crate::error::{MerkleTreeError, Result};
+use crate::rfc6962::InclusionProof;
+use crate::{Hash, MerkleTree};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::Value;
+
+/// The inclusion proof of a Rekor log entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RekorInclusionProof {
+    pub log_index: u64,
+    pub tree_size: u64,
+    pub root_hash: Hash,
+    pub hashes: Vec<Hash>,
+    pub checkpoint: Option<String>,
+}
+
+impl RekorInclusionProof {
+    /// Read an `inclusionProof` object.
+    pub fn from_json(value: &Value) -> Result<RekorInclusionProof> {
+        let field = |name: &str| {
+            value[name]
+                .as_u64()
+                .ok_or_else(|| invalid(&format!("missing {name}")))
+        };
+        let hash = |value: &Value| {
+            value
+                .as_str()
+                .ok_or_else(|| invalid("hash must be a string"))
+                .and_then(MerkleTree::hash_from_hex)
+        };
+
+        let hashes = value["hashes"]
+            .as_array()
+            .ok_or_else(|| invalid("missing hashes"))?
+            .iter()
+            .map(hash)
+            .collect::<Result<_>>()?;
+
+        let checkpoint = match &value["checkpoint"] {
+            Value::Null => None,
+            Value::String(checkpoint) => Some(checkpoint.clone()),
+            _ => return Err(invalid("checkpoint must be a string")),
+        };
+
+        Ok(RekorInclusionProof {
+            log_index: field("logIndex")?,
+            tree_size: field("treeSize")?,
+            root_hash: hash(&value["rootHash"])?,
+            hashes,
+            checkpoint,
+        })
+    }
+
+    /// The proof as an RFC 6962 inclusion proof.
+    pub fn inclusion_proof(&self) -> InclusionProof {
+        InclusionProof {
+            leaf_index: self.log_index,
+            tree_size: self.tree_size,
+            path: self.hashes.clone(),
+        }
+    }
+
+    /// Whether the proof leads from a leaf hash to its root, and its
+    /// checkpoint (if any) is for the same tree size and root.
+    pub fn verify(&self, leaf: &Hash) -> bool {
+        let checkpoint = self.checkpoint.as_deref().is_none_or(|checkpoint| {
+            let mut lines = checkpoint.lines().skip(1);
+            let size = lines.next().and_then(|size| size.parse::<u64>().ok());
+            let root = lines.next().and_then(|root| STANDARD.decode(root).ok());
+
+            size == Some(self.tree_size) && root.as_deref() == Some(&self.root_hash[..])
+        });
+
+        checkpoint && self.inclusion_proof().verify(&self.root_hash, leaf)
+    }
+}
+
+/// A Rekor log entry's body and inclusion proof.
+///
+/// ```rust
+/// use merkle_tree::rekor::RekorEntry;
+///
+/// # let entry = serde_json::json!({ "24296fb24b8ad77a": {
+/// #     "body": "eyJraW5kIjoiaGFzaGVkcmVrb3JkIn0=",
+/// #     "verification": { "inclusionProof": {
+/// #         "logIndex": 0, "treeSize": 1, "hashes": [],
+/// #         "rootHash": "caf6b539d9cbed2236739ae10e4008b6fce2f4fe0fc3d828088cc3c5249efb8a",
+/// #     } },
+/// # } });
+/// // e.g. the output of `rekor-cli get --format json`
+/// let entry = RekorEntry::from_json(&entry).unwrap();
+/// assert!(entry.verify());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RekorEntry {
+    pub body: Vec<u8>,
+    pub proof: RekorInclusionProof,
+}
+
+impl RekorEntry {
+    /// Read a log entry, either keyed by its UUID as the Rekor API returns
+    /// it, or on its own.
+    pub fn from_json(value: &Value) -> Result<RekorEntry> {
+        let entry = match value.as_object() {
+            Some(entries) if entries.len() == 1 && value.get("body").is_none() => {
+                entries.values().next().unwrap()
+            }
+            _ => value,
+        };
+
+        let body = entry["body"]
+            .as_str()
+            .ok_or_else(|| invalid("missing body"))?;
+        let body = STANDARD
+            .decode(body)
+            .map_err(|error| invalid(&format!("body: {error}")))?;
+        let proof = RekorInclusionProof::from_json(&entry["verification"]["inclusionProof"])?;
+
+        Ok(RekorEntry { body, proof })
+    }
+
+    /// The RFC 6962 leaf hash of the entry.
+    pub fn leaf_hash(&self) -> Hash {
+        (Hasher::RFC_6962.leaf)(&self.body)
+    }
+
+    /// Whether the entry is included in the tree of its proof.
+    pub fn verify(&self) -> bool {
+        self.proof.verify(&self.leaf_hash())
+    }
+}
+
+fn invalid(reason: &str) -> MerkleTreeError {
+    MerkleTreeError::InvalidEncoding(format!("Rekor {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rfc6962;
+    use serde_json::json;
+
+    /// Log entries of a tree as the Rekor API returns them.
+    fn entries(bodies: &[&[u8]]) -> Vec<Value> {
+        let tree = rfc6962::builder().build_from_data(bodies).unwrap();
+        let root = STANDARD.encode(tree.root());
+        let checkpoint = format!(
+            "rekor.example - 1\n{}\n{root}\n\n— rekor.example sig\n",
+            bodies.len()
+        );
+
+        bodies
+            .iter()
+            .enumerate()
+            .map(|(offset, body)| {
+                let proof = tree.inclusion_proof(offset).unwrap();
+                json!({ format!("uuid{offset}"): {
+                    "body": STANDARD.encode(body),
+                    "logIndex": offset + 1000,
+                    "verification": { "inclusionProof": {
+                        "logIndex": offset,
+                        "treeSize": bodies.len(),
+                        "rootHash": hex::encode(tree.root()),
+                        "hashes": proof.path.iter().map(hex::encode).collect::<Vec<_>>(),
+                        "checkpoint": checkpoint,
+                    } },
+                } })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn verifies_entries() {
+        let bodies: [&[u8]; 5] = [
+            b"{\"a\":1}",
+            b"{\"b\":2}",
+            b"{\"c\":3}",
+            b"{\"d\":4}",
+            b"{}",
+        ];
+
+        for (offset, entry) in entries(&bodies).iter().enumerate() {
+            let entry = RekorEntry::from_json(entry).unwrap();
+            assert!(entry.verify());
+            assert_eq!(entry.body, bodies[offset]);
+
+            let mut other = entry.clone();
+            other.body = b"{\"e\":5}".to_vec();
+            assert!(!other.verify());
+
+            // the checkpoint must be for the same tree
+            let mut stale = entry.clone();
+            stale.proof.checkpoint = Some("rekor.example - 1\n4\n".to_owned());
+            assert!(!stale.verify());
+
+            let mut unchecked = entry.clone();
+            unchecked.proof.checkpoint = None;
+            assert!(unchecked.verify());
+        }
+    }
+
+    /// Entries for the leaves of the RFC 6962 reference tree (the test
+    /// vectors of the Certificate Transparency reference implementation),
+    /// written out by hand in the shape `rekor-cli get --format json` prints,
+    /// so nothing here comes from the code under test.  Only the bodies,
+    /// proofs, roots and checkpoint bodies are meaningful; the UUIDs, log ID,
+    /// times and signatures are placeholders.
+    const REFERENCE_ENTRIES: &str = r#"[
+        {
+            "24296fb24b8ad77a5d5ac4b9e5a2ae8e54f53ac9b6d6ee1e5db2c0e1b3f4b9d4": {
+                "body": "QEFCQw==",
+                "integratedTime": 1700000000,
+                "logID": "c0d23d6ad406973f9559f3ba2d1ca01f84147d8ffc5b8445c224f98b9591801d",
+                "logIndex": 90000005,
+                "verification": {
+                    "inclusionProof": {
+                        "checkpoint": "rekor.sigstore.dev - 1193050959916656506\n8\nXcnaeacGWamtVZy3Ad7ZoqudgjqtL0lgz+Nw7/RgQyg=\n\n— rekor.sigstore.dev wNI9ajBFAiEA\n",
+                        "hashes": [
+                            "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+                            "ca854ea128ed050b41b35ffc1b87b8eb2bde461e9e3b5596ece6b9d5975a0ae0",
+                            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7"
+                        ],
+                        "logIndex": 5,
+                        "rootHash": "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+                        "treeSize": 8
+                    },
+                    "signedEntryTimestamp": "MEUCIQ=="
+                }
+            }
+        },
+        {
+            "24296fb24b8ad77a3f1e7a1b0b8e5c9d8a7f6e5d4c3b2a1908f7e6d5c4b3a291": {
+                "body": "UFFSU1RVVlc=",
+                "integratedTime": 1700000001,
+                "logID": "c0d23d6ad406973f9559f3ba2d1ca01f84147d8ffc5b8445c224f98b9591801d",
+                "logIndex": 90000006,
+                "verification": {
+                    "inclusionProof": {
+                        "checkpoint": "rekor.sigstore.dev - 1193050959916656506\n7\n3bib5AOAnjJXUNPSY814kpwpQreUKjS3fhIslZSnTIw=\n\n— rekor.sigstore.dev wNI9ajBFAiEA\n",
+                        "hashes": [
+                            "0ebc5d3437fbe2db158b9f126a1d118e308181031d0a949f8dededebc558ef6a",
+                            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7"
+                        ],
+                        "logIndex": 6,
+                        "rootHash": "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+                        "treeSize": 7
+                    },
+                    "signedEntryTimestamp": "MEUCIQ=="
+                }
+            }
+        }
+    ]"#;
+
+    #[test]
+    fn verifies_reference_entries() {
+        let entries: Vec<Value> = serde_json::from_str(REFERENCE_ENTRIES).unwrap();
+
+        for (entry, (log_index, body)) in entries
+            .iter()
+            .zip([(5, &b"\x40\x41\x42\x43"[..]), (6, b"PQRSTUVW")])
+        {
+            let entry = RekorEntry::from_json(entry).unwrap();
+            assert_eq!(entry.body, body);
+            assert_eq!(entry.proof.log_index, log_index);
+            assert!(entry.verify());
+
+            let mut other = entry.clone();
+            other.body[0] ^= 1;
+            assert!(!other.verify());
+
+            // a single flipped byte anywhere in the proof fails it too
+            for (offset, byte) in (0..entry.proof.hashes.len()).zip([0, 17, 31]) {
+                let mut other = entry.clone();
+                other.proof.hashes[offset][byte] ^= 1;
+                assert!(!other.verify());
+            }
+
+            let mut other = entry.clone();
+            other.proof.root_hash[31] ^= 1;
+            assert!(!other.verify());
+
+            // the proof and checkpoint are for a single tree size
+            let mut stale = entry.clone();
+            stale.proof.tree_size ^= 15;
+            assert!(!stale.verify());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_entries() {
+        let entry = entries(&[b"a", b"b"]).remove(0);
+        let inner = entry["uuid0"].clone();
+        assert!(RekorEntry::from_json(&inner).unwrap().verify());
+
+        let mut bad_hash = inner.clone();
+        bad_hash["verification"]["inclusionProof"]["hashes"][0] = json!("abc");
+        assert!(RekorEntry::from_json(&bad_hash).is_err());
+
+        let mut bad_body = inner.clone();
+        bad_body["body"] = json!("not base64!");
+        assert!(RekorEntry::from_json(&bad_body).is_err());
+
+        let mut no_size = inner;
+        no_size["verification"]["inclusionProof"]["treeSize"] = Value::Null;
+        assert!(RekorEntry::from_json(&no_size).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Render a tree for humans, e.g. when debugging an unexpected root.
+
+use crate::MerkleTree;
+use std::fmt;
+
+/// The number of hex characters each hash is truncated to by `Display`.
+const DISPLAY_HEX_LEN: usize = 8;
+
+impl MerkleTree {
+    /// Render the tree level by level from the root, one line per level, with
+    /// each hash truncated to `hex_len` hex characters.  Padding isn't shown.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let tree = MerkleTree::from_data(["a", "b", "c"]).unwrap();
+    ///
+    /// assert_eq!(
+    ///     tree.format_tree(4),
+    ///     "0: 78c7\n1: 29df 8567\n2: 8008 b039 263a\n"
+    /// );
+    /// ```
+    pub fn format_tree(&self, hex_len: usize) -> String {
+        self.levels()
+            .enumerate()
+            .map(|(level, nodes)| {
+                let hashes = nodes
+                    .iter()
+                    .map(|hash| {
+                        let hex = hex::encode(hash);
+                        hex[..hex_len.min(hex.len())].to_owned()
+                    })
+                    .collect::<Vec<_>>();
+
+                format!("{level}: {}\n", hashes.join(" "))
+            })
+            .collect()
+    }
+
+    /// Describe the tree in the Graphviz DOT language, with each node
+    /// labelled by its level, index within the level and truncated hash.  A
+    /// last node that is paired with a duplicate of itself gets a second,
+    /// dashed edge, one paired with an empty subtree gets a dashed sibling,
+    /// and one promoted to the level above gets neither.
+    /// With `highlight`, the path of the leaf at that offset is filled and the
+    /// siblings making up its proof are outlined, apart from duplicates, whose
+    /// dashed edges stand in for them.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let tree = MerkleTree::from_data(["a", "b", "c"]).unwrap();
+    /// let dot = tree.to_dot(Some(2));
+    ///
+    /// assert!(dot.starts_with("digraph merkle_tree {"));
+    /// assert!(dot.contains("n2 -> n5;"));
+    /// assert!(dot.contains("n2 -> n5 [style=dashed];"));
+    /// ```
+    pub fn to_dot(&self, highlight: Option<usize>) -> String {
+        let path = highlight
+            .and_then(|offset| self.path_indices(offset).ok())
+            .unwrap_or_default();
+        let siblings = path
+            .iter()
+            .filter_map(|index| Self::sibling_index(*index))
+            .collect::<Vec<_>>();
+
+        let mut dot = String::from("digraph merkle_tree {\n    node [shape=box];\n");
+
+        for (level, index, hash) in self.iter_nodes() {
+            let node = Self::get_level_start(level) + index;
+            let style = if path.contains(&node) {
+                " style=filled fillcolor=lightblue"
+            } else if siblings.contains(&node) {
+                " color=red penwidth=2"
+            } else {
+                ""
+            };
+
+            dot += &format!(
+                "    n{node} [label=\"{level}.{index}\\n{}\"{style}];\n",
+                &hex::encode(hash)[..DISPLAY_HEX_LEN]
+            );
+        }
+
+        for level in 0..self.num_levels() {
+            let start = Self::get_level_start(level);
+            let child_width = self.level_width(level + 1);
+
+            for node in start..start + self.level_width(level) {
+                let left = node * 2 + 1;
+                dot += &format!("    n{node} -> n{left};\n");
+
+                if left + 1 < Self::get_level_start(level + 1) + child_width {
+                    dot += &format!("    n{node} -> n{};\n", left + 1);
+                    continue;
+                }
+
+                let height = self.num_levels() - level - 1;
+
+                match self.padding_for(&self.nodes[left], height) {
+                    // a promoted node is its own parent
+                    None => {}
+                    Some(sibling) if sibling == self.nodes[left] => {
+                        dot += &format!("    n{node} -> n{left} [style=dashed];\n");
+                    }
+                    Some(sibling) => {
+                        dot += &format!(
+                            "    n{} [label=\"empty\\n{}\" style=dashed];\n",
+                            left + 1,
+                            &hex::encode(sibling)[..DISPLAY_HEX_LEN]
+                        );
+                        dot += &format!("    n{node} -> n{} [style=dashed];\n", left + 1);
+                    }
+                }
+            }
+        }
+
+        dot + "}\n"
+    }
+}
+
+/// Render the tree with `format_tree()`, truncating hashes to 8 hex
+/// characters, or showing them in full with the alternate flag (`{:#}`).
+impl fmt::Display for MerkleTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex_len = if f.alternate() { 64 } else { DISPLAY_HEX_LEN };
+        write!(f, "{}", self.format_tree(hex_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::padding::{PromoteOdd, ZeroHash};
+    use crate::MerkleTree;
+
+    #[test]
+    fn displays_each_level_on_a_line() {
+        let tree = MerkleTree::from_data(["a", "b", "c", "d", "e"]).unwrap();
+        let display = tree.to_string();
+        let lines = display.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], format!("0: {}", &hex::encode(tree.root())[..8]));
+        assert_eq!(lines[3].split(' ').count(), 6);
+        assert!(format!("{tree:#}").contains(&hex::encode(tree.root())));
+    }
+
+    #[test]
+    fn describes_the_tree_in_dot() {
+        let tree = MerkleTree::from_data(["a", "b", "c", "d", "e"]).unwrap();
+        let dot = tree.to_dot(Some(4));
+        let count = |pattern: &str| dot.lines().filter(|line| line.contains(pattern)).count();
+
+        assert_eq!(count("label="), 11);
+        assert_eq!(count(" -> "), 12);
+        assert_eq!(count("dashed"), 2);
+        assert_eq!(count("filled"), 4);
+        assert_eq!(count("color=red"), 1);
+        assert!(!tree.to_dot(None).contains("filled"));
+
+        let tree = MerkleTree::builder()
+            .padding(&ZeroHash)
+            .build_from_data(["a", "b", "c", "d", "e"])
+            .unwrap();
+        let dot = tree.to_dot(None);
+        assert_eq!(dot.matches("label=\"empty").count(), 2);
+        assert!(dot.contains("n6 [label=\"empty"));
+        assert!(dot.contains("n5 -> n12 [style=dashed];"));
+        assert!(dot.ends_with("}\n"));
+
+        let tree = MerkleTree::builder()
+            .padding(&PromoteOdd)
+            .build_from_data(["a", "b", "c", "d", "e"])
+            .unwrap();
+        let dot = tree.to_dot(None);
+        assert_eq!(dot.matches(" -> ").count(), 10);
+        assert!(!dot.contains("dashed"));
+    }
+}
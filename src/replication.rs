@@ -0,0 +1,166 @@
+//! Record the mutations applied to a tree so that a replica can replay them
+//! and arrive at the identical root.
+//!
+//! Every successful mutation is logged as the offset-based operation it
+//! performed, so mutations addressed by value (e.g. `update_by_value()`) are
+//! logged as the offset they resolved to.  Replaying a log produces one new
+//! version per mutation, the same as on the tree that recorded it.
+
+use crate::error::Result;
+use crate::{Hash, MerkleTree};
+
+/// A single mutation of a tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mutation {
+    Update {
+        offset: usize,
+        value: Hash,
+    },
+    UpdateBatch(Vec<(usize, Hash)>),
+    Swap(usize, usize),
+    Push(Hash),
+    Pop,
+    Extend(Vec<Hash>),
+    Insert {
+        offset: usize,
+        leaf: Hash,
+    },
+    Remove(usize),
+    Truncate(usize),
+    Rebuild(Vec<Hash>),
+
+    /// A committed transaction, which appends its pushes before applying its
+    /// updates.
+    Commit {
+        updates: Vec<(usize, Hash)>,
+        pushes: Vec<Hash>,
+    },
+}
+
+impl MerkleTree {
+    /// Start recording mutations, to be collected with `take_log()`.
+    /// Mutations made before logging started aren't recorded.
+    pub fn start_log(&mut self) {
+        self.log.get_or_insert_with(Vec::new);
+    }
+
+    /// Return the mutations recorded since logging started or since the last
+    /// call, oldest first.  Logging continues.
+    pub fn take_log(&mut self) -> Vec<Mutation> {
+        self.log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Replay a log of mutations recorded by another tree, in order, and
+    /// return the new root hash.  Replaying stops at the first mutation that
+    /// fails, leaving the mutations before it applied.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let mut leader = MerkleTree::new(&leaves[0..2]).unwrap();
+    /// let mut follower = MerkleTree::new(&leaves[0..2]).unwrap();
+    ///
+    /// leader.start_log();
+    /// leader.push(leaves[2]).unwrap();
+    /// leader.remove(0).unwrap();
+    ///
+    /// let root = follower.apply_log(&leader.take_log()).unwrap();
+    /// assert_eq!(root, leader.root());
+    /// ```
+    pub fn apply_log(&mut self, log: &[Mutation]) -> Result<Hash> {
+        for mutation in log {
+            match mutation {
+                Mutation::Update { offset, value } => self.update(*offset, *value)?,
+                Mutation::UpdateBatch(updates) => self.update_batch(updates)?,
+                Mutation::Swap(offset1, offset2) => self.swap(*offset1, *offset2)?,
+                Mutation::Push(leaf) => _ = self.push(*leaf)?,
+                Mutation::Pop => _ = self.pop()?,
+                Mutation::Extend(leaves) => _ = self.extend(leaves)?,
+                Mutation::Insert { offset, leaf } => _ = self.insert(*offset, *leaf)?,
+                Mutation::Remove(offset) => _ = self.remove(*offset)?,
+                Mutation::Truncate(leaf_count) => _ = self.truncate(*leaf_count)?,
+                Mutation::Rebuild(leaves) => _ = self.rebuild(leaves)?,
+                Mutation::Commit { updates, pushes } => {
+                    let mut transaction = self.transaction();
+
+                    for leaf in pushes {
+                        transaction.push(*leaf);
+                    }
+
+                    for (offset, value) in updates {
+                        transaction.update(*offset, *value)?;
+                    }
+
+                    transaction.commit()?;
+                }
+            }
+        }
+
+        Ok(self.root())
+    }
+
+    /// Record a mutation if logging has started.  The mutation is only built
+    /// when it will be recorded.
+    pub(crate) fn log_mutation(&mut self, mutation: impl FnOnce() -> Mutation) {
+        if let Some(log) = self.log.as_mut() {
+            log.push(mutation());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lazy::LazyMerkleTree;
+    use crate::{Hash, MerkleTree};
+
+    fn leaves() -> Vec<Hash> {
+        (0..12u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn replays_every_mutation() {
+        let leaves = leaves();
+        let mut leader = MerkleTree::new(&leaves[0..3]).unwrap();
+        let mut follower = MerkleTree::new(&leaves[0..3]).unwrap();
+
+        leader.push(leaves[3]).unwrap();
+        leader.start_log();
+        follower.push(leaves[3]).unwrap();
+
+        leader.update(0, leaves[4]).unwrap();
+        leader.update_by_value(&leaves[1], leaves[5]).unwrap();
+        leader
+            .update_batch(&[(2, leaves[6]), (3, leaves[7])])
+            .unwrap();
+        leader.swap(0, 3).unwrap();
+        leader.extend(&leaves[8..10]).unwrap();
+        leader.pop().unwrap();
+        leader.insert(1, leaves[10]).unwrap();
+        leader.remove(0).unwrap();
+        leader.truncate(4).unwrap();
+        leader.truncate(5).unwrap();
+
+        let mut transaction = leader.transaction();
+        transaction.push(leaves[11]);
+        transaction.update(0, leaves[0]).unwrap();
+        transaction.commit().unwrap();
+
+        let mut lazy = LazyMerkleTree::from(leader);
+        lazy.update(1, leaves[1]).unwrap();
+        lazy.update(2, leaves[2]).unwrap();
+        let mut leader = lazy.into_inner();
+
+        let log = leader.take_log();
+        assert_eq!(log.len(), 11);
+        assert!(leader.take_log().is_empty());
+
+        assert_eq!(follower.apply_log(&log).unwrap(), leader.root());
+        assert_eq!(follower.nodes, leader.nodes);
+        assert_eq!(follower.version(), leader.version());
+
+        leader.rebuild(&leaves[0..2]).unwrap();
+        follower.apply_log(&leader.take_log()).unwrap();
+        assert_eq!(follower.nodes, leader.nodes);
+    }
+}
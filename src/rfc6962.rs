@@ -0,0 +1,406 @@
+//! Trees and inclusion proofs with the semantics of RFC 6962 (and RFC 9162),
+//! as used by Certificate Transparency and other transparency logs, behind
+//! the `rfc6962` feature.
+//!
+//! A tree built with `builder()` hashes leaves as `SHA-256(0x00 || data)`
+//! and nodes as `SHA-256(0x01 || left || right)`, and promotes the last node
+//! of an odd level (see `padding::PromoteOdd`), so its root is the RFC's
+//! Merkle Tree Hash of its leaves.  An `InclusionProof` is the RFC's audit
+//! path along with the leaf's index and the size of the tree, which is all a
+//! verifier needs, independent of this crate.  A `ConsistencyProof` shows
+//! that an earlier version of an append-only tree is a prefix of the current
+//! one, as monitors of a log check between its signed tree heads.
+
+use crate::builder::{Hasher, MerkleTreeBuilder};
+use crate::error::{MerkleTreeError, Result};
+use crate::padding::PromoteOdd;
+use crate::{Hash, MerkleTree, EMPTY_NODE};
+use sha2::{Digest, Sha256};
+
+impl Hasher {
+    /// SHA-256 of the data prefixed by `0x00`, and of both children prefixed
+    /// by `0x01`, as RFC 6962 hashes leaves and nodes.
+    pub const RFC_6962: Hasher = Hasher {
+        id: 4,
+        leaf: rfc6962_leaf,
+        node: rfc6962_node,
+    };
+}
+
+fn rfc6962_leaf(data: &[u8]) -> Hash {
+    Sha256::new()
+        .chain_update([0])
+        .chain_update(data)
+        .finalize()
+        .into()
+}
+
+fn rfc6962_node(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update([1])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+/// A builder for trees with RFC 6962 semantics.
+///
+/// ```rust
+/// use merkle_tree::rfc6962;
+///
+/// let tree = rfc6962::builder().build_from_data(["a", "b", "c"]).unwrap();
+///
+/// let proof = tree.inclusion_proof(2).unwrap();
+/// assert_eq!((proof.leaf_index, proof.tree_size), (2, 3));
+/// assert!(proof.verify(&tree.root(), &tree[2]));
+/// ```
+pub fn builder() -> MerkleTreeBuilder {
+    MerkleTree::builder()
+        .hasher(Hasher::RFC_6962)
+        .padding(&PromoteOdd)
+}
+
+/// The audit path of a leaf, from the leaf up, along with the leaf's index
+/// and the size of the tree it was taken from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InclusionProof {
+    pub leaf_index: u64,
+    pub tree_size: u64,
+    pub path: Vec<Hash>,
+}
+
+impl InclusionProof {
+    /// The root the proof leads to from a leaf hash, or `None` if the path
+    /// has the wrong length for the index and size, following the
+    /// verification algorithm of RFC 9162 (section 2.1.3.2).
+    pub fn root(&self, leaf: &Hash) -> Option<Hash> {
+        if self.leaf_index >= self.tree_size {
+            return None;
+        }
+
+        let mut index = self.leaf_index;
+        let mut last = self.tree_size - 1;
+        let mut root = *leaf;
+
+        for sibling in &self.path {
+            if last == 0 {
+                return None;
+            }
+
+            if index & 1 == 1 || index == last {
+                root = rfc6962_node(sibling, &root);
+
+                // skip the levels where the node was promoted
+                while index & 1 == 0 && index != 0 {
+                    index >>= 1;
+                    last >>= 1;
+                }
+            } else {
+                root = rfc6962_node(&root, sibling);
+            }
+
+            index >>= 1;
+            last >>= 1;
+        }
+
+        (last == 0).then_some(root)
+    }
+
+    /// Whether the proof leads from a leaf hash to `root`.
+    pub fn verify(&self, root: &Hash, leaf: &Hash) -> bool {
+        self.root(leaf).as_ref() == Some(root)
+    }
+}
+
+/// The hashes proving that a tree of `first_size` leaves is a prefix of one
+/// of `second_size` leaves, as RFC 6962 consistency proofs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsistencyProof {
+    pub first_size: u64,
+    pub second_size: u64,
+    pub path: Vec<Hash>,
+}
+
+impl ConsistencyProof {
+    /// Whether the proof shows that the tree of `first_root` is a prefix of
+    /// the tree of `second_root`, following the verification algorithm of
+    /// RFC 9162 (section 2.1.4.2).
+    pub fn verify(&self, first_root: &Hash, second_root: &Hash) -> bool {
+        if self.first_size == self.second_size {
+            return self.path.is_empty() && first_root == second_root;
+        }
+
+        if self.first_size == 0 || self.first_size > self.second_size {
+            return false;
+        }
+
+        // the first root is implied when it's a complete subtree
+        let mut path = self.path.iter();
+        let start = if self.first_size.is_power_of_two() {
+            first_root
+        } else {
+            match path.next() {
+                Some(hash) => hash,
+                None => return false,
+            }
+        };
+
+        let mut first = self.first_size - 1;
+        let mut second = self.second_size - 1;
+
+        while first & 1 == 1 {
+            first >>= 1;
+            second >>= 1;
+        }
+
+        let (mut first_hash, mut second_hash) = (*start, *start);
+
+        for sibling in path {
+            if second == 0 {
+                return false;
+            }
+
+            if first & 1 == 1 || first == second {
+                first_hash = rfc6962_node(sibling, &first_hash);
+                second_hash = rfc6962_node(sibling, &second_hash);
+
+                while first & 1 == 0 && first != 0 {
+                    first >>= 1;
+                    second >>= 1;
+                }
+            } else {
+                second_hash = rfc6962_node(&second_hash, sibling);
+            }
+
+            first >>= 1;
+            second >>= 1;
+        }
+
+        second == 0 && &first_hash == first_root && &second_hash == second_root
+    }
+}
+
+impl MerkleTree {
+    /// The RFC 6962 inclusion proof of the leaf at an offset, for a tree
+    /// built with `rfc6962::builder()`.
+    pub fn inclusion_proof(&self, offset: usize) -> Result<InclusionProof> {
+        self.check_rfc6962()?;
+
+        let path = self
+            .proof_at(offset)?
+            .into_iter()
+            .map(|(_, hash)| *hash)
+            .collect();
+
+        Ok(InclusionProof {
+            leaf_index: offset as u64,
+            tree_size: self.leaf_count as u64,
+            path,
+        })
+    }
+
+    /// The RFC 6962 consistency proof from the tree of the first
+    /// `first_size` leaves to this one, for a tree built with
+    /// `rfc6962::builder()`.
+    ///
+    /// ```rust
+    /// use merkle_tree::rfc6962;
+    ///
+    /// let mut tree = rfc6962::builder().build_from_data(["a", "b", "c"]).unwrap();
+    /// let first_root = tree.root();
+    /// tree.extend(&[tree[0], tree[1]]).unwrap();
+    ///
+    /// let proof = tree.consistency_proof(3).unwrap();
+    /// assert!(proof.verify(&first_root, &tree.root()));
+    /// ```
+    pub fn consistency_proof(&self, first_size: usize) -> Result<ConsistencyProof> {
+        self.check_rfc6962()?;
+
+        if first_size == 0 || first_size > self.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(
+                first_size,
+                self.leaf_count,
+            ));
+        }
+
+        let mut path = Vec::new();
+        self.consistency_path(first_size, 0, self.leaf_count, true, &mut path);
+
+        Ok(ConsistencyProof {
+            first_size: first_size as u64,
+            second_size: self.leaf_count as u64,
+            path,
+        })
+    }
+
+    /// Whether the tree has RFC 6962 semantics.
+    pub(crate) fn check_rfc6962(&self) -> Result<()> {
+        if self.config.hasher.id != Hasher::RFC_6962.id {
+            return Err(MerkleTreeError::HasherMismatch(
+                Hasher::RFC_6962.id,
+                self.config.hasher.id,
+            ));
+        }
+
+        if self
+            .config
+            .padding
+            .sibling(&EMPTY_NODE, &EMPTY_NODE)
+            .is_some()
+        {
+            return Err(MerkleTreeError::InvalidEncoding(
+                "RFC 6962 trees promote odd nodes".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Append the RFC's `SUBPROOF(first_size, D[start:start + size],
+    /// complete)` to `path`.
+    fn consistency_path(
+        &self,
+        first_size: usize,
+        start: usize,
+        size: usize,
+        complete: bool,
+        path: &mut Vec<Hash>,
+    ) {
+        if first_size == size {
+            if !complete {
+                path.push(self.subtree_root(start, size));
+            }
+            return;
+        }
+
+        // the largest power of two smaller than the size
+        let split = 1 << (size - 1).ilog2();
+
+        if first_size <= split {
+            self.consistency_path(first_size, start, split, complete, path);
+            path.push(self.subtree_root(start + split, size - split));
+        } else {
+            self.consistency_path(first_size - split, start + split, size - split, false, path);
+            path.push(self.subtree_root(start, split));
+        }
+    }
+
+    /// The Merkle Tree Hash of `size` leaves from `start`, which is a
+    /// multiple of the smallest power of two holding them.  With odd nodes
+    /// promoted, it's the node above those leaves.
+    fn subtree_root(&self, start: usize, size: usize) -> Hash {
+        let width = size.next_power_of_two();
+        let level = self.num_levels() - width.ilog2() as usize;
+
+        self.nodes[Self::get_level_start(level) + start / width]
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// The leaves of the RFC 6962 test vectors of Certificate Transparency.
+    pub(crate) fn leaves() -> Vec<Vec<u8>> {
+        [
+            &b""[..],
+            b"\x00",
+            b"\x10",
+            b"\x20\x21",
+            b"\x30\x31",
+            b"\x40\x41\x42\x43",
+            b"\x50\x51\x52\x53\x54\x55\x56\x57",
+            b"\x60\x61\x62\x63\x64\x65\x66\x67\x68\x69\x6a\x6b\x6c\x6d\x6e\x6f",
+        ]
+        .map(<[u8]>::to_vec)
+        .to_vec()
+    }
+
+    #[test]
+    fn matches_the_test_vectors() {
+        let leaves = leaves();
+
+        for (size, root) in [
+            (
+                1,
+                "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            ),
+            (
+                3,
+                "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+            ),
+            (
+                8,
+                "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+            ),
+        ] {
+            let tree = builder().build_from_data(&leaves[..size]).unwrap();
+            assert_eq!(hex::encode(tree.root()), root);
+        }
+    }
+
+    #[test]
+    fn verifies_inclusion_proofs() {
+        let leaves = leaves();
+
+        for size in 1..=leaves.len() {
+            let tree = builder().build_from_data(&leaves[..size]).unwrap();
+
+            for offset in 0..size {
+                let proof = tree.inclusion_proof(offset).unwrap();
+                assert!(proof.verify(&tree.root(), &tree[offset]));
+
+                // the index and size decide the side of each sibling
+                let moved = InclusionProof {
+                    leaf_index: (proof.leaf_index + 1) % size as u64,
+                    ..proof.clone()
+                };
+                assert!(size == 1 || !moved.verify(&tree.root(), &tree[offset]));
+
+                let grown = InclusionProof {
+                    tree_size: proof.tree_size * 2 + 1,
+                    ..proof.clone()
+                };
+                assert!(!grown.verify(&tree.root(), &tree[offset]));
+            }
+        }
+
+        let tree = MerkleTree::builder().build_from_data(&leaves).unwrap();
+        assert!(tree.inclusion_proof(0).is_err());
+    }
+
+    #[test]
+    fn verifies_consistency_proofs() {
+        let leaves = leaves();
+        let roots = (1..=leaves.len())
+            .map(|size| builder().build_from_data(&leaves[..size]).unwrap().root())
+            .collect::<Vec<_>>();
+
+        for second in 1..=leaves.len() {
+            let tree = builder().build_from_data(&leaves[..second]).unwrap();
+
+            for first in 1..=second {
+                let proof = tree.consistency_proof(first).unwrap();
+                assert!(proof.verify(&roots[first - 1], &roots[second - 1]));
+
+                for other in (0..leaves.len()).filter(|&other| other != first - 1) {
+                    assert!(!proof.verify(&roots[other], &roots[second - 1]));
+                }
+
+                if !proof.path.is_empty() {
+                    let mut tampered = proof.clone();
+                    tampered.path.pop();
+                    assert!(!tampered.verify(&roots[first - 1], &roots[second - 1]));
+                }
+            }
+        }
+
+        // the proof from 3 to 7 leaves of the RFC's example
+        let tree = builder().build_from_data(&leaves[..7]).unwrap();
+        assert_eq!(tree.consistency_proof(3).unwrap().path.len(), 4);
+
+        assert!(tree.consistency_proof(0).is_err());
+        assert!(tree.consistency_proof(8).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! Keep the nodes of a `MerkleTree` in a RocksDB database, behind the
+//! `rocksdb` feature.
+//!
+//! Leaves are stored in the `leaves` column family under their index, and
+//! internal nodes in the `nodes` column family under their level and index,
+//! all big-endian `u64`s, so each can be tuned and compacted separately.  The
+//! number of leaves is stored in the default column family under the key
+//! `leaf_count`.  Batches of nodes are written with a single `WriteBatch`.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::storage::{NodeStore, Position};
+use crate::Hash;
+use ::rocksdb::{ColumnFamily, Options, WriteBatch, DB};
+use std::path::Path;
+
+/// The column family of the leaves.
+pub const LEAVES: &str = "leaves";
+
+/// The column family of the internal nodes.
+pub const NODES: &str = "nodes";
+
+/// The key the number of leaves is stored under.
+const LEAF_COUNT: &[u8] = b"leaf_count";
+
+/// A store in a RocksDB database with `LEAVES` and `NODES` column families.
+///
+/// ```rust,no_run
+/// use merkle_tree::{rocksdb::RocksDbStore, MerkleTree};
+///
+/// let store = RocksDbStore::open("/path/to/tree.db").unwrap();
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+/// let tree = MerkleTree::create(store, leaves).unwrap();
+///
+/// let proof = tree.proof_at(2).unwrap();
+/// assert!(tree.verify(&proof, &leaves[2]).unwrap());
+/// ```
+#[derive(Debug)]
+pub struct RocksDbStore {
+    db: DB,
+}
+
+impl RocksDbStore {
+    /// Store nodes in a database already opened with the `LEAVES` and `NODES`
+    /// column families, e.g. with options tuned for the deployment.
+    pub fn new(db: DB) -> Result<RocksDbStore> {
+        for name in [LEAVES, NODES] {
+            if db.cf_handle(name).is_none() {
+                return Err(MerkleTreeError::Storage(format!(
+                    "missing column family {name}"
+                )));
+            }
+        }
+
+        Ok(RocksDbStore { db })
+    }
+
+    /// Open (or create) a database at `path` with the default options.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<RocksDbStore> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+
+        let db = DB::open_cf(&options, path, [LEAVES, NODES]).map_err(storage_error)?;
+        RocksDbStore::new(db)
+    }
+
+    /// The database holding the nodes.
+    pub fn db(&self) -> &DB {
+        &self.db
+    }
+
+    /// The column family and key of the node at a position.
+    fn locate(&self, position: Position) -> (&ColumnFamily, Vec<u8>) {
+        let index = (position.index as u64).to_be_bytes();
+
+        // both column families are checked to exist by `new()`
+        if position.level == 0 {
+            (self.db.cf_handle(LEAVES).unwrap(), index.to_vec())
+        } else {
+            let key = [(position.level as u64).to_be_bytes(), index].concat();
+            (self.db.cf_handle(NODES).unwrap(), key)
+        }
+    }
+}
+
+impl NodeStore for RocksDbStore {
+    fn get(&self, position: Position) -> Result<Option<Hash>> {
+        let (column_family, key) = self.locate(position);
+        let node = self.db.get_cf(column_family, key).map_err(storage_error)?;
+
+        node.map(|node| to_hash(position, &node)).transpose()
+    }
+
+    fn put(&mut self, position: Position, node: Hash) -> Result<()> {
+        let (column_family, key) = self.locate(position);
+        self.db
+            .put_cf(column_family, key, node)
+            .map_err(storage_error)
+    }
+
+    fn get_batch(&self, positions: &[Position]) -> Result<Vec<Option<Hash>>> {
+        let keys = positions
+            .iter()
+            .map(|position| self.locate(*position))
+            .collect::<Vec<_>>();
+        let nodes = self.db.multi_get_cf(
+            keys.iter()
+                .map(|(column_family, key)| (*column_family, key)),
+        );
+
+        positions
+            .iter()
+            .zip(nodes)
+            .map(|(position, node)| {
+                let node = node.map_err(storage_error)?;
+                node.map(|node| to_hash(*position, &node)).transpose()
+            })
+            .collect()
+    }
+
+    fn put_batch(&mut self, nodes: &[(Position, Hash)]) -> Result<()> {
+        let mut batch = WriteBatch::default();
+
+        for (position, node) in nodes {
+            let (column_family, key) = self.locate(*position);
+            batch.put_cf(column_family, key, node);
+        }
+
+        self.db.write(batch).map_err(storage_error)
+    }
+
+    fn leaf_count(&self) -> Result<usize> {
+        let Some(leaf_count) = self.db.get(LEAF_COUNT).map_err(storage_error)? else {
+            return Ok(0);
+        };
+
+        let leaf_count = <[u8; 8]>::try_from(leaf_count.as_slice())
+            .map_err(|_| MerkleTreeError::Storage("leaf count isn't 8 bytes".to_owned()))?;
+
+        Ok(u64::from_be_bytes(leaf_count) as usize)
+    }
+
+    fn set_leaf_count(&mut self, leaf_count: usize) -> Result<()> {
+        self.db
+            .put(LEAF_COUNT, (leaf_count as u64).to_be_bytes())
+            .map_err(storage_error)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.db.flush_wal(true).map_err(storage_error)
+    }
+}
+
+fn to_hash(position: Position, node: &[u8]) -> Result<Hash> {
+    Hash::try_from(node)
+        .map_err(|_| MerkleTreeError::Storage(format!("node at {position:?} isn't 32 bytes")))
+}
+
+fn storage_error(error: ::rocksdb::Error) -> MerkleTreeError {
+    MerkleTreeError::Storage(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::{assert_matches, leaves};
+    use crate::MerkleTree;
+
+    #[test]
+    fn stores_trees_in_rocksdb() {
+        let path = std::env::temp_dir().join(format!("merkle-tree-{}.rocksdb", std::process::id()));
+        let mut leaves = leaves(5);
+
+        let mut tree =
+            MerkleTree::create(RocksDbStore::open(&path).unwrap(), leaves.clone()).unwrap();
+        assert_matches(&tree, &leaves);
+
+        leaves.push(MerkleTree::hash(b"x"));
+        tree.push(leaves[5]).unwrap();
+        leaves[0] = MerkleTree::hash(b"y");
+        tree.update(0, leaves[0]).unwrap();
+
+        // leaves and internal nodes are kept apart
+        let store = tree.into_store();
+        let leaf_family = store.db().cf_handle(LEAVES).unwrap();
+        let leaf = store.db().get_cf(leaf_family, 5u64.to_be_bytes()).unwrap();
+        assert_eq!(leaf.as_deref(), Some(leaves[5].as_slice()));
+
+        let tree = MerkleTree::open(store).unwrap();
+        assert_eq!(tree.leaf_count(), 6);
+        assert_matches(&tree, &leaves);
+
+        drop(tree);
+        DB::destroy(&Options::default(), &path).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+//! Inclusion proofs as COSE receipts, as used by SCITT transparency services,
+//! behind the `cose` feature.
+//!
+//! A receipt is a tagged `COSE_Sign1` signed with ed25519, following COSE
+//! Receipts (draft-ietf-cose-merkle-tree-proofs): the protected header
+//! declares the `RFC9162_SHA256` verifiable data structure, the unprotected
+//! header holds the inclusion proofs as `[tree_size, leaf_index, path]`, and
+//! the detached payload is the root the proofs lead to.  The tree must be
+//! built with `rfc6962::builder()`, since receipts have RFC 9162 semantics.
+
+use crate::attestation::{SigningKey, VerifyingKey};
+use crate::error::{MerkleTreeError, Result};
+use crate::rfc6962::InclusionProof;
+use crate::{Hash, MerkleTree};
+use coset::cbor::value::Value;
+use coset::{iana, CoseSign1, CoseSign1Builder, HeaderBuilder, Label, TaggedCborSerializable};
+use ed25519_dalek::{Signature, Signer};
+
+/// The header label of the verifiable data structure.
+pub const VDS: i64 = 395;
+
+/// The header label of the verifiable data structure's proofs.
+pub const VDP: i64 = 396;
+
+/// The verifiable data structure of RFC 9162 trees.
+pub const RFC9162_SHA256: i64 = 1;
+
+/// The key of the inclusion proofs among the proofs.
+pub const INCLUSION_PROOFS: i64 = -1;
+
+impl MerkleTree {
+    /// A receipt for the leaf at an offset, signed with `key`.
+    ///
+    /// ```rust
+    /// use merkle_tree::attestation::SigningKey;
+    /// use merkle_tree::{rfc6962, scitt};
+    ///
+    /// let key = SigningKey::from_bytes(&[7; 32]);
+    /// let tree = rfc6962::builder().build_from_data(["a", "b", "c"]).unwrap();
+    ///
+    /// let receipt = tree.scitt_receipt(1, &key).unwrap();
+    /// assert!(scitt::verify_receipt(&receipt, &tree[1], &key.verifying_key()));
+    /// ```
+    pub fn scitt_receipt(&self, offset: usize, key: &SigningKey) -> Result<Vec<u8>> {
+        receipt(&self.inclusion_proof(offset)?, &self.root(), key)
+    }
+}
+
+/// A receipt for an inclusion proof leading to `root`, signed with `key`.
+pub fn receipt(proof: &InclusionProof, root: &Hash, key: &SigningKey) -> Result<Vec<u8>> {
+    let protected = HeaderBuilder::new()
+        .algorithm(iana::Algorithm::EdDSA)
+        .value(VDS, Value::from(RFC9162_SHA256))
+        .build();
+
+    let mut encoded = Vec::new();
+    coset::cbor::ser::into_writer(&proof_to_cbor(proof), &mut encoded)
+        .map_err(|error| invalid(&error.to_string()))?;
+
+    let proofs = Value::Map(vec![(
+        Value::from(INCLUSION_PROOFS),
+        Value::Array(vec![Value::Bytes(encoded)]),
+    )]);
+    let unprotected = HeaderBuilder::new().value(VDP, proofs).build();
+
+    CoseSign1Builder::new()
+        .protected(protected)
+        .unprotected(unprotected)
+        .create_detached_signature(root, &[], |data| key.sign(data).to_vec())
+        .build()
+        .to_tagged_vec()
+        .map_err(|error| invalid(&error.to_string()))
+}
+
+/// The inclusion proofs of a receipt.  The signature isn't checked, see
+/// `verify_receipt()`.
+pub fn decode_receipt(receipt: &[u8]) -> Result<Vec<InclusionProof>> {
+    Ok(decode(receipt)?.1)
+}
+
+/// Whether a receipt was signed by the holder of `key`, and each of its
+/// inclusion proofs leads from the leaf hash to the signed root.
+pub fn verify_receipt(receipt: &[u8], leaf: &Hash, key: &VerifyingKey) -> bool {
+    let Ok((sign1, proofs)) = decode(receipt) else {
+        return false;
+    };
+
+    let Some(root) = proofs.first().and_then(|proof| proof.root(leaf)) else {
+        return false;
+    };
+
+    proofs.iter().all(|proof| proof.root(leaf) == Some(root))
+        && sign1
+            .verify_detached_signature(&root, &[], |signature, data| {
+                let signature = Signature::from_slice(signature)?;
+                key.verify_strict(data, &signature)
+            })
+            .is_ok()
+}
+
+fn decode(receipt: &[u8]) -> Result<(CoseSign1, Vec<InclusionProof>)> {
+    let sign1 =
+        CoseSign1::from_tagged_slice(receipt).map_err(|error| invalid(&error.to_string()))?;
+    let header = |label: i64, header: &coset::Header| {
+        header
+            .rest
+            .iter()
+            .find(|(key, _)| *key == Label::Int(label))
+            .map(|(_, value)| value.clone())
+    };
+
+    if sign1.protected.header.alg != Some(coset::Algorithm::Assigned(iana::Algorithm::EdDSA))
+        || header(VDS, &sign1.protected.header) != Some(Value::from(RFC9162_SHA256))
+        || sign1.payload.is_some()
+    {
+        return Err(invalid("receipt must be an EdDSA RFC9162_SHA256 receipt"));
+    }
+
+    let proofs = header(VDP, &sign1.unprotected)
+        .and_then(|proofs| proofs.into_map().ok())
+        .and_then(|proofs| {
+            proofs
+                .into_iter()
+                .find(|(key, _)| *key == Value::from(INCLUSION_PROOFS))
+        })
+        .and_then(|(_, proofs)| proofs.into_array().ok())
+        .ok_or_else(|| invalid("receipt has no inclusion proofs"))?
+        .into_iter()
+        .map(|proof| {
+            let encoded = proof
+                .into_bytes()
+                .map_err(|_| invalid("inclusion proof must be a bstr"))?;
+            let proof = coset::cbor::de::from_reader(encoded.as_slice())
+                .map_err(|error| invalid(&error.to_string()))?;
+            proof_from_cbor(proof)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if proofs.is_empty() {
+        return Err(invalid("receipt has no inclusion proofs"));
+    }
+
+    Ok((sign1, proofs))
+}
+
+fn proof_to_cbor(proof: &InclusionProof) -> Value {
+    let path = proof.path.iter().map(|hash| Value::Bytes(hash.to_vec()));
+
+    Value::Array(vec![
+        Value::from(proof.tree_size),
+        Value::from(proof.leaf_index),
+        Value::Array(path.collect()),
+    ])
+}
+
+fn proof_from_cbor(value: Value) -> Result<InclusionProof> {
+    let malformed = || invalid("inclusion proof must be [tree_size, leaf_index, path]");
+    let integer = |value: &Value| {
+        value
+            .as_integer()
+            .and_then(|integer| u64::try_from(integer).ok())
+            .ok_or_else(malformed)
+    };
+
+    let [tree_size, leaf_index, path] =
+        <[Value; 3]>::try_from(value.into_array().map_err(|_| malformed())?)
+            .map_err(|_| malformed())?;
+
+    let path = path
+        .into_array()
+        .map_err(|_| malformed())?
+        .into_iter()
+        .map(|hash| {
+            hash.into_bytes()
+                .ok()
+                .and_then(|hash| Hash::try_from(hash).ok())
+                .ok_or_else(malformed)
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(InclusionProof {
+        leaf_index: integer(&leaf_index)?,
+        tree_size: integer(&tree_size)?,
+        path,
+    })
+}
+
+fn invalid(reason: &str) -> MerkleTreeError {
+    MerkleTreeError::InvalidEncoding(format!("COSE {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rfc6962;
+
+    #[test]
+    fn round_trips_receipts() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        let tree = rfc6962::builder()
+            .build_from_data((0..6u8).map(|i| [i]))
+            .unwrap();
+
+        for offset in 0..6 {
+            let receipt = tree.scitt_receipt(offset, &key).unwrap();
+
+            assert_eq!(
+                decode_receipt(&receipt).unwrap(),
+                vec![tree.inclusion_proof(offset).unwrap()]
+            );
+            assert!(verify_receipt(
+                &receipt,
+                &tree[offset],
+                &key.verifying_key()
+            ));
+            assert!(!verify_receipt(
+                &receipt,
+                &tree[(offset + 1) % 6],
+                &key.verifying_key()
+            ));
+            assert!(!verify_receipt(&receipt, &tree[offset], &other));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_receipts() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let tree = rfc6962::builder().build_from_data(["a", "b"]).unwrap();
+        let receipt = tree.scitt_receipt(0, &key).unwrap();
+
+        assert!(decode_receipt(&receipt[..receipt.len() - 1]).is_err());
+        assert!(!verify_receipt(
+            &receipt[1..],
+            &tree[0],
+            &key.verifying_key()
+        ));
+
+        // a receipt for a root that the proof doesn't lead to
+        let proof = tree.inclusion_proof(0).unwrap();
+        let forged = super::receipt(&proof, &MerkleTree::hash(b"x"), &key).unwrap();
+        assert!(!verify_receipt(&forged, &tree[0], &key.verifying_key()));
+
+        // the proofs are in the unprotected header, so changing them must
+        // still fail against the signed root
+        let other = rfc6962::builder().build_from_data(["a", "c"]).unwrap();
+        let swapped = super::receipt(&proof, &other.root(), &key).unwrap();
+        assert!(!verify_receipt(&swapped, &tree[0], &key.verifying_key()));
+    }
+}
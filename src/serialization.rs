@@ -0,0 +1,151 @@
+//! serde support for trees, so a tree can be persisted and loaded without
+//! rehashing its leaves.
+//!
+//! A tree is serialized as its flattened node layout, number of leaves,
+//! duplicate policy and the `sorted_pairs` and `depth` options.  The hash
+//! functions and padding strategy are code rather than data, so they aren't
+//! serialized and a deserialized tree uses the defaults.  Listeners,
+//! subscriptions, the root history and the mutation log aren't serialized
+//! either, so a deserialized tree starts at version 0.
+//!
+//! Deserializing checks that the layout is well formed, which is O(1).
+//! Deserialize a `Validated` to also check that every node is consistent
+//! with its children, which is O(n).
+
+use crate::builder::Config;
+use crate::{DuplicatePolicy, Hash, MerkleTree};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Serialize)]
+#[serde(rename = "MerkleTree")]
+struct TreeRef<'a> {
+    leaf_count: usize,
+    nodes: &'a [Hash],
+    duplicate_policy: DuplicatePolicy,
+    sorted_pairs: bool,
+    depth: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "MerkleTree")]
+struct Tree {
+    leaf_count: usize,
+    nodes: Vec<Hash>,
+    duplicate_policy: DuplicatePolicy,
+    sorted_pairs: bool,
+    depth: Option<usize>,
+}
+
+impl Serialize for MerkleTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TreeRef {
+            leaf_count: self.leaf_count,
+            nodes: &self.nodes,
+            duplicate_policy: self.duplicate_policy,
+            sorted_pairs: self.config.sorted_pairs,
+            depth: self.config.depth,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MerkleTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MerkleTree, D::Error> {
+        let tree = Tree::deserialize(deserializer)?;
+        let config = Config {
+            sorted_pairs: tree.sorted_pairs,
+            depth: tree.depth,
+            ..Config::default()
+        };
+
+        // the layout must be the one the tree would have been built with
+        let padded_count = config
+            .padded_count(tree.leaf_count)
+            .map_err(D::Error::custom)?;
+
+        if tree.leaf_count == 0 || tree.nodes.len() != padded_count * 2 - 1 {
+            return Err(D::Error::custom(format!(
+                "{} nodes can't hold {} leaves",
+                tree.nodes.len(),
+                tree.leaf_count
+            )));
+        }
+
+        Ok(
+            MerkleTree::from_parts(tree.nodes, tree.leaf_count, tree.duplicate_policy)
+                .with_config(config),
+        )
+    }
+}
+
+/// A deserialized tree whose nodes have been checked to be consistent with
+/// their children, see `MerkleTree::integrity_check()`.
+///
+/// ```rust
+/// use merkle_tree::{serialization::Validated, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+/// let tree = MerkleTree::new(&leaves).unwrap();
+///
+/// let json = serde_json::to_string(&tree).unwrap();
+/// let Validated(loaded) = serde_json::from_str(&json).unwrap();
+/// assert_eq!(loaded, tree);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validated(pub MerkleTree);
+
+impl<'de> Deserialize<'de> for Validated {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Validated, D::Error> {
+        let tree = MerkleTree::deserialize(deserializer)?;
+        tree.integrity_check().map_err(D::Error::custom)?;
+
+        Ok(Validated(tree))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves() -> Vec<Hash> {
+        (0..5u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn round_trips_trees() {
+        let leaves = leaves();
+        let tree = MerkleTree::builder()
+            .sorted_pairs(true)
+            .depth(4)
+            .duplicate_policy(DuplicatePolicy::Reject)
+            .build(&leaves)
+            .unwrap();
+
+        let json = serde_json::to_string(&tree).unwrap();
+        let mut loaded = serde_json::from_str::<MerkleTree>(&json).unwrap();
+        assert_eq!(loaded, tree);
+        assert_eq!(loaded.num_levels(), 4);
+
+        // the options are kept, so mutations hash the same way
+        let mut tree = tree;
+        tree.update(2, MerkleTree::hash(b"z")).unwrap();
+        loaded.update(2, MerkleTree::hash(b"z")).unwrap();
+        assert_eq!(loaded, tree);
+        assert!(loaded.push(leaves[0]).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_trees() {
+        let tree = MerkleTree::new(&leaves()).unwrap();
+        let mut value = serde_json::to_value(&tree).unwrap();
+
+        value["leaf_count"] = 9.into();
+        assert!(serde_json::from_value::<MerkleTree>(value.clone()).is_err());
+
+        value["leaf_count"] = 5.into();
+        value["nodes"][3][0] = 1.into();
+        assert!(serde_json::from_value::<MerkleTree>(value.clone()).is_ok());
+        assert!(serde_json::from_value::<Validated>(value).is_err());
+    }
+}
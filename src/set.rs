@@ -0,0 +1,255 @@
+//! Commit to a set of values, where the same values always build the same
+//! tree.
+//!
+//! Elements are kept sorted and unique, so membership is a binary search and
+//! two sets with a common prefix of elements share the subtrees covering it.
+//! Set operations between two sets compare those subtrees to skip the common
+//! prefix without comparing it element by element.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::{Hash, MerkleTree, Proof};
+use std::cmp::Ordering;
+
+/// A tree of unique leaves in ascending order.
+///
+/// ```rust
+/// use merkle_tree::{set::MerkleSet, MerkleTree};
+///
+/// let [a, b, c] = [b"a", b"b", b"c"].map(|data| MerkleTree::hash(data));
+/// let mut set = MerkleSet::new(&[c, a]).unwrap();
+/// set.insert(b).unwrap();
+///
+/// assert!(set.contains(&b));
+/// assert_eq!(set.root(), MerkleSet::new(&[a, b, c]).unwrap().root());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleSet {
+    tree: MerkleTree,
+}
+
+impl MerkleSet {
+    /// Create a new set from leaves in any order, ignoring repeated leaves.
+    pub fn new(leaves: &[Hash]) -> Result<MerkleSet> {
+        let mut elements = leaves.to_vec();
+        elements.sort_unstable();
+        elements.dedup();
+
+        Ok(MerkleSet {
+            tree: MerkleTree::new(&elements)?,
+        })
+    }
+
+    /// The underlying tree.
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Return the root hash of the set.
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    /// The elements of the set in ascending order.
+    pub fn elements(&self) -> &[Hash] {
+        self.tree.leaves()
+    }
+
+    /// The number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.tree.leaf_count()
+    }
+
+    /// A set always holds at least one element.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Whether the set holds an element.
+    ///
+    /// O(log n)
+    pub fn contains(&self, element: &Hash) -> bool {
+        self.elements().binary_search(element).is_ok()
+    }
+
+    /// Add an element, returning whether it wasn't already in the set.
+    pub fn insert(&mut self, element: Hash) -> Result<bool> {
+        match self.elements().binary_search(&element) {
+            Ok(_) => Ok(false),
+            Err(offset) => {
+                self.tree.insert(offset, element)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Remove an element, returning whether it was in the set.  The last
+    /// element can't be removed.
+    pub fn remove(&mut self, element: &Hash) -> Result<bool> {
+        match self.elements().binary_search(element) {
+            Ok(offset) => {
+                self.tree.remove(offset)?;
+                Ok(true)
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Generate a Merkle Proof of membership for an element.
+    pub fn prove(&self, element: &Hash) -> Result<Proof<'_>> {
+        match self.elements().binary_search(element) {
+            Ok(offset) => self.tree.proof_at(offset),
+            Err(_) => Err(MerkleTreeError::CannotFindLeaf(hex::encode(element))),
+        }
+    }
+
+    /// The elements of this set that aren't in `other`, in ascending order.
+    pub fn difference(&self, other: &MerkleSet) -> Vec<Hash> {
+        self.compare(other).only_self
+    }
+
+    /// The elements in both sets, in ascending order.
+    pub fn intersection(&self, other: &MerkleSet) -> Vec<Hash> {
+        self.compare(other).shared
+    }
+
+    /// The elements in either set, in ascending order.
+    pub fn union(&self, other: &MerkleSet) -> Vec<Hash> {
+        let Comparison {
+            only_self,
+            mut shared,
+            only_other,
+        } = self.compare(other);
+
+        shared.extend(only_self);
+        shared.extend(only_other);
+        shared.sort_unstable();
+        shared
+    }
+
+    /// Sort the elements of both sets by which of them hold each element.
+    /// The elements after the common prefix are merged one by one.
+    fn compare(&self, other: &MerkleSet) -> Comparison {
+        let mine = self.elements();
+        let theirs = other.elements();
+        let prefix = self.shared_prefix(other);
+
+        let mut comparison = Comparison {
+            only_self: Vec::new(),
+            shared: mine[..prefix].to_vec(),
+            only_other: Vec::new(),
+        };
+        let (mut i, mut j) = (prefix, prefix);
+
+        while i < mine.len() && j < theirs.len() {
+            match mine[i].cmp(&theirs[j]) {
+                Ordering::Less => {
+                    comparison.only_self.push(mine[i]);
+                    i += 1;
+                }
+                Ordering::Greater => {
+                    comparison.only_other.push(theirs[j]);
+                    j += 1;
+                }
+                Ordering::Equal => {
+                    comparison.shared.push(mine[i]);
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+
+        comparison.only_self.extend_from_slice(&mine[i..]);
+        comparison.only_other.extend_from_slice(&theirs[j..]);
+        comparison
+    }
+
+    /// The number of leading elements both sets hold.  Full subtrees at the
+    /// same position hold the same elements if their hashes are equal, so
+    /// the prefix is extended by the largest such subtree at each height.
+    ///
+    /// O(log n)
+    fn shared_prefix(&self, other: &MerkleSet) -> usize {
+        let common = self.len().min(other.len());
+        let mut prefix = 0;
+
+        for height in (0..=self.tree.num_levels().min(other.tree.num_levels())).rev() {
+            let offset = prefix >> height;
+
+            // the prefix is a multiple of every larger subtree, so it's aligned
+            if prefix + (1 << height) <= common
+                && self.tree.node_at_height(height, offset)
+                    == other.tree.node_at_height(height, offset)
+            {
+                prefix += 1 << height;
+            }
+        }
+
+        prefix
+    }
+}
+
+/// The elements of two sets, by which of the sets hold them.
+struct Comparison {
+    only_self: Vec<Hash>,
+    shared: Vec<Hash>,
+    only_other: Vec<Hash>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elements() -> Vec<Hash> {
+        let mut elements = (0..20u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<_>>();
+        elements.sort_unstable();
+        elements
+    }
+
+    #[test]
+    fn keeps_elements_sorted_and_unique() {
+        let elements = elements();
+        let mut set = MerkleSet::new(&[elements[3], elements[1], elements[3]]).unwrap();
+
+        assert!(set.insert(elements[2]).unwrap());
+        assert!(!set.insert(elements[1]).unwrap());
+        assert!(set.insert(elements[0]).unwrap());
+        assert!(set.remove(&elements[3]).unwrap());
+        assert!(!set.remove(&elements[3]).unwrap());
+
+        assert_eq!(set, MerkleSet::new(&elements[0..3]).unwrap());
+        assert!(set.contains(&elements[2]));
+        assert!(!set.contains(&elements[3]));
+
+        let proof = set.prove(&elements[1]).unwrap();
+        assert!(set.tree().verify(&proof, &elements[1]));
+        assert!(set.prove(&elements[3]).is_err());
+
+        let mut single = MerkleSet::new(&elements[0..1]).unwrap();
+        assert!(single.remove(&elements[0]).is_err());
+    }
+
+    #[test]
+    fn computes_set_operations() {
+        let elements = elements();
+        let set = MerkleSet::new(&elements[0..12]).unwrap();
+        let other = MerkleSet::new(&[&elements[0..8], &elements[10..16]].concat()).unwrap();
+
+        assert_eq!(set.difference(&other), &elements[8..10]);
+        assert_eq!(other.difference(&set), &elements[12..16]);
+        assert_eq!(
+            set.intersection(&other),
+            [&elements[0..8], &elements[10..12]].concat()
+        );
+        assert_eq!(set.union(&other), &elements[0..16]);
+        assert_eq!(set.shared_prefix(&other), 8);
+
+        assert_eq!(set.intersection(&set), set.elements());
+        assert!(set.difference(&set).is_empty());
+
+        let prefix = MerkleSet::new(&elements[0..5]).unwrap();
+        assert_eq!(set.difference(&prefix), &elements[5..12]);
+        assert!(prefix.difference(&set).is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+//! Combine trees built in parallel, e.g. one per shard, and split trees
+//! apart again for re-sharding.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::{DuplicatePolicy, Hash, MerkleTree, EMPTY_NODE};
+
+impl MerkleTree {
+    /// Join two trees under a new root, which is the concatenation of their
+    /// roots.  The result is identical to a tree created from the leaves of
+    /// `left` followed by the leaves of `right`, but no hashes other than the
+    /// new root are calculated.  The joined tree hashes like `left`.
+    ///
+    /// For the new root to commit to both roots, `left` must be full (a power
+    /// of two leaves, and at least two) and both trees must have the same
+    /// number of levels.  A proof from `left` extends into the joined tree by appending
+    /// `(Direction::Right, right.root())`, and a proof from `right` by
+    /// appending `(Direction::Left, left.root())`.
+    ///
+    /// ```rust
+    /// use merkle_tree::{Direction, MerkleTree};
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let left = MerkleTree::new(&leaves[0..2]).unwrap();
+    /// let right = MerkleTree::new(&leaves[2..3]).unwrap();
+    ///
+    /// let mut proof = right.proof(&leaves[2]).unwrap();
+    /// let left_root = left.root();
+    /// proof.push((Direction::Left, &left_root));
+    ///
+    /// let joined = MerkleTree::join(&left, &right).unwrap();
+    /// assert_eq!(joined.root(), MerkleTree::concat(&left.root(), &right.root()));
+    /// assert!(joined.verify(&proof, &leaves[2]));
+    /// ```
+    pub fn join(left: &MerkleTree, right: &MerkleTree) -> Result<MerkleTree> {
+        if left.leaf_count != left.num_leaves() {
+            return Err(MerkleTreeError::CannotJoin(format!(
+                "the left tree isn't full ({} of {} leaves)",
+                left.leaf_count,
+                left.num_leaves()
+            )));
+        }
+
+        if left.num_levels() != right.num_levels() {
+            return Err(MerkleTreeError::CannotJoin(format!(
+                "the trees have {} and {} levels",
+                left.num_levels(),
+                right.num_levels()
+            )));
+        }
+
+        if let Some(depth) = left.config.depth {
+            return Err(MerkleTreeError::CannotJoin(format!(
+                "the trees have a fixed depth of {depth}"
+            )));
+        }
+
+        let duplicate_policy = left.duplicate_policy;
+
+        if duplicate_policy == DuplicatePolicy::Reject {
+            if let Some(duplicate) =
+                Self::find_duplicate(left.leaves().iter().chain(right.leaves()))
+            {
+                return Err(MerkleTreeError::DuplicateLeaf(hex::encode(duplicate)));
+            }
+        }
+
+        // each level of the joined tree is a level of the left tree followed by
+        // the same level of the right tree
+        let mut nodes = vec![EMPTY_NODE; left.nodes.len() * 2 + 1];
+        nodes[0] = left.config.combine(&left.root(), &right.root());
+
+        for level in 0..=left.num_levels() {
+            let start = Self::get_level_start(level);
+            let new_start = Self::get_level_start(level + 1);
+            let width = 1 << level;
+
+            nodes[new_start..new_start + width].copy_from_slice(&left.nodes[start..start + width]);
+            nodes[new_start + width..new_start + width * 2]
+                .copy_from_slice(&right.nodes[start..start + width]);
+        }
+
+        let leaf_count = left.leaf_count + right.leaf_count;
+        left.config.padded_count(leaf_count)?;
+
+        Ok(Self::from_parts(nodes, leaf_count, duplicate_policy).with_config(left.config))
+    }
+
+    /// Split a tree into two independent trees, the first holding the leaves
+    /// before `offset` and the second the leaves from `offset` on.  Internal
+    /// hashes of the original tree are reused wherever a subtree of the new
+    /// tree covers exactly the same leaves, so the first tree only rehashes
+    /// the branch of its last leaf.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let (left, right) = tree.split_at(2).unwrap();
+    ///
+    /// assert_eq!(left.root(), MerkleTree::new(&leaves[0..2]).unwrap().root());
+    /// assert_eq!(right.root(), MerkleTree::new(&leaves[2..3]).unwrap().root());
+    /// ```
+    pub fn split_at(&self, offset: usize) -> Result<(MerkleTree, MerkleTree)> {
+        if offset == 0 || offset >= self.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, self.leaf_count));
+        }
+
+        self.config.padded_count(offset)?;
+        self.config.padded_count(self.leaf_count - offset)?;
+
+        Ok((self.slice(0, offset), self.slice(offset, self.leaf_count)))
+    }
+
+    /// Build a tree from the leaves from `start` up to `end`, copying any
+    /// node of this tree whose subtree holds exactly the same leaves.
+    fn slice(&self, start: usize, end: usize) -> MerkleTree {
+        let leaf_count = end - start;
+        let depth = self
+            .config
+            .padded_count(leaf_count)
+            .expect("fewer leaves fit")
+            .ilog2() as usize;
+        let mut nodes = vec![EMPTY_NODE; (2 << depth) - 1];
+        let mut level = self.leaves()[start..end].to_vec();
+
+        for height in 0..=depth {
+            if height > 0 {
+                let aligned = start.is_multiple_of(1 << height);
+
+                level = (0..leaf_count.div_ceil(1 << height))
+                    .map(|offset| {
+                        if aligned && (offset + 1) << height <= leaf_count {
+                            *self.node_at_height(height, (start >> height) + offset)
+                        } else {
+                            let left = &level[offset * 2];
+                            match level.get(offset * 2 + 1) {
+                                Some(right) => self.config.combine(left, right),
+                                None => self.pad_parent(left, height - 1),
+                            }
+                        }
+                    })
+                    .collect::<Vec<Hash>>();
+            }
+
+            let level_start = Self::get_level_start(depth - height);
+            nodes[level_start..level_start + level.len()].copy_from_slice(&level);
+
+            // pad the last node of levels with an odd number of nodes
+            if height < depth && !level.len().is_multiple_of(2) {
+                nodes[level_start + level.len()] = self
+                    .padding_for(&level[level.len() - 1], height)
+                    .unwrap_or(EMPTY_NODE);
+            }
+        }
+
+        Self::from_parts(nodes, leaf_count, self.duplicate_policy).with_config(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Direction, Hash, MerkleTree};
+
+    fn leaves() -> Vec<Hash> {
+        (0..16u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn joins_two_trees_under_a_new_root() {
+        let leaves = leaves();
+
+        for split in [2, 4, 8] {
+            for end in split + 1..=split * 2 {
+                let left = MerkleTree::new(&leaves[0..split]).unwrap();
+                let right = MerkleTree::new(&leaves[split..end]).unwrap();
+
+                if left.num_levels() != right.num_levels() {
+                    assert!(MerkleTree::join(&left, &right).is_err());
+                    continue;
+                }
+
+                let joined = MerkleTree::join(&left, &right).unwrap();
+                assert_eq!(
+                    joined.nodes,
+                    MerkleTree::new(&leaves[0..end]).unwrap().nodes
+                );
+
+                let right_root = right.root();
+                let mut proof = left.proof(&leaves[0]).unwrap();
+                proof.push((Direction::Right, &right_root));
+                assert!(joined.verify(&proof, &leaves[0]));
+            }
+        }
+    }
+
+    #[test]
+    fn splits_a_tree_in_two() {
+        let leaves = leaves();
+        let tree = MerkleTree::new(&leaves[0..13]).unwrap();
+
+        for offset in 1..13 {
+            let (left, right) = tree.split_at(offset).unwrap();
+            assert_eq!(
+                left.nodes,
+                MerkleTree::new(&leaves[0..offset]).unwrap().nodes
+            );
+            assert_eq!(
+                right.nodes,
+                MerkleTree::new(&leaves[offset..13]).unwrap().nodes
+            );
+        }
+
+        assert!(tree.split_at(0).is_err());
+        assert!(tree.split_at(13).is_err());
+    }
+
+    #[test]
+    fn errors_when_the_left_tree_is_not_full() {
+        let leaves = leaves();
+        let left = MerkleTree::new(&leaves[0..3]).unwrap();
+        let right = MerkleTree::new(&leaves[3..6]).unwrap();
+        assert!(MerkleTree::join(&left, &right).is_err());
+
+        let left = MerkleTree::new(&leaves[0..1]).unwrap();
+        let right = MerkleTree::new(&leaves[1..2]).unwrap();
+        assert!(MerkleTree::join(&left, &right).is_err());
+    }
+}
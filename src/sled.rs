@@ -0,0 +1,210 @@
+//! Keep the nodes of a `MerkleTree` in a sled database, behind the `sled`
+//! feature.
+//!
+//! Each node is stored under a 16 byte key of its level and index, both
+//! big-endian `u64`s, so the nodes of a level are contiguous and ordered.
+//! The number of leaves is stored under the key `leaf_count`.
+//!
+//! A `SledStore` is also a `snapshot::ContentStore`, keeping each node of a
+//! snapshot under a 40 byte key of its height, a big-endian `u64`, and its
+//! hash, so it can't be confused with the nodes of a tree.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::snapshot::ContentStore;
+use crate::storage::{NodeStore, Position};
+use crate::Hash;
+use std::path::Path;
+
+/// The key the number of leaves is stored under.
+const LEAF_COUNT: &[u8] = b"leaf_count";
+
+/// A store in a sled tree, which may be shared with other data in the same
+/// database.
+///
+/// ```rust
+/// use merkle_tree::{sled::SledStore, MerkleTree};
+///
+/// let db = sled::Config::new().temporary(true).open().unwrap();
+/// let store = SledStore::new(db.open_tree("merkle_tree").unwrap());
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+/// let tree = MerkleTree::create(store, leaves).unwrap();
+///
+/// let proof = tree.proof_at(2).unwrap();
+/// assert!(tree.verify(&proof, &leaves[2]).unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct SledStore {
+    tree: ::sled::Tree,
+}
+
+impl SledStore {
+    /// Store nodes in a tree of a sled database.
+    pub fn new(tree: ::sled::Tree) -> SledStore {
+        SledStore { tree }
+    }
+
+    /// Open (or create) a sled database at `path`, storing nodes in its
+    /// default tree.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SledStore> {
+        let db = ::sled::open(path).map_err(storage_error)?;
+        Ok(SledStore::new(::sled::Tree::clone(&db)))
+    }
+
+    /// The sled tree holding the nodes.
+    pub fn tree(&self) -> &::sled::Tree {
+        &self.tree
+    }
+}
+
+impl NodeStore for SledStore {
+    fn get(&self, position: Position) -> Result<Option<Hash>> {
+        let Some(node) = self.tree.get(key(position)).map_err(storage_error)? else {
+            return Ok(None);
+        };
+
+        let node = Hash::try_from(node.as_ref()).map_err(|_| {
+            MerkleTreeError::Storage(format!("node at {position:?} isn't 32 bytes"))
+        })?;
+
+        Ok(Some(node))
+    }
+
+    fn put(&mut self, position: Position, node: Hash) -> Result<()> {
+        self.tree
+            .insert(key(position), &node)
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn put_batch(&mut self, nodes: &[(Position, Hash)]) -> Result<()> {
+        let mut batch = ::sled::Batch::default();
+
+        for (position, node) in nodes {
+            batch.insert(&key(*position), node);
+        }
+
+        self.tree.apply_batch(batch).map_err(storage_error)
+    }
+
+    fn leaf_count(&self) -> Result<usize> {
+        let Some(leaf_count) = self.tree.get(LEAF_COUNT).map_err(storage_error)? else {
+            return Ok(0);
+        };
+
+        let leaf_count = <[u8; 8]>::try_from(leaf_count.as_ref())
+            .map_err(|_| MerkleTreeError::Storage("leaf count isn't 8 bytes".to_owned()))?;
+
+        Ok(u64::from_be_bytes(leaf_count) as usize)
+    }
+
+    fn set_leaf_count(&mut self, leaf_count: usize) -> Result<()> {
+        self.tree
+            .insert(LEAF_COUNT, &(leaf_count as u64).to_be_bytes())
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.tree.flush().map_err(storage_error)?;
+        Ok(())
+    }
+}
+
+impl ContentStore for SledStore {
+    fn get(&self, height: usize, node: &Hash) -> Result<Option<[Hash; 2]>> {
+        let Some(children) = self
+            .tree
+            .get(content_key(height, node))
+            .map_err(storage_error)?
+        else {
+            return Ok(None);
+        };
+
+        let children = <[u8; 64]>::try_from(children.as_ref()).map_err(|_| {
+            MerkleTreeError::Storage(format!("children of {} aren't 64 bytes", hex::encode(node)))
+        })?;
+
+        Ok(Some([
+            children[..32].try_into().unwrap(),
+            children[32..].try_into().unwrap(),
+        ]))
+    }
+
+    fn put(&mut self, height: usize, node: Hash, children: [Hash; 2]) -> Result<()> {
+        self.tree
+            .insert(content_key(height, &node), children.as_flattened())
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.tree.flush().map_err(storage_error)?;
+        Ok(())
+    }
+}
+
+/// The key of the node at a position.
+fn key(position: Position) -> [u8; 16] {
+    let mut key = [0; 16];
+    key[..8].copy_from_slice(&(position.level as u64).to_be_bytes());
+    key[8..].copy_from_slice(&(position.index as u64).to_be_bytes());
+    key
+}
+
+/// The key of a node of a snapshot.
+fn content_key(height: usize, node: &Hash) -> [u8; 40] {
+    let mut key = [0; 40];
+    key[..8].copy_from_slice(&(height as u64).to_be_bytes());
+    key[8..].copy_from_slice(node);
+    key
+}
+
+fn storage_error(error: ::sled::Error) -> MerkleTreeError {
+    MerkleTreeError::Storage(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::SnapshotStore;
+    use crate::storage::tests::{assert_matches, leaves};
+    use crate::MerkleTree;
+
+    #[test]
+    fn stores_trees_in_sled() {
+        let db = ::sled::Config::new().temporary(true).open().unwrap();
+        let mut leaves = leaves(5);
+
+        let store = SledStore::new(db.open_tree("tree").unwrap());
+        let mut tree = MerkleTree::create(store, leaves.clone()).unwrap();
+        assert_matches(&tree, &leaves);
+
+        leaves.push(MerkleTree::hash(b"x"));
+        tree.push(leaves[5]).unwrap();
+        leaves[0] = MerkleTree::hash(b"y");
+        tree.update(0, leaves[0]).unwrap();
+
+        // reopening reads every node from the database
+        drop(tree);
+        let tree = MerkleTree::open(SledStore::new(db.open_tree("tree").unwrap())).unwrap();
+        assert_eq!(tree.leaf_count(), 6);
+        assert_matches(&tree, &leaves);
+    }
+
+    #[test]
+    fn stores_snapshots_in_sled() {
+        let db = ::sled::Config::new().temporary(true).open().unwrap();
+        let mut tree = MerkleTree::new(&leaves(5)).unwrap();
+
+        let mut snapshots = SnapshotStore::new(SledStore::new(db.open_tree("snapshots").unwrap()));
+        let first = snapshots.save(&tree).unwrap();
+        let saved = tree.clone();
+        tree.update(2, MerkleTree::hash(b"x")).unwrap();
+        let second = snapshots.save(&tree).unwrap();
+
+        let snapshots = SnapshotStore::new(SledStore::new(db.open_tree("snapshots").unwrap()));
+        assert_eq!(snapshots.load(&first).unwrap(), saved);
+        assert_eq!(snapshots.load(&second).unwrap(), tree);
+    }
+}
@@ -0,0 +1,270 @@
+//! A content-addressed store of tree snapshots, where nodes shared between
+//! snapshots are stored once.
+//!
+//! Each internal node is stored under its hash and its height above the
+//! leaves, with the two nodes below it, so a snapshot is just its root, its
+//! number of leaves and its height.  Saving a snapshot stops descending at
+//! every node the store already holds, since the whole subtree below it was
+//! stored with it, so persisting snapshots of a slowly changing tree costs
+//! space and writes proportional to what changed since the last one.  The
+//! height is part of the key because a promoted node (see
+//! `padding::PromoteOdd`) has the same hash as the node below it.
+//!
+//! Nodes are written children first, so a node in the store always has its
+//! subtree, even if a save is interrupted.  Nodes are never removed, since
+//! any number of snapshots may share them.
+
+use crate::builder::MerkleTreeBuilder;
+use crate::error::{MerkleTreeError, Result};
+use crate::{Hash, MerkleTree, EMPTY_NODE};
+use std::collections::{HashMap, HashSet};
+
+/// A saved tree, which can be loaded from the store that saved it.  Keep
+/// snapshots wherever is convenient, e.g. alongside the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Snapshot {
+    pub root: Hash,
+    pub leaf_count: usize,
+    pub height: usize,
+}
+
+/// A place to keep the nodes of snapshots, keyed by their height and hash.
+pub trait ContentStore {
+    /// The two nodes below the node with a hash at a height, if it has been
+    /// stored.
+    fn get(&self, height: usize, node: &Hash) -> Result<Option<[Hash; 2]>>;
+
+    /// Store the two nodes below the node with a hash at a height.
+    fn put(&mut self, height: usize, node: Hash, children: [Hash; 2]) -> Result<()>;
+
+    /// Make every write so far durable.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A content store in a hash map.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryContentStore {
+    nodes: HashMap<(usize, Hash), [Hash; 2]>,
+}
+
+impl MemoryContentStore {
+    /// Create an empty store.
+    pub fn new() -> MemoryContentStore {
+        MemoryContentStore::default()
+    }
+
+    /// The number of nodes stored.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether no nodes are stored.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl ContentStore for MemoryContentStore {
+    fn get(&self, height: usize, node: &Hash) -> Result<Option<[Hash; 2]>> {
+        Ok(self.nodes.get(&(height, *node)).copied())
+    }
+
+    fn put(&mut self, height: usize, node: Hash, children: [Hash; 2]) -> Result<()> {
+        self.nodes.insert((height, node), children);
+        Ok(())
+    }
+}
+
+/// Saves and loads snapshots of trees, sharing their nodes in a
+/// `ContentStore`.
+///
+/// ```rust
+/// use merkle_tree::snapshot::{MemoryContentStore, SnapshotStore};
+/// use merkle_tree::MerkleTree;
+///
+/// let leaves = (0..64u8).map(|i| MerkleTree::hash(&[i])).collect::<Vec<_>>();
+/// let mut tree = MerkleTree::new(&leaves).unwrap();
+/// let mut snapshots = SnapshotStore::new(MemoryContentStore::new());
+///
+/// let first = snapshots.save(&tree).unwrap();
+/// tree.update(0, MerkleTree::hash(b"x")).unwrap();
+/// let second = snapshots.save(&tree).unwrap();
+///
+/// // the second snapshot only added the branch of the updated leaf
+/// assert_eq!(snapshots.store().len(), 63 + 6);
+/// assert_eq!(snapshots.load(&first).unwrap(), MerkleTree::new(&leaves).unwrap());
+/// assert_eq!(snapshots.load(&second).unwrap().root(), tree.root());
+/// ```
+#[derive(Debug, Default)]
+pub struct SnapshotStore<S: ContentStore = MemoryContentStore> {
+    store: S,
+}
+
+impl<S: ContentStore> SnapshotStore<S> {
+    /// Keep snapshots in a content store.
+    pub fn new(store: S) -> SnapshotStore<S> {
+        SnapshotStore { store }
+    }
+
+    /// The store holding the nodes.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Stop using the snapshots, returning their store.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Save a snapshot of a tree, storing only the nodes the store doesn't
+    /// already hold.
+    pub fn save(&mut self, tree: &MerkleTree) -> Result<Snapshot> {
+        let height = tree.num_levels();
+        let mut new = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![0];
+
+        while let Some(index) = stack.pop() {
+            let level = MerkleTree::get_level(index);
+            let node_height = height - level;
+            let node = tree.nodes[index];
+
+            if node_height == 0
+                || index - MerkleTree::get_level_start(level) >= tree.level_width(level)
+                || !seen.insert((node_height, node))
+                || self.store.get(node_height, &node)?.is_some()
+            {
+                continue;
+            }
+
+            let children = [tree.nodes[index * 2 + 1], tree.nodes[index * 2 + 2]];
+            new.push((node_height, node, children));
+            stack.extend([index * 2 + 1, index * 2 + 2]);
+        }
+
+        // children first, so every stored node has its subtree
+        for (height, node, children) in new.into_iter().rev() {
+            self.store.put(height, node, children)?;
+        }
+
+        self.store.flush()?;
+
+        Ok(Snapshot {
+            root: tree.root(),
+            leaf_count: tree.leaf_count,
+            height,
+        })
+    }
+
+    /// Load a snapshot into a tree with the default options.
+    pub fn load(&self, snapshot: &Snapshot) -> Result<MerkleTree> {
+        MerkleTree::builder().load_snapshot(self, snapshot)
+    }
+}
+
+impl MerkleTreeBuilder {
+    /// Load a snapshot into a tree with these options, which should be those
+    /// of the tree that was saved.
+    pub fn load_snapshot<S: ContentStore>(
+        &self,
+        snapshots: &SnapshotStore<S>,
+        snapshot: &Snapshot,
+    ) -> Result<MerkleTree> {
+        let Snapshot {
+            root,
+            leaf_count,
+            height,
+        } = *snapshot;
+
+        if leaf_count == 0 || leaf_count > 1 << height {
+            return Err(MerkleTreeError::InvalidEncoding(format!(
+                "{leaf_count} leaves can't be held by a tree of height {height}"
+            )));
+        }
+
+        let mut nodes = vec![EMPTY_NODE; (2 << height) - 1];
+        nodes[0] = root;
+
+        for level in 0..height {
+            let level_start = MerkleTree::get_level_start(level);
+            let width = leaf_count.div_ceil(1 << (height - level));
+
+            for index in level_start..level_start + width {
+                let children = snapshots.store.get(height - level, &nodes[index])?.ok_or(
+                    MerkleTreeError::MissingNode(height - level, index - level_start),
+                )?;
+
+                nodes[index * 2 + 1..=index * 2 + 2].copy_from_slice(&children);
+            }
+        }
+
+        Ok(
+            MerkleTree::from_parts(nodes, leaf_count, self.duplicate_policy)
+                .with_config(self.config),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::padding::PromoteOdd;
+
+    fn leaves(count: u8) -> Vec<Hash> {
+        (0..count).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn shares_nodes_between_snapshots() {
+        let mut leaves = leaves(100);
+        let mut tree = MerkleTree::new(&leaves).unwrap();
+        let mut snapshots = SnapshotStore::new(MemoryContentStore::new());
+        let mut saved = vec![(snapshots.save(&tree).unwrap(), tree.clone())];
+        let stored = snapshots.store().len();
+
+        for i in 0..4u8 {
+            leaves[i as usize * 10] = MerkleTree::hash(&[b'u', i]);
+            tree.update(i as usize * 10, leaves[i as usize * 10])
+                .unwrap();
+            saved.push((snapshots.save(&tree).unwrap(), tree.clone()));
+
+            // one new node per level
+            let added = snapshots.store().len() - stored;
+            assert_eq!(added, (i as usize + 1) * tree.num_levels());
+        }
+
+        tree.push(MerkleTree::hash(b"p")).unwrap();
+        saved.push((snapshots.save(&tree).unwrap(), tree.clone()));
+
+        // saving an unchanged tree stores nothing
+        let stored = snapshots.store().len();
+        assert_eq!(snapshots.save(&tree).unwrap(), saved[saved.len() - 1].0);
+        assert_eq!(snapshots.store().len(), stored);
+
+        for (snapshot, tree) in &saved {
+            let loaded = snapshots.load(snapshot).unwrap();
+            assert_eq!(&loaded, tree);
+            assert!(loaded.integrity_check().is_ok());
+        }
+
+        let missing = Snapshot {
+            root: MerkleTree::hash(b"x"),
+            ..saved[0].0
+        };
+        assert!(snapshots.load(&missing).is_err());
+    }
+
+    #[test]
+    fn keeps_promoted_nodes_apart() {
+        let builder = MerkleTree::builder().padding(&PromoteOdd);
+        let mut snapshots = SnapshotStore::new(MemoryContentStore::new());
+
+        for count in [1, 5, 6, 7] {
+            let tree = builder.build(&leaves(count)).unwrap();
+            let snapshot = snapshots.save(&tree).unwrap();
+            assert_eq!(builder.load_snapshot(&snapshots, &snapshot).unwrap(), tree);
+        }
+    }
+}
@@ -0,0 +1,298 @@
+//! SSZ encoding of trees, roots and proofs, as used by Ethereum consensus
+//! clients.
+//!
+//! A tree built with `list_builder()` hashes its leaves as the chunks of an
+//! SSZ `List[Bytes32, limit]`: with SHA-256, padded with empty subtrees to
+//! the depth of the limit, so `hash_tree_root()` is the SSZ
+//! `hash_tree_root` of the list, i.e. the root mixed in with the number of
+//! leaves.
+//!
+//! Leaves are serialized as the list (their concatenation), roots as a
+//! `Bytes32`, and proofs as the container
+//!
+//! ```text
+//! class Branch(Container):
+//!     index: uint64                 # the leaf's index at the proof's depth
+//!     branch: List[Bytes32, 64]     # sibling hashes, leaf first
+//! ```
+//!
+//! whose fields are those of the spec's `is_valid_merkle_branch()`.
+
+use crate::builder::{Hasher, MerkleTreeBuilder};
+use crate::error::{MerkleTreeError, Result};
+use crate::padding::ZeroHash;
+use crate::{Direction, Hash, MerkleTree, OwnedProof};
+use sha2::{Digest, Sha256};
+use std::borrow::Borrow;
+
+/// The most siblings a serialized proof may hold.
+const MAX_BRANCH_LEN: usize = 64;
+
+/// The length of the fixed part of a serialized proof: its index and the
+/// offset of its branch.
+const FIXED_PROOF_LEN: usize = 12;
+
+impl Hasher {
+    /// SHA-256 of the data, and of the concatenation of both children, as
+    /// SSZ merkleization uses.
+    pub const SHA_256: Hasher = Hasher {
+        id: 3,
+        leaf: sha_256,
+        node: sha_256_node,
+    };
+}
+
+fn sha_256(data: &[u8]) -> Hash {
+    Sha256::digest(data).into()
+}
+
+fn sha_256_node(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+/// A builder for trees of the chunks of an SSZ `List[Bytes32, limit]`.  The
+/// limit is rounded up to a power of two, and must be at least 2 and at most
+/// `2^MAX_DEPTH`, since the tree stores every chunk up to the limit.
+///
+/// ```rust
+/// use merkle_tree::{ssz, MerkleTree};
+///
+/// let leaves = [[1; 32], [2; 32], [3; 32]];
+/// let builder = ssz::list_builder(8);
+/// let tree = builder.build(&leaves).unwrap();
+///
+/// let root = ssz::hash_tree_root(&tree);
+/// let proof = ssz::list_proof(&tree, 2).unwrap();
+/// assert!(builder.verify(&root, &proof, &leaves[2]));
+/// ```
+pub fn list_builder(limit: usize) -> MerkleTreeBuilder {
+    MerkleTree::builder()
+        .hasher(Hasher::SHA_256)
+        .padding(&ZeroHash)
+        .depth(
+            limit
+                .checked_next_power_of_two()
+                .map_or(usize::BITS, usize::ilog2) as usize,
+        )
+}
+
+/// The SSZ `hash_tree_root` of the list of a tree's leaves: its root mixed
+/// in with the number of leaves.
+pub fn hash_tree_root(tree: &MerkleTree) -> Hash {
+    tree.config
+        .combine(&tree.root(), &length_chunk(tree.leaf_count))
+}
+
+/// A proof for the leaf at an offset against `hash_tree_root()`, i.e. the
+/// proof against the root followed by the length chunk.
+pub fn list_proof(tree: &MerkleTree, offset: usize) -> Result<OwnedProof> {
+    let mut proof = tree
+        .proof_at(offset)?
+        .into_iter()
+        .map(|(direction, hash)| (direction, *hash))
+        .collect::<OwnedProof>();
+    proof.push((Direction::Right, length_chunk(tree.leaf_count)));
+
+    Ok(proof)
+}
+
+fn length_chunk(length: usize) -> Hash {
+    let mut chunk = [0; 32];
+    chunk[..8].copy_from_slice(&(length as u64).to_le_bytes());
+    chunk
+}
+
+/// Serialize a tree's leaves as an SSZ `List[Bytes32, limit]`.
+pub fn encode_leaves(tree: &MerkleTree) -> Vec<u8> {
+    tree.leaves().as_flattened().to_vec()
+}
+
+/// Build a tree with the builder's options from leaves serialized with
+/// `encode_leaves()`.
+pub fn decode_leaves(builder: &MerkleTreeBuilder, bytes: &[u8]) -> Result<MerkleTree> {
+    let (leaves, rest) = bytes.as_chunks::<32>();
+
+    if !rest.is_empty() {
+        return Err(invalid(format!(
+            "{} bytes isn't a list of 32 byte leaves",
+            bytes.len()
+        )));
+    }
+
+    builder.build(leaves)
+}
+
+/// Serialize a root as an SSZ `Bytes32`.
+pub fn encode_root(root: &Hash) -> Vec<u8> {
+    root.to_vec()
+}
+
+/// Deserialize a root serialized with `encode_root()`.
+pub fn decode_root(bytes: &[u8]) -> Result<Hash> {
+    Hash::try_from(bytes).map_err(|_| invalid(format!("root of {} bytes", bytes.len())))
+}
+
+/// Serialize a proof as an SSZ `Branch` container (see the module
+/// documentation), with the index implied by the sides of its siblings.
+///
+/// ```rust
+/// use merkle_tree::{ssz, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+/// let tree = MerkleTree::new(&leaves).unwrap();
+/// let proof = tree.proof(&leaves[2]).unwrap();
+///
+/// let decoded = ssz::decode_proof(&ssz::encode_proof(&proof).unwrap()).unwrap();
+/// assert!(tree.verify(&decoded, &leaves[2]));
+/// ```
+pub fn encode_proof<H: Borrow<Hash>>(proof: &[(Direction, H)]) -> Result<Vec<u8>> {
+    if proof.len() > MAX_BRANCH_LEN {
+        return Err(invalid(format!(
+            "proof of {} siblings exceeds {MAX_BRANCH_LEN}",
+            proof.len()
+        )));
+    }
+
+    // the sibling is on the left of a right child
+    let index = proof
+        .iter()
+        .enumerate()
+        .filter(|(_, (direction, _))| *direction == Direction::Left)
+        .fold(0u64, |index, (level, _)| index | 1 << level);
+
+    let mut bytes = Vec::with_capacity(FIXED_PROOF_LEN + proof.len() * 32);
+    bytes.extend_from_slice(&index.to_le_bytes());
+    bytes.extend_from_slice(&(FIXED_PROOF_LEN as u32).to_le_bytes());
+
+    for (_, hash) in proof {
+        bytes.extend_from_slice(hash.borrow());
+    }
+
+    Ok(bytes)
+}
+
+/// Deserialize a proof serialized with `encode_proof()`.
+pub fn decode_proof(bytes: &[u8]) -> Result<OwnedProof> {
+    let Some((fixed, branch)) = bytes.split_first_chunk::<FIXED_PROOF_LEN>() else {
+        return Err(invalid(format!("proof of {} bytes", bytes.len())));
+    };
+
+    let index = u64::from_le_bytes(fixed[..8].try_into().unwrap());
+    let offset = u32::from_le_bytes(fixed[8..].try_into().unwrap());
+
+    if offset as usize != FIXED_PROOF_LEN {
+        return Err(invalid(format!("branch at offset {offset}")));
+    }
+
+    let (branch, rest) = branch.as_chunks::<32>();
+
+    if !rest.is_empty() || branch.len() > MAX_BRANCH_LEN {
+        return Err(invalid(format!(
+            "branch of {} bytes",
+            bytes.len() - FIXED_PROOF_LEN
+        )));
+    }
+
+    if branch.len() < 64 && index >> branch.len() != 0 {
+        return Err(invalid(format!(
+            "index {index} is beyond a branch of {} siblings",
+            branch.len()
+        )));
+    }
+
+    Ok(branch
+        .iter()
+        .enumerate()
+        .map(|(level, hash)| {
+            let direction = match index >> level & 1 {
+                1 => Direction::Left,
+                _ => Direction::Right,
+            };
+            (direction, *hash)
+        })
+        .collect())
+}
+
+fn invalid(reason: String) -> MerkleTreeError {
+    MerkleTreeError::InvalidEncoding(format!("SSZ {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_ssz_hash_tree_root() {
+        let leaves = [[1; 32], [2; 32], [3; 32]];
+        let builder = list_builder(8);
+        let tree = builder.build(&leaves).unwrap();
+
+        // from a reference implementation of the consensus specs
+        assert_eq!(
+            hex::encode(tree.root()),
+            "16e7bc9c1de9b5c63df0e8ef884b1006155d390b6e1aee4ffd3844e548ec2d41"
+        );
+        assert_eq!(
+            hex::encode(hash_tree_root(&tree)),
+            "090b96a38cf0a0356b60d89d7ca5f9345e4e404f74e3c59f1f47827feea76e5e"
+        );
+
+        for (offset, leaf) in leaves.iter().enumerate() {
+            let proof = list_proof(&tree, offset).unwrap();
+            assert!(builder.verify(&hash_tree_root(&tree), &proof, leaf));
+        }
+
+        assert!(list_builder(1).build(&leaves[..1]).is_err());
+        assert!(list_builder(1 << 40).build(&leaves[..1]).is_err());
+        assert!(list_builder(usize::MAX).build(&leaves[..1]).is_err());
+    }
+
+    #[test]
+    fn round_trips() {
+        let builder = list_builder(16);
+        let leaves = (0..11u8).map(|i| [i; 32]).collect::<Vec<_>>();
+        let tree = builder.build(&leaves).unwrap();
+
+        let decoded = decode_leaves(&builder, &encode_leaves(&tree)).unwrap();
+        assert_eq!(decoded, tree);
+        assert_eq!(
+            decode_root(&encode_root(&tree.root())).unwrap(),
+            tree.root()
+        );
+
+        for (offset, leaf) in leaves.iter().enumerate() {
+            let proof = list_proof(&tree, offset).unwrap();
+            let bytes = encode_proof(&proof).unwrap();
+
+            // the index of the leaf under the length mix-in
+            assert_eq!(bytes[..8], (offset as u64).to_le_bytes());
+            assert_eq!(decode_proof(&bytes).unwrap(), proof);
+            assert!(builder.verify(&hash_tree_root(&tree), &proof, leaf));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_encodings() {
+        let proof =
+            encode_proof(&[(Direction::Left, [1; 32]), (Direction::Right, [2; 32])]).unwrap();
+
+        assert!(decode_proof(&proof[..proof.len() - 1]).is_err());
+        assert!(decode_proof(&proof[..8]).is_err());
+
+        let mut moved = proof.clone();
+        moved[8] = 16;
+        assert!(decode_proof(&moved).is_err());
+
+        let mut beyond = proof.clone();
+        beyond[0] = 4;
+        assert!(decode_proof(&beyond).is_err());
+
+        assert!(encode_proof(&vec![(Direction::Left, [0; 32]); 65]).is_err());
+        assert!(decode_root(&[0; 31]).is_err());
+        assert!(decode_leaves(&list_builder(4), &[0; 33]).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! Report the size of a tree, e.g. for capacity planning.
+
+use crate::lookup::Occurrences;
+use crate::replication::Mutation;
+use crate::{Hash, MerkleTree};
+use std::mem::size_of;
+
+/// The size of a tree and its storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeStats {
+    /// The number of leaves, excluding padding.
+    pub leaf_count: usize,
+
+    /// The number of leaves the flattened layout is sized for, which is a
+    /// power of two (minimum of 2).
+    pub padded_leaf_count: usize,
+
+    /// The number of levels below the root, which is the length of a proof.
+    pub depth: usize,
+
+    /// The number of nodes in the flattened layout, including padding and
+    /// unused slots.
+    pub node_count: usize,
+
+    /// The number of nodes the storage can hold without reallocating.
+    pub node_capacity: usize,
+
+    /// An estimate of the heap memory held by the tree: its node storage,
+    /// root history, mutation log and construction records.  Listeners and
+    /// subscriptions aren't counted.
+    pub heap_bytes: usize,
+}
+
+impl MerkleTree {
+    /// Report the size of the tree and an estimate of its heap memory.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let stats = MerkleTree::new(&leaves).unwrap().stats();
+    ///
+    /// assert_eq!(stats.leaf_count, 3);
+    /// assert_eq!(stats.padded_leaf_count, 4);
+    /// assert_eq!(stats.depth, 2);
+    /// assert_eq!(stats.node_count, 7);
+    /// assert!(stats.heap_bytes >= 7 * 32);
+    /// ```
+    pub fn stats(&self) -> TreeStats {
+        let hashes =
+            self.nodes.capacity() + self.empty_subtrees.capacity() + self.root_history.capacity();
+        let offsets = [&self.sorted_positions, &self.removed_duplicates]
+            .iter()
+            .filter_map(|offsets| offsets.as_ref())
+            .map(Vec::capacity)
+            .sum::<usize>();
+        let log = self
+            .log
+            .as_ref()
+            .map(|log| {
+                log.capacity() * size_of::<Mutation>()
+                    + log.iter().map(Mutation::heap_bytes).sum::<usize>()
+            })
+            .unwrap_or(0);
+        let lookup = self.lookup.capacity() * size_of::<(Hash, Occurrences)>();
+
+        TreeStats {
+            leaf_count: self.leaf_count,
+            padded_leaf_count: self.num_leaves(),
+            depth: self.num_levels(),
+            node_count: self.nodes.len(),
+            node_capacity: self.nodes.capacity(),
+            heap_bytes: hashes * size_of::<Hash>() + offsets * size_of::<usize>() + log + lookup,
+        }
+    }
+}
+
+impl Mutation {
+    /// The heap memory held by the leaves and updates of a mutation.
+    fn heap_bytes(&self) -> usize {
+        match self {
+            Mutation::UpdateBatch(updates) => updates.capacity() * size_of::<(usize, Hash)>(),
+            Mutation::Extend(leaves) | Mutation::Rebuild(leaves) => {
+                leaves.capacity() * size_of::<Hash>()
+            }
+            Mutation::Commit { updates, pushes } => {
+                updates.capacity() * size_of::<(usize, Hash)>()
+                    + pushes.capacity() * size_of::<Hash>()
+            }
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Hash, MerkleTree};
+
+    #[test]
+    fn reports_the_size_of_the_tree() {
+        let leaves = (0..5u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let mut tree = MerkleTree::new(&leaves).unwrap();
+        let stats = tree.stats();
+
+        assert_eq!(stats.padded_leaf_count, 8);
+        assert_eq!(stats.depth, 3);
+        assert_eq!(stats.node_count, 15);
+        assert!(stats.node_capacity >= 15);
+
+        tree.start_log();
+        tree.extend(&leaves).unwrap();
+        let stats_after = tree.stats();
+
+        assert_eq!(stats_after.leaf_count, 10);
+        assert_eq!(stats_after.padded_leaf_count, 16);
+        assert!(stats_after.heap_bytes >= stats.heap_bytes + 16 * 32 + 5 * 32);
+    }
+}
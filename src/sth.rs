@@ -0,0 +1,294 @@
+//! RFC 6962 signed tree heads, behind the `sth` feature, so the operator of
+//! an append-only log built with `rfc6962::builder()` can publish signed
+//! roots, and monitors can audit the log with consistency proofs between
+//! them, as with Certificate Transparency.
+//!
+//! The signature is an ed25519 signature of the RFC's `TreeHeadSignature`:
+//! the version (`v1`), the signature type (`tree_hash`), then the timestamp
+//! (milliseconds since the Unix epoch), the tree size and the root.  Tree
+//! heads and consistency proofs convert to and from the JSON of the RFC's
+//! `get-sth` and `get-sth-consistency` responses, where the signature is a
+//! `DigitallySigned` struct with the `ed25519` signature scheme.
+
+use crate::attestation::{SigningKey, VerifyingKey};
+use crate::error::{MerkleTreeError, Result};
+use crate::rfc6962::ConsistencyProof;
+use crate::{Hash, MerkleTree};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer};
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The `v1` version of the signed structure.
+const VERSION: u8 = 0;
+
+/// The `tree_hash` signature type of the signed structure.
+const TREE_HASH: u8 = 1;
+
+/// The `ed25519` signature scheme, as a `DigitallySigned` hash and
+/// signature algorithm.
+const ED25519: [u8; 2] = [8, 7];
+
+/// A root signed by the operator of a log, along with the size of the tree
+/// and when it was signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignedTreeHead {
+    pub tree_size: u64,
+    pub timestamp: u64,
+    pub root_hash: Hash,
+    pub signature: [u8; 64],
+}
+
+impl SignedTreeHead {
+    /// Sign the root of a tree of `tree_size` leaves, at `timestamp`
+    /// milliseconds since the Unix epoch.
+    pub fn sign(tree_size: u64, timestamp: u64, root_hash: Hash, key: &SigningKey) -> Self {
+        let signed_data = signed_data(tree_size, timestamp, &root_hash);
+
+        SignedTreeHead {
+            tree_size,
+            timestamp,
+            root_hash,
+            signature: key.sign(&signed_data).to_bytes(),
+        }
+    }
+
+    /// The `TreeHeadSignature` the signature covers.
+    pub fn signed_data(&self) -> Vec<u8> {
+        signed_data(self.tree_size, self.timestamp, &self.root_hash)
+    }
+
+    /// Whether the tree head was signed by the holder of `key`.
+    pub fn verify(&self, key: &VerifyingKey) -> bool {
+        let signature = Signature::from_bytes(&self.signature);
+        key.verify_strict(&self.signed_data(), &signature).is_ok()
+    }
+
+    /// Whether both tree heads were signed by the holder of `key`, and the
+    /// proof shows that the tree of this one is a prefix of the tree of
+    /// `later`.
+    ///
+    /// ```rust
+    /// use merkle_tree::attestation::SigningKey;
+    /// use merkle_tree::rfc6962;
+    ///
+    /// let key = SigningKey::from_bytes(&[7; 32]);
+    /// let mut tree = rfc6962::builder().build_from_data(["a", "b", "c"]).unwrap();
+    /// let first = tree.signed_tree_head(&key).unwrap();
+    ///
+    /// tree.push(tree[0]).unwrap();
+    /// let second = tree.signed_tree_head(&key).unwrap();
+    ///
+    /// let proof = tree.consistency_proof(first.tree_size as usize).unwrap();
+    /// assert!(first.verify_consistency(&second, &proof, &key.verifying_key()));
+    /// ```
+    pub fn verify_consistency(
+        &self,
+        later: &SignedTreeHead,
+        proof: &ConsistencyProof,
+        key: &VerifyingKey,
+    ) -> bool {
+        proof.first_size == self.tree_size
+            && proof.second_size == later.tree_size
+            && self.verify(key)
+            && later.verify(key)
+            && proof.verify(&self.root_hash, &later.root_hash)
+    }
+
+    /// The tree head as a `get-sth` response.
+    pub fn to_json(&self) -> Value {
+        let signature = [&ED25519[..], &64u16.to_be_bytes(), &self.signature].concat();
+
+        json!({
+            "tree_size": self.tree_size,
+            "timestamp": self.timestamp,
+            "sha256_root_hash": STANDARD.encode(self.root_hash),
+            "tree_head_signature": STANDARD.encode(signature),
+        })
+    }
+
+    /// Read a `get-sth` response.  The signature isn't checked, see
+    /// `verify()`.
+    pub fn from_json(value: &Value) -> Result<SignedTreeHead> {
+        let field = |name: &str| {
+            value[name]
+                .as_u64()
+                .ok_or_else(|| invalid(&format!("missing {name}")))
+        };
+
+        let root_hash = decode(&value["sha256_root_hash"])?
+            .try_into()
+            .map_err(|_| invalid("root hash must be 32 bytes"))?;
+
+        let signature = decode(&value["tree_head_signature"])?;
+        let signature = match signature.split_at_checked(4) {
+            Some((header, signature)) if header == [ED25519[0], ED25519[1], 0, 64] => signature,
+            _ => return Err(invalid("signature must be an ed25519 signature")),
+        };
+
+        Ok(SignedTreeHead {
+            tree_size: field("tree_size")?,
+            timestamp: field("timestamp")?,
+            root_hash,
+            signature: signature
+                .try_into()
+                .map_err(|_| invalid("signature must be 64 bytes"))?,
+        })
+    }
+}
+
+fn signed_data(tree_size: u64, timestamp: u64, root_hash: &Hash) -> Vec<u8> {
+    [
+        &[VERSION, TREE_HASH][..],
+        &timestamp.to_be_bytes(),
+        &tree_size.to_be_bytes(),
+        root_hash,
+    ]
+    .concat()
+}
+
+impl ConsistencyProof {
+    /// The proof as a `get-sth-consistency` response.
+    pub fn to_json(&self) -> Value {
+        let path = self.path.iter().map(|hash| STANDARD.encode(hash));
+        json!({ "consistency": path.collect::<Vec<_>>() })
+    }
+
+    /// Read a `get-sth-consistency` response for the trees of `first_size`
+    /// and `second_size` leaves.
+    pub fn from_json(value: &Value, first_size: u64, second_size: u64) -> Result<Self> {
+        let path = value["consistency"]
+            .as_array()
+            .ok_or_else(|| invalid("missing consistency"))?
+            .iter()
+            .map(|hash| {
+                decode(hash)?
+                    .try_into()
+                    .map_err(|_| invalid("hash must be 32 bytes"))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(ConsistencyProof {
+            first_size,
+            second_size,
+            path,
+        })
+    }
+}
+
+impl MerkleTree {
+    /// Sign the current root and number of leaves, timestamped now, for a
+    /// tree built with `rfc6962::builder()`.
+    pub fn signed_tree_head(&self, key: &SigningKey) -> Result<SignedTreeHead> {
+        self.check_rfc6962()?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+
+        Ok(SignedTreeHead::sign(
+            self.leaf_count as u64,
+            timestamp,
+            self.root(),
+            key,
+        ))
+    }
+}
+
+fn decode(value: &Value) -> Result<Vec<u8>> {
+    let encoded = value
+        .as_str()
+        .ok_or_else(|| invalid("expected a base64 string"))?;
+
+    STANDARD
+        .decode(encoded)
+        .map_err(|error| invalid(&error.to_string()))
+}
+
+fn invalid(reason: &str) -> MerkleTreeError {
+    MerkleTreeError::InvalidEncoding(format!("STH {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Hasher;
+    use crate::rfc6962;
+
+    #[test]
+    fn audits_an_append_only_log() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let other = SigningKey::from_bytes(&[8; 32]).verifying_key();
+        let leaves = rfc6962::tests::leaves();
+
+        let mut tree = rfc6962::builder().build_from_data(&leaves[..3]).unwrap();
+        let first = tree.signed_tree_head(&key).unwrap();
+        assert_eq!(first.tree_size, 3);
+        assert!(first.verify(&key.verifying_key()));
+        assert!(!first.verify(&other));
+
+        for data in &leaves[3..] {
+            tree.push((Hasher::RFC_6962.leaf)(data)).unwrap();
+        }
+        let second = SignedTreeHead::sign(8, first.timestamp + 1, tree.root(), &key);
+
+        let proof = tree.consistency_proof(3).unwrap();
+        assert!(first.verify_consistency(&second, &proof, &key.verifying_key()));
+        assert!(!second.verify_consistency(&first, &proof, &key.verifying_key()));
+        assert!(!first.verify_consistency(&second, &proof, &other));
+
+        // a log that rewrote its history can't prove consistency
+        let forked = SignedTreeHead::sign(8, second.timestamp, tree[0], &key);
+        assert!(!first.verify_consistency(&forked, &proof, &key.verifying_key()));
+
+        // every field is covered by the signature
+        for tampered in [
+            SignedTreeHead {
+                tree_size: 9,
+                ..second
+            },
+            SignedTreeHead {
+                timestamp: 0,
+                ..second
+            },
+        ] {
+            assert!(!tampered.verify(&key.verifying_key()));
+        }
+
+        let tree = MerkleTree::builder().build_from_data(&leaves).unwrap();
+        assert!(tree.signed_tree_head(&key).is_err());
+    }
+
+    #[test]
+    fn round_trips_json() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let tree = rfc6962::builder()
+            .build_from_data(rfc6962::tests::leaves())
+            .unwrap();
+        let sth = SignedTreeHead::sign(8, 1_700_000_000_000, tree.root(), &key);
+
+        let json = sth.to_json();
+        assert_eq!(json["tree_size"], 8);
+        assert_eq!(
+            json["sha256_root_hash"],
+            "XcnaeacGWamtVZy3Ad7ZoqudgjqtL0lgz+Nw7/RgQyg="
+        );
+        assert_eq!(SignedTreeHead::from_json(&json).unwrap(), sth);
+
+        let proof = tree.consistency_proof(5).unwrap();
+        assert_eq!(
+            ConsistencyProof::from_json(&proof.to_json(), 5, 8).unwrap(),
+            proof
+        );
+
+        let mut other_scheme = json.clone();
+        let signature = STANDARD.encode([&[4, 3, 0, 64][..], &sth.signature].concat());
+        other_scheme["tree_head_signature"] = json!(signature);
+        assert!(SignedTreeHead::from_json(&other_scheme).is_err());
+
+        let mut short_root = json;
+        short_root["sha256_root_hash"] = json!(STANDARD.encode([0; 31]));
+        assert!(SignedTreeHead::from_json(&short_root).is_err());
+    }
+}
@@ -0,0 +1,717 @@
+//! Trees whose nodes are kept in a pluggable `NodeStore`, e.g. a database,
+//! so trees larger than RAM can serve roots, proofs and updates with bounded
+//! memory.
+//!
+//! `MerkleTree<S>` runs over any store `S`, and the default
+//! `MerkleTree<Vec<Hash>>` keeps every node in one flattened vector.  Build a
+//! tree in a store with `MerkleTree::create()`, or `MerkleTreeBuilder::create()`
+//! to choose the hasher, `sorted_pairs`, padding and depth, and reopen it with
+//! `open()`.  A tree in a store serves `root()`, `leaf()`, `proof_at()`,
+//! `verify()`, `update()` and `push()`, reading and writing only the nodes
+//! they need, and its proofs own their hashes, since a store returns nodes by
+//! value.  The rest of the API needs every node in memory, see `to_tree()`.
+//!
+//! Nodes are addressed by `Position`, a level and an index within it, where
+//! level 0 holds the leaves and the root is the only node of the top level,
+//! so leaves keep their positions as the tree grows.  Padding isn't stored:
+//! the last node of a level with an odd number of nodes is paired as the
+//! tree's padding strategy says, so a tree in a store has the same root and
+//! proofs as a tree of the same leaves built in memory with the same options.
+//!
+//! `MemoryStore` keeps nodes in vectors.  The `sled` and `rocksdb` features
+//! add database backed stores.
+//!
+//! Give a tree a `wal::WriteAheadLog` with `with_wal()` so an update or push
+//! interrupted by a crash is replayed or discarded when the tree is reopened.
+
+use crate::builder::MerkleTreeBuilder;
+use crate::error::{MerkleTreeError, Result};
+use crate::hooks::RootListeners;
+use crate::lookup::LeafLookup;
+use crate::subscription::Subscribers;
+use crate::wal::{AttachedLog, WriteAheadLog};
+use crate::{Direction, DuplicatePolicy, Hash, MerkleTree, OwnedProof, EMPTY_NODE};
+use std::borrow::Borrow;
+
+/// The position of a node: its level above the leaves and its index within
+/// the level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Position {
+    pub level: usize,
+    pub index: usize,
+}
+
+impl Position {
+    /// The position of the leaf at a given offset.
+    pub fn leaf(offset: usize) -> Position {
+        Position {
+            level: 0,
+            index: offset,
+        }
+    }
+
+    /// The position of the node's parent.
+    pub fn parent(&self) -> Position {
+        Position {
+            level: self.level + 1,
+            index: self.index / 2,
+        }
+    }
+
+    /// The position of the node's sibling, which may be past the end of a
+    /// level with an odd number of nodes.
+    pub fn sibling(&self) -> Position {
+        Position {
+            level: self.level,
+            index: self.index ^ 1,
+        }
+    }
+
+    /// The position of the node's left child.
+    pub fn left_child(&self) -> Position {
+        Position {
+            level: self.level - 1,
+            index: self.index * 2,
+        }
+    }
+
+    /// The position of the node's right child.
+    pub fn right_child(&self) -> Position {
+        Position {
+            level: self.level - 1,
+            index: self.index * 2 + 1,
+        }
+    }
+}
+
+/// A place to keep the nodes of a `MerkleTree`, e.g. a database.
+pub trait NodeStore {
+    /// The node at a position, if it has been stored.
+    fn get(&self, position: Position) -> Result<Option<Hash>>;
+
+    /// Store the node at a position, replacing any previous node.
+    fn put(&mut self, position: Position, node: Hash) -> Result<()>;
+
+    /// The nodes at many positions, e.g. with a single read of a database.
+    /// Proofs read every sibling with one batch.
+    fn get_batch(&self, positions: &[Position]) -> Result<Vec<Option<Hash>>> {
+        positions
+            .iter()
+            .map(|position| self.get(*position))
+            .collect()
+    }
+
+    /// Store many nodes, e.g. with a single write to a database.
+    /// Construction writes nodes in batches of up to `BATCH_SIZE`, and
+    /// updates write each branch with one batch.
+    fn put_batch(&mut self, nodes: &[(Position, Hash)]) -> Result<()> {
+        for (position, node) in nodes {
+            self.put(*position, *node)?;
+        }
+
+        Ok(())
+    }
+
+    /// The number of leaves of the stored tree, or 0 if none has been stored.
+    fn leaf_count(&self) -> Result<usize>;
+
+    /// Record the number of leaves of the stored tree.
+    fn set_leaf_count(&mut self, leaf_count: usize) -> Result<()>;
+
+    /// Make every write so far durable.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A store keeping each level of nodes in a vector.  Nodes put past the end
+/// of a level extend it with empty nodes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryStore {
+    levels: Vec<Vec<Hash>>,
+    leaf_count: usize,
+}
+
+impl MemoryStore {
+    /// Create an empty store.
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl NodeStore for MemoryStore {
+    fn get(&self, position: Position) -> Result<Option<Hash>> {
+        Ok(self
+            .levels
+            .get(position.level)
+            .and_then(|level| level.get(position.index))
+            .copied())
+    }
+
+    fn put(&mut self, position: Position, node: Hash) -> Result<()> {
+        if self.levels.len() <= position.level {
+            self.levels.resize(position.level + 1, Vec::new());
+        }
+
+        let level = &mut self.levels[position.level];
+
+        if level.len() <= position.index {
+            level.resize(position.index + 1, EMPTY_NODE);
+        }
+
+        level[position.index] = node;
+        Ok(())
+    }
+
+    fn leaf_count(&self) -> Result<usize> {
+        Ok(self.leaf_count)
+    }
+
+    fn set_leaf_count(&mut self, leaf_count: usize) -> Result<()> {
+        self.leaf_count = leaf_count;
+        Ok(())
+    }
+}
+
+/// The number of nodes written at a time by `MerkleTree::create()`.
+pub const BATCH_SIZE: usize = 4096;
+
+impl MerkleTreeBuilder {
+    /// Build a tree in an empty store from the leaves, hashing with these
+    /// options, see `MerkleTree::create()`.  Only the hashing and padding
+    /// options apply (the hasher, `sorted_pairs`, padding and depth): leaves
+    /// keep their order and duplicates are kept.
+    ///
+    /// ```rust
+    /// use merkle_tree::padding::ZeroHash;
+    /// use merkle_tree::storage::MemoryStore;
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let builder = MerkleTree::builder().padding(&ZeroHash).depth(4);
+    /// let tree = builder.create(MemoryStore::new(), leaves).unwrap();
+    ///
+    /// assert_eq!(tree.root().unwrap(), builder.build(&leaves).unwrap().root());
+    /// ```
+    pub fn create<S, I>(&self, store: S, leaves: I) -> Result<MerkleTree<S>>
+    where
+        S: NodeStore,
+        I: IntoIterator<Item = Hash>,
+    {
+        if store.leaf_count()? != 0 {
+            return Err(MerkleTreeError::Storage(
+                "store already holds a tree".to_owned(),
+            ));
+        }
+
+        let mut tree = self.in_store(store, 0);
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+
+        for (offset, leaf) in leaves.into_iter().enumerate() {
+            batch.push((Position::leaf(offset), leaf));
+            tree.leaf_count += 1;
+            tree.write_full_batch(&mut batch)?;
+        }
+
+        if tree.leaf_count == 0 {
+            return Err(MerkleTreeError::Empty);
+        }
+
+        tree.check_layout()?;
+
+        for level in 1..=tree.levels() {
+            // the level below must be stored before its parents are hashed
+            tree.nodes.put_batch(&batch)?;
+            batch.clear();
+
+            for index in 0..tree.level_width(level) {
+                let position = Position { level, index };
+                batch.push((position, tree.hash_children(position)?));
+                tree.write_full_batch(&mut batch)?;
+            }
+        }
+
+        tree.nodes.put_batch(&batch)?;
+        tree.nodes.set_leaf_count(tree.leaf_count)?;
+        tree.nodes.flush()?;
+
+        Ok(tree)
+    }
+
+    /// Open a tree previously built in the store, hashing with these
+    /// options.  A store records only the nodes and the number of leaves, so
+    /// these must be the options the tree was created with.
+    pub fn open<S: NodeStore>(&self, store: S) -> Result<MerkleTree<S>> {
+        let leaf_count = store.leaf_count()?;
+
+        if leaf_count == 0 {
+            return Err(MerkleTreeError::Empty);
+        }
+
+        let mut tree = self.in_store(store, leaf_count);
+        tree.check_layout()?;
+
+        Ok(tree)
+    }
+
+    fn in_store<S: NodeStore>(&self, store: S, leaf_count: usize) -> MerkleTree<S> {
+        MerkleTree {
+            nodes: store,
+            leaf_count,
+            duplicate_policy: DuplicatePolicy::default(),
+            config: self.config,
+            empty_subtrees: Vec::new(),
+            sorted_positions: None,
+            removed_duplicates: None,
+            root_history: Vec::new(),
+            root_listeners: RootListeners::default(),
+            subscribers: Subscribers::default(),
+            log: None,
+            lookup: LeafLookup::default(),
+            wal: AttachedLog::default(),
+        }
+    }
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Build a tree in an empty store from the leaves, streaming them into
+    /// the store and hashing each level from the one below, so only a batch
+    /// of nodes is held in memory at a time.  See
+    /// `MerkleTreeBuilder::create()` to build with other options.
+    ///
+    /// ```rust
+    /// use merkle_tree::storage::MemoryStore;
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::create(MemoryStore::new(), leaves).unwrap();
+    ///
+    /// assert_eq!(tree.root().unwrap(), MerkleTree::new(&leaves).unwrap().root());
+    /// ```
+    pub fn create<I>(store: S, leaves: I) -> Result<MerkleTree<S>>
+    where
+        I: IntoIterator<Item = Hash>,
+    {
+        MerkleTreeBuilder::new().create(store, leaves)
+    }
+
+    /// Open a tree previously built in the store with the default options,
+    /// see `MerkleTreeBuilder::open()`.
+    pub fn open(store: S) -> Result<MerkleTree<S>> {
+        MerkleTreeBuilder::new().open(store)
+    }
+
+    /// Log every update and push to `wal` before applying it, first replaying
+    /// any mutation a crash left in the log, see the `wal` module.
+    ///
+    /// ```rust
+    /// use merkle_tree::storage::MemoryStore;
+    /// use merkle_tree::wal::WriteAheadLog;
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let path = std::env::temp_dir().join("merkle-tree-wal-example.wal");
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let tree = MerkleTree::create(MemoryStore::new(), leaves).unwrap();
+    /// let mut tree = tree.with_wal(WriteAheadLog::open(&path).unwrap()).unwrap();
+    ///
+    /// tree.push(MerkleTree::hash(b"c")).unwrap();
+    /// assert_eq!(tree.leaf_count(), 3);
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn with_wal(mut self, mut wal: WriteAheadLog) -> Result<MerkleTree<S>> {
+        if let Some((leaf_count, branch)) = wal.pending()? {
+            self.apply(leaf_count, &branch)?;
+            wal.commit()?;
+        }
+
+        self.wal = AttachedLog(Some(wal));
+        Ok(self)
+    }
+
+    /// The store holding the nodes.
+    pub fn store(&self) -> &S {
+        &self.nodes
+    }
+
+    /// Stop using the tree, returning its store.
+    pub fn into_store(self) -> S {
+        self.nodes
+    }
+
+    /// The number of leaves.
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// The root hash of the tree.
+    pub fn root(&self) -> Result<Hash> {
+        self.node(Position {
+            level: self.levels(),
+            index: 0,
+        })
+    }
+
+    /// The leaf at a given offset.
+    pub fn leaf(&self, offset: usize) -> Result<Hash> {
+        self.check_offset(offset)?;
+        self.node(Position::leaf(offset))
+    }
+
+    /// Generate a Merkle Proof for the leaf at a given offset, reading every
+    /// node it needs with one batch.  The proof owns its hashes, since a
+    /// store returns nodes by value.
+    pub fn proof_at(&self, offset: usize) -> Result<OwnedProof> {
+        self.check_offset(offset)?;
+
+        // the sibling at each level, or the node itself where it's padded
+        let positions = (0..self.levels())
+            .map(|level| {
+                let position = Position {
+                    level,
+                    index: offset >> level,
+                };
+                let sibling = position.sibling();
+
+                if sibling.index < self.level_width(level) {
+                    sibling
+                } else {
+                    position
+                }
+            })
+            .collect::<Vec<_>>();
+        let nodes = self.nodes.get_batch(&positions)?;
+        let mut proof = OwnedProof::with_capacity(positions.len());
+
+        for (position, node) in positions.into_iter().zip(nodes) {
+            let node = node.ok_or_else(|| missing(position))?;
+            let index = offset >> position.level;
+
+            if position.index != index {
+                let direction = if position.index < index {
+                    Direction::Left
+                } else {
+                    Direction::Right
+                };
+                proof.push((direction, node));
+            } else if let Some(padding) = self.padding_sibling(position, &node) {
+                proof.push(padding);
+            }
+        }
+
+        Ok(proof)
+    }
+
+    /// Verify a Merkle Proof for a given leaf against the stored root.
+    pub fn verify<H: Borrow<Hash>>(&self, proof: &[(Direction, H)], leaf: &Hash) -> Result<bool> {
+        Ok(self.config.root_from_proof(proof, leaf) == self.root()?)
+    }
+
+    /// Update the value of an existing leaf, and recalculate its branch.
+    ///
+    /// O(log n) reads and writes
+    pub fn update(&mut self, offset: usize, leaf: Hash) -> Result<()> {
+        self.check_offset(offset)?;
+
+        let branch = self.branch(offset, leaf)?;
+        self.write(self.leaf_count, &branch)
+    }
+
+    /// Append a leaf, and recalculate its branch.  Each node it pads is the
+    /// last of its level, so is also on the branch.
+    ///
+    /// O(log n) reads and writes
+    pub fn push(&mut self, leaf: Hash) -> Result<()> {
+        let leaf_count = self.leaf_count;
+
+        // the branch is hashed as it will be with the leaf
+        self.leaf_count += 1;
+        let branch = self
+            .check_layout()
+            .and_then(|()| self.branch(leaf_count, leaf));
+        self.leaf_count = leaf_count;
+
+        self.write(leaf_count + 1, &branch?)
+    }
+
+    /// Copy the leaves into a `MerkleTree` in memory, built with the same
+    /// options.
+    pub fn to_tree(&self) -> Result<MerkleTree> {
+        let leaves = (0..self.leaf_count)
+            .map(|offset| self.node(Position::leaf(offset)))
+            .collect::<Result<Vec<Hash>>>()?;
+
+        let mut builder = MerkleTreeBuilder::new();
+        builder.config = self.config;
+        builder.build(&leaves)
+    }
+
+    /// Write the nodes of a mutation and the number of leaves after it,
+    /// logging them first if the tree has a write-ahead log.  If this fails
+    /// part way, the log still holds the mutation, to be replayed when the
+    /// tree is reopened.
+    fn write(&mut self, leaf_count: usize, branch: &[(Position, Hash)]) -> Result<()> {
+        if let Some(wal) = &mut self.wal.0 {
+            wal.begin(leaf_count, branch)?;
+        }
+
+        self.apply(leaf_count, branch)?;
+
+        if let Some(wal) = &mut self.wal.0 {
+            wal.commit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the nodes of a mutation and the number of leaves after it, with
+    /// one batch, and make them durable.
+    fn apply(&mut self, leaf_count: usize, branch: &[(Position, Hash)]) -> Result<()> {
+        self.nodes.put_batch(branch)?;
+        self.nodes.set_leaf_count(leaf_count)?;
+        self.nodes.flush()?;
+        self.leaf_count = leaf_count;
+
+        Ok(())
+    }
+
+    /// Check that the leaves fit the depth and padding of the tree, and
+    /// precompute any empty subtrees the padding needs up to the root.
+    fn check_layout(&mut self) -> Result<()> {
+        self.config.padded_count(self.leaf_count)?;
+
+        if self.empty_subtrees.len() <= self.levels() {
+            self.empty_subtrees = self.config.empty_subtrees(self.levels());
+        }
+
+        Ok(())
+    }
+
+    /// A leaf and every ancestor recalculated from it.
+    fn branch(&self, offset: usize, leaf: Hash) -> Result<Vec<(Position, Hash)>> {
+        let mut position = Position::leaf(offset);
+        let mut node = leaf;
+        let mut branch = vec![(position, node)];
+
+        while position.level < self.levels() {
+            node = self.parent(position, node)?;
+            position = position.parent();
+            branch.push((position, node));
+        }
+
+        Ok(branch)
+    }
+
+    /// Write a batch of nodes once it's full.
+    fn write_full_batch(&mut self, batch: &mut Vec<(Position, Hash)>) -> Result<()> {
+        if batch.len() == BATCH_SIZE {
+            self.nodes.put_batch(batch)?;
+            batch.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Hash the children of the node at a position.
+    fn hash_children(&self, position: Position) -> Result<Hash> {
+        let left = position.left_child();
+        self.parent(left, self.node(left)?)
+    }
+
+    /// The parent of a node, paired with its sibling, or with padding if the
+    /// node is the last of its level and has no sibling.
+    fn parent(&self, position: Position, node: Hash) -> Result<Hash> {
+        let sibling = position.sibling();
+
+        let sibling = if sibling.index < self.level_width(position.level) {
+            let direction = if sibling.index < position.index {
+                Direction::Left
+            } else {
+                Direction::Right
+            };
+            (direction, self.node(sibling)?)
+        } else {
+            match self.padding_sibling(position, &node) {
+                Some(padding) => padding,
+                None => return Ok(node),
+            }
+        };
+
+        Ok(self.config.root_from_proof(&[sibling], &node))
+    }
+
+    /// The padding the last node of a level is paired with, as the padding
+    /// strategy says, or `None` if the node is promoted.  Padding isn't
+    /// stored, so it's always the right sibling.
+    fn padding_sibling(&self, position: Position, node: &Hash) -> Option<(Direction, Hash)> {
+        self.config
+            .padding_for(&self.empty_subtrees, node, position.level)
+            .map(|padding| (Direction::Right, padding))
+    }
+
+    /// The number of levels above the leaves, as `MerkleTree::num_levels()`.
+    fn levels(&self) -> usize {
+        self.config
+            .depth
+            .unwrap_or_else(|| self.leaf_count.next_power_of_two().max(2).ilog2() as usize)
+    }
+
+    /// The number of nodes stored at a level.
+    fn level_width(&self, level: usize) -> usize {
+        self.leaf_count.div_ceil(1 << level)
+    }
+
+    fn node(&self, position: Position) -> Result<Hash> {
+        self.nodes.get(position)?.ok_or_else(|| missing(position))
+    }
+
+    fn check_offset(&self, offset: usize) -> Result<()> {
+        if offset >= self.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, self.leaf_count));
+        }
+
+        Ok(())
+    }
+}
+
+fn missing(position: Position) -> MerkleTreeError {
+    MerkleTreeError::MissingNode(position.level, position.index)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::builder::Hasher;
+    use crate::padding::{PromoteOdd, RequirePowerOfTwo, ZeroHash};
+
+    /// Check a tree in a store against a tree of the same leaves in memory.
+    pub(crate) fn assert_matches<S: NodeStore>(stored: &MerkleTree<S>, leaves: &[Hash]) {
+        assert_built_with(&MerkleTree::builder(), stored, leaves);
+    }
+
+    /// Check a tree in a store against a tree of the same leaves built in
+    /// memory with the given options.
+    fn assert_built_with<S: NodeStore>(
+        builder: &MerkleTreeBuilder,
+        stored: &MerkleTree<S>,
+        leaves: &[Hash],
+    ) {
+        let tree = builder.build(leaves).unwrap();
+        assert_eq!(stored.root().unwrap(), tree.root());
+
+        for (offset, leaf) in leaves.iter().enumerate() {
+            let proof = tree.proof_at(offset).unwrap();
+            let expected = proof.iter().map(|(d, h)| (*d, **h)).collect::<OwnedProof>();
+            assert_eq!(stored.proof_at(offset).unwrap(), expected);
+            assert!(stored.verify(&expected, leaf).unwrap());
+        }
+    }
+
+    pub(crate) fn leaves(count: u8) -> Vec<Hash> {
+        (0..count).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn matches_trees_in_memory() {
+        for count in [1, 2, 5, 8] {
+            let leaves = leaves(count);
+            let stored = MerkleTree::create(MemoryStore::new(), leaves.clone()).unwrap();
+            assert_matches(&stored, &leaves);
+            assert_eq!(stored.to_tree().unwrap(), MerkleTree::new(&leaves).unwrap());
+        }
+
+        assert!(MerkleTree::create(MemoryStore::new(), []).is_err());
+    }
+
+    #[test]
+    fn builds_in_batches() {
+        let leaves = (0..BATCH_SIZE as u32 * 2 + 3)
+            .map(|i| MerkleTree::hash(&i.to_le_bytes()))
+            .collect::<Vec<Hash>>();
+        let stored = MerkleTree::create(MemoryStore::new(), leaves.clone()).unwrap();
+
+        assert_eq!(
+            stored.root().unwrap(),
+            MerkleTree::new(&leaves).unwrap().root()
+        );
+    }
+
+    #[test]
+    fn updates_and_pushes_leaves() {
+        let mut leaves = leaves(3);
+        let mut stored = MerkleTree::create(MemoryStore::new(), leaves.clone()).unwrap();
+
+        leaves[1] = MerkleTree::hash(b"x");
+        stored.update(1, leaves[1]).unwrap();
+        assert_matches(&stored, &leaves);
+
+        for i in 0..6 {
+            leaves.push(MerkleTree::hash(&[b'p', i]));
+            stored.push(leaves[leaves.len() - 1]).unwrap();
+            assert_matches(&stored, &leaves);
+        }
+
+        assert!(stored.update(9, leaves[0]).is_err());
+        assert!(stored.proof_at(9).is_err());
+
+        // the tree can be reopened from its store
+        let stored = MerkleTree::open(stored.into_store()).unwrap();
+        assert_matches(&stored, &leaves);
+        assert!(MerkleTree::create(stored.into_store(), leaves).is_err());
+    }
+
+    #[test]
+    fn builds_with_the_options_of_the_builder() {
+        let builders = [
+            MerkleTree::builder().hasher(Hasher::KECCAK_256),
+            MerkleTree::builder().sorted_pairs(true),
+            MerkleTree::builder().padding(&ZeroHash),
+            MerkleTree::builder().padding(&PromoteOdd),
+            MerkleTree::builder().depth(4),
+            MerkleTree::builder().padding(&ZeroHash).depth(5),
+            MerkleTree::builder().padding(&PromoteOdd).depth(4),
+        ];
+
+        for builder in builders {
+            for count in [1, 2, 3, 5, 8] {
+                let mut leaves = leaves(count);
+                let mut stored = builder.create(MemoryStore::new(), leaves.clone()).unwrap();
+                assert_built_with(&builder, &stored, &leaves);
+                assert_eq!(stored.to_tree().unwrap(), builder.build(&leaves).unwrap());
+
+                leaves[0] = MerkleTree::hash(b"x");
+                stored.update(0, leaves[0]).unwrap();
+                assert_built_with(&builder, &stored, &leaves);
+
+                for i in 0..(16 - count).min(5) {
+                    leaves.push(MerkleTree::hash(&[b'p', i]));
+                    stored.push(leaves[leaves.len() - 1]).unwrap();
+                    assert_built_with(&builder, &stored, &leaves);
+                }
+
+                let stored = builder.open(stored.into_store()).unwrap();
+                assert_built_with(&builder, &stored, &leaves);
+            }
+        }
+    }
+
+    #[test]
+    fn checks_the_depth_and_padding() {
+        let builder = MerkleTree::builder().depth(2);
+        let mut stored = builder.create(MemoryStore::new(), leaves(4)).unwrap();
+        assert!(matches!(
+            stored.push(MerkleTree::hash(b"x")),
+            Err(MerkleTreeError::DepthExceeded(2, 5))
+        ));
+        assert_built_with(&builder, &stored, &leaves(4));
+        assert!(builder.create(MemoryStore::new(), leaves(5)).is_err());
+
+        let builder = MerkleTree::builder().padding(&RequirePowerOfTwo);
+        let mut stored = builder.create(MemoryStore::new(), leaves(4)).unwrap();
+        assert!(matches!(
+            stored.push(MerkleTree::hash(b"x")),
+            Err(MerkleTreeError::PaddingRequired(5))
+        ));
+        assert_eq!(stored.store().leaf_count().unwrap(), 4);
+        assert!(builder.create(MemoryStore::new(), leaves(3)).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Subscribe to the proof of a leaf and receive a fresh proof whenever a
+//! mutation invalidates the previous one.
+
+use crate::error::Result;
+use crate::{Hash, MerkleTree, OwnedProof};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// A new proof for a subscribed leaf, as of a version of the tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProofUpdate {
+    pub version: usize,
+    pub root: Hash,
+    pub leaf: Hash,
+    pub proof: OwnedProof,
+}
+
+/// The receiving end of a subscription created by `MerkleTree::subscribe()`.
+/// The subscription ends when it's dropped, or when its leaf is removed from
+/// the tree.
+#[derive(Debug)]
+pub struct ProofSubscription {
+    offset: usize,
+    receiver: Receiver<ProofUpdate>,
+}
+
+impl ProofSubscription {
+    /// The offset of the subscribed leaf.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Return the oldest update that hasn't been received yet, if any.
+    pub fn try_recv(&self) -> Option<ProofUpdate> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Skip to the newest update that hasn't been received yet, if any.
+    pub fn latest(&self) -> Option<ProofUpdate> {
+        self.receiver.try_iter().last()
+    }
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    offset: usize,
+    last: ProofUpdate,
+    sender: Sender<ProofUpdate>,
+}
+
+/// The subscribers of a tree, notified each time a new root is recorded.
+#[derive(Debug, Default)]
+pub(crate) struct Subscribers(Vec<Subscriber>);
+
+/// Subscriptions belong to the tree they were made on, so a clone of a tree
+/// starts without any.
+impl Clone for Subscribers {
+    fn clone(&self) -> Subscribers {
+        Subscribers::default()
+    }
+}
+
+impl MerkleTree {
+    /// Subscribe to the proof of the leaf at a given offset.  The current
+    /// proof is delivered immediately, then a new one each time a mutation
+    /// changes the leaf or its proof.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let mut tree = MerkleTree::new(&leaves).unwrap();
+    /// let subscription = tree.subscribe(0).unwrap();
+    ///
+    /// tree.update(2, MerkleTree::hash(b"z")).unwrap();
+    /// let update = subscription.latest().unwrap();
+    ///
+    /// assert_eq!(update.version, 1);
+    /// assert!(tree.verify(&update.proof, &leaves[0]));
+    /// ```
+    pub fn subscribe(&mut self, offset: usize) -> Result<ProofSubscription> {
+        let last = self.proof_update(offset)?;
+        let (sender, receiver) = channel();
+        sender
+            .send(last.clone())
+            .expect("receiver of a new subscription");
+
+        self.subscribers.0.push(Subscriber {
+            offset,
+            last,
+            sender,
+        });
+
+        Ok(ProofSubscription { offset, receiver })
+    }
+
+    /// Send a new proof to every subscriber whose leaf or proof changed,
+    /// dropping subscribers that hung up or whose leaf no longer exists.
+    ///
+    /// O(s log n) for s subscribers
+    pub(crate) fn notify_subscribers(&mut self) {
+        let mut subscribers = std::mem::take(&mut self.subscribers);
+
+        subscribers.0.retain_mut(|subscriber| {
+            let Ok(update) = self.proof_update(subscriber.offset) else {
+                return false;
+            };
+
+            if update.leaf == subscriber.last.leaf && update.proof == subscriber.last.proof {
+                return true;
+            }
+
+            subscriber.last = update.clone();
+            subscriber.sender.send(update).is_ok()
+        });
+
+        self.subscribers = subscribers;
+    }
+
+    fn proof_update(&self, offset: usize) -> Result<ProofUpdate> {
+        let proof = self
+            .proof_at(offset)?
+            .into_iter()
+            .map(|(direction, hash)| (direction, *hash))
+            .collect();
+
+        Ok(ProofUpdate {
+            version: self.version(),
+            root: self.root(),
+            leaf: self.leaves()[offset],
+            proof,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Hash, MerkleTree};
+
+    fn leaves() -> Vec<Hash> {
+        (0..6u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn delivers_proofs_invalidated_by_mutations() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new(&leaves[0..5]).unwrap();
+        let first = tree.subscribe(0).unwrap();
+        let last = tree.subscribe(4).unwrap();
+        assert!(tree.subscribe(5).is_err());
+
+        assert_eq!(first.try_recv().unwrap().version, 0);
+        assert_eq!(last.try_recv().unwrap().version, 0);
+
+        tree.push(leaves[5]).unwrap();
+        tree.update(1, leaves[0]).unwrap();
+
+        let update = first.latest().unwrap();
+        assert_eq!(update.version, 2);
+        assert_eq!(update.root, tree.root());
+        assert!(tree.verify(&update.proof, &leaves[0]));
+
+        let update = last.latest().unwrap();
+        assert_eq!(update.version, 2);
+        assert!(tree.verify(&update.proof, &leaves[4]));
+
+        // setting a leaf to its current value doesn't invalidate any proofs
+        tree.update(0, leaves[0]).unwrap();
+        assert!(first.try_recv().is_none());
+        assert!(last.try_recv().is_none());
+    }
+
+    #[test]
+    fn ends_subscriptions_to_removed_leaves() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new(&leaves).unwrap();
+        let subscription = tree.subscribe(5).unwrap();
+        let dropped = tree.subscribe(0).unwrap();
+        drop(dropped);
+
+        tree.pop().unwrap();
+        assert!(subscription.latest().is_some());
+        assert!(subscription.receiver.recv().is_err());
+        assert!(tree.subscribers.0.is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+//! Instrument the crate with `tracing` spans, behind the `tracing` feature.
+//!
+//! Each instrumented operation holds a `Timed` guard, which enters a span
+//! recording the operation and the number of leaves it covers, and emits an
+//! event with the elapsed time when dropped.  Without the feature the guard
+//! is empty and compiles away.
+
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+/// A span around an operation, closed when dropped.
+#[must_use]
+pub(crate) struct Timed {
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+
+    #[cfg(feature = "tracing")]
+    started: Instant,
+}
+
+impl Timed {
+    #[cfg(feature = "tracing")]
+    fn new(span: tracing::Span) -> Timed {
+        Timed {
+            span: span.entered(),
+            started: Instant::now(),
+        }
+    }
+}
+
+/// Time an operation on many leaves, e.g. construction or a batch update, at
+/// the debug level.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn operation(name: &'static str, leaf_count: usize) -> Timed {
+    #[cfg(feature = "tracing")]
+    return Timed::new(tracing::debug_span!(
+        "merkle_tree",
+        operation = name,
+        leaf_count
+    ));
+
+    #[cfg(not(feature = "tracing"))]
+    Timed {}
+}
+
+/// Time an operation on a single leaf, e.g. generating a proof, at the trace
+/// level, since there may be millions of them.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn leaf_operation(name: &'static str, leaf_count: usize) -> Timed {
+    #[cfg(feature = "tracing")]
+    return Timed::new(tracing::trace_span!(
+        "merkle_tree",
+        operation = name,
+        leaf_count
+    ));
+
+    #[cfg(not(feature = "tracing"))]
+    Timed {}
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for Timed {
+    fn drop(&mut self) {
+        // events of disabled spans would be emitted without their context
+        if !self.span.is_disabled() {
+            let elapsed_us = self.started.elapsed().as_micros() as u64;
+            tracing::debug!(elapsed_us, "done");
+        }
+    }
+}
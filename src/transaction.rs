@@ -0,0 +1,173 @@
+//! Stage many mutations and apply them atomically.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::replication::Mutation;
+use crate::{trace, DuplicatePolicy, Hash, MerkleTree};
+
+/// A guard that stages `update()` and `push()` operations against a tree.
+/// Nothing is applied until `commit()`, which recalculates every dirty node
+/// exactly once.  Calling `rollback()`, or dropping the transaction, leaves
+/// the tree in its pre-transaction state.
+#[derive(Debug)]
+pub struct Transaction<'a> {
+    tree: &'a mut MerkleTree,
+    updates: Vec<(usize, Hash)>,
+    pushes: Vec<Hash>,
+}
+
+impl MerkleTree {
+    /// Start a transaction that stages mutations until they are committed.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let mut tree = MerkleTree::new(&leaves[0..1]).unwrap();
+    ///
+    /// let mut transaction = tree.transaction();
+    /// transaction.push(leaves[1]);
+    /// transaction.push(MerkleTree::hash(b"z"));
+    /// transaction.update(2, leaves[2]).unwrap();
+    /// let root = transaction.commit().unwrap();
+    ///
+    /// assert_eq!(root, MerkleTree::new(&leaves).unwrap().root());
+    /// ```
+    pub fn transaction(&mut self) -> Transaction<'_> {
+        Transaction {
+            tree: self,
+            updates: Vec::new(),
+            pushes: Vec::new(),
+        }
+    }
+}
+
+impl Transaction<'_> {
+    /// Stage an update of the leaf at the given offset, which may be a leaf
+    /// pushed earlier in this transaction.
+    pub fn update(&mut self, offset: usize, value: Hash) -> Result<()> {
+        let leaf_count = self.leaf_count();
+
+        if offset >= leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, leaf_count));
+        }
+
+        match offset.checked_sub(self.tree.leaf_count) {
+            Some(pushed) => self.pushes[pushed] = value,
+            None => self.updates.push((offset, value)),
+        }
+
+        Ok(())
+    }
+
+    /// Stage appending a leaf to the end of the tree.
+    pub fn push(&mut self, leaf: Hash) {
+        self.pushes.push(leaf);
+    }
+
+    /// The number of leaves the tree will have once committed.
+    pub fn leaf_count(&self) -> usize {
+        self.tree.leaf_count + self.pushes.len()
+    }
+
+    /// Apply all of the staged mutations and return the new root hash.  If
+    /// the mutations violate the tree's duplicate policy, nothing is applied.
+    pub fn commit(self) -> Result<Hash> {
+        let _timed = trace::operation("commit", self.updates.len() + self.pushes.len());
+        let tree = self.tree;
+
+        if tree.duplicate_policy == DuplicatePolicy::Reject {
+            let mut leaves = [tree.leaves(), &self.pushes].concat();
+
+            for (offset, value) in self.updates.iter() {
+                leaves[*offset] = *value;
+            }
+
+            if let Some(duplicate) = MerkleTree::find_duplicate(leaves.iter()) {
+                return Err(MerkleTreeError::DuplicateLeaf(hex::encode(duplicate)));
+            }
+        }
+
+        let start = tree.leaf_count;
+        let end = start + self.pushes.len();
+        tree.make_room(end)?;
+
+        let position = tree.get_index_from_offset(start);
+        tree.nodes[position..position + self.pushes.len()].copy_from_slice(&self.pushes);
+        tree.leaf_count = end;
+
+        for (offset, value) in self.updates.iter() {
+            let position = tree.get_index_from_offset(*offset);
+            tree.nodes[position] = *value;
+        }
+
+        let dirty = self
+            .updates
+            .iter()
+            .map(|(offset, _)| *offset)
+            .chain(start..end)
+            .collect();
+        tree.recompute_offsets(dirty);
+        tree.log_mutation(|| Mutation::Commit {
+            updates: self.updates,
+            pushes: self.pushes,
+        });
+        tree.record_root();
+
+        Ok(tree.root())
+    }
+
+    /// Discard all of the staged mutations.
+    pub fn rollback(self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DuplicatePolicy, Hash, MerkleTree};
+
+    fn leaves() -> Vec<Hash> {
+        (0..12u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn commits_staged_mutations() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new(&leaves[0..5]).unwrap();
+        let mut transaction = tree.transaction();
+
+        for leaf in leaves[5..].iter() {
+            transaction.push(*leaf);
+        }
+
+        let new_leaf = MerkleTree::hash(b"z");
+        transaction.update(1, new_leaf).unwrap();
+        transaction.update(8, new_leaf).unwrap();
+        assert!(transaction.update(12, new_leaf).is_err());
+        transaction.commit().unwrap();
+
+        let mut expected = leaves.clone();
+        expected[1] = new_leaf;
+        expected[8] = new_leaf;
+        assert_eq!(tree.nodes, MerkleTree::new(&expected).unwrap().nodes);
+    }
+
+    #[test]
+    fn rolls_back_staged_mutations() {
+        let leaves = leaves();
+        let mut tree = MerkleTree::new_with_policy(&leaves[0..5], DuplicatePolicy::Reject).unwrap();
+        let nodes = tree.nodes.clone();
+
+        let mut transaction = tree.transaction();
+        transaction.push(leaves[5]);
+        transaction.update(0, leaves[6]).unwrap();
+        transaction.rollback();
+        assert_eq!(tree.nodes, nodes);
+
+        // a failed commit leaves the tree untouched
+        let mut transaction = tree.transaction();
+        transaction.push(leaves[5]);
+        transaction.update(0, leaves[5]).unwrap();
+        assert!(transaction.commit().is_err());
+        assert_eq!(tree.nodes, nodes);
+        assert_eq!(tree.leaf_count, 5);
+    }
+}
@@ -0,0 +1,130 @@
+//! Walk the structure of a tree without depending on the flattened layout.
+
+use crate::{Hash, MerkleTree};
+
+impl MerkleTree {
+    /// Iterate over the levels of the tree from the root down to the leaves,
+    /// each as a slice of its nodes excluding padding.  Reverse the iterator
+    /// to walk from the leaves up.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let widths = tree.levels().map(|level| level.len()).collect::<Vec<_>>();
+    ///
+    /// assert_eq!(widths, [1, 2, 3]);
+    /// assert_eq!(tree.levels().next_back().unwrap(), leaves);
+    /// ```
+    pub fn levels(&self) -> impl DoubleEndedIterator<Item = &[Hash]> + '_ {
+        (0..=self.num_levels()).map(|level| {
+            let start = Self::get_level_start(level);
+            &self.nodes[start..start + self.level_width(level)]
+        })
+    }
+
+    /// Iterate over every node of the tree excluding padding as
+    /// `(level, index_in_level, hash)`, level by level from the root.  The
+    /// root is level 0 and the leaves are the last level.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    /// let nodes = tree.iter_nodes().collect::<Vec<_>>();
+    ///
+    /// assert_eq!(nodes, [(0, 0, &tree.root()), (1, 0, &leaves[0]), (1, 1, &leaves[1])]);
+    /// ```
+    pub fn iter_nodes(&self) -> impl Iterator<Item = (usize, usize, &Hash)> + '_ {
+        self.levels().enumerate().flat_map(|(level, nodes)| {
+            nodes
+                .iter()
+                .enumerate()
+                .map(move |(index, hash)| (level, index, hash))
+        })
+    }
+
+    /// Return the node at an index within a level, where the root is level 0
+    /// and the leaves are the last level.  Padding isn't addressable, so
+    /// indices past the nodes derived from real leaves return `None`.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let tree = MerkleTree::new(&leaves).unwrap();
+    ///
+    /// assert_eq!(tree.node_at(0, 0), Some(tree.root()));
+    /// assert_eq!(tree.node_at(1, 0), Some(MerkleTree::concat(&leaves[0], &leaves[1])));
+    /// assert_eq!(tree.node_at(2, 3), None);
+    /// ```
+    pub fn node_at(&self, level: usize, index: usize) -> Option<Hash> {
+        self.levels().nth(level)?.get(index).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Hash, MerkleTree};
+
+    #[test]
+    fn iterates_over_levels() {
+        let leaves = (0..5u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let tree = MerkleTree::new(&leaves).unwrap();
+        let levels = tree.levels().rev().collect::<Vec<_>>();
+
+        assert_eq!(levels.len(), 4);
+        assert_eq!(levels[0], leaves);
+        assert_eq!(levels[3], [tree.root()]);
+
+        for (level, parents) in levels.iter().zip(&levels[1..]) {
+            for (offset, parent) in parents.iter().enumerate() {
+                let left = &level[offset * 2];
+                let right = level.get(offset * 2 + 1).unwrap_or(left);
+                assert_eq!(*parent, MerkleTree::concat(left, right));
+            }
+        }
+    }
+
+    #[test]
+    fn iterates_over_nodes_with_coordinates() {
+        let leaves = (0..5u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let tree = MerkleTree::new(&leaves).unwrap();
+        let nodes = tree.iter_nodes().collect::<Vec<_>>();
+
+        assert_eq!(nodes.len(), 1 + 2 + 3 + 5);
+        assert_eq!(nodes[0], (0, 0, &tree.root()));
+        assert!(nodes[6..]
+            .iter()
+            .zip(leaves.iter().enumerate())
+            .all(|(node, (index, leaf))| *node == (3, index, leaf)));
+
+        for (level, index, hash) in nodes {
+            let node = MerkleTree::get_level_start(level) + index;
+            assert_eq!(tree.nodes()[node], *hash);
+        }
+    }
+
+    #[test]
+    fn gets_nodes_by_level_and_index() {
+        let leaves = (0..5u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let tree = MerkleTree::new(&leaves).unwrap();
+
+        for (level, index, hash) in tree.iter_nodes() {
+            assert_eq!(tree.node_at(level, index), Some(*hash));
+        }
+
+        assert_eq!(tree.node_at(3, 4), Some(leaves[4]));
+        assert_eq!(tree.node_at(3, 5), None);
+        assert_eq!(tree.node_at(2, 3), None);
+        assert_eq!(tree.node_at(4, 0), None);
+    }
+}
@@ -0,0 +1,288 @@
+//! A versioned tree where every committed version is an immutable snapshot
+//! that shares unchanged nodes with the other versions.
+//!
+//! Each version is a `PersistentMerkleTree`, so a mutation only copies the
+//! branch from the changed leaf up to the root (path copying).  Roots are
+//! identical to those of a `MerkleTree` holding the same leaves.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::persistent::PersistentMerkleTree;
+use crate::{Direction, Hash, MerkleTree, OwnedProof};
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Weak};
+
+/// A tree whose committed versions remain provable after later mutations.
+///
+/// ```rust
+/// use merkle_tree::{versioned::VersionedMerkleTree, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+/// let mut tree = VersionedMerkleTree::new(&leaves).unwrap();
+///
+/// tree.update(1, MerkleTree::hash(b"c")).unwrap();
+/// let version = tree.commit();
+///
+/// // the leaf was included as of version 0, but not the latest version
+/// let proof = tree.proof(0, &leaves[1]).unwrap();
+/// assert!(tree.verify(0, &proof, &leaves[1]));
+/// assert!(!tree.verify(version, &proof, &leaves[1]));
+/// ```
+#[derive(Debug)]
+pub struct VersionedMerkleTree {
+    working: PersistentMerkleTree,
+    versions: BTreeMap<usize, PersistentMerkleTree>,
+    latest_version: usize,
+    pins: Vec<(usize, Weak<()>)>,
+}
+
+/// A handle that keeps a committed version from being dropped by
+/// `VersionedMerkleTree::retain_last()`.  The version is unpinned once every
+/// handle to it is dropped.
+#[derive(Debug, Clone)]
+pub struct VersionPin {
+    version: usize,
+    _token: Arc<()>,
+}
+
+impl VersionPin {
+    /// The pinned version number.
+    pub fn version(&self) -> usize {
+        self.version
+    }
+}
+
+impl VersionedMerkleTree {
+    /// Create a new versioned tree.  The leaves are committed as version 0.
+    pub fn new(leaves: &[Hash]) -> Result<VersionedMerkleTree> {
+        let working = PersistentMerkleTree::new(leaves)?;
+
+        Ok(VersionedMerkleTree {
+            versions: BTreeMap::from([(0, working.clone())]),
+            working,
+            latest_version: 0,
+            pins: Vec::new(),
+        })
+    }
+
+    /// Stage an update of the leaf at the given offset.  Only the leaf's
+    /// branch is copied; every other node is shared with earlier versions.
+    pub fn update(&mut self, offset: usize, value: Hash) -> Result<()> {
+        self.working.update(offset, value)
+    }
+
+    /// Stage appending a leaf to the end of the tree.
+    pub fn push(&mut self, leaf: Hash) {
+        self.working.push(leaf);
+    }
+
+    /// Commit the staged mutations as a new immutable version and return its
+    /// version number.
+    pub fn commit(&mut self) -> usize {
+        self.latest_version += 1;
+        self.versions
+            .insert(self.latest_version, self.working.clone());
+        self.latest_version
+    }
+
+    /// The most recently committed version number.
+    pub fn latest_version(&self) -> usize {
+        self.latest_version
+    }
+
+    /// The committed version numbers that haven't been dropped, oldest first.
+    pub fn versions(&self) -> impl Iterator<Item = usize> + '_ {
+        self.versions.keys().copied()
+    }
+
+    /// Pin a committed version so that `retain_last()` keeps it for as long
+    /// as the returned handle (or a clone of it) is alive.
+    pub fn pin(&mut self, version: usize) -> Result<VersionPin> {
+        self.snapshot(version)?;
+
+        let token = Arc::new(());
+        self.pins.push((version, Arc::downgrade(&token)));
+
+        Ok(VersionPin {
+            version,
+            _token: token,
+        })
+    }
+
+    /// Drop every version except the last `count` versions (at least the
+    /// latest version is always kept) and any pinned versions, returning the
+    /// number of versions dropped.  Nodes that were only referenced by the
+    /// dropped versions are freed; nodes shared with a kept version are not.
+    ///
+    /// ```rust
+    /// use merkle_tree::{versioned::VersionedMerkleTree, MerkleTree};
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b")];
+    /// let mut tree = VersionedMerkleTree::new(&leaves).unwrap();
+    /// let pin = tree.pin(0).unwrap();
+    ///
+    /// for leaf in leaves {
+    ///     tree.push(leaf);
+    ///     tree.commit();
+    /// }
+    ///
+    /// assert_eq!(tree.retain_last(1), 1);
+    /// assert_eq!(tree.versions().collect::<Vec<_>>(), [0, 2]);
+    ///
+    /// drop(pin);
+    /// assert_eq!(tree.retain_last(1), 1);
+    /// assert!(tree.root_at(0).is_err());
+    /// ```
+    pub fn retain_last(&mut self, count: usize) -> usize {
+        self.pins.retain(|(_, token)| token.strong_count() > 0);
+
+        let oldest = (self.latest_version + 1).saturating_sub(count.max(1));
+        let pins = &self.pins;
+        let before = self.versions.len();
+
+        self.versions.retain(|version, _| {
+            *version >= oldest || pins.iter().any(|(pinned, _)| pinned == version)
+        });
+
+        before - self.versions.len()
+    }
+
+    /// Return the hash root of a committed version.
+    pub fn root_at(&self, version: usize) -> Result<Hash> {
+        Ok(self.snapshot(version)?.root())
+    }
+
+    /// Return the number of leaves in a committed version.
+    pub fn leaf_count_at(&self, version: usize) -> Result<usize> {
+        Ok(self.snapshot(version)?.leaf_count())
+    }
+
+    /// Generate a Merkle Proof for the leaf at a given offset as of a
+    /// committed version.
+    pub fn proof_at(&self, version: usize, offset: usize) -> Result<OwnedProof> {
+        self.snapshot(version)?.proof_at(offset)
+    }
+
+    /// Generate a Merkle Proof for the first occurrence of a leaf as of a
+    /// committed version.
+    pub fn proof(&self, version: usize, leaf: &Hash) -> Result<OwnedProof> {
+        self.snapshot(version)?.proof(leaf)
+    }
+
+    /// Verify a Merkle Proof for a given leaf against the root of a committed
+    /// version.  Unknown versions never verify.
+    pub fn verify<H: Borrow<Hash>>(
+        &self,
+        version: usize,
+        proof: &[(Direction, H)],
+        leaf: &Hash,
+    ) -> bool {
+        self.root_at(version)
+            .is_ok_and(|root| MerkleTree::verify_with_root(&root, proof, leaf))
+    }
+
+    fn snapshot(&self, version: usize) -> Result<&PersistentMerkleTree> {
+        self.versions
+            .get(&version)
+            .ok_or(MerkleTreeError::UnknownVersion(version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistent::Node;
+    use std::sync::Arc;
+
+    fn leaves() -> Vec<Hash> {
+        (0..11u8).map(|i| MerkleTree::hash(&[i])).collect()
+    }
+
+    #[test]
+    fn matches_the_roots_and_proofs_of_a_merkle_tree() {
+        let leaves = leaves();
+        let mut tree = VersionedMerkleTree::new(&leaves[0..1]).unwrap();
+
+        for count in 2..=leaves.len() {
+            tree.push(leaves[count - 1]);
+            let version = tree.commit();
+            let expected = MerkleTree::new(&leaves[0..count]).unwrap();
+
+            assert_eq!(tree.root_at(version).unwrap(), expected.root());
+
+            for (offset, leaf) in leaves[0..count].iter().enumerate() {
+                let proof = tree.proof_at(version, offset).unwrap();
+                assert!(expected.verify(&proof, leaf));
+            }
+        }
+    }
+
+    #[test]
+    fn proves_inclusion_as_of_earlier_versions() {
+        let leaves = leaves();
+        let mut tree = VersionedMerkleTree::new(&leaves).unwrap();
+        let new_leaf = MerkleTree::hash(b"z");
+
+        tree.update(4, new_leaf).unwrap();
+        assert!(tree.proof(1, &new_leaf).is_err());
+        let version = tree.commit();
+
+        let old_proof = tree.proof(0, &leaves[4]).unwrap();
+        let new_proof = tree.proof(version, &new_leaf).unwrap();
+        assert!(tree.verify(0, &old_proof, &leaves[4]));
+        assert!(tree.verify(version, &new_proof, &new_leaf));
+        assert!(!tree.verify(version, &old_proof, &leaves[4]));
+        assert!(!tree.verify(2, &old_proof, &leaves[4]));
+        assert!(tree.proof(0, &new_leaf).is_err());
+    }
+
+    #[test]
+    fn shares_unchanged_nodes_between_versions() {
+        let leaves = leaves();
+        let mut tree = VersionedMerkleTree::new(&leaves[0..8]).unwrap();
+        tree.update(7, MerkleTree::hash(b"z")).unwrap();
+        tree.commit();
+
+        let (Node::Branch { left: old, .. }, Node::Branch { left: new, .. }) = (
+            tree.versions[&0].root.as_ref(),
+            tree.versions[&1].root.as_ref(),
+        ) else {
+            panic!("roots are branches");
+        };
+
+        assert!(Arc::ptr_eq(old, new));
+    }
+
+    #[test]
+    fn drops_unpinned_versions_and_their_nodes() {
+        let leaves = leaves();
+        let mut tree = VersionedMerkleTree::new(&leaves[0..8]).unwrap();
+        let pin = tree.pin(0).unwrap();
+        assert!(tree.pin(1).is_err());
+
+        for leaf in leaves[8..].iter() {
+            tree.update(7, *leaf).unwrap();
+            tree.commit();
+        }
+
+        // the old copies of leaf 7 are only referenced by their own versions
+        let Node::Branch {
+            right: Some(right), ..
+        } = tree.versions[&1].root.as_ref()
+        else {
+            panic!("root is a branch with a right subtree");
+        };
+        let dropped = Arc::downgrade(right);
+
+        assert_eq!(tree.retain_last(2), 1);
+        assert_eq!(tree.versions().collect::<Vec<_>>(), [0, 2, 3]);
+        assert!(dropped.upgrade().is_none());
+        assert!(tree.root_at(1).is_err());
+
+        drop(pin);
+        assert_eq!(tree.retain_last(0), 2);
+        assert_eq!(tree.versions().collect::<Vec<_>>(), [3]);
+        assert_eq!(tree.commit(), 4);
+        assert_eq!(tree.root_at(4).unwrap(), tree.root_at(3).unwrap());
+    }
+}
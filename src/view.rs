@@ -0,0 +1,370 @@
+//! Read-only views of tree files, behind the `mmap` feature.
+//!
+//! A `MerkleTreeView` memory-maps a file written by `MerkleTree::save()` and
+//! serves the root, leaves and proofs straight from the mapping, so opening a
+//! tree of any size is instant and its nodes are never copied onto the heap;
+//! the operating system pages in only the nodes a proof touches.
+//!
+//! Trees are also built straight from a memory-mapped file of leaves with
+//! `MerkleTree::from_leaf_file()`, which hashes the mapped leaves without
+//! reading them onto the heap first.
+
+use crate::builder::{Config, MerkleTreeBuilder};
+use crate::bytes::{decode_layout, HEADER_LEN};
+use crate::error::{MerkleTreeError, Result};
+use crate::file::{split_file, verify_checksum, COMPRESSED, FILE_HEADER_LEN};
+use crate::{Direction, Hash, MerkleTree, Proof};
+use memmap2::Mmap;
+use std::borrow::Borrow;
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+
+/// A tree served from a memory-mapped tree file.
+///
+/// ```rust
+/// use merkle_tree::{view::MerkleTreeView, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+/// let tree = MerkleTree::new(&leaves).unwrap();
+///
+/// let path = std::env::temp_dir().join("merkle-tree-view-example.tree");
+/// tree.save_to_path(&path).unwrap();
+///
+/// let view = MerkleTreeView::open(&path).unwrap();
+/// let proof = view.proof_at(2).unwrap();
+/// assert_eq!(view.root(), tree.root());
+/// assert!(view.verify(&proof, &leaves[2]));
+/// # drop(view);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct MerkleTreeView {
+    map: Mmap,
+    nodes: Range<usize>,
+    leaf_count: usize,
+    config: Config,
+    empty_subtrees: Vec<Hash>,
+}
+
+impl MerkleTreeView {
+    /// Map a tree file written with the default options.  The header and
+    /// layout are checked, but not the checksum, which would read the whole
+    /// file, see `verify_checksum()`.  Compressed files can't be mapped and
+    /// fail with `UnsupportedFormatFlags`.
+    ///
+    /// The file must not be modified while it is mapped.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MerkleTreeView> {
+        MerkleTreeView::map(File::open(path)?, Config::default())
+    }
+
+    fn map(file: File, config: Config) -> Result<MerkleTreeView> {
+        // SAFETY: the mapping is read-only, and every node read from it is
+        // checked against the layout below, but like any memory map it is
+        // undefined behaviour for another process to truncate or modify the
+        // file while it's mapped, which callers are told not to do
+        let map = unsafe { Mmap::map(&file)? };
+
+        let (flags, body) = split_file(&map)?;
+
+        if flags & COMPRESSED != 0 {
+            return Err(MerkleTreeError::UnsupportedFormatFlags(flags));
+        }
+
+        let (nodes, leaf_count, config) = decode_layout(body, config)?;
+        let start = FILE_HEADER_LEN + HEADER_LEN;
+        let empty_subtrees = config.empty_subtrees(MerkleTree::num_levels_from_leaves(nodes));
+        let nodes = start..start + size_of_val(nodes);
+
+        Ok(MerkleTreeView {
+            map,
+            nodes,
+            leaf_count,
+            config,
+            empty_subtrees,
+        })
+    }
+
+    /// Check the file's checksum, reading every byte of it.
+    pub fn verify_checksum(&self) -> Result<()> {
+        verify_checksum(&self.map)
+    }
+
+    /// The nodes of the tree, in the layout of `MerkleTree`.
+    fn nodes(&self) -> &[Hash] {
+        self.map[self.nodes.clone()].as_chunks().0
+    }
+
+    /// The root hash of the tree.
+    pub fn root(&self) -> Hash {
+        self.nodes()[0]
+    }
+
+    /// The number of leaves in the tree.
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// The leaves of the tree, excluding any padding.
+    pub fn leaves(&self) -> &[Hash] {
+        let nodes = self.nodes();
+        let start = nodes.len() / 2;
+        &nodes[start..start + self.leaf_count]
+    }
+
+    /// Generate a Merkle Proof for the leaf at a given offset, borrowing the
+    /// sibling hashes from the mapping.
+    pub fn proof_at(&self, offset: usize) -> Result<Proof<'_>> {
+        if offset >= self.leaf_count {
+            return Err(MerkleTreeError::OffsetOutOfBounds(offset, self.leaf_count));
+        }
+
+        let nodes = self.nodes();
+        let siblings = MerkleTree::path_siblings(
+            nodes,
+            self.leaf_count,
+            &self.config,
+            &self.empty_subtrees,
+            nodes.len() / 2 + offset,
+        );
+
+        Ok(siblings
+            .map(|(direction, sibling)| (direction, &nodes[sibling]))
+            .collect())
+    }
+
+    /// Generate a Merkle Proof for the first occurrence of a given leaf.
+    /// This searches the leaves, so prefer `proof_at()` if the offset is
+    /// known.
+    pub fn proof(&self, leaf: &Hash) -> Result<Proof<'_>> {
+        let offset = self
+            .leaves()
+            .iter()
+            .position(|candidate| candidate == leaf)
+            .ok_or_else(|| MerkleTreeError::CannotFindLeaf(hex::encode(leaf)))?;
+
+        self.proof_at(offset)
+    }
+
+    /// Verify a Merkle Proof for a given leaf against the root of the tree.
+    pub fn verify<H: Borrow<Hash>>(&self, proof: &[(Direction, H)], leaf: &Hash) -> bool {
+        self.config.root_from_proof(proof, leaf) == self.root()
+    }
+
+    /// Copy the tree into a `MerkleTree`.
+    pub fn to_tree(&self) -> MerkleTree {
+        MerkleTree::from_parts(self.nodes().to_vec(), self.leaf_count, Default::default())
+            .with_config(self.config)
+    }
+}
+
+impl MerkleTree {
+    /// Build a tree with the default options from a file of leaves, each 32
+    /// bytes, see `MerkleTreeBuilder::build_from_leaf_file()`.
+    pub fn from_leaf_file<P: AsRef<Path>>(path: P) -> Result<MerkleTree> {
+        MerkleTree::builder().build_from_leaf_file(path)
+    }
+}
+
+impl MerkleTreeBuilder {
+    /// Map a tree file written by `MerkleTree::save()`, hashing with these
+    /// options, see `MerkleTreeView::open()`.
+    pub fn open_view<P: AsRef<Path>>(&self, path: P) -> Result<MerkleTreeView> {
+        MerkleTreeView::map(File::open(path)?, self.config)
+    }
+
+    /// Build a tree from a file of leaves, each 32 bytes, by mapping it and
+    /// building over the mapped leaves, so they're copied once, straight into
+    /// the tree, rather than read into a buffer first.  This keeps the peak
+    /// memory of huge inputs to the tree itself.  Leaves already cast from a
+    /// mapping of some other layout (e.g. with `zero_copy::cast_nodes()`) are
+    /// built without copying the same way, by passing them to `build()`.
+    ///
+    /// The file must not be modified while the tree is built.
+    ///
+    /// ```rust
+    /// use merkle_tree::MerkleTree;
+    ///
+    /// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+    /// let path = std::env::temp_dir().join("merkle-tree-leaf-file-example.leaves");
+    /// std::fs::write(&path, leaves.concat()).unwrap();
+    ///
+    /// let tree = MerkleTree::builder().sorted_pairs(true).build_from_leaf_file(&path).unwrap();
+    /// assert_eq!(tree.leaves(), leaves);
+    /// # std::fs::remove_file(&path).unwrap();
+    /// ```
+    pub fn build_from_leaf_file<P: AsRef<Path>>(&self, path: P) -> Result<MerkleTree> {
+        let file = File::open(path)?;
+
+        if file.metadata()?.len() == 0 {
+            return Err(MerkleTreeError::Empty);
+        }
+
+        // SAFETY: the mapping is read-only and only lives for the build, but
+        // like any memory map it is undefined behaviour for another process to
+        // truncate or modify the file while it's mapped, which callers are
+        // told not to do
+        let map = unsafe { Mmap::map(&file)? };
+        let (leaves, rest) = map.as_chunks::<{ size_of::<Hash>() }>();
+
+        if !rest.is_empty() {
+            return Err(MerkleTreeError::InvalidEncoding(format!(
+                "{} bytes aren't a whole number of leaves",
+                map.len()
+            )));
+        }
+
+        self.build(leaves)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::padding::{PaddingStrategy, PromoteOdd, ZeroHash};
+
+    /// Promotes some nodes and pads others, so proofs must ask the strategy
+    /// about each node rather than about the strategy as a whole.
+    #[derive(Debug)]
+    struct PromoteEven;
+
+    impl PaddingStrategy for PromoteEven {
+        fn id(&self) -> u32 {
+            256
+        }
+
+        fn sibling(&self, last: &Hash, _empty_subtree: &Hash) -> Option<Hash> {
+            (last[0] % 2 == 1).then_some(*last)
+        }
+    }
+
+    fn saved(tree: &MerkleTree, name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "merkle-tree-view-{name}-{}.tree",
+            std::process::id()
+        ));
+        tree.save_to_path(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn serves_proofs_from_the_file() {
+        let leaves = (0..7u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+
+        for (name, builder) in [
+            ("sorted", MerkleTree::builder().sorted_pairs(true)),
+            ("promoted", MerkleTree::builder().padding(&PromoteOdd)),
+            ("zero", MerkleTree::builder().padding(&ZeroHash)),
+        ] {
+            let tree = builder.build(&leaves).unwrap();
+            let path = saved(&tree, name);
+            let view = builder.open_view(&path).unwrap();
+
+            assert!(view.verify_checksum().is_ok());
+            assert_eq!(view.root(), tree.root());
+            assert_eq!(view.leaf_count(), leaves.len());
+            assert_eq!(view.leaves(), leaves);
+
+            for (offset, leaf) in leaves.iter().enumerate() {
+                let proof = view.proof_at(offset).unwrap();
+                assert_eq!(proof, tree.proof_at(offset).unwrap());
+                assert!(view.verify(&proof, leaf));
+                assert!(!view.verify(&proof, &MerkleTree::hash(b"x")));
+            }
+            assert_eq!(view.proof(&leaves[3]).unwrap(), tree.proof_at(3).unwrap());
+            assert!(view.proof_at(leaves.len()).is_err());
+            assert!(view.proof(&MerkleTree::hash(b"x")).is_err());
+            assert_eq!(view.to_tree(), tree);
+
+            drop(view);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn asks_the_padding_strategy_about_each_node() {
+        let leaves = (0..20u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let builder = MerkleTree::builder().padding(&PromoteEven);
+
+        for count in 1..=leaves.len() {
+            let tree = builder.build(&leaves[..count]).unwrap();
+            let path = saved(&tree, &format!("custom-{count}"));
+            let view = builder.open_view(&path).unwrap();
+
+            for offset in 0..count {
+                assert_eq!(
+                    view.proof_at(offset).unwrap(),
+                    tree.proof_at(offset).unwrap()
+                );
+            }
+
+            drop(view);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_files_it_cant_map() {
+        let tree = MerkleTree::new(&[MerkleTree::hash(b"a"), MerkleTree::hash(b"b")]).unwrap();
+        let path = saved(&tree, "corrupted");
+
+        // the checksum is only checked on demand
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let view = MerkleTreeView::open(&path).unwrap();
+        assert!(matches!(
+            view.verify_checksum(),
+            Err(MerkleTreeError::ChecksumMismatch)
+        ));
+        drop(view);
+
+        bytes[12] = 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            MerkleTreeView::open(&path),
+            Err(MerkleTreeError::UnsupportedFormatFlags(1))
+        ));
+
+        std::fs::write(&path, &bytes[..40]).unwrap();
+        assert!(MerkleTreeView::open(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn builds_from_a_leaf_file() {
+        let leaves = (0..13u8)
+            .map(|i| MerkleTree::hash(&[i]))
+            .collect::<Vec<Hash>>();
+        let path = std::env::temp_dir().join(format!(
+            "merkle-tree-view-leaves-{}.leaves",
+            std::process::id()
+        ));
+
+        std::fs::write(&path, leaves.concat()).unwrap();
+        let tree = MerkleTree::from_leaf_file(&path).unwrap();
+        assert_eq!(tree, MerkleTree::new(&leaves).unwrap());
+
+        let builder = MerkleTree::builder().padding(&PromoteOdd);
+        let tree = builder.build_from_leaf_file(&path).unwrap();
+        assert_eq!(tree.nodes, builder.build(&leaves).unwrap().nodes);
+
+        std::fs::write(&path, &leaves.concat()[..100]).unwrap();
+        assert!(MerkleTree::from_leaf_file(&path).is_err());
+
+        std::fs::write(&path, []).unwrap();
+        assert!(matches!(
+            MerkleTree::from_leaf_file(&path),
+            Err(MerkleTreeError::Empty)
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
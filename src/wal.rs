@@ -0,0 +1,259 @@
+//! A write-ahead log making the mutations of a tree in a `storage::NodeStore`
+//! crash-safe.
+//!
+//! An update or push rewrites a leaf and every node of its branch, and a
+//! crash part way through would leave the stored nodes disagreeing with the
+//! stored root.  With a log, every node a mutation will write and the
+//! resulting number of leaves are first written to the log as a single
+//! record and synced to disk, then applied to the store, and only once the
+//! store has been flushed is the log truncated.  On restart,
+//! `MerkleTree::with_wal()` replays a complete record left in the log, which
+//! is harmless if it was already applied, and discards an incomplete one,
+//! which was never applied, so the store always holds the tree before or
+//! after each mutation.
+//!
+//! A record is the number of leaves and the number of nodes, then the level,
+//! index and hash of each node, followed by a SHA3-256 checksum of every
+//! preceding byte, with all integers little-endian `u64`s.
+
+use crate::error::Result;
+use crate::storage::Position;
+use crate::Hash;
+use sha3::{Digest, Sha3_256};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// The length of a node in a record: its level, index and hash.
+const NODE_LEN: usize = 48;
+
+/// A mutation read back from the log: the number of leaves after it and the
+/// nodes it writes.
+pub(crate) type Record = (usize, Vec<(Position, Hash)>);
+
+/// A log file holding at most one mutation that hasn't been fully applied.
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    file: File,
+}
+
+/// The log a tree was given with `MerkleTree::with_wal()`, if any.
+#[derive(Debug, Default)]
+pub(crate) struct AttachedLog(pub(crate) Option<WriteAheadLog>);
+
+/// A log belongs to the tree it was given to, so a clone of a tree starts
+/// without one.
+impl Clone for AttachedLog {
+    fn clone(&self) -> AttachedLog {
+        AttachedLog::default()
+    }
+}
+
+impl WriteAheadLog {
+    /// Open (or create) the log at `path`.  Keep it next to the store, and
+    /// never share it between trees.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<WriteAheadLog> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        Ok(WriteAheadLog { file })
+    }
+
+    /// Whether the log holds no mutation, i.e. every mutation logged so far
+    /// has been fully applied.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.file.metadata()?.len() == 0)
+    }
+
+    /// Durably record a mutation before applying it.
+    pub(crate) fn begin(&mut self, leaf_count: usize, nodes: &[(Position, Hash)]) -> Result<()> {
+        let mut record = Vec::with_capacity(16 + nodes.len() * NODE_LEN + 32);
+        record.extend_from_slice(&(leaf_count as u64).to_le_bytes());
+        record.extend_from_slice(&(nodes.len() as u64).to_le_bytes());
+
+        for (position, node) in nodes {
+            record.extend_from_slice(&(position.level as u64).to_le_bytes());
+            record.extend_from_slice(&(position.index as u64).to_le_bytes());
+            record.extend_from_slice(node);
+        }
+
+        let checksum: Hash = Sha3_256::digest(&record).into();
+        record.extend_from_slice(&checksum);
+
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&record)?;
+        self.file.sync_data()?;
+
+        Ok(())
+    }
+
+    /// Mark the logged mutation as fully applied.
+    pub(crate) fn commit(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_data()?;
+
+        Ok(())
+    }
+
+    /// The logged mutation, if it was recorded completely.  A record torn by
+    /// a crash was never applied, so is discarded.
+    pub(crate) fn pending(&mut self) -> Result<Option<Record>> {
+        let mut record = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut record)?;
+
+        if record.is_empty() {
+            return Ok(None);
+        }
+
+        let decoded = decode(&record);
+
+        if decoded.is_none() {
+            self.commit()?;
+        }
+
+        Ok(decoded)
+    }
+}
+
+/// Decode a record, or `None` if it's incomplete.
+fn decode(record: &[u8]) -> Option<Record> {
+    let (contents, checksum) = record.split_last_chunk::<32>()?;
+
+    if Sha3_256::digest(contents).as_slice() != checksum {
+        return None;
+    }
+
+    let (header, body) = contents.split_first_chunk::<16>()?;
+    let u64_at = |bytes: &[u8], offset: usize| {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize
+    };
+
+    let leaf_count = u64_at(header, 0);
+    let (nodes, rest) = body.as_chunks::<NODE_LEN>();
+
+    if nodes.len() != u64_at(header, 8) || !rest.is_empty() {
+        return None;
+    }
+
+    let nodes = nodes
+        .iter()
+        .map(|node| {
+            let position = Position {
+                level: u64_at(node, 0),
+                index: u64_at(node, 8),
+            };
+            (position, node[16..].try_into().unwrap())
+        })
+        .collect();
+
+    Some((leaf_count, nodes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MerkleTreeError;
+    use crate::storage::tests::{assert_matches, leaves};
+    use crate::storage::{MemoryStore, NodeStore};
+    use crate::MerkleTree;
+    use std::path::PathBuf;
+
+    /// A store that fails part way through its next batch, as a crash would.
+    #[derive(Debug, Default)]
+    struct CrashingStore {
+        store: MemoryStore,
+        crash: bool,
+    }
+
+    impl NodeStore for CrashingStore {
+        fn get(&self, position: Position) -> Result<Option<Hash>> {
+            self.store.get(position)
+        }
+
+        fn put(&mut self, position: Position, node: Hash) -> Result<()> {
+            self.store.put(position, node)
+        }
+
+        fn put_batch(&mut self, nodes: &[(Position, Hash)]) -> Result<()> {
+            if self.crash {
+                self.store.put_batch(&nodes[..nodes.len() / 2])?;
+                return Err(MerkleTreeError::Storage("crashed".to_owned()));
+            }
+
+            self.store.put_batch(nodes)
+        }
+
+        fn leaf_count(&self) -> Result<usize> {
+            self.store.leaf_count()
+        }
+
+        fn set_leaf_count(&mut self, leaf_count: usize) -> Result<()> {
+            self.store.set_leaf_count(leaf_count)
+        }
+    }
+
+    fn log_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("merkle-tree-{name}-{}.wal", std::process::id()))
+    }
+
+    #[test]
+    fn replays_interrupted_mutations() {
+        let path = log_path("replay");
+        let mut leaves = leaves(5);
+
+        let tree = MerkleTree::create(CrashingStore::default(), leaves.clone()).unwrap();
+        let mut tree = tree.with_wal(WriteAheadLog::open(&path).unwrap()).unwrap();
+
+        leaves[1] = MerkleTree::hash(b"x");
+        tree.update(1, leaves[1]).unwrap();
+        assert_matches(&tree, &leaves);
+        assert!(WriteAheadLog::open(&path).unwrap().is_empty().unwrap());
+
+        // the push is logged, then only half applied
+        leaves.push(MerkleTree::hash(b"y"));
+        let mut store = tree.into_store();
+        store.crash = true;
+        let mut tree = MerkleTree::open(store).unwrap();
+        tree = tree.with_wal(WriteAheadLog::open(&path).unwrap()).unwrap();
+        assert!(tree.push(leaves[5]).is_err());
+        assert!(!WriteAheadLog::open(&path).unwrap().is_empty().unwrap());
+
+        let mut store = tree.into_store();
+        store.crash = false;
+        let tree = MerkleTree::open(store).unwrap();
+        let tree = tree.with_wal(WriteAheadLog::open(&path).unwrap()).unwrap();
+
+        assert_eq!(tree.leaf_count(), 6);
+        assert_matches(&tree, &leaves);
+        assert!(WriteAheadLog::open(&path).unwrap().is_empty().unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn discards_torn_records() {
+        let path = log_path("torn");
+        let leaves = leaves(3);
+
+        let mut log = WriteAheadLog::open(&path).unwrap();
+        log.begin(4, &[(Position::leaf(3), MerkleTree::hash(b"x"))])
+            .unwrap();
+        let length = log.file.metadata().unwrap().len();
+        log.file.set_len(length - 1).unwrap();
+
+        let tree = MerkleTree::create(MemoryStore::new(), leaves.clone()).unwrap();
+        let tree = tree.with_wal(log).unwrap();
+
+        assert_eq!(tree.leaf_count(), 3);
+        assert_matches(&tree, &leaves);
+        assert!(WriteAheadLog::open(&path).unwrap().is_empty().unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+//! Zero-copy access to node arrays, so embedders can cast a buffer such as a
+//! memory map of `MerkleTree::as_bytes()` to nodes without parsing each one.
+//!
+//! A `Hash` is a `[u8; 32]`, which has no padding and an alignment of 1, so
+//! it implements `bytemuck::Pod` and zerocopy's `FromBytes`, `IntoBytes`,
+//! `Immutable` and `KnownLayout`, and slices of nodes can be cast to and from
+//! bytes of any alignment.  This module is enabled by either the `bytemuck`
+//! or `zerocopy` feature, which asserts those implementations.  Load a tree
+//! from cast nodes with `MerkleTree::from_nodes()`, which copies them in bulk.
+
+use crate::error::{MerkleTreeError, Result};
+use crate::Hash;
+
+#[cfg(feature = "bytemuck")]
+const _: fn() = || {
+    fn pod<T: bytemuck::Pod>() {}
+    pod::<Hash>();
+};
+
+#[cfg(feature = "zerocopy")]
+const _: fn() = || {
+    fn zero_copy<T>()
+    where
+        T: zerocopy::FromBytes
+            + zerocopy::IntoBytes
+            + zerocopy::Immutable
+            + zerocopy::KnownLayout
+            + ?Sized,
+    {
+    }
+    zero_copy::<Hash>();
+    zero_copy::<[Hash]>();
+};
+
+/// Cast bytes, e.g. a memory map of `MerkleTree::as_bytes()`, to nodes
+/// without copying them.  The length must be a multiple of 32 bytes.
+///
+/// ```rust
+/// use merkle_tree::{zero_copy, MerkleTree};
+///
+/// let leaves = [MerkleTree::hash(b"a"), MerkleTree::hash(b"b"), MerkleTree::hash(b"c")];
+/// let tree = MerkleTree::new(&leaves).unwrap();
+///
+/// let nodes = zero_copy::cast_nodes(tree.as_bytes()).unwrap();
+/// assert_eq!(nodes, tree.nodes());
+///
+/// let loaded = MerkleTree::from_nodes(nodes.to_vec(), 3, true).unwrap();
+/// assert_eq!(loaded, tree);
+/// ```
+pub fn cast_nodes(bytes: &[u8]) -> Result<&[Hash]> {
+    #[cfg(feature = "zerocopy")]
+    return <[Hash] as zerocopy::FromBytes>::ref_from_bytes(bytes)
+        .map_err(|error| MerkleTreeError::InvalidEncoding(error.to_string()));
+
+    #[cfg(not(feature = "zerocopy"))]
+    bytemuck::try_cast_slice(bytes)
+        .map_err(|error| MerkleTreeError::InvalidEncoding(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn casts_bytes_to_nodes() {
+        let tree = MerkleTree::new(&[MerkleTree::hash(b"a"), MerkleTree::hash(b"b")]).unwrap();
+
+        // casting doesn't depend on the alignment of the bytes
+        let bytes = [&[0][..], tree.as_bytes()].concat();
+        assert_eq!(cast_nodes(&bytes[1..]).unwrap(), tree.nodes());
+        assert!(cast_nodes(&bytes).is_err());
+    }
+}